@@ -1,11 +1,57 @@
-use inquire::Select;
+use std::{cell::RefCell, future::Future, io::Write, path::PathBuf, sync::Mutex, time::Instant};
+
+use chrono::{NaiveDate, NaiveTime};
+use inquire::{Confirm, Select};
 use polestar_core::{
-  model::{AppData, ChannelCfg, Msg, MsgAction, MsgBody, MsgCont, MsgMeta, MsgRole},
-  service::{open_ai::deal_open_ai_stream, req::create_text_request},
+  db::pool::runtime,
+  error::PolestarError,
+  model::{
+    AppData, BotId, ChannelCfg, ChannelSummary, Msg, MsgAction, MsgBody, MsgId, MsgMeta, MsgRating,
+    MsgRole, MsgSearchFilter, Rating, SearchRole, UsageGroup, UsageKey,
+  },
+  service::{
+    dataset::DatasetFormat,
+    export::{ExportFormat, ExportOptions},
+    image::generate_images,
+    pipeline::PipelineRun,
+    provider::deal_chat_stream,
+    req::{
+      cancelable, chat_request_content, create_text_request, regenerate_request_content,
+      request_tokens, summary_request_content, CancelHandle,
+    },
+    tool::request_with_tools,
+  },
 };
 use reedline_repl_rs::{clap::ArgMatches, Result as ReplResult};
+use serde_json::{Map as JsonMap, Value as JsonValue};
 use uuid::Uuid;
 
+/// The request Ctrl-C stops, only one request runs at a time.
+static INTERRUPTIBLE: Mutex<Option<CancelHandle>> = Mutex::new(None);
+
+/// Listen to Ctrl-C in the app's runtime for all the requests, it stops the
+/// running request but keeps the part already received.
+pub fn listen_ctrl_c() {
+  runtime().spawn(async {
+    while tokio::signal::ctrl_c().await.is_ok() {
+      if let Some(handle) = INTERRUPTIBLE.lock().unwrap().take() {
+        handle.cancel();
+      }
+    }
+  });
+}
+
+/// Run the request until it's done or stopped by Ctrl-C.
+async fn interruptible<T>(
+  fut: impl Future<Output = Result<T, PolestarError>>,
+) -> Result<T, PolestarError> {
+  let (query, handle) = cancelable(fut);
+  *INTERRUPTIBLE.lock().unwrap() = Some(handle);
+  let res = query.await;
+  INTERRUPTIBLE.lock().unwrap().take();
+  res
+}
+
 pub fn channel_handler(args: ArgMatches, app_data: &mut AppData) -> ReplResult<Option<String>> {
   match args.subcommand() {
     Some(("all", _args)) => {
@@ -69,6 +115,127 @@ pub fn channel_handler(args: ArgMatches, app_data: &mut AppData) -> ReplResult<O
       }
       Ok(None)
     }
+    Some(("fork", args)) => {
+      let channel = app_data.cur_channel().expect("current channel not found");
+      let channel_id = *channel.id();
+      let msg_id = match args.get_one::<String>("msg") {
+        Some(id) => match Uuid::parse_str(id) {
+          Ok(id) => id,
+          Err(e) => return Ok(Some(format!("invalid message id `{}`: {}", id, e))),
+        },
+        None => match channel.branch_msgs().last() {
+          Some(msg) => *msg.id(),
+          None => return Ok(Some("no message to fork from".to_owned())),
+        },
+      };
+      match app_data.fork_channel(&channel_id, &msg_id) {
+        Some(id) => {
+          let name = app_data
+            .get_channel(&id)
+            .map_or("", |channel| channel.name());
+          println!("{}: {}", id, name);
+          Ok(None)
+        }
+        None => Ok(Some(format!("message {} not found", msg_id))),
+      }
+    }
+    Some(("memory", args)) => {
+      let memory = args.get_one::<String>("switch").map(String::as_str) == Some("on");
+      let channel = app_data
+        .cur_channel_mut()
+        .expect("current channel not found");
+      let mut cfg = channel.cfg().clone();
+      cfg.set_memory(memory);
+      channel.set_cfg(cfg);
+      Ok(None)
+    }
+    Some(("summary", args)) => {
+      let channel = app_data
+        .cur_channel_mut()
+        .expect("current channel not found");
+      if args.get_flag("reset") {
+        channel.set_summary(None);
+      } else if let Some(summary) = channel.summary() {
+        println!("{}", summary.content());
+      } else {
+        println!("[no summary]");
+      }
+      Ok(None)
+    }
+    Some(("export", args)) => {
+      let path = PathBuf::from(args.get_one::<String>("path").expect("path is required"));
+      let Some(format) = ExportFormat::from_path(&path) else {
+        return Ok(Some(
+          "the file extension must be md, json or html".to_owned(),
+        ));
+      };
+      let channel_ids = if args.get_flag("all") {
+        vec![]
+      } else {
+        let channel = app_data.cur_channel().expect("current channel not found");
+        vec![*channel.id()]
+      };
+      let opts = ExportOptions {
+        alternates: args.get_flag("alternates"),
+        embed_images: args.get_flag("embed"),
+        ..ExportOptions::new(format)
+      };
+      match app_data
+        .export_channels(&channel_ids, &opts)
+        .and_then(|export| export.write(&path))
+      {
+        Ok(()) => println!("exported to {}", path.display()),
+        Err(e) => println!("error: {}", e),
+      }
+      Ok(None)
+    }
+    Some(("dataset", args)) => {
+      let path = args.get_one::<String>("path").expect("path is required");
+      let channel_ids = if args.get_flag("all") {
+        vec![]
+      } else {
+        app_data
+          .info()
+          .cur_channel_id()
+          .into_iter()
+          .cloned()
+          .collect()
+      };
+      let format = if args.get_flag("preference") {
+        DatasetFormat::Preference
+      } else {
+        DatasetFormat::FineTune
+      };
+      let rst = app_data
+        .export_dataset(&channel_ids, format)
+        .and_then(|dataset| {
+          std::fs::write(path, &dataset)?;
+          Ok(dataset.lines().count())
+        });
+      match rst {
+        Ok(count) => println!("{} lines exported to {}", count, path),
+        Err(e) => println!("error: {}", e),
+      }
+      Ok(None)
+    }
+    Some(("import", args)) => {
+      let path = args.get_one::<String>("file").expect("file is required");
+      let rst = std::fs::read_to_string(path)
+        .map_err(PolestarError::from)
+        .and_then(|content| app_data.import_channels(&content));
+      match rst {
+        Ok(ids) => {
+          for id in ids {
+            let channel = app_data
+              .get_channel(&id)
+              .expect("imported channel not found");
+            println!("{}: {} [{} msgs]", id, channel.name(), channel.msgs().len());
+          }
+        }
+        Err(e) => println!("error: {}", e),
+      }
+      Ok(None)
+    }
     _ => Ok(None),
   }
 }
@@ -76,58 +243,683 @@ pub fn channel_handler(args: ArgMatches, app_data: &mut AppData) -> ReplResult<O
 pub fn msg_handler(args: ArgMatches, app_data: &mut AppData) -> ReplResult<Option<String>> {
   match args.subcommand() {
     Some(("send", args)) => {
-      let content = args.get_one::<String>("questions");
+      let content = args
+        .get_one::<String>("questions")
+        .expect("content is required")
+        .to_owned();
+      let bot_id = app_data.info().def_bot().id().clone();
+      let mut images = vec![];
+      for src in args.get_many::<String>("image").into_iter().flatten() {
+        match app_data.attach_image(src) {
+          Ok(image) => images.push(image),
+          Err(e) => return Ok(Some(format!("attach image `{}` failed: {}", src, e))),
+        }
+      }
+      if !images.is_empty()
+        && !app_data
+          .info()
+          .bot(&bot_id)
+          .is_some_and(|bot| bot.accepts_images())
       {
+        println!("[warning] the bot doesn't accept images, only the text is sent");
+      }
+      let is_pipeline = app_data
+        .info()
+        .bot(&bot_id)
+        .is_some_and(|bot| bot.pipeline().is_some());
+      if is_pipeline {
+        return Ok(run_pipeline(app_data, &bot_id, content));
+      }
+      let msg_id = {
         let cur_channel = app_data
           .cur_channel_mut()
           .expect("current channel not found");
-        let mut msg_cont = MsgCont::init_text();
-        msg_cont.action(MsgAction::Receiving(MsgBody::Text(
-          content.map(|s| s.to_owned()),
-        )));
-        cur_channel.add_msg(Msg::new(
-          MsgRole::User,
-          vec![msg_cont],
-          MsgMeta::default(),
-          None,
-        ));
+        let user_msg = Msg::new_user_images(&content, images, MsgMeta::default());
+        let bot_msg = Msg::new_bot_text(bot_id.clone(), MsgMeta::reply(*user_msg.id()));
+        let msg_id = *bot_msg.id();
+        cur_channel.add_msg(user_msg);
+        cur_channel.add_msg(bot_msg);
+        msg_id
+      };
+
+      let body = {
+        let info = app_data.info();
+        let bot = info.bot(&bot_id).expect("bot not found");
+        let cur_channel = app_data.cur_channel().expect("current channel not found");
+        println!("[{} tokens]", request_tokens(bot, cur_channel, &content));
+        chat_request_content(info, bot, cur_channel, &content)
+      };
+      stream_answer(app_data, &bot_id, msg_id, 0, body);
+      Ok(None)
+    }
+    Some(("regenerate", args)) => {
+      let cur_channel = app_data.cur_channel().expect("current channel not found");
+      let msg_id = match args.get_one::<String>("msg") {
+        Some(id) => match Uuid::parse_str(id) {
+          Ok(id) => id,
+          Err(e) => return Ok(Some(format!("invalid message id `{}`: {}", id, e))),
+        },
+        None => match cur_channel
+          .branch_msgs()
+          .into_iter()
+          .rev()
+          .find(|msg| msg.role().is_bot())
+        {
+          Some(msg) => *msg.id(),
+          None => return Ok(Some("no answer to regenerate".to_owned())),
+        },
+      };
+      let mut params = JsonMap::new();
+      for param in args.get_many::<String>("param").into_iter().flatten() {
+        let Some((key, val)) = param.split_once('=') else {
+          return Ok(Some(format!("invalid param `{}`, expect key=value", param)));
+        };
+        let val = serde_json::from_str(val).unwrap_or_else(|_| JsonValue::from(val));
+        params.insert(key.trim().to_owned(), val);
       }
+      let Some(bot_id) = args
+        .get_one::<String>("bot")
+        .or_else(|| cur_channel.msg(&msg_id).and_then(|msg| msg.role().bot()))
+        .cloned()
+      else {
+        return Ok(Some(format!("message {} is not an answer", msg_id)));
+      };
 
-      let mut ret_msg = String::new();
-      let bot_id = app_data.info().def_bot().id().clone();
-      let runtime = tokio::runtime::Runtime::new().unwrap();
-      runtime.block_on(async {
-        if let Ok(mut stream) = create_text_request(app_data.info(), bot_id)
-          .request(content.expect("content is required").clone())
-          .await
+      let body = {
+        let info = app_data.info();
+        let Some(bot) = info.bot(&bot_id) else {
+          return Ok(Some(format!("bot `{}` not found", bot_id)));
+        };
+        regenerate_request_content(info, bot, cur_channel, &msg_id, Some(&params))
+      };
+      let Some(body) = body else {
+        return Ok(Some(format!("message {} is not an answer", msg_id)));
+      };
+      let cur_channel = app_data
+        .cur_channel_mut()
+        .expect("current channel not found");
+      let Some(idx) = cur_channel.regenerate(&msg_id, Some(bot_id.clone())) else {
+        return Ok(Some(format!("message {} is not an answer", msg_id)));
+      };
+      let count = cur_channel.msg(&msg_id).map_or(0, |msg| msg.cont_count());
+      println!("[answer {}/{}]", idx + 1, count);
+      stream_answer(app_data, &bot_id, msg_id, idx, body);
+      Ok(None)
+    }
+    Some(("save-image", args)) => {
+      let path = args.get_one::<String>("path").expect("path is required");
+      let cur_channel = app_data.cur_channel().expect("current channel not found");
+      let msg = match args.get_one::<String>("msg") {
+        Some(id) => match Uuid::parse_str(id) {
+          Ok(id) => cur_channel.msg(&id),
+          Err(e) => return Ok(Some(format!("invalid message id `{}`: {}", id, e))),
+        },
+        None => cur_channel
+          .branch_msgs()
+          .into_iter()
+          .rev()
+          .find(|msg| msg.cur_cont_ref().image().is_some()),
+      };
+      let Some(img) = msg.and_then(|msg| msg.cur_cont_ref().image()) else {
+        return Ok(Some("no image to save".to_owned()));
+      };
+      match app_data.save_image(img, &PathBuf::from(path)) {
+        Ok(path) => Ok(Some(format!("saved to {}", path.display()))),
+        Err(e) => Ok(Some(format!("error: {}", e))),
+      }
+    }
+    Some(("search", args)) => {
+      let query = args.get_one::<String>("query").expect("query is required");
+      let date = |key: &str| {
+        args.get_one::<String>(key).map(|date| {
+          NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+            .map_err(|e| format!("invalid date `{}`: {}", date, e))
+        })
+      };
+      let since = match date("since").transpose() {
+        Ok(since) => since,
+        Err(e) => return Ok(Some(e)),
+      };
+      let until = match date("until").transpose() {
+        Ok(until) => until,
+        Err(e) => return Ok(Some(e)),
+      };
+      let bot = args.get_one::<String>("bot").cloned();
+      let role = match args.get_one::<String>("role").map(String::as_str) {
+        Some("user") => Some(SearchRole::User),
+        Some("system") => Some(SearchRole::System),
+        Some("bot") => Some(SearchRole::Bot(bot)),
+        _ => bot.map(|bot| SearchRole::Bot(Some(bot))),
+      };
+      let channel_id = args
+        .get_flag("channel")
+        .then(|| app_data.info().cur_channel_id().cloned())
+        .flatten();
+      let filter = MsgSearchFilter {
+        channel_id,
+        role,
+        since,
+        until,
+        ..<_>::default()
+      };
+
+      match app_data.search_msgs(query, &filter) {
+        Ok(hits) if hits.is_empty() => println!("[no message found]"),
+        Ok(hits) => {
+          for hit in hits {
+            let channel = app_data
+              .get_channel(&hit.channel_id)
+              .map_or("", |channel| channel.name());
+            let role = match &hit.role {
+              MsgRole::User => "user".to_owned(),
+              MsgRole::Bot(bot_id) => bot_id.clone(),
+              MsgRole::System(_) => "system".to_owned(),
+              MsgRole::Tool(call) => format!("tool {}", call.name),
+            };
+            println!(
+              "{} [{}] {} {}: {}",
+              hit.msg_id,
+              channel,
+              hit.created_at.format("%Y-%m-%d %H:%M"),
+              role,
+              hit.snippet
+            );
+          }
+        }
+        Err(e) => println!("error: {}", e),
+      }
+      Ok(None)
+    }
+    Some(("rate", args)) => {
+      let cur_channel = app_data
+        .cur_channel_mut()
+        .expect("current channel not found");
+      let msg_id = match args.get_one::<String>("msg") {
+        Some(id) => match Uuid::parse_str(id) {
+          Ok(id) => id,
+          Err(e) => return Ok(Some(format!("invalid message id `{}`: {}", id, e))),
+        },
+        None => match cur_channel
+          .branch_msgs()
+          .into_iter()
+          .rev()
+          .find(|msg| msg.role().is_bot())
         {
-          let res = deal_open_ai_stream(&mut stream, |s| print!("{}", s)).await;
-          match res {
-            Ok(s) => {
-              ret_msg = s;
-              println!();
-            }
-            Err(e) => println!("\nerror: {:?}", e),
-          };
+          Some(msg) => *msg.id(),
+          None => return Ok(Some("no answer to rate".to_owned())),
+        },
+      };
+      let Some(idx) = cur_channel
+        .msg(&msg_id)
+        .filter(|msg| msg.role().is_bot())
+        .map(|msg| msg.cur_idx())
+      else {
+        return Ok(Some(format!("message {} is not an answer", msg_id)));
+      };
+      let comment = args.get_one::<String>("comment").cloned();
+      let rating = match args.get_one::<String>("rating").map(String::as_str) {
+        Some("up") => Some(MsgRating::new(Rating::Up, comment)),
+        Some("down") => Some(MsgRating::new(Rating::Down, comment)),
+        _ => None,
+      };
+      cur_channel.rate(&msg_id, idx, rating);
+      Ok(None)
+    }
+    Some(("pick", args)) => {
+      let cur_channel = app_data
+        .cur_channel_mut()
+        .expect("current channel not found");
+      let msg_id = match args.get_one::<String>("msg") {
+        Some(id) => match Uuid::parse_str(id) {
+          Ok(id) => id,
+          Err(e) => return Ok(Some(format!("invalid message id `{}`: {}", id, e))),
+        },
+        None => match cur_channel
+          .branch_msgs()
+          .into_iter()
+          .rev()
+          .find(|msg| msg.role().is_bot())
+        {
+          Some(msg) => *msg.id(),
+          None => return Ok(Some("no answer to pick".to_owned())),
+        },
+      };
+      let Some(question_id) = cur_channel
+        .msg(&msg_id)
+        .filter(|msg| msg.role().is_bot())
+        .and_then(|msg| msg.meta().source_id().copied())
+      else {
+        return Ok(Some(format!("message {} is not an answer", msg_id)));
+      };
+      if cur_channel.answers(&question_id).len() < 2 {
+        return Ok(Some("only one bot answered the question".to_owned()));
+      }
+      if args.get_flag("clear") {
+        cur_channel.pick_best(&question_id, None);
+      } else {
+        cur_channel.pick_best(&question_id, Some(msg_id));
+      }
+      Ok(None)
+    }
+    Some(("delete", args)) => {
+      let cur_channel = app_data
+        .cur_channel_mut()
+        .expect("current channel not found");
+      let msg_id = match args.get_one::<String>("msg") {
+        Some(id) => match Uuid::parse_str(id) {
+          Ok(id) => id,
+          Err(e) => return Ok(Some(format!("invalid message id `{}`: {}", id, e))),
+        },
+        None => match cur_channel.branch_msgs().last() {
+          Some(msg) => *msg.id(),
+          None => return Ok(Some("no message to delete".to_owned())),
+        },
+      };
+      let removed = if args.get_flag("group") {
+        cur_channel.remove_msg_group(&msg_id)
+      } else {
+        cur_channel.remove_msg(&msg_id)
+      };
+      if removed.is_empty() {
+        return Ok(Some(format!("message {} not found", msg_id)));
+      }
+      for msg in removed {
+        println!("deleted {}", msg.id());
+      }
+      Ok(None)
+    }
+    _ => Ok(None),
+  }
+}
+
+pub fn trash_handler(args: ArgMatches, app_data: &mut AppData) -> ReplResult<Option<String>> {
+  let parse_id = |args: &ArgMatches| {
+    args
+      .get_one::<String>("id")
+      .map(|id| Uuid::parse_str(id).map_err(|e| format!("invalid id `{}`: {}", id, e)))
+      .transpose()
+  };
+  match args.subcommand() {
+    Some(("list", _args)) => {
+      match app_data.trash() {
+        Ok(items) if items.is_empty() => println!("[trash is empty]"),
+        Ok(items) => {
+          for item in items {
+            let kind = if item.msg_id.is_some() {
+              "msg"
+            } else {
+              "channel"
+            };
+            println!(
+              "{} [{}] {}: {}",
+              item.id(),
+              kind,
+              item.deleted_at.format("%Y-%m-%d %H:%M"),
+              item.title
+            );
+          }
         }
-      });
+        Err(e) => println!("error: {}", e),
+      }
+      Ok(None)
+    }
+    Some(("restore", args)) => {
+      let id = match parse_id(args) {
+        Ok(id) => id.expect("id is required"),
+        Err(e) => return Ok(Some(e)),
+      };
+      match app_data.restore_trash(&id) {
+        Ok(true) => Ok(None),
+        Ok(false) => Ok(Some(format!("{} is not in the trash", id))),
+        Err(e) => Ok(Some(format!("error: {}", e))),
+      }
+    }
+    Some(("purge", args)) => {
+      let id = match parse_id(args) {
+        Ok(id) => id,
+        Err(e) => return Ok(Some(e)),
+      };
+      match app_data.purge_trash(id.as_ref()) {
+        Ok(count) => Ok(Some(format!("{} purged", count))),
+        Err(e) => Ok(Some(format!("error: {}", e))),
+      }
+    }
+    _ => Ok(None),
+  }
+}
 
-      {
-        let cur_channel = app_data
-          .cur_channel_mut()
-          .expect("current channel not found");
-        let mut msg_cont = MsgCont::init_text();
-        msg_cont.action(MsgAction::Receiving(MsgBody::Text(Some(ret_msg.clone()))));
-        cur_channel.add_msg(Msg::new(
-          MsgRole::Bot(String::new()),
-          vec![msg_cont],
-          MsgMeta::default(),
-          None,
-        ));
+pub fn stats_handler(args: ArgMatches, app_data: &mut AppData) -> ReplResult<Option<String>> {
+  let group = match args.get_one::<String>("by").map(String::as_str) {
+    Some("bot") => UsageGroup::Bot,
+    Some("provider") => UsageGroup::Provider,
+    Some("channel") => UsageGroup::Channel,
+    _ => UsageGroup::Day,
+  };
+  let since = match args.get_one::<String>("since").map(|date| {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+      .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+      .map_err(|e| format!("invalid date `{}`: {}", date, e))
+  }) {
+    Some(Ok(since)) => Some(since),
+    Some(Err(e)) => return Ok(Some(e)),
+    None => None,
+  };
+
+  match app_data.usage_stats(group, since) {
+    Ok(stats) => {
+      println!(
+        "{:<36} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "", "answers", "prompt", "completion", "latency", "cost"
+      );
+      for stat in stats {
+        let key = match &stat.key {
+          UsageKey::Channel(id) => app_data
+            .get_channel(id)
+            .map_or_else(|| id.to_string(), |channel| channel.name().to_owned()),
+          key => key.to_string(),
+        };
+        // mark the groups with estimated tokens.
+        let estimated = if stat.estimated > 0 { "~" } else { "" };
+        println!(
+          "{:<36} {:>8} {:>10} {:>10} {:>8}ms {:>10.4}",
+          key,
+          stat.answers,
+          format!("{}{}", estimated, stat.prompt_tokens),
+          format!("{}{}", estimated, stat.completion_tokens),
+          stat.avg_latency_ms,
+          stat.cost
+        );
       }
 
+      let info = app_data.info();
+      for (provider, month, cost) in info.spending().totals() {
+        let cap = info
+          .providers()
+          .get(provider)
+          .and_then(|sp| sp.budget)
+          .and_then(|budget| budget.hard)
+          .map_or_else(String::new, |hard| format!(" of {:.2}", hard));
+        println!("{} spent {:.4}{} in {}", provider, cost, cap, month);
+      }
       Ok(None)
     }
-    _ => Ok(None),
+    Err(e) => Ok(Some(format!("error: {}", e))),
+  }
+}
+
+pub fn arena_handler(_args: ArgMatches, app_data: &mut AppData) -> ReplResult<Option<String>> {
+  match app_data.arena_stats() {
+    Ok(stats) if stats.is_empty() => Ok(Some("[no picked answers]".to_owned())),
+    Ok(stats) => {
+      println!("{:<36} {:>8} {:>8} {:>8}", "", "wins", "rounds", "rate");
+      for stat in stats {
+        println!(
+          "{:<36} {:>8} {:>8} {:>7.0}%",
+          stat.bot,
+          stat.wins,
+          stat.rounds,
+          stat.wins as f64 * 100. / stat.rounds as f64
+        );
+      }
+      Ok(None)
+    }
+    Err(e) => Ok(Some(format!("error: {}", e))),
+  }
+}
+
+pub fn mcp_handler(_args: ArgMatches, app_data: &mut AppData) -> ReplResult<Option<String>> {
+  let clients = app_data.info().mcp_clients();
+  if clients.is_empty() {
+    return Ok(Some("[no MCP server in the config]".to_owned()));
+  }
+  for client in clients {
+    println!("{}:", client.name());
+    match runtime().block_on(client.list_tools()) {
+      Ok(tools) => tools.iter().for_each(|tool| {
+        let desc = tool.description.as_deref().unwrap_or_default();
+        println!("  tool {}__{}: {}", client.name(), tool.name, desc);
+      }),
+      Err(e) => println!("  tools error: {}", e),
+    }
+    match runtime().block_on(client.list_resources()) {
+      Ok(resources) => resources.iter().for_each(|resource| {
+        println!("  resource {}: {}", resource.uri, resource.name);
+      }),
+      Err(e) => println!("  resources error: {}", e),
+    }
+    match runtime().block_on(client.list_prompts()) {
+      Ok(prompts) => prompts.iter().for_each(|prompt| {
+        let desc = prompt.description.as_deref().unwrap_or_default();
+        println!("  prompt {}: {}", prompt.name, desc);
+      }),
+      Err(e) => println!("  prompts error: {}", e),
+    }
+  }
+  Ok(None)
+}
+
+/// Send the question to the pipeline bot, its steps answer one by one in the
+/// current channel until one of them is not fulfilled.
+fn run_pipeline(app_data: &mut AppData, bot_id: &BotId, content: String) -> Option<String> {
+  let user_msg = Msg::new_user_text(&content, MsgMeta::default());
+  let info = app_data.info();
+  let mut run = PipelineRun::new(info.bot(bot_id)?, *user_msg.id(), content)?;
+  if let Err(e) = run.check(info) {
+    return Some(format!("error: {}", e));
+  }
+  app_data
+    .cur_channel_mut()
+    .expect("current channel not found")
+    .add_msg(user_msg);
+
+  loop {
+    let cur_channel = app_data.cur_channel().expect("current channel not found");
+    let Some(msg) = run.next_msg(cur_channel) else {
+      break;
+    };
+    let step_bot = run.cur_bot().expect("step bot not found").clone();
+    let msg_id = *msg.id();
+    let step = msg.meta().pipeline().map_or(0, |link| link.step);
+    app_data
+      .cur_channel_mut()
+      .expect("current channel not found")
+      .add_msg(msg);
+
+    let info = app_data.info();
+    let cur_channel = app_data.cur_channel().expect("current channel not found");
+    let body = run.request_content(info, cur_channel)?;
+    let name = info
+      .bot(&step_bot)
+      .map_or(step_bot.as_str(), |bot| bot.name());
+    println!("[step {}/{}: {}]", step + 1, run.steps().len(), name);
+    stream_answer(app_data, &step_bot, msg_id, 0, body);
+  }
+  None
+}
+
+/// Stream the bot's answer into the content `idx` of the message in current
+/// channel, the channel is summarised after the answer is done.
+fn stream_answer(app_data: &mut AppData, bot_id: &BotId, msg_id: MsgId, idx: usize, body: String) {
+  if app_data
+    .info()
+    .bot(bot_id)
+    .is_some_and(|bot| bot.is_image_gen())
+  {
+    return generate_image(app_data, bot_id, msg_id, idx, body);
+  }
+  let req = create_text_request(app_data.info(), bot_id.clone());
+  // the request over the hard cap fails with the error.
+  if let Some(warning) = app_data
+    .info()
+    .budget(bot_id)
+    .filter(|budget| !budget.is_over())
+    .and_then(|budget| budget.message())
+  {
+    println!("[warning] {}", warning);
+  }
+  let started = Instant::now();
+  // the bot calls only the tools it enables.
+  let info = app_data.info();
+  let tools = info
+    .bot(bot_id)
+    .map(|bot| info.tools().for_bot(bot))
+    .unwrap_or_default();
+  let cur_channel = app_data
+    .cur_channel_mut()
+    .expect("current channel not found");
+  let question_id = cur_channel
+    .msg(&msg_id)
+    .and_then(|msg| msg.meta().source_id().copied());
+  let channel = RefCell::new(&mut *cur_channel);
+  // the requests run in the app's runtime, the pooled connections of the http
+  // client can't outlive the runtime they're created in.
+  let res = runtime().block_on(async {
+    interruptible(request_with_tools(
+      req,
+      body.clone(),
+      &tools,
+      |attempt| {
+        println!("[retrying, attempt {}]", attempt);
+        channel
+          .borrow_mut()
+          .update_msg(&msg_id, idx, MsgAction::Retrying(attempt));
+      },
+      |s| {
+        print!("{}", s);
+        let _ = std::io::stdout().flush();
+        channel
+          .borrow_mut()
+          .update_msg(&msg_id, idx, MsgAction::Receiving(MsgBody::Text(Some(s))));
+      },
+      |_, question| {
+        println!();
+        let allowed = Confirm::new(question)
+          .with_default(false)
+          .prompt()
+          .unwrap_or(false);
+        std::future::ready(allowed)
+      },
+      |call, result| {
+        println!("[tool {}({})]", call.name, call.arguments);
+        let meta = question_id.map_or_else(MsgMeta::default, MsgMeta::reply);
+        channel
+          .borrow_mut()
+          .add_tool_msg(&msg_id, Msg::new_tool(call, &result, meta));
+      },
+    ))
+    .await
+  });
+
+  match res {
+    Ok(answer) => {
+      println!();
+      let usage = app_data
+        .info()
+        .bot(bot_id)
+        .map(|bot| answer.msg_usage(bot, &body, started));
+      let channel_id = *app_data
+        .info()
+        .cur_channel_id()
+        .expect("current channel not found");
+      if let Some(usage) = usage {
+        app_data.record_usage(&channel_id, &msg_id, idx, usage);
+      }
+      let cur_channel = app_data
+        .cur_channel_mut()
+        .expect("current channel not found");
+      cur_channel.update_msg(&msg_id, idx, MsgAction::Fulfilled);
+      update_summary(app_data, bot_id);
+    }
+    Err(PolestarError::Canceled) => {
+      println!("\n[interrupted]");
+      cur_channel.update_msg(&msg_id, idx, MsgAction::Interrupted);
+    }
+    Err(e) => {
+      println!("\nerror: {}", e);
+      let received = cur_channel
+        .msg(&msg_id)
+        .and_then(|msg| msg.cont_list().get(idx))
+        .is_some_and(|cont| cont.text().is_some());
+      let act = if received {
+        MsgAction::Fulfilled
+      } else {
+        MsgAction::Rejected
+      };
+      cur_channel.update_msg(&msg_id, idx, act);
+    }
+  }
+}
+
+/// Draw the images of the image bot into the content `idx` of the message in
+/// current channel, the extra images are added as the alternatives.
+fn generate_image(app_data: &mut AppData, bot_id: &BotId, msg_id: MsgId, idx: usize, body: String) {
+  let req = create_text_request(app_data.info(), bot_id.clone());
+  let channel_id = *app_data
+    .info()
+    .cur_channel_id()
+    .expect("current channel not found");
+  println!("[generating image]");
+  let cur_channel = app_data
+    .cur_channel_mut()
+    .expect("current channel not found");
+  let res = runtime().block_on(async {
+    interruptible(generate_images(req, body, |attempt| {
+      println!("[retrying, attempt {}]", attempt);
+      cur_channel.update_msg(&msg_id, idx, MsgAction::Retrying(attempt));
+    }))
+    .await
+  });
+
+  match res {
+    Ok(images) => {
+      for img in images.iter() {
+        println!(
+          "[image {} {}x{}]",
+          img.attachment.name(),
+          img.image.width(),
+          img.image.height()
+        );
+      }
+      app_data.add_images(&channel_id, &msg_id, idx, images);
+      println!("[save it by `msg save-image <path>`]");
+    }
+    Err(e) => {
+      let cur_channel = app_data
+        .cur_channel_mut()
+        .expect("current channel not found");
+      if matches!(e, PolestarError::Canceled) {
+        println!("[interrupted]");
+        cur_channel.update_msg(&msg_id, idx, MsgAction::Interrupted);
+      } else {
+        println!("error: {}", e);
+        cur_channel.update_msg(&msg_id, idx, MsgAction::Rejected);
+      }
+    }
+  }
+}
+
+/// Summarise the messages of the current channel out of the context, if its
+/// memory is on.
+fn update_summary(app_data: &mut AppData, bot_id: &BotId) {
+  let Some((body, until)) = app_data.cur_channel().and_then(|channel| {
+    let info = app_data.info();
+    summary_request_content(info, info.bot(bot_id)?, channel)
+  }) else {
+    return;
+  };
+  let req = create_text_request(app_data.info(), bot_id.clone());
+  let provider = req.provider();
+  let res = runtime().block_on(async {
+    let mut stream = req.request_with_retry(body, |_| {}).await?;
+    deal_chat_stream(provider, &mut stream, |_| {}).await
+  });
+  match res {
+    Ok(summary) => {
+      if let Some(channel) = app_data.cur_channel_mut() {
+        channel.set_summary(Some(ChannelSummary::new(summary.text, until)));
+      }
+    }
+    Err(e) => println!("summarise error: {}", e),
   }
 }
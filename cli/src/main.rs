@@ -1,6 +1,9 @@
-use handler::{channel_handler, msg_handler};
+use handler::{
+  arena_handler, channel_handler, listen_ctrl_c, mcp_handler, msg_handler, stats_handler,
+  trash_handler,
+};
 use polestar_core::model::{init_app_data, ChannelCfg};
-use reedline_repl_rs::clap::{Arg, Command};
+use reedline_repl_rs::clap::{Arg, ArgAction, Command};
 use reedline_repl_rs::{Repl, Result as ReplResult};
 
 mod handler;
@@ -11,6 +14,7 @@ static APP_DESC: &str = env!("CARGO_PKG_DESCRIPTION");
 
 fn main() -> ReplResult<()> {
   let mut app_data = init_app_data();
+  listen_ctrl_c();
   app_data.new_channel("quick launcher".to_owned(), None, ChannelCfg::default());
   let mut repl = Repl::new(app_data)
     .with_name(APP_NAME)
@@ -28,15 +32,228 @@ fn main() -> ReplResult<()> {
             .arg(Arg::new("desc"))
             .about("Add channel"),
           Command::new("switch").about("Switch channel"),
+          Command::new("fork")
+            .arg(
+              Arg::new("msg")
+                .help("The id of the message, the last message of current channel by default"),
+            )
+            .about("Fork current channel from the message to a new channel"),
+          Command::new("memory")
+            .arg(
+              Arg::new("switch")
+                .required(true)
+                .value_parser(["on", "off"]),
+            )
+            .about("Summarise the messages out of the context of current channel"),
+          Command::new("summary")
+            .arg(Arg::new("reset").long("reset").action(ArgAction::SetTrue))
+            .about("Show or reset the summary of current channel"),
+          Command::new("export")
+            .arg(
+              Arg::new("path")
+                .required(true)
+                .help("The file to export to, the format is by its extension: md, json or html"),
+            )
+            .arg(
+              Arg::new("all")
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .help("Export all channels"),
+            )
+            .arg(
+              Arg::new("alternates")
+                .long("alternates")
+                .action(ArgAction::SetTrue)
+                .help("Include the alternate answers of the messages"),
+            )
+            .arg(
+              Arg::new("embed")
+                .long("embed")
+                .action(ArgAction::SetTrue)
+                .help("Embed the images instead of writing them next to the file"),
+            )
+            .about("Export current channel to Markdown, JSON or HTML"),
+          Command::new("dataset")
+            .arg(
+              Arg::new("path")
+                .required(true)
+                .help("The JSON Lines file to write to"),
+            )
+            .arg(
+              Arg::new("all")
+                .long("all")
+                .action(ArgAction::SetTrue)
+                .help("Export the rated answers of all channels"),
+            )
+            .arg(
+              Arg::new("preference")
+                .long("preference")
+                .action(ArgAction::SetTrue)
+                .help("Pair the up-rated answers with their other versions"),
+            )
+            .about("Export the rated answers of current channel as a fine-tuning dataset"),
+          Command::new("import")
+            .arg(
+              Arg::new("file")
+                .required(true)
+                .help("Polestar's JSON export or ChatGPT's conversations.json"),
+            )
+            .about("Import channels from a JSON export"),
         ])
         .arg_required_else_help(true),
       channel_handler,
     )
     .with_command(
-      Command::new("msg").subcommands([Command::new("send")
-        .arg(Arg::new("questions").required(true))
-        .about("Send message")]),
+      Command::new("msg").subcommands([
+        Command::new("send")
+          .arg(Arg::new("questions").required(true))
+          .arg(
+            Arg::new("image")
+              .long("image")
+              .action(ArgAction::Append)
+              .help("Attach the image file or url, the bot must accept images"),
+          )
+          .about("Send message"),
+        Command::new("regenerate")
+          .arg(
+            Arg::new("msg")
+              .help("The id of the answer, the last answer of current channel by default"),
+          )
+          .arg(
+            Arg::new("bot")
+              .long("bot")
+              .help("Answer by the bot instead of the answer's bot"),
+          )
+          .arg(
+            Arg::new("param")
+              .long("param")
+              .action(ArgAction::Append)
+              .help("Override a request param for this answer, i.e. temperature=0.2"),
+          )
+          .about("Answer the question again, the previous answers are kept"),
+        Command::new("save-image")
+          .arg(
+            Arg::new("path")
+              .required(true)
+              .help("The file to write to, or a folder to write the image into"),
+          )
+          .arg(
+            Arg::new("msg")
+              .help("The id of the message, the last image of current channel by default"),
+          )
+          .about("Save the image of the message to a file"),
+        Command::new("search")
+          .arg(Arg::new("query").required(true))
+          .arg(
+            Arg::new("channel")
+              .long("channel")
+              .action(ArgAction::SetTrue)
+              .help("Only search current channel"),
+          )
+          .arg(
+            Arg::new("role")
+              .long("role")
+              .value_parser(["user", "bot", "system"]),
+          )
+          .arg(
+            Arg::new("bot")
+              .long("bot")
+              .help("Only search the bot's messages"),
+          )
+          .arg(
+            Arg::new("since")
+              .long("since")
+              .help("Only search the messages since the date, i.e. 2024-01-31"),
+          )
+          .arg(
+            Arg::new("until")
+              .long("until")
+              .help("Only search the messages before the date, i.e. 2024-01-31"),
+          )
+          .about("Search messages of all channels"),
+        Command::new("rate")
+          .arg(
+            Arg::new("rating")
+              .required(true)
+              .value_parser(["up", "down", "clear"]),
+          )
+          .arg(
+            Arg::new("msg")
+              .help("The id of the answer, the last answer of current channel by default"),
+          )
+          .arg(
+            Arg::new("comment")
+              .long("comment")
+              .help("Why the answer is good or bad"),
+          )
+          .about("Rate the current version of the answer"),
+        Command::new("pick")
+          .arg(
+            Arg::new("msg")
+              .help("The id of the answer, the last answer of current channel by default"),
+          )
+          .arg(
+            Arg::new("clear")
+              .long("clear")
+              .action(ArgAction::SetTrue)
+              .help("Clear the pick, all the answers are carried into the context again"),
+          )
+          .about("Pick the answer as the best one of the bots answered the question"),
+        Command::new("delete")
+          .arg(
+            Arg::new("msg")
+              .help("The id of the message, the last message of current channel by default"),
+          )
+          .arg(
+            Arg::new("group")
+              .long("group")
+              .action(ArgAction::SetTrue)
+              .help("Delete the question with all its answers"),
+          )
+          .about("Move the message to the trash"),
+      ]),
       msg_handler,
+    )
+    .with_command(
+      Command::new("trash")
+        .subcommands([
+          Command::new("list").about("Show the deleted channels and messages"),
+          Command::new("restore")
+            .arg(Arg::new("id").required(true))
+            .about("Restore the channel or the message from the trash"),
+          Command::new("purge")
+            .arg(
+              Arg::new("id").help("The item to delete for good, all items in the trash by default"),
+            )
+            .about("Delete the items in the trash for good"),
+        ])
+        .arg_required_else_help(true),
+      trash_handler,
+    )
+    .with_command(
+      Command::new("stats")
+        .arg(
+          Arg::new("by")
+            .long("by")
+            .value_parser(["day", "bot", "provider", "channel"])
+            .default_value("day")
+            .help("Group the usage by"),
+        )
+        .arg(
+          Arg::new("since")
+            .long("since")
+            .help("Only count the answers since the date, i.e. 2024-01-31"),
+        )
+        .about("Show the tokens and the time the answers cost"),
+      stats_handler,
+    )
+    .with_command(
+      Command::new("arena").about("Show how often every bot's answer is picked as the best"),
+      arena_handler,
+    )
+    .with_command(
+      Command::new("mcp").about("Show the tools, resources and prompts of the MCP servers"),
+      mcp_handler,
     );
 
   repl.run()
@@ -1,15 +1,23 @@
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
 use crate::{
   error::PolestarResult,
-  model::{Attachment, ChannelCfg, ChannelId, Msg},
+  model::{ArenaPick, Attachment, ChannelCfg, ChannelId, ChannelSummary, Msg, MsgId, MsgRating},
 };
 
 use super::pool::DbPool;
 
+pub mod arena;
 pub mod attachment;
 pub mod channel;
+pub mod import;
 pub mod msg;
+pub mod rating;
+pub mod search;
+pub mod stats;
+pub mod summary;
+pub mod trash;
 
 #[derive(Clone)]
 pub enum ActionPersist {
@@ -23,6 +31,10 @@ pub enum ActionPersist {
   RemoveMsg {
     msg_id: Uuid,
   },
+  /// Take the message out of the trash.
+  RestoreMsg {
+    msg_id: Uuid,
+  },
   AddChannel {
     id: ChannelId,
     name: String,
@@ -32,6 +44,10 @@ pub enum ActionPersist {
   RemoveChannel {
     channel_id: Uuid,
   },
+  /// Take the channel out of the trash.
+  RestoreChannel {
+    channel_id: Uuid,
+  },
   UpdateChannel {
     id: ChannelId,
     name: String,
@@ -41,6 +57,27 @@ pub enum ActionPersist {
   AddAttachment {
     attachment: Attachment,
   },
+  /// Set the summary of the channel, `None` to reset it.
+  UpdateSummary {
+    channel_id: ChannelId,
+    summary: Option<ChannelSummary>,
+  },
+  /// Rate the content `cont_idx` of the message, `None` to clear the rating.
+  UpdateRating {
+    msg_id: MsgId,
+    cont_idx: usize,
+    rating: Option<MsgRating>,
+  },
+  /// Pick the best answer of the question, `None` to clear the pick.
+  UpdateArenaPick {
+    channel_id: ChannelId,
+    question_id: MsgId,
+    pick: Option<ArenaPick>,
+  },
+  /// Delete the items moved to the trash before `before` for good.
+  PurgeTrash {
+    before: DateTime<Utc>,
+  },
 }
 
 pub trait Persist {
@@ -51,7 +88,10 @@ impl Persist for ActionPersist {
   async fn write(&self, pool: &DbPool) -> PolestarResult<()> {
     match self {
       ActionPersist::AddMsg { channel_id, msg } => {
-        msg::add_msg(pool, channel_id, msg).await?;
+        // the message and its index are added together.
+        let mut tx = pool.begin().await?;
+        msg::add_msg(&mut tx, channel_id, msg).await?;
+        tx.commit().await?;
       }
       ActionPersist::UpdateMsg { msg } => {
         msg::update_msg(pool, msg).await?;
@@ -59,17 +99,48 @@ impl Persist for ActionPersist {
       ActionPersist::RemoveMsg { msg_id } => {
         msg::remove_msg(pool, msg_id).await?;
       }
+      ActionPersist::RestoreMsg { msg_id } => {
+        trash::restore_msg(pool, msg_id).await?;
+      }
       ActionPersist::AddChannel { id, name, desc, cfg } => {
-        channel::add_channel(pool, id, name.as_str(), desc.as_deref(), cfg).await?;
+        channel::add_channel(&mut *pool.acquire().await?, id, name, desc.as_deref(), cfg).await?;
       }
       ActionPersist::RemoveChannel { channel_id } => {
         channel::remove_channel(pool, channel_id).await?;
       }
+      ActionPersist::RestoreChannel { channel_id } => {
+        trash::restore_channel(pool, channel_id).await?;
+      }
       ActionPersist::UpdateChannel { id, name, desc, cfg } => {
         channel::update_channel(pool, id, name, desc.as_deref(), cfg).await?;
       }
       ActionPersist::AddAttachment { attachment } => {
-        attachment::add_attachment(pool, attachment).await?;
+        attachment::add_attachment(&mut *pool.acquire().await?, attachment).await?;
+      }
+      ActionPersist::UpdateSummary { channel_id, summary: Some(summary) } => {
+        summary::update_summary(&mut *pool.acquire().await?, channel_id, summary).await?;
+      }
+      ActionPersist::UpdateSummary { channel_id, summary: None } => {
+        summary::remove_summary(&mut *pool.acquire().await?, channel_id).await?;
+      }
+      ActionPersist::UpdateRating {
+        msg_id,
+        cont_idx,
+        rating: Some(rating),
+      } => {
+        rating::update_rating(&mut *pool.acquire().await?, msg_id, *cont_idx, rating).await?;
+      }
+      ActionPersist::UpdateRating { msg_id, cont_idx, rating: None } => {
+        rating::remove_rating(&mut *pool.acquire().await?, msg_id, *cont_idx).await?;
+      }
+      ActionPersist::UpdateArenaPick { channel_id, pick: Some(pick), .. } => {
+        arena::update_arena_pick(&mut *pool.acquire().await?, channel_id, pick).await?;
+      }
+      ActionPersist::UpdateArenaPick { question_id, pick: None, .. } => {
+        arena::remove_arena_pick(&mut *pool.acquire().await?, question_id).await?;
+      }
+      ActionPersist::PurgeTrash { before } => {
+        trash::purge_trash(pool, *before).await?;
       }
     }
 
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use sqlx::{Row, SqliteConnection};
+
+use crate::{
+  db::pool::DbPool,
+  error::PolestarError,
+  model::{ArenaPick, ArenaStat, BotId, ChannelId, MsgId},
+};
+
+pub async fn update_arena_pick(
+  conn: &mut SqliteConnection,
+  channel_id: &ChannelId,
+  pick: &ArenaPick,
+) -> Result<(), PolestarError> {
+  let res = sqlx::query(
+    r#"
+    INSERT INTO arena_pick (question_id, channel_id, winner_id, winner_bot, bots, question)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+    ON CONFLICT(question_id) DO UPDATE
+    SET winner_id = ?3, winner_bot = ?4, bots = ?5, question = ?6,
+      picked_at = STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')
+    "#,
+  )
+  .bind(pick.question_id)
+  .bind(channel_id)
+  .bind(pick.winner_id)
+  .bind(&pick.winner_bot)
+  .bind(serde_json::to_string(&pick.bots)?)
+  .bind(&pick.question)
+  .execute(conn)
+  .await?;
+
+  log::info!("update arena pick result: {:?}", res);
+
+  Ok(())
+}
+
+pub async fn remove_arena_pick(
+  conn: &mut SqliteConnection,
+  question_id: &MsgId,
+) -> Result<(), PolestarError> {
+  let res = sqlx::query(
+    r#"
+    DELETE FROM arena_pick
+    WHERE question_id = ?1
+    "#,
+  )
+  .bind(question_id)
+  .execute(conn)
+  .await?;
+
+  log::info!("remove arena pick result: {:?}", res);
+
+  Ok(())
+}
+
+/// The picked best answers of the questions in the channel, as the question
+/// id and the answer id.
+pub async fn query_arena_picks_by_channel_id(
+  pool: &DbPool,
+  channel_id: &ChannelId,
+) -> Result<Vec<(MsgId, MsgId)>, PolestarError> {
+  let rows = sqlx::query(
+    r#"
+    SELECT question_id, winner_id
+    FROM arena_pick
+    WHERE channel_id = ?1
+    "#,
+  )
+  .bind(channel_id)
+  .fetch_all(pool)
+  .await?;
+
+  let picks = rows
+    .into_iter()
+    .map(|row| (row.get(0), row.get(1)))
+    .collect::<Vec<_>>();
+
+  log::info!("query arena picks result: {:?}", picks);
+
+  Ok(picks)
+}
+
+/// How often every bot wins, the most winning first.
+pub async fn query_arena_stats(pool: &DbPool) -> Result<Vec<ArenaStat>, PolestarError> {
+  let rows = sqlx::query(
+    r#"
+    SELECT winner_bot, bots
+    FROM arena_pick
+    "#,
+  )
+  .fetch_all(pool)
+  .await?;
+
+  let mut stats: HashMap<BotId, ArenaStat> = HashMap::new();
+  for row in rows {
+    let winner: BotId = row.get(0);
+    let bots: Vec<BotId> = serde_json::from_str(row.get(1))?;
+    for bot in bots {
+      let stat = stats.entry(bot.clone()).or_insert_with(|| ArenaStat {
+        bot: bot.clone(),
+        wins: 0,
+        rounds: 0,
+      });
+      stat.rounds += 1;
+      if bot == winner {
+        stat.wins += 1;
+      }
+    }
+  }
+  let mut stats = stats.into_values().collect::<Vec<_>>();
+  stats.sort_by(|a, b| b.wins.cmp(&a.wins).then(a.bot.cmp(&b.bot)));
+
+  log::info!("query arena stats result: {:?}", stats);
+
+  Ok(stats)
+}
@@ -1,18 +1,23 @@
+use sqlx::SqliteConnection;
 use uuid::Uuid;
 
 use crate::{db::pool::DbPool, error::PolestarError, model::Attachment};
 
-pub async fn add_attachment(pool: &DbPool, attachment: &Attachment) -> Result<Uuid, PolestarError> {
+// the attachment is immutable once added, adding it again is ignored.
+pub async fn add_attachment(
+  conn: &mut SqliteConnection,
+  attachment: &Attachment,
+) -> Result<Uuid, PolestarError> {
   let res = sqlx::query(
     r#"
-    INSERT INTO attachment (name, mime, data)
+    INSERT OR IGNORE INTO attachment (name, mime, data)
     VALUES (?1, ?2, ?3)
     "#,
   )
   .bind(attachment.name())
   .bind(attachment.mime())
   .bind(attachment.data())
-  .execute(pool)
+  .execute(conn)
   .await?;
 
   log::info!("add attachment result: {:?}", res);
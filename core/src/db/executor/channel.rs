@@ -1,4 +1,5 @@
-use sqlx::Row;
+use chrono::Utc;
+use sqlx::{Row, SqliteConnection};
 use uuid::Uuid;
 
 use crate::{
@@ -8,7 +9,7 @@ use crate::{
 };
 
 pub async fn add_channel(
-  pool: &DbPool,
+  conn: &mut SqliteConnection,
   id: &ChannelId,
   name: &str,
   desc: Option<&str>,
@@ -25,7 +26,7 @@ pub async fn add_channel(
   .bind(name)
   .bind(desc)
   .bind(cfg)
-  .execute(pool)
+  .execute(conn)
   .await?;
 
   log::info!("add channel result: {:?}", res);
@@ -33,31 +34,23 @@ pub async fn add_channel(
   Ok(())
 }
 
+/// Move the channel to the trash, its messages and summary are kept until the
+/// trash is purged.
 pub async fn remove_channel(pool: &DbPool, id: &Uuid) -> Result<(), PolestarError> {
   let res = sqlx::query(
     r#"
-    DELETE FROM channel
-    WHERE id = ?1
+    UPDATE channel
+    SET deleted_at = ?1
+    WHERE id = ?2
     "#,
   )
+  .bind(Utc::now())
   .bind(id)
   .execute(pool)
   .await?;
 
   log::info!("remove channel result: {:?}", res);
 
-  let res = sqlx::query(
-    r#"
-    DELETE FROM msg
-    WHERE channel_id = ?1
-    "#,
-  )
-  .bind(id)
-  .execute(pool)
-  .await?;
-
-  log::info!("remove channel msgs result: {:?}", res);
-
   Ok(())
 }
 
@@ -119,6 +112,7 @@ pub async fn query_channels(pool: &DbPool) -> Result<Vec<Channel>, PolestarError
     r#"
     SELECT id, name, desc, cfg
     FROM channel
+    WHERE deleted_at IS NULL
     "#,
   )
   .fetch_all(pool)
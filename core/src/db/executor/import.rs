@@ -0,0 +1,44 @@
+use crate::{
+  db::pool::DbPool,
+  error::PolestarError,
+  model::{Attachment, Channel},
+};
+
+/// Add the channels with their messages and summaries, and the attachments
+/// they refer to, all or nothing.
+pub async fn import_channels(
+  pool: &DbPool,
+  channels: &[Channel],
+  attachments: &[Attachment],
+) -> Result<(), PolestarError> {
+  let mut tx = pool.begin().await?;
+  for attachment in attachments {
+    super::attachment::add_attachment(&mut tx, attachment).await?;
+  }
+  for channel in channels {
+    super::channel::add_channel(
+      &mut tx,
+      channel.id(),
+      channel.name(),
+      channel.desc(),
+      channel.cfg(),
+    )
+    .await?;
+    for msg in channel.msgs() {
+      super::msg::add_msg(&mut tx, channel.id(), msg).await?;
+      for (idx, cont) in msg.cont_list().iter().enumerate() {
+        if let Some(rating) = cont.rating() {
+          super::rating::update_rating(&mut tx, msg.id(), idx, rating).await?;
+        }
+      }
+    }
+    if let Some(summary) = channel.summary() {
+      super::summary::update_summary(&mut tx, channel.id(), summary).await?;
+    }
+  }
+  tx.commit().await?;
+
+  log::info!("import {} channels", channels.len());
+
+  Ok(())
+}
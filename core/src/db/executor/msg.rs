@@ -1,8 +1,14 @@
+use chrono::Utc;
+use sqlx::SqliteConnection;
 use uuid::Uuid;
 
 use crate::{db::pool::DbPool, error::PolestarError, model::Msg};
 
-pub async fn add_msg(pool: &DbPool, channel_id: &Uuid, msg: &Msg) -> Result<(), PolestarError> {
+pub async fn add_msg(
+  conn: &mut SqliteConnection,
+  channel_id: &Uuid,
+  msg: &Msg,
+) -> Result<(), PolestarError> {
   let role = serde_json::to_string(msg.role())?;
   let cont_list = serde_json::to_string(msg.cont_list())?;
   let meta = serde_json::to_string(msg.meta())?;
@@ -19,12 +25,12 @@ pub async fn add_msg(pool: &DbPool, channel_id: &Uuid, msg: &Msg) -> Result<(),
   .bind(cont_list)
   .bind(meta)
   .bind(msg.create_at())
-  .execute(pool)
+  .execute(&mut *conn)
   .await?;
 
   log::info!("add msg result: {:?}", res);
 
-  Ok(())
+  index_msg(conn, msg).await
 }
 
 pub async fn query_msg_by_id(pool: &DbPool, id: &Uuid) -> Result<Msg, PolestarError> {
@@ -46,6 +52,8 @@ pub async fn query_msg_by_id(pool: &DbPool, id: &Uuid) -> Result<Msg, PolestarEr
 
 // TODO: update method need split? or use one method?
 pub async fn update_msg(pool: &DbPool, msg: &Msg) -> Result<(), PolestarError> {
+  // the message and its index are updated together.
+  let mut tx = pool.begin().await?;
   let cont_list = serde_json::to_string(msg.cont_list())?;
   let meta = serde_json::to_string(msg.meta())?;
   let res = sqlx::query(
@@ -59,21 +67,27 @@ pub async fn update_msg(pool: &DbPool, msg: &Msg) -> Result<(), PolestarError> {
   .bind(cont_list)
   .bind(meta)
   .bind(msg.id())
-  .execute(pool)
+  .execute(&mut *tx)
   .await?;
 
   log::info!("update msg result: {:?}", res);
 
+  index_msg(&mut tx, msg).await?;
+  tx.commit().await?;
   Ok(())
 }
 
+/// Move the message to the trash, it's still indexed so it can be restored
+/// as it was.
 pub async fn remove_msg(pool: &DbPool, id: &Uuid) -> Result<(), PolestarError> {
   let res = sqlx::query(
     r#"
-    DELETE FROM msg
-    WHERE id = ?1
+    UPDATE msg
+    SET deleted_at = ?1
+    WHERE id = ?2
     "#,
   )
+  .bind(Utc::now())
   .bind(id)
   .execute(pool)
   .await?;
@@ -83,6 +97,57 @@ pub async fn remove_msg(pool: &DbPool, id: &Uuid) -> Result<(), PolestarError> {
   Ok(())
 }
 
+/// Replace the full-text index of the message with the text of all its
+/// contents.
+async fn index_msg(conn: &mut SqliteConnection, msg: &Msg) -> Result<(), PolestarError> {
+  unindex_msg(conn, msg.id()).await?;
+  let text = msg
+    .cont_list()
+    .iter()
+    .filter_map(|cont| cont.text())
+    .collect::<Vec<_>>()
+    .join("\n");
+  sqlx::query(
+    r#"
+    INSERT INTO msg_fts_key (msg_id)
+    VALUES (?1)
+    ON CONFLICT (msg_id) DO NOTHING
+    "#,
+  )
+  .bind(msg.id())
+  .execute(&mut *conn)
+  .await?;
+  sqlx::query(
+    r#"
+    INSERT INTO msg_fts (rowid, text)
+    SELECT id, ?2 FROM msg_fts_key WHERE msg_id = ?1
+    "#,
+  )
+  .bind(msg.id())
+  .bind(text)
+  .execute(conn)
+  .await?;
+
+  Ok(())
+}
+
+pub(super) async fn unindex_msg(
+  conn: &mut SqliteConnection,
+  id: &Uuid,
+) -> Result<(), PolestarError> {
+  sqlx::query(
+    r#"
+    DELETE FROM msg_fts
+    WHERE rowid = (SELECT id FROM msg_fts_key WHERE msg_id = ?1)
+    "#,
+  )
+  .bind(id)
+  .execute(conn)
+  .await?;
+
+  Ok(())
+}
+
 pub async fn query_msgs_by_channel_id(
   pool: &DbPool,
   channel_id: &Uuid,
@@ -91,7 +156,8 @@ pub async fn query_msgs_by_channel_id(
     r#"
     SELECT id, role, cur_idx, cont_list, meta, created_at
     FROM msg
-    WHERE channel_id = ?1
+    WHERE channel_id = ?1 AND deleted_at IS NULL
+    ORDER BY created_at, rowid
     "#,
   )
   .bind(channel_id)
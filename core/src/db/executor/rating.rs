@@ -0,0 +1,86 @@
+use sqlx::{Row, SqliteConnection};
+
+use crate::{
+  db::pool::DbPool,
+  error::PolestarError,
+  model::{ChannelId, MsgId, MsgRating, Rating},
+};
+
+pub async fn update_rating(
+  conn: &mut SqliteConnection,
+  msg_id: &MsgId,
+  cont_idx: usize,
+  rating: &MsgRating,
+) -> Result<(), PolestarError> {
+  let res = sqlx::query(
+    r#"
+    INSERT INTO msg_rating (msg_id, cont_idx, score, comment)
+    VALUES (?1, ?2, ?3, ?4)
+    ON CONFLICT(msg_id, cont_idx) DO UPDATE
+    SET score = ?3, comment = ?4, updated_at = STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')
+    "#,
+  )
+  .bind(msg_id)
+  .bind(cont_idx as i64)
+  .bind(rating.rating.score())
+  .bind(rating.comment.as_deref())
+  .execute(conn)
+  .await?;
+
+  log::info!("update rating result: {:?}", res);
+
+  Ok(())
+}
+
+pub async fn remove_rating(
+  conn: &mut SqliteConnection,
+  msg_id: &MsgId,
+  cont_idx: usize,
+) -> Result<(), PolestarError> {
+  let res = sqlx::query(
+    r#"
+    DELETE FROM msg_rating
+    WHERE msg_id = ?1 AND cont_idx = ?2
+    "#,
+  )
+  .bind(msg_id)
+  .bind(cont_idx as i64)
+  .execute(conn)
+  .await?;
+
+  log::info!("remove rating result: {:?}", res);
+
+  Ok(())
+}
+
+/// The ratings of the messages in the channel, with the message id and the
+/// index of the rated content.
+pub async fn query_ratings_by_channel_id(
+  pool: &DbPool,
+  channel_id: &ChannelId,
+) -> Result<Vec<(MsgId, usize, MsgRating)>, PolestarError> {
+  let rows = sqlx::query(
+    r#"
+    SELECT msg_rating.msg_id, msg_rating.cont_idx, msg_rating.score, msg_rating.comment
+    FROM msg_rating
+    JOIN msg ON msg.id = msg_rating.msg_id
+    WHERE msg.channel_id = ?1
+    "#,
+  )
+  .bind(channel_id)
+  .fetch_all(pool)
+  .await?;
+
+  let ratings = rows
+    .into_iter()
+    .map(|row| {
+      let cont_idx: i64 = row.get(1);
+      let rating = MsgRating::new(Rating::from_score(row.get(2)), row.get(3));
+      (row.get(0), cont_idx as usize, rating)
+    })
+    .collect::<Vec<_>>();
+
+  log::info!("query ratings result: {:?}", ratings);
+
+  Ok(ratings)
+}
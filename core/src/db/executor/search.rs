@@ -0,0 +1,86 @@
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+use crate::{
+  db::pool::DbPool,
+  error::PolestarError,
+  model::{MsgRole, MsgSearchFilter, MsgSearchHit, SearchRole},
+};
+
+pub async fn search_msgs(
+  pool: &DbPool,
+  query: &str,
+  filter: &MsgSearchFilter,
+) -> Result<Vec<MsgSearchHit>, PolestarError> {
+  let Some(query) = fts_query(query) else {
+    return Ok(vec![]);
+  };
+
+  let mut builder = QueryBuilder::<Sqlite>::new(
+    r#"
+    SELECT msg.id, msg.channel_id, msg.role, msg.created_at,
+      snippet(msg_fts, 0, '**', '**', '...', 16), bm25(msg_fts)
+    FROM msg_fts
+    JOIN msg_fts_key ON msg_fts_key.id = msg_fts.rowid
+    JOIN msg ON msg.id = msg_fts_key.msg_id
+    WHERE msg.deleted_at IS NULL
+      AND msg.channel_id NOT IN (SELECT id FROM channel WHERE deleted_at IS NOT NULL)
+      AND msg_fts MATCH "#,
+  );
+  builder.push_bind(query);
+  if let Some(channel_id) = filter.channel_id {
+    builder.push(" AND msg.channel_id = ").push_bind(channel_id);
+  }
+  match &filter.role {
+    Some(SearchRole::User) => {
+      let role = serde_json::to_string(&MsgRole::User)?;
+      builder.push(" AND msg.role = ").push_bind(role);
+    }
+    Some(SearchRole::Bot(Some(bot_id))) => {
+      let role = serde_json::to_string(&MsgRole::Bot(bot_id.clone()))?;
+      builder.push(" AND msg.role = ").push_bind(role);
+    }
+    Some(SearchRole::Bot(None)) => {
+      builder.push(r#" AND msg.role LIKE '{"bot":%'"#);
+    }
+    Some(SearchRole::System) => {
+      builder.push(r#" AND msg.role LIKE '{"system":%'"#);
+    }
+    None => {}
+  }
+  if let Some(since) = filter.since {
+    builder.push(" AND msg.created_at >= ").push_bind(since);
+  }
+  if let Some(until) = filter.until {
+    builder.push(" AND msg.created_at < ").push_bind(until);
+  }
+  builder
+    .push(" ORDER BY bm25(msg_fts) LIMIT ")
+    .push_bind(filter.limit);
+
+  let rows = builder.build().fetch_all(pool).await?;
+  let mut hits = vec![];
+  for row in rows {
+    hits.push(MsgSearchHit {
+      msg_id: row.get(0),
+      channel_id: row.get(1),
+      role: serde_json::from_str(row.get(2))?,
+      created_at: row.get(3),
+      snippet: row.get(4),
+      rank: row.get(5),
+    });
+  }
+
+  log::info!("search msgs result: {:?}", hits);
+
+  Ok(hits)
+}
+
+/// Quote every word of the user's query, so the FTS5 query syntax in it is
+/// searched as plain text. The words are all required.
+fn fts_query(query: &str) -> Option<String> {
+  let words = query
+    .split_whitespace()
+    .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+    .collect::<Vec<_>>();
+  (!words.is_empty()).then(|| words.join(" "))
+}
@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+use crate::{
+  db::pool::DbPool,
+  error::PolestarError,
+  model::{Spending, UsageGroup, UsageKey, UsageStat},
+};
+
+/// The usage of the bots' answers grouped by `group`, only the answers
+/// created since `since` if it's given. The messages in the trash still count,
+/// they have been paid.
+pub async fn query_usage_stats(
+  pool: &DbPool,
+  group: UsageGroup,
+  since: Option<DateTime<Utc>>,
+) -> Result<Vec<UsageStat>, PolestarError> {
+  let key = match group {
+    UsageGroup::Day => "date(msg.created_at)",
+    // the content may be regenerated by another bot than the message's.
+    UsageGroup::Bot => {
+      "COALESCE(json_extract(cont.value, '$.bot'), json_extract(msg.role, '$.bot'))"
+    }
+    UsageGroup::Provider => "json_extract(cont.value, '$.usage.provider')",
+    UsageGroup::Channel => "msg.channel_id",
+  };
+  let mut builder = QueryBuilder::<Sqlite>::new(format!(
+    r#"
+    SELECT {key} AS group_key, COUNT(*),
+      SUM(json_extract(cont.value, '$.usage.prompt_tokens')),
+      SUM(json_extract(cont.value, '$.usage.completion_tokens')),
+      SUM(COALESCE(json_extract(cont.value, '$.usage.estimated'), 0)),
+      AVG(json_extract(cont.value, '$.usage.latency_ms')),
+      TOTAL(json_extract(cont.value, '$.usage.cost'))
+    FROM msg, json_each(msg.cont_list) AS cont
+    WHERE json_extract(cont.value, '$.usage') IS NOT NULL"#
+  ));
+  if let Some(since) = since {
+    builder.push(" AND msg.created_at >= ").push_bind(since);
+  }
+  builder.push(" GROUP BY group_key ORDER BY group_key");
+
+  let rows = builder.build().fetch_all(pool).await?;
+  let mut stats = vec![];
+  for row in rows {
+    let key = match group {
+      UsageGroup::Day => UsageKey::Day(row.get(0)),
+      UsageGroup::Bot => UsageKey::Bot(row.get(0)),
+      UsageGroup::Provider => UsageKey::Provider(row.get(0)),
+      UsageGroup::Channel => UsageKey::Channel(row.get(0)),
+    };
+    let answers: i64 = row.get(1);
+    let prompt_tokens: i64 = row.get(2);
+    let completion_tokens: i64 = row.get(3);
+    let estimated: i64 = row.get(4);
+    let avg_latency_ms: f64 = row.get(5);
+    let cost: f64 = row.get(6);
+    stats.push(UsageStat {
+      key,
+      answers: answers as u32,
+      prompt_tokens: prompt_tokens as u64,
+      completion_tokens: completion_tokens as u64,
+      estimated: estimated as u32,
+      avg_latency_ms: avg_latency_ms as u64,
+      cost,
+    });
+  }
+
+  log::debug!("query usage stats result: {:?}", stats);
+
+  Ok(stats)
+}
+
+/// The cost of the answers per provider in the `month` like `2024-05`, for the
+/// running totals.
+pub async fn query_spending(pool: &DbPool, month: &str) -> Result<Spending, PolestarError> {
+  let rows = sqlx::query(
+    r#"
+    SELECT json_extract(cont.value, '$.usage.provider'),
+      TOTAL(json_extract(cont.value, '$.usage.cost'))
+    FROM msg, json_each(msg.cont_list) AS cont
+    WHERE json_extract(cont.value, '$.usage.cost') IS NOT NULL
+      AND strftime('%Y-%m', msg.created_at) = ?1
+    GROUP BY 1
+    "#,
+  )
+  .bind(month)
+  .fetch_all(pool)
+  .await?;
+
+  let mut spending = Spending::default();
+  for row in rows {
+    let provider: String = row.get(0);
+    spending.add(&provider, month.to_owned(), row.get(1));
+  }
+
+  log::debug!("query spending result: {:?}", spending);
+
+  Ok(spending)
+}
@@ -0,0 +1,72 @@
+use sqlx::{Row, SqliteConnection};
+
+use crate::{
+  db::pool::DbPool,
+  error::PolestarError,
+  model::{ChannelId, ChannelSummary},
+};
+
+pub async fn update_summary(
+  conn: &mut SqliteConnection,
+  channel_id: &ChannelId,
+  summary: &ChannelSummary,
+) -> Result<(), PolestarError> {
+  let res = sqlx::query(
+    r#"
+    INSERT INTO channel_summary (channel_id, content, until_msg_id)
+    VALUES (?1, ?2, ?3)
+    ON CONFLICT(channel_id) DO UPDATE
+    SET content = ?2, until_msg_id = ?3, updated_at = STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')
+    "#,
+  )
+  .bind(channel_id)
+  .bind(summary.content())
+  .bind(summary.until_msg_id())
+  .execute(conn)
+  .await?;
+
+  log::info!("update summary result: {:?}", res);
+
+  Ok(())
+}
+
+pub async fn remove_summary(
+  conn: &mut SqliteConnection,
+  channel_id: &ChannelId,
+) -> Result<(), PolestarError> {
+  let res = sqlx::query(
+    r#"
+    DELETE FROM channel_summary
+    WHERE channel_id = ?1
+    "#,
+  )
+  .bind(channel_id)
+  .execute(conn)
+  .await?;
+
+  log::info!("remove summary result: {:?}", res);
+
+  Ok(())
+}
+
+pub async fn query_summary_by_channel_id(
+  pool: &DbPool,
+  channel_id: &ChannelId,
+) -> Result<Option<ChannelSummary>, PolestarError> {
+  let row = sqlx::query(
+    r#"
+    SELECT content, until_msg_id
+    FROM channel_summary
+    WHERE channel_id = ?1
+    "#,
+  )
+  .bind(channel_id)
+  .fetch_optional(pool)
+  .await?;
+
+  let summary = row.map(|row| ChannelSummary::new(row.get(0), row.get(1)));
+
+  log::info!("query summary result: {:?}", summary);
+
+  Ok(summary)
+}
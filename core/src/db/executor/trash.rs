@@ -0,0 +1,297 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Row, SqliteConnection};
+use uuid::Uuid;
+
+use crate::{
+  db::pool::DbPool,
+  error::PolestarError,
+  model::{ChannelId, MsgCont, TrashItem},
+};
+
+/// The deleted channels, and the deleted messages of the other channels, the
+/// latest deleted first.
+pub async fn query_trash(pool: &DbPool) -> Result<Vec<TrashItem>, PolestarError> {
+  let rows = sqlx::query(
+    r#"
+    SELECT id, name, deleted_at
+    FROM channel
+    WHERE deleted_at IS NOT NULL
+    "#,
+  )
+  .fetch_all(pool)
+  .await?;
+
+  let mut items = vec![];
+  for row in rows {
+    items.push(TrashItem {
+      msg_id: None,
+      channel_id: row.get(0),
+      title: row.get(1),
+      deleted_at: row.get(2),
+    });
+  }
+
+  let rows = sqlx::query(
+    r#"
+    SELECT id, channel_id, cur_idx, cont_list, deleted_at
+    FROM msg
+    WHERE deleted_at IS NOT NULL
+      AND channel_id NOT IN (SELECT id FROM channel WHERE deleted_at IS NOT NULL)
+    "#,
+  )
+  .fetch_all(pool)
+  .await?;
+
+  for row in rows {
+    let cur_idx: i32 = row.get(2);
+    let cont_list: Vec<MsgCont> = serde_json::from_str(row.get(3))?;
+    let title = cont_list
+      .get(cur_idx as usize)
+      .and_then(|cont| cont.text())
+      .unwrap_or_default()
+      .to_owned();
+    items.push(TrashItem {
+      msg_id: Some(row.get(0)),
+      channel_id: row.get(1),
+      title,
+      deleted_at: row.get(4),
+    });
+  }
+  items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+
+  log::info!("query trash result: {:?}", items);
+
+  Ok(items)
+}
+
+/// Take the channel out of the trash, return `false` if it's not in the trash.
+pub async fn restore_channel(pool: &DbPool, id: &ChannelId) -> Result<bool, PolestarError> {
+  let res = sqlx::query(
+    r#"
+    UPDATE channel
+    SET deleted_at = NULL
+    WHERE id = ?1 AND deleted_at IS NOT NULL
+    "#,
+  )
+  .bind(id)
+  .execute(pool)
+  .await?;
+
+  log::info!("restore channel result: {:?}", res);
+
+  Ok(res.rows_affected() > 0)
+}
+
+pub async fn restore_msg(pool: &DbPool, id: &Uuid) -> Result<(), PolestarError> {
+  let res = sqlx::query(
+    r#"
+    UPDATE msg
+    SET deleted_at = NULL
+    WHERE id = ?1
+    "#,
+  )
+  .bind(id)
+  .execute(pool)
+  .await?;
+
+  log::info!("restore msg result: {:?}", res);
+
+  Ok(())
+}
+
+/// Delete the channels and messages moved to the trash before `before` for
+/// good. Return how many items are purged.
+pub async fn purge_trash(pool: &DbPool, before: DateTime<Utc>) -> Result<usize, PolestarError> {
+  let mut tx = pool.begin().await?;
+  let channel_ids: Vec<ChannelId> = sqlx::query_scalar(
+    r#"
+    SELECT id
+    FROM channel
+    WHERE deleted_at IS NOT NULL AND deleted_at < ?1
+    "#,
+  )
+  .bind(before)
+  .fetch_all(&mut *tx)
+  .await?;
+  for id in &channel_ids {
+    purge_channel(&mut tx, id).await?;
+  }
+
+  let msg_ids: Vec<Uuid> = sqlx::query_scalar(
+    r#"
+    SELECT id
+    FROM msg
+    WHERE deleted_at IS NOT NULL AND deleted_at < ?1
+    "#,
+  )
+  .bind(before)
+  .fetch_all(&mut *tx)
+  .await?;
+  for id in &msg_ids {
+    purge_msg(&mut tx, id).await?;
+  }
+  tx.commit().await?;
+
+  log::info!(
+    "purge trash: {} channels, {} msgs",
+    channel_ids.len(),
+    msg_ids.len()
+  );
+
+  Ok(channel_ids.len() + msg_ids.len())
+}
+
+/// Delete the channel or the message in the trash for good, return `false` if
+/// nothing with the id is in the trash.
+pub async fn purge_trash_item(pool: &DbPool, id: &Uuid) -> Result<bool, PolestarError> {
+  let mut tx = pool.begin().await?;
+  let channels: i64 = sqlx::query_scalar(
+    r#"
+    SELECT count(*)
+    FROM channel
+    WHERE id = ?1 AND deleted_at IS NOT NULL
+    "#,
+  )
+  .bind(id)
+  .fetch_one(&mut *tx)
+  .await?;
+  let msgs: i64 = sqlx::query_scalar(
+    r#"
+    SELECT count(*)
+    FROM msg
+    WHERE id = ?1 AND deleted_at IS NOT NULL
+    "#,
+  )
+  .bind(id)
+  .fetch_one(&mut *tx)
+  .await?;
+  if channels > 0 {
+    purge_channel(&mut tx, id).await?;
+  } else if msgs > 0 {
+    purge_msg(&mut tx, id).await?;
+  }
+  tx.commit().await?;
+
+  Ok(channels > 0 || msgs > 0)
+}
+
+async fn purge_channel(conn: &mut SqliteConnection, id: &ChannelId) -> Result<(), PolestarError> {
+  sqlx::query(
+    r#"
+    DELETE FROM msg_fts
+    WHERE rowid IN (
+      SELECT msg_fts_key.id
+      FROM msg_fts_key
+      JOIN msg ON msg.id = msg_fts_key.msg_id
+      WHERE msg.channel_id = ?1
+    )
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  sqlx::query(
+    r#"
+    DELETE FROM msg_fts_key
+    WHERE msg_id IN (SELECT id FROM msg WHERE channel_id = ?1)
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  sqlx::query(
+    r#"
+    DELETE FROM msg_rating
+    WHERE msg_id IN (SELECT id FROM msg WHERE channel_id = ?1)
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  sqlx::query(
+    r#"
+    DELETE FROM arena_pick
+    WHERE channel_id = ?1
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  sqlx::query(
+    r#"
+    DELETE FROM msg
+    WHERE channel_id = ?1
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  super::summary::remove_summary(conn, id).await?;
+
+  let res = sqlx::query(
+    r#"
+    DELETE FROM channel
+    WHERE id = ?1
+    "#,
+  )
+  .bind(id)
+  .execute(conn)
+  .await?;
+
+  log::info!("purge channel result: {:?}", res);
+
+  Ok(())
+}
+
+async fn purge_msg(conn: &mut SqliteConnection, id: &Uuid) -> Result<(), PolestarError> {
+  super::msg::unindex_msg(&mut *conn, id).await?;
+
+  sqlx::query(
+    r#"
+    DELETE FROM msg_fts_key
+    WHERE msg_id = ?1
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  sqlx::query(
+    r#"
+    DELETE FROM msg_rating
+    WHERE msg_id = ?1
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  sqlx::query(
+    r#"
+    DELETE FROM arena_pick
+    WHERE question_id = ?1 OR winner_id = ?1
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  let res = sqlx::query(
+    r#"
+    DELETE FROM msg
+    WHERE id = ?1
+    "#,
+  )
+  .bind(id)
+  .execute(&mut *conn)
+  .await?;
+
+  log::info!("purge msg result: {:?}", res);
+
+  Ok(())
+}
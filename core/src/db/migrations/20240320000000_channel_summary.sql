@@ -0,0 +1,9 @@
+-- Create channel summary table, the memory of a channel's earlier messages
+
+CREATE TABLE IF NOT EXISTS channel_summary (
+  channel_id BLOB PRIMARY KEY CHECK(length(channel_id) = 16) NOT NULL,
+  content TEXT NOT NULL,
+  until_msg_id BLOB CHECK(length(until_msg_id) = 16) NOT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT (STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+  UNIQUE(channel_id)
+);
@@ -0,0 +1,28 @@
+-- Create the full-text index of the messages' text. The index is keyed by the
+-- integer key of the message in `msg_fts_key`, unlike the rowid of `msg` it
+-- stays the same after a VACUUM, and the message is unindexed by a lookup
+-- instead of scanning the whole index.
+
+CREATE TABLE IF NOT EXISTS msg_fts_key (
+  id INTEGER PRIMARY KEY,
+  msg_id BLOB NOT NULL UNIQUE
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS msg_fts USING fts5 (
+  text,
+  tokenize = 'unicode61'
+);
+
+-- Index the existing messages, with the text of all their contents
+
+INSERT INTO msg_fts_key (msg_id)
+SELECT id FROM msg;
+
+INSERT INTO msg_fts (rowid, text)
+SELECT msg_fts_key.id, (
+  SELECT group_concat(json_extract(cont.value, '$.body.text'), char(10))
+  FROM json_each(msg.cont_list) AS cont
+  WHERE json_extract(cont.value, '$.body.text') IS NOT NULL
+)
+FROM msg
+JOIN msg_fts_key ON msg_fts_key.msg_id = msg.id;
@@ -0,0 +1,5 @@
+-- Move the deleted channels and messages to the trash, they are purged later
+
+ALTER TABLE channel ADD COLUMN deleted_at TIMESTAMP;
+
+ALTER TABLE msg ADD COLUMN deleted_at TIMESTAMP;
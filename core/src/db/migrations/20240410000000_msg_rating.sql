@@ -0,0 +1,11 @@
+-- Create the rating table, the user's rating of the contents of the answers
+
+CREATE TABLE IF NOT EXISTS msg_rating (
+  msg_id BLOB CHECK(length(msg_id) = 16) NOT NULL,
+  cont_idx INTEGER NOT NULL,
+  -- 1 for up and -1 for down
+  score INTEGER NOT NULL,
+  comment TEXT,
+  updated_at TIMESTAMP NOT NULL DEFAULT (STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW')),
+  PRIMARY KEY (msg_id, cont_idx)
+);
@@ -0,0 +1,13 @@
+-- Create the arena pick table, the best answer the user picked among the
+-- answers of several bots to a question
+
+CREATE TABLE IF NOT EXISTS arena_pick (
+  question_id BLOB CHECK(length(question_id) = 16) PRIMARY KEY NOT NULL,
+  channel_id BLOB CHECK(length(channel_id) = 16) NOT NULL,
+  winner_id BLOB CHECK(length(winner_id) = 16) NOT NULL,
+  winner_bot TEXT NOT NULL,
+  -- JSON array of all the bots answered the question
+  bots TEXT NOT NULL,
+  question TEXT NOT NULL,
+  picked_at TIMESTAMP NOT NULL DEFAULT (STRFTIME('%Y-%m-%d %H:%M:%f', 'NOW'))
+);
@@ -1,8 +1,12 @@
 use std::marker::PhantomPinned;
 
-use crate::model::{Msg, ANONYMOUS_USER};
+use crate::model::{
+  ArenaStat, ChannelSummary, Msg, MsgId, MsgRating, MsgSearchFilter, MsgSearchHit, Spending,
+  TrashItem, UsageGroup, UsageStat, ANONYMOUS_USER,
+};
 use crate::utils::user_data_path;
 use crate::{error::PolestarResult, model::Channel};
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
 use sqlx::{migrate::MigrateDatabase, sqlite::SqlitePool, Sqlite};
 use tokio::{runtime::Handle, sync::mpsc::UnboundedSender};
@@ -82,6 +86,103 @@ impl PersistenceDB {
     super::executor::msg::query_msgs_by_channel_id(&self.inner, channel_id).await
   }
 
+  /// The ratings of the messages in the channel, with the message id and the
+  /// index of the rated content.
+  pub async fn query_ratings_by_channel_id(
+    &self,
+    channel_id: &uuid::Uuid,
+  ) -> PolestarResult<Vec<(MsgId, usize, MsgRating)>> {
+    super::executor::rating::query_ratings_by_channel_id(&self.inner, channel_id).await
+  }
+
+  /// The picked best answers of the questions in the channel, as the question
+  /// id and the answer id.
+  pub async fn query_arena_picks_by_channel_id(
+    &self,
+    channel_id: &uuid::Uuid,
+  ) -> PolestarResult<Vec<(MsgId, MsgId)>> {
+    super::executor::arena::query_arena_picks_by_channel_id(&self.inner, channel_id).await
+  }
+
+  /// How often every bot wins, the most winning first.
+  pub async fn query_arena_stats(&self) -> PolestarResult<Vec<ArenaStat>> {
+    super::executor::arena::query_arena_stats(&self.inner).await
+  }
+
+  /// Search the text of all messages, the best matched first.
+  pub async fn search_msgs(
+    &self,
+    query: &str,
+    filter: &MsgSearchFilter,
+  ) -> PolestarResult<Vec<MsgSearchHit>> {
+    super::executor::search::search_msgs(&self.inner, query, filter).await
+  }
+
+  /// The usage of the bots' answers grouped by `group`, since `since` if it's
+  /// given.
+  pub async fn query_usage_stats(
+    &self,
+    group: UsageGroup,
+    since: Option<DateTime<Utc>>,
+  ) -> PolestarResult<Vec<UsageStat>> {
+    super::executor::stats::query_usage_stats(&self.inner, group, since).await
+  }
+
+  /// The cost of the answers per provider in the `month`.
+  pub async fn query_spending(&self, month: &str) -> PolestarResult<Spending> {
+    super::executor::stats::query_spending(&self.inner, month).await
+  }
+
+  pub async fn query_summary_by_channel_id(
+    &self,
+    channel_id: &uuid::Uuid,
+  ) -> PolestarResult<Option<ChannelSummary>> {
+    super::executor::summary::query_summary_by_channel_id(&self.inner, channel_id).await
+  }
+
+  /// Add the channels and the attachments in one transaction.
+  pub async fn import_channels(
+    &self,
+    channels: &[Channel],
+    attachments: &[crate::model::Attachment],
+  ) -> PolestarResult<()> {
+    super::executor::import::import_channels(&self.inner, channels, attachments).await
+  }
+
+  pub async fn query_channel_by_id(&self, id: &Uuid) -> PolestarResult<Channel> {
+    super::executor::channel::query_channel_by_id(&self.inner, id).await
+  }
+
+  pub async fn query_msg_by_id(&self, id: &Uuid) -> PolestarResult<Msg> {
+    super::executor::msg::query_msg_by_id(&self.inner, id).await
+  }
+
+  /// The deleted channels and messages, the latest deleted first.
+  pub async fn query_trash(&self) -> PolestarResult<Vec<TrashItem>> {
+    super::executor::trash::query_trash(&self.inner).await
+  }
+
+  pub async fn restore_channel(&self, id: &Uuid) -> PolestarResult<bool> {
+    super::executor::trash::restore_channel(&self.inner, id).await
+  }
+
+  /// Delete the items moved to the trash before `before` for good.
+  pub async fn purge_trash(&self, before: DateTime<Utc>) -> PolestarResult<usize> {
+    super::executor::trash::purge_trash(&self.inner, before).await
+  }
+
+  pub async fn purge_trash_item(&self, id: &Uuid) -> PolestarResult<bool> {
+    super::executor::trash::purge_trash_item(&self.inner, id).await
+  }
+
+  /// Add the attachment now but not in the queue of `persist_async`, so it
+  /// can be read at once.
+  pub async fn add_attachment(&self, attachment: &crate::model::Attachment) -> PolestarResult<()> {
+    let mut conn = self.inner.acquire().await?;
+    super::executor::attachment::add_attachment(&mut conn, attachment).await?;
+    Ok(())
+  }
+
   pub async fn query_attachment_by_name(
     &self,
     name: &Uuid,
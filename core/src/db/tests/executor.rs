@@ -7,7 +7,11 @@ use crate::{
     executor::ActionPersist,
     pool::{runtime, PersistenceDB},
   },
-  model::{Attachment, ChannelCfg, Msg, MsgCont, MsgMeta, MsgRole, MIME},
+  model::{
+    ArenaPick, ArenaStat, Attachment, Channel, ChannelCfg, ChannelSummary, Msg, MsgAction, MsgBody,
+    MsgBranch, MsgCont, MsgMeta, MsgRating, MsgRole, MsgSearchFilter, MsgUsage, Rating, SearchRole,
+    Spending, UsageGroup, UsageKey, MIME,
+  },
 };
 
 use super::common::init_db;
@@ -321,3 +325,481 @@ fn add_attachment_test() {
   assert_eq!(attachment_clone.mime(), &MIME::ImagePng);
   assert_eq!(attachment_clone.data(), &[1, 2, 3, 4]);
 }
+
+#[test]
+fn update_summary_test() {
+  let persistence_db = Box::new(PersistenceDB::connect(init_db()).expect("Failed to connect db"));
+
+  let channel_id = Uuid::new_v4();
+  let query_summary = || {
+    runtime().block_on(async {
+      persistence_db
+        .query_summary_by_channel_id(&channel_id)
+        .await
+        .expect("Failed to query summary")
+    })
+  };
+
+  let summary = ChannelSummary::new("first".to_owned(), Uuid::new_v4());
+  persistence_db.persist_async(ActionPersist::UpdateSummary {
+    channel_id,
+    summary: Some(summary.clone()),
+  });
+  sleep(Duration::from_millis(100));
+  assert_eq!(query_summary(), Some(summary));
+
+  let summary = ChannelSummary::new("second".to_owned(), Uuid::new_v4());
+  persistence_db.persist_async(ActionPersist::UpdateSummary {
+    channel_id,
+    summary: Some(summary.clone()),
+  });
+  sleep(Duration::from_millis(100));
+  assert_eq!(query_summary(), Some(summary));
+
+  persistence_db.persist_async(ActionPersist::UpdateSummary { channel_id, summary: None });
+  sleep(Duration::from_millis(100));
+  assert_eq!(query_summary(), None);
+}
+
+#[test]
+fn search_msgs_test() {
+  let persistence_db = Box::new(PersistenceDB::connect(init_db()).expect("Failed to connect db"));
+  let search = |query: &str, filter: MsgSearchFilter| {
+    runtime().block_on(async {
+      persistence_db
+        .search_msgs(query, &filter)
+        .await
+        .expect("Failed to search msgs")
+    })
+  };
+
+  let channel_1 = Uuid::new_v4();
+  let channel_2 = Uuid::new_v4();
+  let user_msg = Msg::new_user_text("How does the borrow checker work?", MsgMeta::default());
+  let mut bot_msg = Msg::new_bot_text("bot".to_owned(), MsgMeta::reply(*user_msg.id()));
+  let other_msg = Msg::new_user_text("borrow a book", MsgMeta::default());
+  for (channel_id, msg) in [
+    (channel_1, user_msg.clone()),
+    (channel_1, bot_msg.clone()),
+    (channel_2, other_msg.clone()),
+  ] {
+    persistence_db.persist_async(ActionPersist::AddMsg { channel_id, msg });
+  }
+  bot_msg
+    .cont_mut(0)
+    .action(MsgAction::Receiving(MsgBody::Text(Some(
+      "The borrow checker tracks lifetimes.".to_owned(),
+    ))));
+  persistence_db.persist_async(ActionPersist::UpdateMsg { msg: bot_msg.clone() });
+
+  sleep(Duration::from_millis(100));
+
+  let hits = search("borrow", MsgSearchFilter::default());
+  assert_eq!(hits.len(), 3);
+  assert!(hits.iter().all(|hit| hit.snippet.contains("**borrow**")));
+
+  let hits = search("borrow checker", MsgSearchFilter::default());
+  assert_eq!(hits.len(), 2);
+
+  let hits = search("lifetimes", MsgSearchFilter::default());
+  assert_eq!(hits.len(), 1);
+  assert_eq!(&hits[0].msg_id, bot_msg.id());
+  assert_eq!(hits[0].channel_id, channel_1);
+
+  let filter = MsgSearchFilter {
+    channel_id: Some(channel_2),
+    ..<_>::default()
+  };
+  assert_eq!(search("borrow", filter).len(), 1);
+
+  let filter = MsgSearchFilter {
+    role: Some(SearchRole::Bot(None)),
+    ..<_>::default()
+  };
+  assert_eq!(search("borrow", filter).len(), 1);
+
+  let filter = MsgSearchFilter {
+    role: Some(SearchRole::User),
+    until: Some(*other_msg.create_at()),
+    ..<_>::default()
+  };
+  let hits = search("borrow", filter);
+  assert_eq!(hits.len(), 1);
+  assert_eq!(&hits[0].msg_id, user_msg.id());
+
+  // the syntax of FTS5 is searched as plain text.
+  assert!(search("\"borrow OR", MsgSearchFilter::default()).is_empty());
+
+  persistence_db.persist_async(ActionPersist::RemoveMsg { msg_id: *user_msg.id() });
+  sleep(Duration::from_millis(100));
+  assert_eq!(search("borrow", MsgSearchFilter::default()).len(), 2);
+}
+
+#[test]
+fn search_after_vacuum_test() {
+  let pool = runtime().block_on(init_db()).expect("Failed to init db");
+  let db = pool.clone();
+  let persistence_db =
+    Box::new(PersistenceDB::connect(async move { Ok(db) }).expect("Failed to connect db"));
+  let channel_id = Uuid::new_v4();
+  let msgs = ["first apple", "second banana", "third cherry"]
+    .map(|text| Msg::new_user_text(text, MsgMeta::default()));
+  for msg in msgs.iter() {
+    persistence_db.persist_async(ActionPersist::AddMsg { channel_id, msg: msg.clone() });
+  }
+  // purge the first message and vacuum the database, which is allowed to
+  // renumber the rowids of the messages.
+  persistence_db.persist_async(ActionPersist::RemoveMsg { msg_id: *msgs[0].id() });
+  sleep(Duration::from_millis(100));
+  runtime()
+    .block_on(persistence_db.purge_trash(chrono::Utc::now()))
+    .unwrap();
+  runtime()
+    .block_on(sqlx::query("VACUUM").execute(&pool))
+    .unwrap();
+
+  let search = |query: &str| {
+    runtime()
+      .block_on(persistence_db.search_msgs(query, &MsgSearchFilter::default()))
+      .unwrap()
+  };
+  assert!(search("apple").is_empty());
+  let hits = search("cherry");
+  assert_eq!(hits.len(), 1);
+  assert_eq!(&hits[0].msg_id, msgs[2].id());
+  assert_eq!(&search("banana")[0].msg_id, msgs[1].id());
+}
+
+#[test]
+fn import_channels_test() {
+  let persistence_db = Box::new(PersistenceDB::connect(init_db()).expect("Failed to connect db"));
+
+  let mut channel = Channel::new(
+    Uuid::new_v4(),
+    "imported".to_owned(),
+    None,
+    ChannelCfg::default(),
+    None,
+    None,
+  );
+  let user_msg = Msg::new_user_text("imported question", MsgMeta::default());
+  let summary = ChannelSummary::new("imported summary".to_owned(), *user_msg.id());
+  channel.add_msg(user_msg);
+  channel.load_summary(Some(summary.clone()));
+  let attachment = Attachment::new(MIME::ImagePng, vec![1, 2, 3]);
+
+  runtime().block_on(async {
+    persistence_db
+      .import_channels(&[channel.clone()], std::slice::from_ref(&attachment))
+      .await
+      .expect("Failed to import channels");
+    // the attachment is added again with the same name.
+    persistence_db
+      .import_channels(&[], std::slice::from_ref(&attachment))
+      .await
+      .expect("Failed to import attachment again");
+  });
+
+  let (channels, msgs, saved_summary, hits) = runtime().block_on(async {
+    (
+      persistence_db.query_channels().await.unwrap(),
+      persistence_db
+        .query_msgs_by_channel_id(channel.id())
+        .await
+        .unwrap(),
+      persistence_db
+        .query_summary_by_channel_id(channel.id())
+        .await
+        .unwrap(),
+      persistence_db
+        .search_msgs("imported", &MsgSearchFilter::default())
+        .await
+        .unwrap(),
+    )
+  });
+  assert_eq!(channels.len(), 1);
+  assert_eq!(msgs.len(), 1);
+  assert_eq!(saved_summary, Some(summary));
+  assert_eq!(hits.len(), 1);
+
+  // a failed import adds nothing, the new channel before the existing one
+  // is rolled back.
+  let new_channel = Channel::new(
+    Uuid::new_v4(),
+    "new".to_owned(),
+    None,
+    ChannelCfg::default(),
+    None,
+    None,
+  );
+  let rst = runtime().block_on(persistence_db.import_channels(&[new_channel, channel], &[]));
+  assert!(rst.is_err());
+  let channels = runtime().block_on(persistence_db.query_channels()).unwrap();
+  assert_eq!(channels.len(), 1);
+}
+
+#[test]
+fn persist_msg_branch_test() {
+  let persistence_db = Box::new(PersistenceDB::connect(init_db()).expect("Failed to connect db"));
+  let channel_id = Uuid::new_v4();
+
+  let mut user_msg = Msg::new_user_text("question", MsgMeta::default());
+  let mut bot_msg = Msg::new_bot_text("bot".to_owned(), MsgMeta::reply(*user_msg.id()));
+  let branch = MsgBranch { msg_id: *user_msg.id(), cont_idx: 1 };
+  bot_msg.meta_mut().set_branch(Some(branch));
+  persistence_db.persist_async(ActionPersist::AddMsg { channel_id, msg: user_msg.clone() });
+  persistence_db.persist_async(ActionPersist::AddMsg { channel_id, msg: bot_msg.clone() });
+  user_msg.add_cont(MsgCont::new_text("edited question"));
+  user_msg.switch_cont(1);
+  persistence_db.persist_async(ActionPersist::UpdateMsg { msg: user_msg.clone() });
+
+  sleep(Duration::from_millis(100));
+
+  let msgs = runtime().block_on(async {
+    persistence_db
+      .query_msgs_by_channel_id(&channel_id)
+      .await
+      .expect("Failed to query msgs")
+  });
+  let saved_user_msg = msgs.iter().find(|msg| msg.id() == user_msg.id()).unwrap();
+  assert_eq!(saved_user_msg.cur_idx(), 1);
+  assert_eq!(saved_user_msg.meta().branch(), None);
+  let saved_bot_msg = msgs.iter().find(|msg| msg.id() == bot_msg.id()).unwrap();
+  assert_eq!(saved_bot_msg.meta().branch(), Some(&branch));
+}
+
+#[test]
+fn trash_test() {
+  let persistence_db = Box::new(PersistenceDB::connect(init_db()).expect("Failed to connect db"));
+  let channel_id = Uuid::new_v4();
+  let other_id = Uuid::new_v4();
+  for (id, name) in [(channel_id, "test"), (other_id, "other")] {
+    persistence_db.persist_async(ActionPersist::AddChannel {
+      id,
+      name: name.to_owned(),
+      desc: None,
+      cfg: ChannelCfg::default(),
+    });
+  }
+  let msg = Msg::new_user_text("deleted question", MsgMeta::default());
+  let kept = Msg::new_user_text("kept question", MsgMeta::default());
+  persistence_db.persist_async(ActionPersist::AddMsg { channel_id, msg: msg.clone() });
+  persistence_db.persist_async(ActionPersist::AddMsg { channel_id, msg: kept.clone() });
+  persistence_db.persist_async(ActionPersist::RemoveMsg { msg_id: *msg.id() });
+  persistence_db.persist_async(ActionPersist::RemoveChannel { channel_id: other_id });
+
+  sleep(Duration::from_millis(100));
+
+  let msgs = runtime()
+    .block_on(persistence_db.query_msgs_by_channel_id(&channel_id))
+    .unwrap();
+  assert_eq!(msgs.len(), 1);
+  let hits = runtime()
+    .block_on(persistence_db.search_msgs("question", &MsgSearchFilter::default()))
+    .unwrap();
+  assert_eq!(hits.len(), 1);
+
+  let trash = runtime().block_on(persistence_db.query_trash()).unwrap();
+  assert_eq!(trash.len(), 2);
+  let msg_item = trash.iter().find(|item| item.id() == msg.id()).unwrap();
+  assert_eq!(msg_item.channel_id, channel_id);
+  assert_eq!(msg_item.title, "deleted question");
+  assert!(trash
+    .iter()
+    .any(|item| item.id() == &other_id && item.title == "other"));
+
+  persistence_db.persist_async(ActionPersist::RestoreMsg { msg_id: *msg.id() });
+  sleep(Duration::from_millis(100));
+  let msgs = runtime()
+    .block_on(persistence_db.query_msgs_by_channel_id(&channel_id))
+    .unwrap();
+  assert_eq!(msgs.len(), 2);
+
+  // the trash is purged, the restored message is kept.
+  let purged = runtime()
+    .block_on(persistence_db.purge_trash(chrono::Utc::now()))
+    .unwrap();
+  assert_eq!(purged, 1);
+  assert!(runtime()
+    .block_on(persistence_db.query_trash())
+    .unwrap()
+    .is_empty());
+  assert!(!runtime()
+    .block_on(persistence_db.restore_channel(&other_id))
+    .unwrap());
+  assert_eq!(
+    runtime()
+      .block_on(persistence_db.query_channels())
+      .unwrap()
+      .len(),
+    1
+  );
+}
+
+#[test]
+fn update_rating_test() {
+  let persistence_db = Box::new(PersistenceDB::connect(init_db()).expect("Failed to connect db"));
+  let channel_id = Uuid::new_v4();
+  let msg = Msg::new_bot_text("bot".to_owned(), MsgMeta::default());
+  let msg_id = *msg.id();
+  persistence_db.persist_async(ActionPersist::AddMsg { channel_id, msg });
+  let rating = MsgRating::new(Rating::Down, Some("wrong".to_owned()));
+  persistence_db.persist_async(ActionPersist::UpdateRating {
+    msg_id,
+    cont_idx: 0,
+    rating: Some(rating.clone()),
+  });
+
+  sleep(Duration::from_millis(100));
+
+  let ratings = runtime()
+    .block_on(persistence_db.query_ratings_by_channel_id(&channel_id))
+    .unwrap();
+  assert_eq!(ratings, vec![(msg_id, 0, rating)]);
+
+  persistence_db.persist_async(ActionPersist::UpdateRating { msg_id, cont_idx: 0, rating: None });
+  sleep(Duration::from_millis(100));
+  let ratings = runtime()
+    .block_on(persistence_db.query_ratings_by_channel_id(&channel_id))
+    .unwrap();
+  assert!(ratings.is_empty());
+}
+
+#[test]
+fn usage_stats_test() {
+  let persistence_db = Box::new(PersistenceDB::connect(init_db()).expect("Failed to connect db"));
+  let usage = |provider: &str, prompt_tokens, estimated| MsgUsage {
+    prompt_tokens,
+    completion_tokens: 10,
+    estimated,
+    model: "gpt-4o".to_owned(),
+    provider: provider.to_owned(),
+    latency_ms: 1000,
+    first_token_ms: Some(200),
+    cost: estimated.then_some(0.5),
+  };
+  let channel_1 = Uuid::new_v4();
+  let channel_2 = Uuid::new_v4();
+  for (channel_id, bot, usage) in [
+    (channel_1, "a", usage("openai", 100, false)),
+    (channel_1, "b", usage("ollama", 50, true)),
+    (channel_2, "a", usage("openai", 20, false)),
+  ] {
+    let mut msg = Msg::new_bot_text(bot.to_owned(), MsgMeta::default());
+    msg.cont_mut(0).set_usage(Some(usage));
+    persistence_db.persist_async(ActionPersist::AddMsg { channel_id, msg });
+  }
+  // the answers without usage are not counted.
+  persistence_db.persist_async(ActionPersist::AddMsg {
+    channel_id: channel_2,
+    msg: Msg::new_bot_text("a".to_owned(), MsgMeta::default()),
+  });
+
+  sleep(Duration::from_millis(100));
+
+  let stats = runtime()
+    .block_on(persistence_db.query_usage_stats(UsageGroup::Bot, None))
+    .unwrap();
+  assert_eq!(stats.len(), 2);
+  assert_eq!(stats[0].key, UsageKey::Bot("a".to_owned()));
+  assert_eq!(stats[0].answers, 2);
+  assert_eq!(stats[0].prompt_tokens, 120);
+  assert_eq!(stats[0].completion_tokens, 20);
+  assert_eq!(stats[0].estimated, 0);
+  assert_eq!(stats[0].avg_latency_ms, 1000);
+  assert_eq!(stats[1].estimated, 1);
+
+  let stats = runtime()
+    .block_on(persistence_db.query_usage_stats(UsageGroup::Provider, None))
+    .unwrap();
+  assert_eq!(stats[0].key, UsageKey::Provider("ollama".to_owned()));
+  assert_eq!(stats[1].total_tokens(), 140);
+
+  let stats = runtime()
+    .block_on(persistence_db.query_usage_stats(UsageGroup::Channel, None))
+    .unwrap();
+  let channel_1_stat = stats
+    .iter()
+    .find(|stat| stat.key == UsageKey::Channel(channel_1))
+    .unwrap();
+  assert_eq!(channel_1_stat.answers, 2);
+
+  let stats = runtime()
+    .block_on(persistence_db.query_usage_stats(UsageGroup::Day, None))
+    .unwrap();
+  assert_eq!(stats.len(), 1);
+  assert_eq!(stats[0].key, UsageKey::Day(chrono::Utc::now().date_naive()));
+  assert_eq!(stats[0].answers, 3);
+
+  let month = Spending::month_of(chrono::Utc::now());
+  let spending = runtime()
+    .block_on(persistence_db.query_spending(&month))
+    .unwrap();
+  assert_eq!(spending.get("ollama", &month), 0.5);
+  assert_eq!(spending.get("openai", &month), 0.);
+  // the other months are not summed.
+  let spending = runtime()
+    .block_on(persistence_db.query_spending("2000-01"))
+    .unwrap();
+  assert!(spending.totals().is_empty());
+
+  let tomorrow = chrono::Utc::now() + chrono::Duration::days(1);
+  let stats = runtime()
+    .block_on(persistence_db.query_usage_stats(UsageGroup::Day, Some(tomorrow)))
+    .unwrap();
+  assert!(stats.is_empty());
+}
+
+#[test]
+fn arena_pick_test() {
+  let persistence_db = Box::new(PersistenceDB::connect(init_db()).expect("Failed to connect db"));
+  let channel_id = Uuid::new_v4();
+  let pick = |winner_bot: &str| ArenaPick {
+    question_id: Uuid::new_v4(),
+    winner_id: Uuid::new_v4(),
+    winner_bot: winner_bot.to_owned(),
+    bots: vec!["a".to_owned(), "b".to_owned()],
+    question: "question".to_owned(),
+  };
+  let (pick_1, pick_2) = (pick("a"), pick("a"));
+  for pick in [&pick_1, &pick_2] {
+    persistence_db.persist_async(ActionPersist::UpdateArenaPick {
+      channel_id,
+      question_id: pick.question_id,
+      pick: Some(pick.clone()),
+    });
+  }
+  // pick another answer of the question.
+  persistence_db.persist_async(ActionPersist::UpdateArenaPick {
+    channel_id,
+    question_id: pick_2.question_id,
+    pick: Some(ArenaPick {
+      question_id: pick_2.question_id,
+      ..pick("b")
+    }),
+  });
+
+  sleep(Duration::from_millis(100));
+
+  let picks = runtime()
+    .block_on(persistence_db.query_arena_picks_by_channel_id(&channel_id))
+    .unwrap();
+  assert_eq!(picks.len(), 2);
+  assert!(picks.contains(&(pick_1.question_id, pick_1.winner_id)));
+  let stats = runtime()
+    .block_on(persistence_db.query_arena_stats())
+    .unwrap();
+  let stat = |bot: &str, wins| ArenaStat { bot: bot.to_owned(), wins, rounds: 2 };
+  assert_eq!(stats, vec![stat("a", 1), stat("b", 1)]);
+
+  persistence_db.persist_async(ActionPersist::UpdateArenaPick {
+    channel_id,
+    question_id: pick_1.question_id,
+    pick: None,
+  });
+  sleep(Duration::from_millis(100));
+  let picks = runtime()
+    .block_on(persistence_db.query_arena_picks_by_channel_id(&channel_id))
+    .unwrap();
+  assert_eq!(picks.len(), 1);
+}
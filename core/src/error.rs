@@ -19,6 +19,18 @@ pub enum PolestarError {
   UTF8(#[from] std::string::FromUtf8Error),
   #[error("Token not found")]
   TokenNotFound,
+  #[error("request canceled")]
+  Canceled,
+  #[error("import error: {0}")]
+  Import(String),
+  #[error("{0}")]
+  OverBudget(String),
+  #[error("pipeline error: {0}")]
+  Pipeline(String),
+  #[error("tool error: {0}")]
+  Tool(String),
+  #[error("image error: {0}")]
+  Image(String),
   #[error("{}: {}.", .0.message, "Please try again later or contact us at Discord")]
   PolestarServerError(PolestarServerError),
 }
@@ -27,6 +39,10 @@ pub enum PolestarError {
 pub struct PolestarServerError {
   pub kind: PolestarServerErrType,
   pub message: String,
+  /// seconds to wait before retry, from the `Retry-After` header of a 429/503
+  /// response.
+  #[serde(skip)]
+  pub retry_after: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd)]
@@ -55,6 +71,25 @@ pub enum PolestarServerErrType {
   AttachmentNotFound,
 }
 
+impl PolestarServerErrType {
+  /// The error type of a failed response by its HTTP status.
+  pub fn from_status(status: reqwest::StatusCode) -> Self {
+    use reqwest::StatusCode;
+    match status {
+      StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Self::InvalidContent,
+      StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::UnAuthed,
+      StatusCode::NOT_FOUND => Self::NotFound,
+      StatusCode::PAYMENT_REQUIRED | StatusCode::TOO_MANY_REQUESTS => Self::OverQuota,
+      StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => Self::TimedOut,
+      StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE => Self::NetWork,
+      status if status.is_server_error() => Self::ServerError,
+      _ => Self::Unknown,
+    }
+  }
+
+  pub fn is_retryable(&self) -> bool { matches!(self, Self::NetWork | Self::TimedOut) }
+}
+
 impl ToString for PolestarServerErrType {
   fn to_string(&self) -> String {
     match self {
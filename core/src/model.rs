@@ -18,3 +18,27 @@ pub use user::*;
 
 mod feedback;
 pub use feedback::*;
+
+mod search;
+pub use search::*;
+
+mod trash;
+pub use trash::*;
+
+mod rating;
+pub use rating::*;
+
+mod usage;
+pub use usage::*;
+
+mod budget;
+pub use budget::*;
+
+mod arena;
+pub use arena::*;
+
+mod pipeline;
+pub use pipeline::*;
+
+mod tool;
+pub use tool::*;
@@ -1,18 +1,39 @@
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, ptr::NonNull, rc::Rc, sync::Mutex};
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  ptr::NonNull,
+  rc::Rc,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
 use uuid::Uuid;
 
 use crate::{
   db::{executor::ActionPersist, pool::PersistenceDB},
-  utils, BotCfg, LocalState,
+  error::{PolestarError, PolestarResult},
+  service::{
+    client,
+    dataset::{self, DatasetFormat},
+    export::{self, Export, ExportOptions},
+    image::{self, GeneratedImage},
+    import::{self, Import},
+    mcp::{load_mcp_tools, McpClient},
+    tool::builtin_tools,
+  },
+  utils, BotCfg, LocalState, NetworkCfg,
 };
 use serde_json::Value as JsonValue;
 
 use super::{
   bot::Bot,
   channel::{Channel, ChannelCfg},
-  BotId, ChannelId, User, UserBuilder,
+  find_price, ArenaStat, Attachment, BotId, Budget, BudgetCap, ChannelId, Image, ModelPrice,
+  MsgAction, MsgBody, MsgCont, MsgId, MsgSearchFilter, MsgSearchHit, MsgUsage, Spending, Tool,
+  ToolRegistry, TrashItem, UsageGroup, UsageStat, User, UserBuilder, DEF_TRASH_DAYS, MIME,
 };
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -21,10 +42,42 @@ pub struct ServerProvider {
   pub base_url: String,
   pub token: String,
   pub extend: Option<JsonValue>,
+  // which wire format the provider speaks, default is OpenAI compatible.
+  #[serde(default)]
+  pub kind: ProviderKind,
+  // override the app retry policy for the requests to this provider.
+  #[serde(default)]
+  pub retry: Option<RetryPolicy>,
+  // the price of the models of this provider, override the global prices.
+  #[serde(default)]
+  pub prices: HashMap<String, ModelPrice>,
+  // the monthly spending caps of this provider.
+  #[serde(default)]
+  pub budget: Option<BudgetCap>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Default)]
+pub enum ProviderKind {
+  #[default]
+  #[serde(rename = "openai")]
+  OpenAI,
+  #[serde(rename = "anthropic")]
+  Anthropic,
+  #[serde(rename = "ollama")]
+  Ollama,
+  // llama.cpp server, use it's OpenAI compatible chat completions API.
+  #[serde(rename = "llamacpp")]
+  LlamaCpp,
 }
 pub struct AppInfo {
   bots: Rc<Vec<Bot>>,
   providers: HashMap<String, ServerProvider>,
+  prices: HashMap<String, ModelPrice>,
+  spending: Spending,
+  // the local tools the bots can call.
+  tools: ToolRegistry,
+  // the clients of the MCP servers, their tools are in `tools`.
+  mcp_clients: Vec<Arc<McpClient>>,
   user: Option<User>,
   cfg: AppCfg,
   cur_channel_id: Option<Uuid>,
@@ -111,12 +164,60 @@ impl AppInfo {
   pub fn need_login(&self) -> bool { self.user.is_none() }
 
   pub fn providers(&self) -> &HashMap<String, ServerProvider> { &self.providers }
+
+  /// The price of the `model` of the `provider`, the provider's price table
+  /// take precedence over the global one.
+  pub fn model_price(&self, provider: &str, model: &str) -> Option<ModelPrice> {
+    self
+      .providers
+      .get(provider)
+      .and_then(|sp| find_price(&sp.prices, model))
+      .or_else(|| find_price(&self.prices, model))
+  }
+
+  pub fn spending(&self) -> &Spending { &self.spending }
+
+  #[inline]
+  pub fn tools(&self) -> &ToolRegistry { &self.tools }
+
+  /// The clients of the MCP servers in the config.
+  #[inline]
+  pub fn mcp_clients(&self) -> &[Arc<McpClient>] { &self.mcp_clients }
+
+  /// Add the tool for the bots to call, the bots enable it by their
+  /// `use_tools` param.
+  pub fn register_tool(&mut self, tool: impl Tool + 'static) { self.tools.register(tool); }
+
+  /// The spending of the bot's provider this month, `None` if the provider
+  /// has no budget caps.
+  pub fn budget(&self, bot_id: &BotId) -> Option<Budget> {
+    let provider = self.bot(bot_id)?.sp();
+    let cap = self.providers.get(provider)?.budget?;
+    let month = Spending::month_of(Utc::now());
+    Some(Budget {
+      provider: provider.to_owned(),
+      spent: self.spending.get(provider, &month),
+      cap,
+    })
+  }
+
+  /// Apply the network config to the shared http client and save it for the
+  /// current user, nothing changes if `network` is invalid.
+  pub fn set_network_cfg(&mut self, network: NetworkCfg) -> PolestarResult<()> {
+    client::set_network_cfg(&network)?;
+    let cur_user = utils::read_current_user().unwrap_or(ANONYMOUS_USER.to_owned());
+    utils::write_user_network_cfg(&cur_user, &network)?;
+    self.cfg.set_network(network);
+    Ok(())
+  }
 }
 
 pub struct AppData {
   channels: Vec<Channel>,
   db: Option<Box<PersistenceDB>>,
   info: Box<AppInfo>,
+  // the attachments added in this session, they may not be saved yet.
+  attachments: HashMap<Uuid, Attachment>,
 }
 
 pub static ANONYMOUS_USER: &str = "anonymous";
@@ -125,10 +226,29 @@ pub fn init_app_data() -> AppData {
   utils::launch::setup_project();
   // 2. load user info from local file.
   let cur_user = utils::read_current_user().unwrap_or(ANONYMOUS_USER.to_owned());
-  let BotCfg { bots, providers } =
-    utils::load_bot_cfg(cur_user.as_str()).expect("Failed to load bot config");
+  let BotCfg {
+    bots,
+    providers,
+    retry,
+    trash_days,
+    prices,
+    tools: tools_cfg,
+    mcp_servers,
+  } = utils::load_bot_cfg(cur_user.as_str()).expect("Failed to load bot config");
+  let network = utils::read_user_network_cfg(&cur_user)
+    .or_else(|_| utils::read_network_cfg())
+    .unwrap_or_default();
+  if let Err(err) = client::set_network_cfg(&network) {
+    log::warn!("invalid network config, requests go without proxy: {}", err);
+  }
   // TODO: how to set app default bot
-  let cfg = AppCfg::new(None, bots[0].id().clone());
+  let mut cfg = AppCfg::new(network, bots[0].id().clone());
+  if let Some(retry) = retry {
+    cfg.set_retry(retry);
+  }
+  if let Some(trash_days) = trash_days {
+    cfg.set_trash_days(trash_days);
+  }
   let local_state = utils::read_local_state(&cur_user).unwrap_or_default();
   let (user_data_path, user) = local_state.uid().map_or_else(
     || (None, None),
@@ -154,7 +274,7 @@ pub fn init_app_data() -> AppData {
 
   let (db, mut channels) = if let Some(user_data_path) = user_data_path {
     utils::create_if_not_exist_dir(user_data_path);
-    init_db(user.as_ref().map(|user| user.uid()))
+    init_db(user.as_ref().map(|user| user.uid()), cfg.trash_days())
   } else {
     (None, vec![])
   };
@@ -172,9 +292,27 @@ pub fn init_app_data() -> AppData {
     channels.last().map(|channel| *channel.id())
   };
 
+  // only this month's spending counts for the budgets.
+  let spending = db.as_ref().map_or_else(Spending::default, |db| {
+    let month = Spending::month_of(Utc::now());
+    crate::db::pool::runtime()
+      .block_on(db.query_spending(&month))
+      .unwrap_or_else(|e| {
+        log::warn!("query spending failed: {}", e);
+        Spending::default()
+      })
+  });
+
+  let mut tools = builtin_tools(&tools_cfg);
+  let mcp_clients = crate::db::pool::runtime().block_on(load_mcp_tools(&mcp_servers, &mut tools));
+
   let info = AppInfo {
     bots: Rc::new(bots),
     providers,
+    prices,
+    spending,
+    tools,
+    mcp_clients,
     user,
     cfg,
     cur_channel_id,
@@ -191,9 +329,13 @@ pub fn init_app_data() -> AppData {
 }
 
 #[cfg(feature = "persistence")]
-fn init_db(uid: Option<u64>) -> (Option<Box<PersistenceDB>>, Vec<Channel>) {
+fn init_db(uid: Option<u64>, trash_days: u32) -> (Option<Box<PersistenceDB>>, Vec<Channel>) {
   use crate::db::pool::{db_path, init_db, runtime};
   let db = PersistenceDB::connect(init_db(&db_path(uid))).expect("Failed to connect db");
+  // the expired items in the trash are purged every time the app starts.
+  db.persist_async(ActionPersist::PurgeTrash {
+    before: Utc::now() - chrono::Duration::days(trash_days.into()),
+  });
 
   let mut channels =
     runtime().block_on(async { db.query_channels().await.expect("Failed to query channels") });
@@ -205,6 +347,24 @@ fn init_db(uid: Option<u64>) -> (Option<Box<PersistenceDB>>, Vec<Channel>) {
         .expect("Failed to query msgs")
     });
     channel.load_msgs(msgs);
+    let ratings = runtime().block_on(async {
+      db.query_ratings_by_channel_id(channel.id())
+        .await
+        .expect("Failed to query ratings")
+    });
+    channel.load_ratings(ratings);
+    let picks = runtime().block_on(async {
+      db.query_arena_picks_by_channel_id(channel.id())
+        .await
+        .expect("Failed to query arena picks")
+    });
+    channel.load_arena_picks(picks);
+    let summary = runtime().block_on(async {
+      db.query_summary_by_channel_id(channel.id())
+        .await
+        .expect("Failed to query summary")
+    });
+    channel.load_summary(summary);
   });
 
   let db = Box::new(db);
@@ -237,7 +397,7 @@ fn init_db(uid: Option<u64>) -> (Option<Box<PersistenceDB>>, Vec<Channel>) {
 }
 
 #[cfg(not(feature = "persistence"))]
-fn init_db(_uid: Option<u64>) -> (Option<Box<PersistenceDB>>, Vec<Channel>) {
+fn init_db(_uid: Option<u64>, _trash_days: u32) -> (Option<Box<PersistenceDB>>, Vec<Channel>) {
   let channels = serde_json::from_str::<Vec<Channel>>(include_str!(concat!(
     env!("CARGO_MANIFEST_DIR"),
     "/..",
@@ -253,7 +413,12 @@ impl AppData {
     db: Option<Box<PersistenceDB>>,
     info: Box<AppInfo>,
   ) -> Self {
-    Self { channels, db, info }
+    Self {
+      channels,
+      db,
+      info,
+      attachments: HashMap::new(),
+    }
   }
 
   #[inline]
@@ -323,7 +488,57 @@ impl AppData {
     channel_id
   }
 
-  pub fn remove_channel(&mut self, channel_id: &Uuid) {
+  /// Fork the channel from the message to a new channel, see
+  /// [`Channel::fork`]. The new channel becomes the current one, return its
+  /// id.
+  pub fn fork_channel(&mut self, channel_id: &ChannelId, msg_id: &MsgId) -> Option<ChannelId> {
+    let mut channel = self.get_channel(channel_id)?.fork(msg_id)?;
+    let id = *channel.id();
+    if let Some(db) = self.db.as_mut() {
+      db.persist_async(ActionPersist::AddChannel {
+        id,
+        name: channel.name().to_owned(),
+        desc: channel.desc().map(String::from),
+        cfg: channel.cfg().clone(),
+      });
+      for msg in channel.msgs() {
+        db.persist_async(ActionPersist::AddMsg { channel_id: id, msg: msg.clone() });
+        for (cont_idx, cont) in msg.cont_list().iter().enumerate() {
+          if let Some(rating) = cont.rating() {
+            db.persist_async(ActionPersist::UpdateRating {
+              msg_id: *msg.id(),
+              cont_idx,
+              rating: Some(rating.clone()),
+            });
+          }
+        }
+      }
+      if let Some(summary) = channel.summary() {
+        db.persist_async(ActionPersist::UpdateSummary {
+          channel_id: id,
+          summary: Some(summary.clone()),
+        });
+      }
+      channel.set_db(NonNull::from(&**db));
+      let picks = channel
+        .arena_picks()
+        .iter()
+        .map(|(question_id, winner_id)| (*question_id, *winner_id))
+        .collect::<Vec<_>>();
+      for (question_id, winner_id) in picks {
+        channel.pick_best(&question_id, Some(winner_id));
+      }
+    }
+    channel.set_app_info(NonNull::from(&*self.info));
+
+    self.channels.push(channel);
+    self.info.as_mut().set_cur_channel_id(Some(id));
+    Some(id)
+  }
+
+  /// Move the channel to the trash, return it to undo the deletion with
+  /// `restore_channel`.
+  pub fn remove_channel(&mut self, channel_id: &Uuid) -> Option<Channel> {
     // guard channels is empty after remove channel
     if self.channels.len() == 1 {
       self.new_channel("Untitled".to_owned(), None, ChannelCfg::default());
@@ -348,7 +563,306 @@ impl AppData {
       self.info.as_mut().set_cur_channel_id(cur_channel_id);
     }
 
-    self.channels.retain(|channel| channel.id() != channel_id);
+    let pos = self
+      .channels
+      .iter()
+      .position(|channel| channel.id() == channel_id)?;
+    Some(self.channels.remove(pos))
+  }
+
+  /// Take the channel removed by `remove_channel` out of the trash.
+  pub fn restore_channel(&mut self, channel: Channel) {
+    if self.get_channel(channel.id()).is_some() {
+      return;
+    }
+    if let Some(db) = self.db.as_mut() {
+      db.persist_async(ActionPersist::RestoreChannel { channel_id: *channel.id() });
+    }
+    self.channels.push(channel);
+  }
+
+  /// The deleted channels and messages, the latest deleted first. The trash
+  /// is empty without the database.
+  pub fn trash(&self) -> PolestarResult<Vec<TrashItem>> {
+    let Some(db) = self.db.as_ref() else {
+      return Ok(vec![]);
+    };
+    crate::db::pool::runtime().block_on(db.query_trash())
+  }
+
+  /// Take the channel or the message with the id out of the trash, a channel
+  /// is added back to the channel list. Return `false` if it's not in the
+  /// trash.
+  pub fn restore_trash(&mut self, id: &Uuid) -> PolestarResult<bool> {
+    let Some(item) = self.trash()?.into_iter().find(|item| item.id() == id) else {
+      return Ok(false);
+    };
+    let Some(db) = self.db.as_ref() else {
+      return Ok(false);
+    };
+    let rt = crate::db::pool::runtime();
+    match item.msg_id {
+      Some(msg_id) => {
+        let msg = rt.block_on(db.query_msg_by_id(&msg_id))?;
+        let Some(channel) = self.get_channel_mut(&item.channel_id) else {
+          return Ok(false);
+        };
+        channel.restore_msgs(vec![msg]);
+      }
+      None => {
+        rt.block_on(db.restore_channel(id))?;
+        let mut channel = rt.block_on(db.query_channel_by_id(id))?;
+        channel.load_msgs(rt.block_on(db.query_msgs_by_channel_id(id))?);
+        channel.load_ratings(rt.block_on(db.query_ratings_by_channel_id(id))?);
+        channel.load_arena_picks(rt.block_on(db.query_arena_picks_by_channel_id(id))?);
+        channel.load_summary(rt.block_on(db.query_summary_by_channel_id(id))?);
+        channel.set_db(NonNull::from(&**db));
+        channel.set_app_info(NonNull::from(&*self.info));
+        self.channels.push(channel);
+      }
+    }
+    Ok(true)
+  }
+
+  /// Delete the item with the id in the trash for good, or all items in the
+  /// trash if `id` is `None`. Return how many items are purged.
+  pub fn purge_trash(&mut self, id: Option<&Uuid>) -> PolestarResult<usize> {
+    let Some(db) = self.db.as_ref() else {
+      return Ok(0);
+    };
+    let rt = crate::db::pool::runtime();
+    match id {
+      Some(id) => Ok(rt.block_on(db.purge_trash_item(id))? as usize),
+      None => rt.block_on(db.purge_trash(Utc::now())),
+    }
+  }
+
+  /// Search the text of the messages in all channels, the best matched first.
+  /// Nothing is found without the database.
+  pub fn search_msgs(
+    &self,
+    query: &str,
+    filter: &MsgSearchFilter,
+  ) -> PolestarResult<Vec<MsgSearchHit>> {
+    let Some(db) = self.db.as_ref() else {
+      return Ok(vec![]);
+    };
+    crate::db::pool::runtime().block_on(db.search_msgs(query, filter))
+  }
+
+  /// Record the usage of the answer in the content `idx` of the message, with
+  /// its cost by the price table, and add the cost to the spending of its
+  /// provider. It's saved with the content when the answer is finished.
+  pub fn record_usage(
+    &mut self,
+    channel_id: &ChannelId,
+    msg_id: &MsgId,
+    idx: usize,
+    mut usage: MsgUsage,
+  ) {
+    usage.cost = self
+      .info
+      .model_price(&usage.provider, &usage.model)
+      .map(|price| price.cost(usage.prompt_tokens, usage.completion_tokens));
+    if let Some(cost) = usage.cost {
+      let month = Spending::month_of(Utc::now());
+      self.info.spending.add(&usage.provider, month, cost);
+    }
+    if let Some(channel) = self.get_channel_mut(channel_id) {
+      channel.set_usage(msg_id, idx, usage);
+    }
+  }
+
+  /// Put the drawn images into the content `idx` of the message and finish
+  /// it, the first image is the content and the others are added as its
+  /// alternatives. The images are saved as the attachments.
+  pub fn add_images(
+    &mut self,
+    channel_id: &ChannelId,
+    msg_id: &MsgId,
+    idx: usize,
+    images: Vec<GeneratedImage>,
+  ) {
+    let Some(channel) = self
+      .channels
+      .iter_mut()
+      .find(|channel| channel.id() == channel_id)
+    else {
+      return;
+    };
+    let bot = channel
+      .msg(msg_id)
+      .and_then(|msg| msg.cont_list().get(idx))
+      .and_then(|cont| cont.bot().cloned());
+    for (i, GeneratedImage { image, attachment }) in images.into_iter().enumerate() {
+      if let Some(db) = self.db.as_ref() {
+        db.persist_async(ActionPersist::AddAttachment { attachment: attachment.clone() });
+      }
+      self.attachments.insert(*attachment.name(), attachment);
+      if i == 0 {
+        channel.update_msg(
+          msg_id,
+          idx,
+          MsgAction::Receiving(MsgBody::Image(Some(image))),
+        );
+      } else {
+        let mut cont = MsgCont::new_image(image);
+        cont.set_bot(bot.clone());
+        channel.add_cont(msg_id, cont);
+      }
+    }
+    channel.update_msg(msg_id, idx, MsgAction::Fulfilled);
+  }
+
+  /// The attachment `name`, it's loaded from the database if it's not added
+  /// in this session.
+  pub fn attachment(&self, name: &Uuid) -> PolestarResult<Attachment> {
+    if let Some(attachment) = self.attachments.get(name) {
+      return Ok(attachment.clone());
+    }
+    let Some(db) = self.db.as_ref() else {
+      return Err(PolestarError::DatabaseNotFound);
+    };
+    crate::db::pool::runtime().block_on(db.query_attachment_by_name(name))
+  }
+
+  /// Attach the image `src` to the user message, `src` is the url of the image
+  /// on the web or the path of the local image file. The local image is saved
+  /// as the attachment at once, so it can be sent right after.
+  pub fn attach_image(&mut self, src: &str) -> PolestarResult<Image> {
+    if src.starts_with("http://") || src.starts_with("https://") {
+      return Ok(Image::new_url(src.to_owned(), 0, 0));
+    }
+    self.attach_image_data(std::fs::read(src)?)
+  }
+
+  /// Attach the image of the data, i.e. the pasted image.
+  pub fn attach_image_data(&mut self, data: Vec<u8>) -> PolestarResult<Image> {
+    let mime = MIME::from_image_data(&data)
+      .ok_or_else(|| PolestarError::Image("unsupported image format".to_owned()))?;
+    let (width, height) = image::image_size(&mime, &data).unwrap_or_default();
+    let attachment = Attachment::new(mime, data);
+    if let Some(db) = self.db.as_ref() {
+      crate::db::pool::runtime().block_on(db.add_attachment(&attachment))?;
+    }
+    let image = Image::new_file(*attachment.name(), width, height);
+    self.attachments.insert(*attachment.name(), attachment);
+    Ok(image)
+  }
+
+  /// Write the stored image to `path`, if `path` is a folder the image is
+  /// written into it by its name. Return the path of the file.
+  pub fn save_image(&self, img: &Image, path: &Path) -> PolestarResult<PathBuf> {
+    let Some(name) = img.file() else {
+      return Err(PolestarError::Image(
+        "the image is not stored locally".to_owned(),
+      ));
+    };
+    let attachment = self.attachment(name)?;
+    let path = if path.is_dir() {
+      path.join(format!("{}.{}", name, attachment.mime().ext()))
+    } else {
+      path.to_owned()
+    };
+    std::fs::write(&path, attachment.data())?;
+    Ok(path)
+  }
+
+  /// The usage of the bots' answers grouped by `group`, since `since` if it's
+  /// given.
+  pub fn usage_stats(
+    &self,
+    group: UsageGroup,
+    since: Option<DateTime<Utc>>,
+  ) -> PolestarResult<Vec<UsageStat>> {
+    let Some(db) = self.db.as_ref() else {
+      return Ok(vec![]);
+    };
+    crate::db::pool::runtime().block_on(db.query_usage_stats(group, since))
+  }
+
+  /// How often every bot's answer is picked as the best, the most winning
+  /// first.
+  pub fn arena_stats(&self) -> PolestarResult<Vec<ArenaStat>> {
+    let Some(db) = self.db.as_ref() else {
+      return Ok(vec![]);
+    };
+    crate::db::pool::runtime().block_on(db.query_arena_stats())
+  }
+
+  /// Export the channels, or all channels if `channel_ids` is empty. The
+  /// images are loaded from the database.
+  pub fn export_channels(
+    &self,
+    channel_ids: &[ChannelId],
+    opts: &ExportOptions,
+  ) -> PolestarResult<Export> {
+    let channels = self
+      .channels
+      .iter()
+      .filter(|channel| channel_ids.is_empty() || channel_ids.contains(channel.id()))
+      .collect::<Vec<_>>();
+
+    let mut attachments = HashMap::new();
+    if let Some(db) = self.db.as_ref() {
+      for name in export::attachment_names(channels.iter().copied()) {
+        match crate::db::pool::runtime().block_on(db.query_attachment_by_name(&name)) {
+          Ok(attachment) => {
+            attachments.insert(name, attachment);
+          }
+          Err(err) => log::warn!("export attachment {} failed: {}", name, err),
+        }
+      }
+    }
+
+    export::export_channels(&channels, self.info.bots(), &attachments, opts)
+  }
+
+  /// Export the rated answers of the channels as a fine-tuning dataset, or
+  /// of all channels if `channel_ids` is empty.
+  pub fn export_dataset(
+    &self,
+    channel_ids: &[ChannelId],
+    format: DatasetFormat,
+  ) -> PolestarResult<String> {
+    let channels = self
+      .channels
+      .iter()
+      .filter(|channel| channel_ids.is_empty() || channel_ids.contains(channel.id()))
+      .collect::<Vec<_>>();
+    dataset::export_dataset(&channels, self.info.bots(), format)
+  }
+
+  /// Import the channels from Polestar's JSON export or ChatGPT's
+  /// `conversations.json`, they are saved in one transaction. Return the ids
+  /// of the new channels.
+  pub fn import_channels(&mut self, content: &str) -> PolestarResult<Vec<ChannelId>> {
+    let Import { channels, attachments } = import::parse_import(content, self.info.def_bot().id())?;
+    if let Some(db) = self.db.as_ref() {
+      crate::db::pool::runtime().block_on(db.import_channels(&channels, &attachments))?;
+    }
+
+    let db = self.db.as_ref().map(|db| NonNull::from(&**db));
+    let app_info = NonNull::from(&*self.info);
+    let mut ids = vec![];
+    for mut channel in channels {
+      if let Some(db) = db {
+        channel.set_db(db);
+        // pick again to save the picks with the bots of the answers.
+        let picks = channel
+          .arena_picks()
+          .iter()
+          .map(|(question_id, winner_id)| (*question_id, *winner_id))
+          .collect::<Vec<_>>();
+        for (question_id, winner_id) in picks {
+          channel.pick_best(&question_id, Some(winner_id));
+        }
+      }
+      channel.set_app_info(app_info);
+      ids.push(*channel.id());
+      self.channels.push(channel);
+    }
+    Ok(ids)
   }
 
   pub fn login(&mut self, user: User) {
@@ -356,7 +870,7 @@ impl AppData {
     self.info.as_mut().set_user(Some(user));
     let local_state = utils::read_local_state(&uid.to_string()).unwrap_or_default();
 
-    let (db, mut channels) = init_db(Some(uid));
+    let (db, mut channels) = init_db(Some(uid), self.info.cfg().trash_days());
     self.db = db;
 
     let cur_channel_id = local_state.cur_channel_id();
@@ -390,19 +904,115 @@ impl AppData {
 
 #[derive(Debug, Default)]
 pub struct AppCfg {
-  proxy: Option<String>,
+  network: NetworkCfg,
   def_bot_id: String,
+  retry: RetryPolicy,
+  trash_days: Option<u32>,
 }
 
 impl AppCfg {
-  pub fn new(proxy: Option<String>, def_bot_id: BotId) -> Self { Self { proxy, def_bot_id } }
+  pub fn new(network: NetworkCfg, def_bot_id: BotId) -> Self {
+    Self {
+      network,
+      def_bot_id,
+      retry: RetryPolicy::default(),
+      trash_days: None,
+    }
+  }
+
+  #[inline]
+  pub fn proxy(&self) -> Option<&str> { self.network.proxy.as_deref() }
 
   #[inline]
-  pub fn proxy(&self) -> Option<&str> { self.proxy.as_deref() }
+  pub fn set_proxy(&mut self, proxy: Option<String>) { self.network.proxy = proxy; }
 
   #[inline]
-  pub fn set_proxy(&mut self, proxy: Option<String>) { self.proxy = proxy; }
+  pub fn no_proxy(&self) -> &[String] { &self.network.no_proxy }
+
+  #[inline]
+  pub fn network(&self) -> &NetworkCfg { &self.network }
+
+  #[inline]
+  pub fn set_network(&mut self, network: NetworkCfg) { self.network = network; }
 
   #[inline]
   pub fn def_bot_id(&self) -> &BotId { &self.def_bot_id }
+
+  #[inline]
+  pub fn retry(&self) -> &RetryPolicy { &self.retry }
+
+  #[inline]
+  pub fn set_retry(&mut self, retry: RetryPolicy) { self.retry = retry; }
+
+  /// How many days the deleted channels and messages are kept in the trash.
+  #[inline]
+  pub fn trash_days(&self) -> u32 { self.trash_days.unwrap_or(DEF_TRASH_DAYS) }
+
+  #[inline]
+  pub fn set_trash_days(&mut self, trash_days: u32) { self.trash_days = Some(trash_days); }
+}
+
+/// How to retry a request failed with a retryable error, the delay of retry
+/// grows exponentially.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+  /// the max times to send a request, include the first one.
+  pub max_attempts: u32,
+  /// the delay before the first retry, doubled by every retry.
+  pub base_delay_ms: u64,
+  /// the upper limit of the delay, a `Retry-After` longer than it won't retry.
+  pub max_delay_ms: u64,
+  /// the random factor of the delay, `0.2` means the delay changes in ±20%.
+  pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_attempts: 3,
+      base_delay_ms: 500,
+      max_delay_ms: 10_000,
+      jitter: 0.2,
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// The delay before the retry after `attempt` times failed.
+  pub fn backoff(&self, attempt: u32) -> Duration {
+    let exp = attempt.saturating_sub(1).min(16);
+    let delay = self
+      .base_delay_ms
+      .saturating_mul(1 << exp)
+      .min(self.max_delay_ms) as f64;
+    let jitter = self.jitter.clamp(0., 1.);
+    let factor = if jitter > 0. {
+      rand::thread_rng().gen_range(1. - jitter..=1. + jitter)
+    } else {
+      1.
+    };
+    Duration::from_millis((delay * factor) as u64)
+  }
+
+  /// Return the delay to wait before the next attempt, or `None` if the
+  /// request failed by `err` after `attempt` times should not retry.
+  pub fn retry_delay(&self, attempt: u32, err: &PolestarError) -> Option<Duration> {
+    if attempt >= self.max_attempts {
+      return None;
+    }
+    match err {
+      PolestarError::PolestarServerError(err) => match err.retry_after {
+        Some(secs) => {
+          let delay = Duration::from_secs(secs);
+          (delay <= Duration::from_millis(self.max_delay_ms)).then_some(delay)
+        }
+        None => err.kind.is_retryable().then(|| self.backoff(attempt)),
+      },
+      PolestarError::Reqwest(err) if err.is_connect() || err.is_timeout() => {
+        Some(self.backoff(attempt))
+      }
+      _ => None,
+    }
+  }
 }
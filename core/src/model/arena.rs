@@ -0,0 +1,24 @@
+use super::{BotId, MsgId};
+
+/// The best answer the user picked among the answers of several bots to a
+/// question, only the best one is carried into the context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArenaPick {
+  pub question_id: MsgId,
+  pub winner_id: MsgId,
+  pub winner_bot: BotId,
+  // all the bots answered the question, include the winner.
+  pub bots: Vec<BotId>,
+  // the text of the question, to analyse which bot wins for which kind of
+  // question.
+  pub question: String,
+}
+
+/// How often the answers of a bot are picked as the best.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArenaStat {
+  pub bot: BotId,
+  pub wins: u32,
+  // how many picks the bot took part in.
+  pub rounds: u32,
+}
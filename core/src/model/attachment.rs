@@ -5,6 +5,42 @@ use uuid::Uuid;
 pub enum MIME {
   #[serde(rename = "image/png")]
   ImagePng,
+  #[serde(rename = "image/jpeg")]
+  ImageJpeg,
+  #[serde(rename = "image/webp")]
+  ImageWebp,
+}
+
+impl MIME {
+  /// Detect the type of the image by its leading bytes.
+  pub fn from_image_data(data: &[u8]) -> Option<Self> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+      Some(MIME::ImagePng)
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+      Some(MIME::ImageJpeg)
+    } else if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+      Some(MIME::ImageWebp)
+    } else {
+      None
+    }
+  }
+
+  pub fn media_type(&self) -> &'static str {
+    match self {
+      MIME::ImagePng => "image/png",
+      MIME::ImageJpeg => "image/jpeg",
+      MIME::ImageWebp => "image/webp",
+    }
+  }
+
+  /// The file extension of the type, without the dot.
+  pub fn ext(&self) -> &'static str {
+    match self {
+      MIME::ImagePng => "png",
+      MIME::ImageJpeg => "jpg",
+      MIME::ImageWebp => "webp",
+    }
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow, Clone)]
@@ -23,6 +59,11 @@ impl Attachment {
     }
   }
 
+  /// Restore an attachment with its original name.
+  pub(crate) fn with_name(name: Uuid, mime: MIME, data: Vec<u8>) -> Self {
+    Self { name, mime, data }
+  }
+
   pub fn name(&self) -> &Uuid { &self.name }
 
   pub fn data(&self) -> &[u8] { &self.data }
@@ -105,6 +105,23 @@ impl Bot {
 
   pub fn onboarding(&self) -> Option<&str> { self.onboarding.as_deref() }
 
+  /// Whether the bot draws images by an OpenAI `images/generations`
+  /// compatible API instead of chatting.
+  pub fn is_image_gen(&self) -> bool {
+    self
+      .url
+      .trim_end_matches('/')
+      .ends_with("images/generations")
+  }
+
+  /// Whether the model of the bot accepts the images of the user messages,
+  /// which is enabled by the `vision` param.
+  pub fn accepts_images(&self) -> bool { self.params.get("vision").is_some_and(|v| v == true) }
+
+  /// Whether the server of the bot reports the token usage at the end of the
+  /// stream when asked, which is enabled by the `stream_usage` param.
+  pub fn stream_usage(&self) -> bool { self.params.get("stream_usage").is_some_and(|v| v == true) }
+
   pub fn merge(&mut self, bot: &PartialBot) {
     if let Some(name) = &bot.name {
       self.name = name.clone();
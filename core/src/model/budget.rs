@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The price of a model per 1K tokens, in the currency of the provider's bill.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+  pub input: f64,
+  pub output: f64,
+}
+
+impl ModelPrice {
+  pub fn cost(&self, prompt_tokens: u32, completion_tokens: u32) -> f64 {
+    (prompt_tokens as f64 * self.input + completion_tokens as f64 * self.output) / 1000.
+  }
+}
+
+/// Find the price of the `model` in the table, the exact name first, then the
+/// longest name prefix, e.g. `gpt-4o` for `gpt-4o-2024-05-13`.
+pub fn find_price(prices: &HashMap<String, ModelPrice>, model: &str) -> Option<ModelPrice> {
+  prices.get(model).copied().or_else(|| {
+    prices
+      .iter()
+      .filter(|(name, _)| model.starts_with(name.as_str()))
+      .max_by_key(|(name, _)| name.len())
+      .map(|(_, price)| *price)
+  })
+}
+
+/// The monthly spending caps of a provider. Over the soft cap only warns, over
+/// the hard cap the requests are refused.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BudgetCap {
+  pub soft: Option<f64>,
+  pub hard: Option<f64>,
+}
+
+/// The spending of a provider this month against its caps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+  pub provider: String,
+  pub spent: f64,
+  pub cap: BudgetCap,
+}
+
+impl Budget {
+  pub fn is_over(&self) -> bool { self.cap.hard.is_some_and(|hard| self.spent >= hard) }
+
+  pub fn is_warning(&self) -> bool { self.cap.soft.is_some_and(|soft| self.spent >= soft) }
+
+  /// The message to show the user, `None` if it's under the caps.
+  pub fn message(&self) -> Option<String> {
+    if self.is_over() {
+      Some(format!(
+        "{} is over its monthly budget: {:.2} of {:.2} spent",
+        self.provider,
+        self.spent,
+        self.cap.hard.unwrap_or_default()
+      ))
+    } else if self.is_warning() {
+      Some(format!(
+        "{} is near its monthly budget: {:.2} spent, the warning is at {:.2}",
+        self.provider,
+        self.spent,
+        self.cap.soft.unwrap_or_default()
+      ))
+    } else {
+      None
+    }
+  }
+}
+
+/// The running totals of the cost per provider and month.
+#[derive(Debug, Clone, Default)]
+pub struct Spending {
+  // keyed by the provider and the month like `2024-05`.
+  totals: HashMap<(String, String), f64>,
+}
+
+impl Spending {
+  pub fn month_of(time: DateTime<Utc>) -> String { time.format("%Y-%m").to_string() }
+
+  pub fn add(&mut self, provider: &str, month: String, cost: f64) {
+    *self.totals.entry((provider.to_owned(), month)).or_default() += cost;
+  }
+
+  pub fn get(&self, provider: &str, month: &str) -> f64 {
+    self
+      .totals
+      .get(&(provider.to_owned(), month.to_owned()))
+      .copied()
+      .unwrap_or_default()
+  }
+
+  /// The totals of every provider and month, the latest month first.
+  pub fn totals(&self) -> Vec<(&str, &str, f64)> {
+    let mut totals = self
+      .totals
+      .iter()
+      .map(|((provider, month), cost)| (provider.as_str(), month.as_str(), *cost))
+      .collect::<Vec<_>>();
+    totals.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    totals
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn price_by_prefix() {
+    let prices = HashMap::from([
+      ("gpt-4".to_owned(), ModelPrice { input: 0.03, output: 0.06 }),
+      (
+        "gpt-4o".to_owned(),
+        ModelPrice { input: 0.005, output: 0.015 },
+      ),
+    ]);
+    let price = find_price(&prices, "gpt-4o-2024-05-13").unwrap();
+    assert_eq!(price.input, 0.005);
+    assert_eq!(find_price(&prices, "gpt-4").unwrap().input, 0.03);
+    assert!(find_price(&prices, "claude-3").is_none());
+    assert!((price.cost(1000, 2000) - 0.035).abs() < 1e-9);
+  }
+
+  #[test]
+  fn budget_caps() {
+    let mut budget = Budget {
+      provider: "OpenAI".to_owned(),
+      spent: 5.,
+      cap: BudgetCap { soft: Some(8.), hard: Some(10.) },
+    };
+    assert!(budget.message().is_none());
+    budget.spent = 9.;
+    assert!(budget.is_warning() && !budget.is_over());
+    budget.spent = 10.;
+    assert!(budget.is_over());
+    assert!(budget
+      .message()
+      .unwrap()
+      .contains("over its monthly budget"));
+  }
+}
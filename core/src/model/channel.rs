@@ -1,11 +1,17 @@
-use std::ptr::NonNull;
+use std::{
+  collections::{HashMap, HashSet},
+  ptr::NonNull,
+};
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::db::{executor::ActionPersist, pool::PersistenceDB};
 
-use super::{msg::Msg, AppInfo, Bot, BotId, MsgAction};
+use super::{
+  msg::Msg, AppInfo, ArenaPick, Attachment, Bot, BotId, MsgAction, MsgBranch, MsgCont, MsgId,
+  MsgRating, MsgUsage,
+};
 
 pub type ChannelId = Uuid;
 
@@ -16,6 +22,12 @@ pub struct Channel {
   desc: Option<String>,
   cfg: ChannelCfg,
   msgs_coll: MsgColl,
+  #[serde(default)]
+  summary: Option<ChannelSummary>,
+  // the picked best answer of the questions answered by several bots, it's
+  // saved in its own table.
+  #[serde(skip)]
+  arena_picks: HashMap<MsgId, MsgId>,
   #[serde(skip)]
   app_info: Option<NonNull<AppInfo>>,
   #[serde(skip)]
@@ -60,6 +72,8 @@ impl Channel {
       desc,
       cfg,
       msgs_coll: MsgColl::default(),
+      summary: None,
+      arena_picks: HashMap::new(),
       app_info,
       db,
     }
@@ -68,6 +82,8 @@ impl Channel {
   #[inline]
   pub fn set_db(&mut self, db: NonNull<PersistenceDB>) { self.db = Some(db); }
 
+  pub(crate) fn db(&self) -> Option<NonNull<PersistenceDB>> { self.db }
+
   #[inline]
   pub fn set_app_info(&mut self, app_info: NonNull<AppInfo>) { self.app_info = Some(app_info); }
 
@@ -75,6 +91,15 @@ impl Channel {
     self.app_info.map(|app_info| unsafe { app_info.as_ref() })
   }
 
+  /// Load the attachment the messages refer to from the database.
+  pub fn attachment(&self, name: &Uuid) -> Option<Attachment> {
+    let db = unsafe { self.db?.as_ref() };
+    crate::db::pool::runtime()
+      .block_on(db.query_attachment_by_name(name))
+      .map_err(|err| log::warn!("load attachment {} failed: {}", name, err))
+      .ok()
+  }
+
   #[inline]
   pub fn id(&self) -> &ChannelId { &self.id }
 
@@ -104,7 +129,12 @@ impl Channel {
     self.update();
   }
 
-  pub fn add_msg(&mut self, msg: Msg) {
+  /// Add the message to the end of the active branch.
+  pub fn add_msg(&mut self, mut msg: Msg) {
+    if msg.meta().branch().is_none() {
+      let branch = self.branch_tip();
+      msg.meta_mut().set_branch(branch);
+    }
     self.msgs_coll.msgs.push(msg.clone());
     let channel_id = *self.id();
     if let Some(db) = self.db.as_mut() {
@@ -115,10 +145,33 @@ impl Channel {
     }
   }
 
+  /// Add the tool message before the bot's answer which calls the tool, as
+  /// the context of the answer.
+  pub fn add_tool_msg(&mut self, answer_id: &MsgId, mut msg: Msg) {
+    let Some(pos) = self.msgs().iter().position(|msg| msg.id() == answer_id) else {
+      return;
+    };
+    let answer = &self.msgs()[pos];
+    msg.meta_mut().set_branch(answer.meta().branch().copied());
+    // the messages are loaded in the order they were created.
+    msg.set_create_at(*answer.create_at() - chrono::Duration::nanoseconds(1));
+    self.msgs_coll.msgs.insert(pos, msg.clone());
+    let channel_id = *self.id();
+    if let Some(db) = self.db.as_mut() {
+      unsafe {
+        db.as_mut()
+          .persist_async(ActionPersist::AddMsg { channel_id, msg })
+      }
+    }
+  }
+
   pub fn update_msg(&mut self, msg_id: &Uuid, idx: usize, act: MsgAction) {
     let msg = self.msg_mut(msg_id);
     if let Some(msg) = msg {
-      let is_need_persist = matches!(act, MsgAction::Fulfilled | MsgAction::Rejected);
+      let is_need_persist = matches!(
+        act,
+        MsgAction::Fulfilled | MsgAction::Rejected | MsgAction::Interrupted
+      );
       msg.cont_mut(idx).action(act);
       if is_need_persist {
         let msg = msg.clone();
@@ -129,6 +182,390 @@ impl Channel {
     }
   }
 
+  /// Edit the user message and keep its previous version: the `text` becomes
+  /// the new current content of the message, the messages after it stay in
+  /// the branch of the previous version. Return the index of the new content.
+  pub fn edit_msg(&mut self, msg_id: &MsgId, text: &str) -> Option<usize> {
+    let msgs = self.msgs();
+    let pos = msgs.iter().position(|msg| msg.id() == msg_id)?;
+    if !msgs[pos].role().is_user() {
+      return None;
+    }
+    let prev = MsgBranch {
+      msg_id: *msg_id,
+      cont_idx: msgs[pos].cur_idx(),
+    };
+
+    // the messages after it in the active branch follow the previous version,
+    // except the ones already follow a later edited message.
+    let active = self
+      .branch_msgs()
+      .iter()
+      .map(|msg| *msg.id())
+      .collect::<HashSet<_>>();
+    let following = msgs[pos + 1..]
+      .iter()
+      .filter(|msg| active.contains(msg.id()))
+      .filter(|msg| {
+        msg
+          .meta()
+          .branch()
+          .is_none_or(|branch| msgs[..=pos].iter().any(|msg| msg.id() == &branch.msg_id))
+      })
+      .map(|msg| *msg.id())
+      .collect::<Vec<_>>();
+    for id in following {
+      if let Some(msg) = self.msg_mut(&id) {
+        msg.meta_mut().set_branch(Some(prev));
+        let msg = msg.clone();
+        self.persist_msg(msg);
+      }
+    }
+
+    let msg = self.msg_mut(msg_id)?;
+    // the attached images are kept by the edited version.
+    let images = msg.cur_cont_ref().images().to_vec();
+    let idx = msg.add_cont(MsgCont::new_text(text).with_images(images));
+    msg.switch_cont(idx);
+    let msg = msg.clone();
+    self.persist_msg(msg);
+    Some(idx)
+  }
+
+  /// Add a fresh content to the bot's message to answer its question again,
+  /// and switch to it. The previous answers are kept as the alternatives.
+  /// `bot_id` is the bot to answer it, if it's not the message's bot. Return
+  /// the index of the new content, to stream the answer into.
+  pub fn regenerate(&mut self, msg_id: &MsgId, bot_id: Option<BotId>) -> Option<usize> {
+    let msg = self.msg_mut(msg_id)?;
+    let msg_bot = msg.role().bot()?;
+    let mut cont = MsgCont::init_text();
+    cont.set_bot(bot_id.filter(|bot_id| bot_id != msg_bot));
+    let idx = msg.add_cont(cont);
+    msg.switch_cont(idx);
+    let msg = msg.clone();
+    self.persist_msg(msg);
+    Some(idx)
+  }
+
+  /// Add the content to the message as an alternative, the current content
+  /// is kept. Return the index of the content.
+  pub fn add_cont(&mut self, msg_id: &MsgId, cont: MsgCont) -> Option<usize> {
+    let msg = self.msg_mut(msg_id)?;
+    let idx = msg.add_cont(cont);
+    let msg = msg.clone();
+    self.persist_msg(msg);
+    Some(idx)
+  }
+
+  /// Switch the current content of the message, if it's an edited message
+  /// the messages follow it switch to the branch of the content too.
+  pub fn switch_cont(&mut self, msg_id: &MsgId, idx: usize) {
+    if let Some(msg) = self.msg_mut(msg_id) {
+      msg.switch_cont(idx);
+      let msg = msg.clone();
+      self.persist_msg(msg);
+    }
+  }
+
+  /// Rate the content `idx` of the bot's message, `None` to clear the rating.
+  pub fn rate(&mut self, msg_id: &MsgId, idx: usize, rating: Option<MsgRating>) {
+    let Some(msg) = self
+      .msg_mut(msg_id)
+      .filter(|msg| msg.role().is_bot() && idx < msg.cont_count())
+    else {
+      return;
+    };
+    msg.cont_mut(idx).set_rating(rating.clone());
+    if let Some(db) = self.db.as_mut() {
+      unsafe {
+        db.as_mut().persist_async(ActionPersist::UpdateRating {
+          msg_id: *msg_id,
+          cont_idx: idx,
+          rating,
+        })
+      }
+    }
+  }
+
+  /// Record the usage of the bot's answer in the content `idx`, it's saved
+  /// with the content when the answer is finished.
+  pub fn set_usage(&mut self, msg_id: &MsgId, idx: usize, usage: MsgUsage) {
+    if let Some(msg) = self
+      .msg_mut(msg_id)
+      .filter(|msg| msg.role().is_bot() && idx < msg.cont_count())
+    {
+      msg.cont_mut(idx).set_usage(Some(usage));
+    }
+  }
+
+  /// Set the ratings loaded from the database to the contents.
+  pub fn load_ratings(&mut self, ratings: Vec<(MsgId, usize, MsgRating)>) {
+    for (msg_id, idx, rating) in ratings {
+      if let Some(msg) = self.msg_mut(&msg_id).filter(|msg| idx < msg.cont_count()) {
+        msg.cont_mut(idx).set_rating(Some(rating));
+      }
+    }
+  }
+
+  /// The answers reply to the question, in the order they were created.
+  pub fn answers(&self, question_id: &MsgId) -> Vec<&Msg> {
+    self
+      .msgs()
+      .iter()
+      .filter(|msg| msg.role().is_bot() && msg.meta().source_id() == Some(question_id))
+      .collect()
+  }
+
+  /// The answer picked as the best one of the question.
+  #[inline]
+  pub fn best_answer(&self, question_id: &MsgId) -> Option<&MsgId> {
+    self.arena_picks.get(question_id)
+  }
+
+  /// Pick the answer as the best one of the question answered by several
+  /// bots, only the best answer is carried into the context. `None` to clear
+  /// the pick.
+  pub fn pick_best(&mut self, question_id: &MsgId, winner_id: Option<MsgId>) {
+    let pick = match winner_id {
+      Some(winner_id) => {
+        let answers = self.answers(question_id);
+        let Some(winner) = answers.iter().find(|msg| msg.id() == &winner_id) else {
+          return;
+        };
+        if answers.len() < 2 {
+          return;
+        }
+        let mut bots = answers
+          .iter()
+          .filter_map(|msg| msg.role().bot().cloned())
+          .collect::<Vec<_>>();
+        bots.dedup();
+        let question = self
+          .msg(question_id)
+          .and_then(|msg| msg.cur_cont_ref().text())
+          .unwrap_or_default()
+          .to_owned();
+        let pick = ArenaPick {
+          question_id: *question_id,
+          winner_id,
+          winner_bot: winner.role().bot().cloned().unwrap_or_default(),
+          bots,
+          question,
+        };
+        self.arena_picks.insert(*question_id, winner_id);
+        Some(pick)
+      }
+      None => {
+        if self.arena_picks.remove(question_id).is_none() {
+          return;
+        }
+        None
+      }
+    };
+    let channel_id = *self.id();
+    if let Some(db) = self.db.as_mut() {
+      unsafe {
+        db.as_mut().persist_async(ActionPersist::UpdateArenaPick {
+          channel_id,
+          question_id: *question_id,
+          pick,
+        })
+      }
+    }
+  }
+
+  /// The picked best answers, the question id to the id of its best answer.
+  #[inline]
+  pub fn arena_picks(&self) -> &HashMap<MsgId, MsgId> { &self.arena_picks }
+
+  /// Set the picks loaded from the database, as the question id and the id of
+  /// its best answer.
+  pub fn load_arena_picks(&mut self, picks: Vec<(MsgId, MsgId)>) { self.arena_picks.extend(picks); }
+
+  /// The messages in the active branch that are sent as the context, the
+  /// answers not picked are left out if the best answer of the question is
+  /// picked.
+  pub fn context_msgs(&self) -> Vec<&Msg> {
+    self
+      .branch_msgs()
+      .into_iter()
+      .filter(|msg| {
+        let picked = msg
+          .meta()
+          .source_id()
+          .filter(|_| msg.role().is_bot())
+          .and_then(|question_id| self.arena_picks.get(question_id));
+        picked.is_none_or(|winner_id| winner_id == msg.id())
+      })
+      .collect()
+  }
+
+  /// The messages in the active branch, that the messages follow the current
+  /// versions of the edited messages.
+  pub fn branch_msgs(&self) -> Vec<&Msg> {
+    let mut active = HashSet::new();
+    let mut msgs = vec![];
+    for msg in self.msgs() {
+      let in_branch = msg.meta().branch().is_none_or(|branch| {
+        match self.msg(&branch.msg_id) {
+          Some(edited) => active.contains(edited.id()) && edited.cur_idx() == branch.cont_idx,
+          // the edited message is removed with the messages follow its other
+          // versions, keep the ones left.
+          None => true,
+        }
+      });
+      if in_branch {
+        active.insert(msg.id());
+        msgs.push(msg);
+      }
+    }
+    msgs
+  }
+
+  /// The branch a new message follows, the one of the last message in the
+  /// active branch, or its current version if it's an edited message.
+  fn branch_tip(&self) -> Option<MsgBranch> {
+    let last = *self.branch_msgs().last()?;
+    if last.role().is_user() && last.cont_count() > 1 {
+      Some(MsgBranch {
+        msg_id: *last.id(),
+        cont_idx: last.cur_idx(),
+      })
+    } else {
+      last.meta().branch().copied()
+    }
+  }
+
+  /// Move the message to the trash, return the removed messages to undo the
+  /// deletion with `restore_msgs`. The messages follow the other versions of
+  /// an edited message are removed with it, so only its current branch goes
+  /// on. Return nothing if it's not in the channel.
+  pub fn remove_msg(&mut self, msg_id: &MsgId) -> Vec<Msg> {
+    let Some(msg) = self.msg(msg_id) else {
+      return vec![];
+    };
+    let cur_idx = msg.cur_idx();
+    // the messages follow an inactive version, and the messages follow the
+    // edited ones of them.
+    let mut inactive = HashSet::new();
+    for msg in self.msgs() {
+      let Some(branch) = msg.meta().branch() else {
+        continue;
+      };
+      if (&branch.msg_id == msg_id && branch.cont_idx != cur_idx)
+        || inactive.contains(&branch.msg_id)
+      {
+        inactive.insert(*msg.id());
+      }
+    }
+    let mut removed = self.remove_one(msg_id).into_iter().collect::<Vec<_>>();
+    let ids = self
+      .msgs()
+      .iter()
+      .filter(|msg| inactive.contains(msg.id()))
+      .map(|msg| *msg.id())
+      .collect::<Vec<_>>();
+    removed.extend(ids.iter().filter_map(|id| self.remove_one(id)));
+    removed
+  }
+
+  fn remove_one(&mut self, msg_id: &MsgId) -> Option<Msg> {
+    let pos = self.msgs().iter().position(|msg| msg.id() == msg_id)?;
+    let msg = self.msgs_coll.msgs.remove(pos);
+    if let Some(db) = self.db.as_mut() {
+      unsafe {
+        db.as_mut()
+          .persist_async(ActionPersist::RemoveMsg { msg_id: *msg_id })
+      }
+    }
+    Some(msg)
+  }
+
+  /// Move the question and the answers reply to it to the trash, `msg_id` is
+  /// the question or one of its answers. Return the removed messages.
+  pub fn remove_msg_group(&mut self, msg_id: &MsgId) -> Vec<Msg> {
+    let Some(msg) = self.msg(msg_id) else {
+      return vec![];
+    };
+    let question_id = *msg.meta().source_id().unwrap_or(msg_id);
+    let ids = self
+      .msgs()
+      .iter()
+      .filter(|msg| msg.id() == &question_id || msg.meta().source_id() == Some(&question_id))
+      .map(|msg| *msg.id())
+      .collect::<Vec<_>>();
+    ids.iter().flat_map(|id| self.remove_msg(id)).collect()
+  }
+
+  /// Take the removed messages out of the trash, they go back to their places
+  /// by the time they were created.
+  pub fn restore_msgs(&mut self, msgs: Vec<Msg>) {
+    for msg in msgs {
+      if self.msg(msg.id()).is_some() {
+        continue;
+      }
+      let msg_id = *msg.id();
+      let pos = self
+        .msgs()
+        .partition_point(|m| m.create_at() <= msg.create_at());
+      self.msgs_coll.msgs.insert(pos, msg);
+      if let Some(db) = self.db.as_mut() {
+        unsafe {
+          db.as_mut()
+            .persist_async(ActionPersist::RestoreMsg { msg_id })
+        }
+      }
+    }
+  }
+
+  fn persist_msg(&mut self, msg: Msg) {
+    if let Some(db) = self.db.as_mut() {
+      unsafe { db.as_mut().persist_async(ActionPersist::UpdateMsg { msg }) }
+    }
+  }
+
+  /// A new channel to go on the conversation from the message without
+  /// changing this one. It has the same config, and copies of the messages
+  /// until the message with new ids. Return `None` if the message is not in
+  /// the channel.
+  pub fn fork(&self, msg_id: &MsgId) -> Option<Channel> {
+    let pos = self.msgs().iter().position(|msg| msg.id() == msg_id)?;
+    let msgs = &self.msgs()[..=pos];
+    let ids = msgs
+      .iter()
+      .map(|msg| (*msg.id(), Uuid::new_v4()))
+      .collect::<HashMap<_, _>>();
+
+    let mut cfg = self.cfg.clone();
+    cfg.set_forked_from(Some(ChannelFork { channel_id: self.id, msg_id: *msg_id }));
+    let mut channel = Channel::new(
+      Uuid::new_v4(),
+      format!("{} (fork)", self.name),
+      self.desc.clone(),
+      cfg,
+      None,
+      None,
+    );
+    channel.load_msgs(msgs.iter().map(|msg| msg.copy_with_ids(&ids)).collect());
+    let summary = self.summary.as_ref().and_then(|summary| {
+      let until_msg_id = ids.get(summary.until_msg_id())?;
+      Some(ChannelSummary::new(
+        summary.content().to_owned(),
+        *until_msg_id,
+      ))
+    });
+    channel.load_summary(summary);
+    channel.load_arena_picks(
+      self
+        .arena_picks
+        .iter()
+        .filter_map(|(question_id, winner_id)| Some((*ids.get(question_id)?, *ids.get(winner_id)?)))
+        .collect(),
+    );
+    Some(channel)
+  }
+
   pub fn load_msgs(&mut self, msgs: Vec<Msg>) {
     self.msgs_coll.msgs.extend(msgs.clone());
     self.msgs_coll.status = MsgsCollStatus::Fetched;
@@ -155,6 +592,23 @@ impl Channel {
       .find(|msg| msg.id() == msg_id)
   }
 
+  #[inline]
+  pub fn summary(&self) -> Option<&ChannelSummary> { self.summary.as_ref() }
+
+  pub fn load_summary(&mut self, summary: Option<ChannelSummary>) { self.summary = summary; }
+
+  /// Replace the summary of the earlier messages, `None` to reset it.
+  pub fn set_summary(&mut self, summary: Option<ChannelSummary>) {
+    self.summary = summary.clone();
+    let channel_id = *self.id();
+    if let Some(db) = self.db.as_mut() {
+      unsafe {
+        db.as_mut()
+          .persist_async(ActionPersist::UpdateSummary { channel_id, summary })
+      }
+    }
+  }
+
   pub fn bots(&self) -> Option<&[Bot]> { self.app_info().map(|info| info.bots()) }
 
   pub fn is_feedback(&self) -> bool { self.cfg.kind == ChannelKind::Feedback }
@@ -173,17 +627,61 @@ impl Channel {
   }
 }
 
+/// The summary of a channel's earlier messages, sent instead of them when the
+/// channel's memory is on.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ChannelSummary {
+  content: String,
+  // the newest message the summary covers.
+  until_msg_id: MsgId,
+}
+
+impl ChannelSummary {
+  pub fn new(content: String, until_msg_id: MsgId) -> Self { Self { content, until_msg_id } }
+
+  #[inline]
+  pub fn content(&self) -> &str { &self.content }
+
+  #[inline]
+  pub fn until_msg_id(&self) -> &MsgId { &self.until_msg_id }
+}
+
 #[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct ChannelCfg {
   mode: ChannelMode,
   kind: ChannelKind,
   // if channel default bot id is none, it means this channel use global default bot id.
   def_bot_id: Option<BotId>,
+  // request params override the bot's `params` in this channel, i.e. `temperature`.
+  #[serde(default)]
+  params: Option<serde_json::Value>,
+  // summarise the messages out of the context budget, and send the summary
+  // instead of them.
+  #[serde(default)]
+  memory: bool,
+  // the channel and the message this channel is forked from.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  forked_from: Option<ChannelFork>,
+}
+
+/// Where a forked channel comes from, the messages until `msg_id` of the
+/// source channel are copied to it.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy)]
+pub struct ChannelFork {
+  pub channel_id: ChannelId,
+  pub msg_id: MsgId,
 }
 
 impl ChannelCfg {
   pub fn new(mode: ChannelMode, kind: ChannelKind, def_bot_id: Option<BotId>) -> Self {
-    Self { mode, kind, def_bot_id }
+    Self {
+      mode,
+      kind,
+      def_bot_id,
+      params: None,
+      memory: false,
+      forked_from: None,
+    }
   }
 
   pub fn feedback_cfg() -> Self {
@@ -211,6 +709,26 @@ impl ChannelCfg {
 
   #[inline]
   pub fn set_def_bot_id(&mut self, def_bot_id: Option<BotId>) { self.def_bot_id = def_bot_id; }
+
+  #[inline]
+  pub fn params(&self) -> Option<&serde_json::Value> { self.params.as_ref() }
+
+  #[inline]
+  pub fn set_params(&mut self, params: Option<serde_json::Value>) { self.params = params; }
+
+  #[inline]
+  pub fn memory(&self) -> bool { self.memory }
+
+  #[inline]
+  pub fn forked_from(&self) -> Option<&ChannelFork> { self.forked_from.as_ref() }
+
+  #[inline]
+  pub fn set_forked_from(&mut self, forked_from: Option<ChannelFork>) {
+    self.forked_from = forked_from;
+  }
+
+  #[inline]
+  pub fn set_memory(&mut self, memory: bool) { self.memory = memory; }
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Default)]
@@ -232,10 +750,168 @@ pub enum ChannelMode {
 }
 
 impl ChannelMode {
-  pub fn context_number(&self) -> usize {
+  /// How many tokens of the model's context `window` the mode use for a
+  /// request.
+  pub fn context_budget(&self, window: usize) -> usize {
     match self {
-      ChannelMode::Balanced => 6,
-      ChannelMode::Performance => 10,
+      ChannelMode::Balanced => window / 4,
+      ChannelMode::Performance => window,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::MsgMeta;
+
+  #[test]
+  fn remove_and_restore_msg_group() {
+    let mut channel = Channel::new(
+      Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      ChannelCfg::default(),
+      None,
+      None,
+    );
+    let question = Msg::new_user_text("question", MsgMeta::default());
+    let answer = Msg::new_bot_text("bot".to_owned(), MsgMeta::reply(*question.id()));
+    let other = Msg::new_bot_text("other".to_owned(), MsgMeta::reply(*question.id()));
+    let after = Msg::new_user_text("after", MsgMeta::default());
+    let (question_id, answer_id) = (*question.id(), *answer.id());
+    channel.add_msg(question);
+    channel.add_msg(answer);
+    channel.add_msg(other);
+    channel.add_msg(after);
+
+    let removed = channel.remove_msg_group(&answer_id);
+    assert_eq!(removed.len(), 3);
+    assert_eq!(channel.msgs().len(), 1);
+    assert!(channel.msg(&question_id).is_none());
+
+    channel.restore_msgs(removed);
+    let msgs = channel.msgs();
+    assert_eq!(msgs.len(), 4);
+    assert_eq!(msgs[0].id(), &question_id);
+    assert_eq!(msgs[1].id(), &answer_id);
+    assert_eq!(msgs[3].cur_cont_ref().text(), Some("after"));
+  }
+
+  #[test]
+  fn remove_edited_msg() {
+    let mut channel = Channel::new(
+      Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      ChannelCfg::default(),
+      None,
+      None,
+    );
+    let texts = |channel: &Channel| {
+      channel
+        .context_msgs()
+        .iter()
+        .filter_map(|m| m.cur_cont_ref().text().map(str::to_owned))
+        .collect::<Vec<_>>()
+    };
+    let question = Msg::new_user_text("q1", MsgMeta::default());
+    let question_id = *question.id();
+    channel.add_msg(question);
+    channel.add_msg(Msg::new_user_text("a1", MsgMeta::default()));
+    channel.add_msg(Msg::new_user_text("q2", MsgMeta::default()));
+    channel.edit_msg(&question_id, "q1 edited").unwrap();
+    channel.add_msg(Msg::new_user_text("a1 edited", MsgMeta::default()));
+    assert_eq!(texts(&channel), ["q1 edited", "a1 edited"]);
+
+    // the followers of the first version go to the trash with the question.
+    let removed = channel.remove_msg(&question_id);
+    assert_eq!(removed.len(), 3);
+    assert_eq!(texts(&channel), ["a1 edited"]);
+
+    channel.restore_msgs(removed);
+    assert_eq!(texts(&channel), ["q1 edited", "a1 edited"]);
+    channel.switch_cont(&question_id, 0);
+    assert_eq!(texts(&channel), ["q1", "a1", "q2"]);
+  }
+
+  #[test]
+  fn fork_until_msg() {
+    let mut cfg = ChannelCfg::default();
+    cfg.set_memory(true);
+    let mut channel = Channel::new(Uuid::new_v4(), "test".to_owned(), None, cfg, None, None);
+    let question = Msg::new_user_text("question", MsgMeta::default());
+    let answer = Msg::new_bot_text("bot".to_owned(), MsgMeta::reply(*question.id()));
+    let quote = Msg::new_user_text("more", MsgMeta::quote(*answer.id()));
+    let (answer_id, quote_id) = (*answer.id(), *quote.id());
+    channel.add_msg(question);
+    channel.add_msg(answer);
+    channel.add_msg(quote);
+    channel.add_msg(Msg::new_user_text("after", MsgMeta::default()));
+    channel.load_summary(Some(ChannelSummary::new("sum".to_owned(), answer_id)));
+
+    assert!(channel.fork(&Uuid::new_v4()).is_none());
+    let fork = channel.fork(&quote_id).unwrap();
+    assert_ne!(fork.id(), channel.id());
+    assert!(fork.cfg().memory());
+    assert_eq!(
+      fork.cfg().forked_from(),
+      Some(&ChannelFork {
+        channel_id: *channel.id(),
+        msg_id: quote_id
+      })
+    );
+
+    let msgs = fork.msgs();
+    assert_eq!(msgs.len(), 3);
+    assert!(msgs.iter().all(|msg| channel.msg(msg.id()).is_none()));
+    assert_eq!(msgs[1].meta().source_id(), Some(msgs[0].id()));
+    assert_eq!(msgs[2].meta().quote_id(), Some(msgs[1].id()));
+    assert_eq!(msgs[2].cur_cont_ref().text(), Some("more"));
+    assert_eq!(fork.summary().map(|s| s.until_msg_id()), Some(msgs[1].id()));
+    // the source channel is not changed.
+    assert_eq!(channel.msgs().len(), 4);
+  }
+
+  #[test]
+  fn pick_best_answer() {
+    let mut channel = Channel::new(
+      Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      ChannelCfg::default(),
+      None,
+      None,
+    );
+    let question = Msg::new_user_text("question", MsgMeta::default());
+    let a = Msg::new_bot_text("a".to_owned(), MsgMeta::reply(*question.id()));
+    let b = Msg::new_bot_text("b".to_owned(), MsgMeta::reply(*question.id()));
+    let after = Msg::new_user_text("after", MsgMeta::default());
+    let single = Msg::new_bot_text("a".to_owned(), MsgMeta::reply(*after.id()));
+    let (question_id, a_id, b_id) = (*question.id(), *a.id(), *b.id());
+    let (after_id, single_id) = (*after.id(), *single.id());
+    channel.add_msg(question);
+    channel.add_msg(a);
+    channel.add_msg(b);
+    channel.add_msg(after);
+    channel.add_msg(single);
+    assert_eq!(channel.answers(&question_id).len(), 2);
+    assert_eq!(channel.context_msgs().len(), 5);
+
+    channel.pick_best(&question_id, Some(b_id));
+    assert_eq!(channel.best_answer(&question_id), Some(&b_id));
+    let context = channel.context_msgs();
+    assert_eq!(context.len(), 4);
+    assert!(context.iter().all(|msg| msg.id() != &a_id));
+
+    // a question with one answer has nothing to compare.
+    channel.pick_best(&after_id, Some(single_id));
+    assert!(channel.best_answer(&after_id).is_none());
+
+    let fork = channel.fork(&single_id).unwrap();
+    assert_eq!(fork.context_msgs().len(), 4);
+
+    channel.pick_best(&question_id, None);
+    assert_eq!(channel.context_msgs().len(), 5);
+  }
+}
@@ -1,9 +1,11 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use uuid::Uuid;
 
-use super::BotId;
+use super::{BotId, MsgRating, MsgUsage, PipelineLink, ToolCall};
 
 pub type MsgId = Uuid;
 
@@ -54,6 +56,33 @@ impl Msg {
 
   #[inline]
   pub fn create_at(&self) -> &DateTime<Utc> { &self.created_at }
+
+  pub(crate) fn meta_mut(&mut self) -> &mut MsgMeta { &mut self.meta }
+
+  pub(crate) fn set_create_at(&mut self, created_at: DateTime<Utc>) {
+    self.created_at = created_at;
+  }
+
+  /// A copy of the message to add to another channel. Its id and the ids of
+  /// the messages it refers to are mapped by `ids`, the ones not in `ids` are
+  /// kept.
+  pub(crate) fn copy_with_ids(&self, ids: &HashMap<MsgId, MsgId>) -> Msg {
+    let map = |id: &MsgId| ids.get(id).copied().unwrap_or(*id);
+    let mut meta = MsgMeta::new(
+      self.meta.quote_id().map(map),
+      self.meta.source_id().map(map),
+    );
+    meta.set_branch(self.meta.branch().map(|branch| MsgBranch {
+      msg_id: map(&branch.msg_id),
+      ..*branch
+    }));
+    meta.pipeline = self.meta.pipeline.clone();
+    Msg {
+      id: map(&self.id),
+      meta,
+      ..self.clone()
+    }
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
@@ -62,16 +91,45 @@ pub struct MsgMeta {
   quote_id: Option<MsgId>,
   // `reply_id` which message is the reply message.
   reply_id: Option<MsgId>,
+  // `branch` which version of an edited message this message follows.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  branch: Option<MsgBranch>,
+  // `pipeline` which step of the pipeline this message is the output of, it
+  // replies to the output of the previous step.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pipeline: Option<PipelineLink>,
+}
+
+/// A version of an edited message, the messages follow it are only in the
+/// active branch when it's the current content of the edited message.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub struct MsgBranch {
+  pub msg_id: MsgId,
+  pub cont_idx: usize,
 }
 
 impl MsgMeta {
   pub fn new(quote_id: Option<MsgId>, reply_id: Option<MsgId>) -> Self {
-    Self { quote_id, reply_id }
+    Self {
+      quote_id,
+      reply_id,
+      branch: None,
+      pipeline: None,
+    }
   }
 
-  pub fn quote(id: MsgId) -> Self { Self { quote_id: Some(id), reply_id: None } }
+  pub fn quote(id: MsgId) -> Self { Self::new(Some(id), None) }
+
+  pub fn reply(id: MsgId) -> Self { Self::new(None, Some(id)) }
 
-  pub fn reply(id: MsgId) -> Self { Self { quote_id: None, reply_id: Some(id) } }
+  /// The output of the pipeline step, replies to `input_id` that its input is
+  /// built from.
+  pub fn pipeline_step(input_id: MsgId, link: PipelineLink) -> Self {
+    Self {
+      pipeline: Some(link),
+      ..Self::reply(input_id)
+    }
+  }
 }
 
 impl MsgMeta {
@@ -80,6 +138,14 @@ impl MsgMeta {
 
   #[inline]
   pub fn source_id(&self) -> Option<&MsgId> { self.reply_id.as_ref() }
+
+  #[inline]
+  pub fn branch(&self) -> Option<&MsgBranch> { self.branch.as_ref() }
+
+  #[inline]
+  pub fn pipeline(&self) -> Option<&PipelineLink> { self.pipeline.as_ref() }
+
+  pub(crate) fn set_branch(&mut self, branch: Option<MsgBranch>) { self.branch = branch; }
 }
 
 impl Msg {
@@ -127,6 +193,25 @@ impl Msg {
     }
   }
 
+  /// The user's text with the images attached to it.
+  pub fn new_user_images(text: &str, images: Vec<Image>, meta: MsgMeta) -> Self {
+    let mut msg = Self::new_user_text(text, meta);
+    msg.cont_list[0].images = images;
+    msg
+  }
+
+  /// The message of the tool call with its result.
+  pub fn new_tool(call: ToolCall, result: &str, meta: MsgMeta) -> Self {
+    Self {
+      id: Uuid::new_v4(),
+      role: MsgRole::Tool(call),
+      cur_idx: 0,
+      cont_list: vec![MsgCont::new_text(result)],
+      meta,
+      created_at: Utc::now(),
+    }
+  }
+
   pub fn new_system_text(text: &str) -> Self {
     let cont_list = vec![MsgCont::new_text(text)];
     Self {
@@ -191,6 +276,10 @@ impl Msg {
   #[inline]
   pub fn cont_count(&self) -> usize { self.cont_list.len() }
 
+  /// The bot answered the current content, the message's bot if it's not
+  /// regenerated by another one.
+  pub fn cur_bot(&self) -> Option<&BotId> { self.cur_cont_ref().bot().or(self.role.bot()) }
+
   pub fn cont_size(&self) -> usize {
     self
       .cont_list
@@ -206,6 +295,47 @@ pub struct Image {
   path: ImagePath,
 }
 
+impl Image {
+  /// The image on the web, its size is unknown if it's `0`.
+  pub fn new_url(url: String, width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      path: ImagePath::Url(url),
+    }
+  }
+
+  /// The image stored in the database as the attachment `name`.
+  pub fn new_file(name: Uuid, width: u32, height: u32) -> Self {
+    Self {
+      width,
+      height,
+      path: ImagePath::File(name),
+    }
+  }
+
+  pub fn width(&self) -> u32 { self.width }
+
+  pub fn height(&self) -> u32 { self.height }
+
+  /// The attachment name of the image if it's stored in the database.
+  pub fn file(&self) -> Option<&Uuid> {
+    match &self.path {
+      ImagePath::File(name) => Some(name),
+      _ => None,
+    }
+  }
+
+  /// The url or the static file name of the image if it's not stored in the
+  /// database.
+  pub fn url(&self) -> Option<&str> {
+    match &self.path {
+      ImagePath::Url(url) | ImagePath::Static(url) => Some(url),
+      ImagePath::File(_) => None,
+    }
+  }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 enum ImagePath {
   #[serde(rename = "url")]
@@ -266,6 +396,19 @@ impl MsgBody {
 pub struct MsgCont {
   body: MsgBody,
   status: MsgStatus,
+  // `bot` which bot answered the content, if it's regenerated by another bot
+  // than the message's.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  bot: Option<BotId>,
+  // the user's rating of the content, it's saved in its own table.
+  #[serde(skip)]
+  rating: Option<MsgRating>,
+  // the tokens and time the answer cost.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  usage: Option<MsgUsage>,
+  // the images the user attached to the text.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  images: Vec<Image>,
 }
 
 #[derive(PartialEq, Eq)]
@@ -274,6 +417,8 @@ pub enum MsgAction {
   Fulfilled,
   Receiving(MsgBody),
   Rejected,
+  Interrupted,
+  Retrying(u32),
 }
 
 impl MsgCont {
@@ -285,11 +430,19 @@ impl MsgCont {
     "[polestar] message content action error: rejected action must be pending status";
   const RECEIVING_WARN: &'static str =
     "[polestar] message content action error: receiving action must be pending status";
+  const RETRYING_WARN: &'static str =
+    "[polestar] message content action error: retrying action must be pending or retrying status";
+  const INTERRUPTED_WARN: &'static str = "[polestar] message content action error: interrupted \
+                                          action must be pending or receiving status";
 
   pub fn init_text() -> Self {
     Self {
       body: MsgBody::Text(None),
       status: MsgStatus::Pending,
+      bot: None,
+      rating: None,
+      usage: None,
+      images: vec![],
     }
   }
 
@@ -297,6 +450,10 @@ impl MsgCont {
     Self {
       body: MsgBody::Text(Some(text.to_owned())),
       status: MsgStatus::Fulfilled,
+      bot: None,
+      rating: None,
+      usage: None,
+      images: vec![],
     }
   }
 
@@ -304,6 +461,10 @@ impl MsgCont {
     Self {
       body: MsgBody::Image(None),
       status: MsgStatus::Pending,
+      bot: None,
+      rating: None,
+      usage: None,
+      images: vec![],
     }
   }
 
@@ -311,6 +472,10 @@ impl MsgCont {
     Self {
       body: MsgBody::Image(Some(img)),
       status: MsgStatus::Fulfilled,
+      bot: None,
+      rating: None,
+      usage: None,
+      images: vec![],
     }
   }
 
@@ -330,26 +495,52 @@ impl MsgCont {
         self.status = MsgStatus::Fulfilled;
       }
       MsgAction::Receiving(body) => {
-        if self.status != MsgStatus::Pending {
+        if !matches!(self.status, MsgStatus::Pending | MsgStatus::Retrying(_)) {
           log::warn!("{}", Self::RECEIVING_WARN);
         }
-        if let MsgBody::Text(str) = body {
-          self.body.receive_text(str)
+        match body {
+          MsgBody::Text(str) => self.body.receive_text(str),
+          img @ MsgBody::Image(Some(_)) => self.body = img,
+          MsgBody::Image(None) => {}
         }
         self.status = MsgStatus::Receiving;
       }
       MsgAction::Rejected => {
-        if self.status != MsgStatus::Pending {
+        if !matches!(self.status, MsgStatus::Pending | MsgStatus::Retrying(_)) {
           log::warn!("{}", Self::REJECTED_WARN);
         }
         self.body.set_none();
         self.status = MsgStatus::Rejected;
       }
+      MsgAction::Retrying(attempt) => {
+        if !matches!(self.status, MsgStatus::Pending | MsgStatus::Retrying(_)) {
+          log::warn!("{}", Self::RETRYING_WARN);
+        }
+        self.status = MsgStatus::Retrying(attempt);
+      }
+      MsgAction::Interrupted => {
+        if !matches!(
+          self.status,
+          MsgStatus::Pending | MsgStatus::Receiving | MsgStatus::Retrying(_)
+        ) {
+          log::warn!("{}", Self::INTERRUPTED_WARN);
+        }
+        self.status = MsgStatus::Interrupted;
+      }
     }
   }
 
+  /// Attach the images to the text of the content.
+  pub fn with_images(mut self, images: Vec<Image>) -> Self {
+    self.images = images;
+    self
+  }
+
   pub fn body(&self) -> &MsgBody { &self.body }
 
+  /// The images attached to the text.
+  pub fn images(&self) -> &[Image] { &self.images }
+
   pub fn text(&self) -> Option<&str> {
     match &self.body {
       MsgBody::Text(s) => s.as_deref(),
@@ -372,6 +563,21 @@ impl MsgCont {
   }
 
   pub fn status(&self) -> &MsgStatus { &self.status }
+
+  #[inline]
+  pub fn bot(&self) -> Option<&BotId> { self.bot.as_ref() }
+
+  pub(crate) fn set_bot(&mut self, bot: Option<BotId>) { self.bot = bot; }
+
+  #[inline]
+  pub fn rating(&self) -> Option<&MsgRating> { self.rating.as_ref() }
+
+  pub(crate) fn set_rating(&mut self, rating: Option<MsgRating>) { self.rating = rating; }
+
+  #[inline]
+  pub fn usage(&self) -> Option<&MsgUsage> { self.usage.as_ref() }
+
+  pub(crate) fn set_usage(&mut self, usage: Option<MsgUsage>) { self.usage = usage; }
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
@@ -389,6 +595,12 @@ pub enum MsgStatus {
   // status
   #[serde(rename = "fulfilled")]
   Fulfilled,
+  // Message is stopped by user before finished, it keeps the partial response.
+  #[serde(rename = "interrupted")]
+  Interrupted,
+  // Request failed and is retrying, with the number of the current attempt.
+  #[serde(rename = "retrying")]
+  Retrying(u32),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
@@ -400,6 +612,9 @@ pub enum MsgRole {
   Bot(BotId),
   #[serde(rename = "system")]
   System(u64),
+  // the call of a tool the bot asks for, the result is the text of the message.
+  #[serde(rename = "tool")]
+  Tool(ToolCall),
 }
 
 impl MsgRole {
@@ -409,6 +624,8 @@ impl MsgRole {
 
   pub fn is_user(&self) -> bool { matches!(self, Self::User) }
 
+  pub fn is_tool(&self) -> bool { matches!(self, Self::Tool(_)) }
+
   pub fn bot(&self) -> Option<&BotId> {
     match self {
       Self::Bot(id) => Some(id),
@@ -422,6 +639,13 @@ impl MsgRole {
       _ => None,
     }
   }
+
+  pub fn tool(&self) -> Option<&ToolCall> {
+    match self {
+      Self::Tool(call) => Some(call),
+      _ => None,
+    }
+  }
 }
 
 #[cfg(test)]
@@ -526,4 +750,44 @@ mod test {
       assert_eq!(captured_logs[3].level, Level::Warn);
     });
   }
+
+  #[test]
+  fn msg_cont_retrying() {
+    testing_logger::setup();
+
+    let mut cont = MsgCont::init_text();
+    cont.action(MsgAction::Retrying(2));
+    assert_eq!(cont.status(), &MsgStatus::Retrying(2));
+    cont.action(MsgAction::Retrying(3));
+    cont.action(MsgAction::Receiving(MsgBody::Text(Some("ok".to_owned()))));
+    assert_eq!(cont.status(), &MsgStatus::Receiving);
+
+    testing_logger::validate(|captured_logs| {
+      assert_eq!(captured_logs.len(), 0);
+    });
+  }
+
+  #[test]
+  fn msg_cont_interrupted() {
+    testing_logger::setup();
+
+    let mut cont = MsgCont::init_text();
+    cont.action(MsgAction::Receiving(MsgBody::Text(Some(
+      "partial".to_owned(),
+    ))));
+    cont.action(MsgAction::Interrupted);
+    assert_eq!(cont.status(), &MsgStatus::Interrupted);
+    assert_eq!(cont.text(), Some("partial"));
+
+    let mut cont = MsgCont::init_text();
+    cont.action(MsgAction::Receiving(MsgBody::Text(Some("done".to_owned()))));
+    cont.action(MsgAction::Fulfilled);
+    cont.action(MsgAction::Interrupted);
+
+    testing_logger::validate(|captured_logs| {
+      assert_eq!(captured_logs.len(), 1);
+      assert_eq!(captured_logs[0].body, MsgCont::INTERRUPTED_WARN);
+      assert_eq!(captured_logs[0].level, Level::Warn);
+    });
+  }
 }
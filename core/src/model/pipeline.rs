@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Bot, BotId};
+
+/// The placeholder in the step's input for the output of the previous step,
+/// or the question for the first step.
+const OUTPUT_VAR: &str = "${output}";
+/// The placeholder in the step's input for the question sent to the pipeline.
+const QUESTION_VAR: &str = "${question}";
+
+/// A step of the pipeline bot, its bot answers the input built from the
+/// output of the previous step.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PipelineStep {
+  pub bot: BotId,
+  // the template of the input sent to the bot, the previous step's output as
+  // it is if not set.
+  #[serde(default)]
+  pub input: Option<String>,
+}
+
+impl PipelineStep {
+  /// The input of the step, with the placeholders in the template replaced.
+  /// The template is scanned once, so a placeholder in the question or the
+  /// output is kept as it is.
+  pub fn input(&self, question: &str, output: &str) -> String {
+    let Some(template) = &self.input else {
+      return output.to_owned();
+    };
+    let mut input = String::with_capacity(template.len());
+    let mut rest = template.as_str();
+    while let Some(pos) = rest.find("${") {
+      input.push_str(&rest[..pos]);
+      rest = &rest[pos..];
+      if let Some(tail) = rest.strip_prefix(OUTPUT_VAR) {
+        input.push_str(output);
+        rest = tail;
+      } else if let Some(tail) = rest.strip_prefix(QUESTION_VAR) {
+        input.push_str(question);
+        rest = tail;
+      } else {
+        input.push_str("${");
+        rest = &rest[2..];
+      }
+    }
+    input.push_str(rest);
+    input
+  }
+}
+
+/// Which pipeline and which step of it the message is the output of.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PipelineLink {
+  pub bot_id: BotId,
+  pub step: usize,
+}
+
+impl Bot {
+  /// The steps of the bot if it's a pipeline, which are defined in its
+  /// `pipeline` param.
+  pub fn pipeline(&self) -> Option<Vec<PipelineStep>> {
+    let steps = self.params().get("pipeline")?;
+    serde_json::from_value::<Vec<PipelineStep>>(steps.clone())
+      .map_err(|e| log::warn!("invalid pipeline of bot {}: {}", self.id(), e))
+      .ok()
+      .filter(|steps| !steps.is_empty())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::service::tests::mock::test_bot;
+
+  #[test]
+  fn pipeline_steps() {
+    let bot = test_bot(json!({
+      "pipeline": [
+        { "bot": "draft" },
+        { "bot": "polish", "input": "Polish the answer to `${question}`:\n${output}" }
+      ]
+    }));
+    let steps = bot.pipeline().unwrap();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[0].input("q", "q"), "q");
+    assert_eq!(steps[1].input("q", "a"), "Polish the answer to `q`:\na");
+    assert_eq!(
+      steps[1].input("${output}", "${question} ${x}"),
+      "Polish the answer to `${output}`:\n${question} ${x}"
+    );
+  }
+}
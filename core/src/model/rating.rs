@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+/// Whether the answer is good or bad, to curate the good answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rating {
+  #[serde(rename = "up")]
+  Up,
+  #[serde(rename = "down")]
+  Down,
+}
+
+impl Rating {
+  /// The score saved in the database, `1` for up and `-1` for down.
+  pub fn score(&self) -> i32 {
+    match self {
+      Rating::Up => 1,
+      Rating::Down => -1,
+    }
+  }
+
+  pub fn from_score(score: i32) -> Self {
+    if score > 0 {
+      Rating::Up
+    } else {
+      Rating::Down
+    }
+  }
+}
+
+/// The user's rating of a content of the bot's message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MsgRating {
+  pub rating: Rating,
+  pub comment: Option<String>,
+}
+
+impl MsgRating {
+  pub fn new(rating: Rating, comment: Option<String>) -> Self { Self { rating, comment } }
+}
@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+
+use super::{BotId, ChannelId, MsgId, MsgRole};
+
+/// Limit the messages `search_msgs` return, no filter by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MsgSearchFilter {
+  pub channel_id: Option<ChannelId>,
+  pub role: Option<SearchRole>,
+  // only the messages created at or after it.
+  pub since: Option<DateTime<Utc>>,
+  // only the messages created before it.
+  pub until: Option<DateTime<Utc>>,
+  pub limit: u32,
+}
+
+impl Default for MsgSearchFilter {
+  fn default() -> Self {
+    Self {
+      channel_id: None,
+      role: None,
+      since: None,
+      until: None,
+      limit: 50,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchRole {
+  User,
+  // the messages of the bot, or of all bots if `None`.
+  Bot(Option<BotId>),
+  System,
+}
+
+/// A message matched by `search_msgs`, with a snippet of its text around the
+/// matched words.
+#[derive(Debug, Clone)]
+pub struct MsgSearchHit {
+  pub msg_id: MsgId,
+  pub channel_id: ChannelId,
+  pub role: MsgRole,
+  pub snippet: String,
+  pub created_at: DateTime<Utc>,
+  // the lower the better.
+  pub rank: f64,
+}
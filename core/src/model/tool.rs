@@ -0,0 +1,223 @@
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::error::PolestarResult;
+
+use super::Bot;
+
+/// A call of the tool the bot asks for, the tool message keeps it with the
+/// result as its text.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, Default)]
+pub struct ToolCall {
+  pub id: String,
+  pub name: String,
+  // the arguments in JSON, as the bot generated them.
+  pub arguments: String,
+}
+
+/// A local function the bot can call to get the information it doesn't know or
+/// do something, i.e. search the web or read a file.
+pub trait Tool: Send + Sync {
+  /// The unique name the bot calls the tool by.
+  fn name(&self) -> &str;
+
+  /// What the tool does, for the bot to decide when to call it.
+  fn desc(&self) -> &str;
+
+  /// The JSON schema of the arguments.
+  fn schema(&self) -> JsonValue;
+
+  /// Run the tool with the arguments, the result is sent back to the bot.
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>>;
+
+  /// The question to ask the user before running the call, `None` if the call
+  /// runs without asking.
+  fn confirm(&self, _args: &JsonValue) -> Option<String> { None }
+}
+
+/// The tools the bots can call, by their names.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+  tools: BTreeMap<String, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+  /// Add the tool, it replaces the tool of the same name.
+  pub fn register(&mut self, tool: impl Tool + 'static) {
+    self.tools.insert(tool.name().to_owned(), Arc::new(tool));
+  }
+
+  pub fn get(&self, name: &str) -> Option<&dyn Tool> { self.tools.get(name).map(|tool| &**tool) }
+
+  pub fn tools(&self) -> impl Iterator<Item = &dyn Tool> { self.tools.values().map(|tool| &**tool) }
+
+  #[inline]
+  pub fn is_empty(&self) -> bool { self.tools.is_empty() }
+
+  /// The tools the bot can call, which are enabled by the `use_tools` param of
+  /// the bot: `true` for all the tools, or a list of the tool names.
+  pub fn bot_tools(&self, bot: &Bot) -> Vec<&dyn Tool> {
+    self
+      .tools()
+      .filter(|tool| is_enabled(bot, tool.name()))
+      .collect()
+  }
+
+  /// The registry of only the tools the bot can call, the calls of the bot
+  /// should be run by it, so the calls of the other tools are refused.
+  pub fn for_bot(&self, bot: &Bot) -> ToolRegistry {
+    let tools = self
+      .tools
+      .iter()
+      .filter(|(name, _)| is_enabled(bot, name))
+      .map(|(name, tool)| (name.clone(), tool.clone()))
+      .collect();
+    ToolRegistry { tools }
+  }
+
+  /// The question to ask the user before running the call, see
+  /// `Tool::confirm`.
+  pub fn confirm(&self, call: &ToolCall) -> Option<String> {
+    let tool = self.get(&call.name)?;
+    tool.confirm(&call_args(call).ok()?)
+  }
+
+  /// Run the call, the error is returned as the result to tell the bot.
+  pub async fn invoke(&self, call: &ToolCall) -> String {
+    let Some(tool) = self.get(&call.name) else {
+      return format!("Error: tool `{}` not found", call.name);
+    };
+    let res = match call_args(call) {
+      Ok(args) => tool.invoke(args).await,
+      Err(e) => Err(e.into()),
+    };
+    res.unwrap_or_else(|e| format!("Error: {}", e))
+  }
+}
+
+fn is_enabled(bot: &Bot, name: &str) -> bool {
+  match bot.params().get("use_tools") {
+    Some(JsonValue::Bool(true)) => true,
+    Some(JsonValue::Array(names)) => names.iter().any(|n| n.as_str() == Some(name)),
+    _ => false,
+  }
+}
+
+fn call_args(call: &ToolCall) -> serde_json::Result<JsonValue> {
+  if call.arguments.trim().is_empty() {
+    Ok(JsonValue::Object(<_>::default()))
+  } else {
+    serde_json::from_str(&call.arguments)
+  }
+}
+
+impl fmt::Debug for ToolRegistry {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_list().entries(self.tools.keys()).finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicBool, Ordering};
+
+  use futures_util::FutureExt;
+  use serde_json::json;
+
+  use super::*;
+  use crate::service::tests::mock::test_bot;
+
+  struct Echo;
+
+  impl Tool for Echo {
+    fn name(&self) -> &str { "echo" }
+
+    fn desc(&self) -> &str { "Echo the text." }
+
+    fn schema(&self) -> JsonValue {
+      json!({ "type": "object", "properties": { "text": { "type": "string" } } })
+    }
+
+    fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+      async move { Ok(args["text"].as_str().unwrap_or_default().to_owned()) }.boxed()
+    }
+  }
+
+  // the tool records whether it's run.
+  struct Spy(Arc<AtomicBool>);
+
+  impl Tool for Spy {
+    fn name(&self) -> &str { "spy" }
+
+    fn desc(&self) -> &str { "Record the call." }
+
+    fn schema(&self) -> JsonValue { json!({ "type": "object" }) }
+
+    fn invoke(&self, _: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+      self.0.store(true, Ordering::SeqCst);
+      async { Ok("run".to_owned()) }.boxed()
+    }
+  }
+
+  #[tokio::test]
+  async fn refuse_tool_not_enabled() {
+    let invoked = Arc::new(AtomicBool::new(false));
+    let mut tools = ToolRegistry::default();
+    tools.register(Echo);
+    tools.register(Spy(invoked.clone()));
+    let call = ToolCall {
+      id: "call_1".to_owned(),
+      name: "spy".to_owned(),
+      arguments: "{}".to_owned(),
+    };
+
+    let bot = test_bot(json!({ "use_tools": ["echo"] }));
+    let enabled = tools.for_bot(&bot);
+    assert_eq!(enabled.tools().count(), 1);
+    assert_eq!(enabled.invoke(&call).await, "Error: tool `spy` not found");
+    assert!(!invoked.load(Ordering::SeqCst));
+
+    let bot = test_bot(json!({ "use_tools": true }));
+    assert_eq!(tools.for_bot(&bot).invoke(&call).await, "run");
+    assert!(invoked.load(Ordering::SeqCst));
+  }
+
+  #[tokio::test]
+  async fn invoke_tool() {
+    let mut tools = ToolRegistry::default();
+    tools.register(Echo);
+    let call = |name: &str, arguments: &str| ToolCall {
+      id: "call_1".to_owned(),
+      name: name.to_owned(),
+      arguments: arguments.to_owned(),
+    };
+    assert_eq!(tools.invoke(&call("echo", r#"{"text":"hi"}"#)).await, "hi");
+    assert_eq!(
+      tools.invoke(&call("search", "{}")).await,
+      "Error: tool `search` not found"
+    );
+    assert!(tools
+      .invoke(&call("echo", "{"))
+      .await
+      .starts_with("Error: "));
+
+    assert_eq!(
+      tools
+        .bot_tools(&test_bot(json!({ "use_tools": true })))
+        .len(),
+      1
+    );
+    assert_eq!(
+      tools
+        .bot_tools(&test_bot(json!({ "use_tools": ["echo", "search"] })))
+        .len(),
+      1
+    );
+    assert!(tools
+      .bot_tools(&test_bot(json!({ "use_tools": false })))
+      .is_empty());
+  }
+}
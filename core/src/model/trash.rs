@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{ChannelId, MsgId};
+
+/// How many days the deleted channels and messages are kept in the trash if
+/// the user config doesn't set `trash_days`.
+pub const DEF_TRASH_DAYS: u32 = 30;
+
+/// A deleted channel, or a message deleted from a channel that is not
+/// deleted.
+#[derive(Debug, Clone)]
+pub struct TrashItem {
+  // the deleted message, `None` if it's the channel deleted.
+  pub msg_id: Option<MsgId>,
+  pub channel_id: ChannelId,
+  // the name of the channel, or the text of the message.
+  pub title: String,
+  pub deleted_at: DateTime<Utc>,
+}
+
+impl TrashItem {
+  /// The id to restore or purge the item, the message's id or the channel's.
+  pub fn id(&self) -> &Uuid { self.msg_id.as_ref().unwrap_or(&self.channel_id) }
+}
@@ -0,0 +1,92 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::{BotId, ChannelId};
+
+/// The tokens the provider reported for a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+  pub prompt_tokens: u32,
+  pub completion_tokens: u32,
+}
+
+impl TokenUsage {
+  /// Merge the usage reported in pieces, like Anthropic report the prompt
+  /// tokens at the start and the completion tokens at the end. The counts are
+  /// cumulative, so the larger one wins.
+  pub fn merge(&mut self, other: TokenUsage) {
+    self.prompt_tokens = self.prompt_tokens.max(other.prompt_tokens);
+    self.completion_tokens = self.completion_tokens.max(other.completion_tokens);
+  }
+}
+
+/// The cost of a bot's answer, it's saved with the content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MsgUsage {
+  pub prompt_tokens: u32,
+  pub completion_tokens: u32,
+  // the tokens are estimated locally, because the provider didn't report them.
+  #[serde(default)]
+  pub estimated: bool,
+  pub model: String,
+  // the name of the service provider.
+  pub provider: String,
+  // the time from the request sent to the answer finished.
+  pub latency_ms: u64,
+  // the time from the request sent to the first piece of the answer.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub first_token_ms: Option<u64>,
+  // the money the answer cost, if the model's price is known.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cost: Option<f64>,
+}
+
+impl MsgUsage {
+  pub fn total_tokens(&self) -> u32 { self.prompt_tokens + self.completion_tokens }
+}
+
+/// How the usage statistics are grouped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGroup {
+  Day,
+  Bot,
+  Provider,
+  Channel,
+}
+
+/// The usage of the answers in a group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageStat {
+  pub key: UsageKey,
+  pub answers: u32,
+  pub prompt_tokens: u64,
+  pub completion_tokens: u64,
+  // how many of the answers have estimated tokens.
+  pub estimated: u32,
+  pub avg_latency_ms: u64,
+  // the cost of the answers with known prices.
+  pub cost: f64,
+}
+
+impl UsageStat {
+  pub fn total_tokens(&self) -> u64 { self.prompt_tokens + self.completion_tokens }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UsageKey {
+  Day(NaiveDate),
+  Bot(BotId),
+  Provider(String),
+  Channel(ChannelId),
+}
+
+impl std::fmt::Display for UsageKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      UsageKey::Day(day) => write!(f, "{}", day),
+      UsageKey::Bot(bot) => write!(f, "{}", bot),
+      UsageKey::Provider(provider) => write!(f, "{}", provider),
+      UsageKey::Channel(channel) => write!(f, "{}", channel),
+    }
+  }
+}
@@ -1,2 +1,16 @@
+pub mod anthropic;
+pub mod client;
+pub mod dataset;
+pub mod export;
+pub mod image;
+pub mod import;
+pub mod mcp;
+pub mod ollama;
 pub mod open_ai;
+pub mod pipeline;
+pub mod provider;
 pub mod req;
+#[cfg(test)]
+pub(crate) mod tests;
+pub mod token;
+pub mod tool;
@@ -0,0 +1,179 @@
+use eventsource_stream::Event;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+  error::{PolestarError, PolestarServerErrType, PolestarServerError},
+  model::{Bot, Channel, TokenUsage},
+};
+
+use super::{
+  open_ai::{ContentPart, MessageContent, Role},
+  provider::{status_error, ChatDelta, ChatProvider},
+  req::{request_messages, request_params, request_system_messages},
+};
+
+/// Anthropic require `max_tokens`, use it when the bot not specify.
+const DEFAULT_MAX_TOKENS: u64 = 1024;
+
+#[derive(Debug, Deserialize)]
+struct AnthropicErrorResp {
+  error: AnthropicError,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicError {
+  #[serde(rename = "type")]
+  kind: String,
+  message: String,
+}
+
+impl AnthropicError {
+  fn into_server_error(self, status: StatusCode) -> PolestarServerError {
+    let kind = match self.kind.as_str() {
+      "authentication_error" | "permission_error" => PolestarServerErrType::UnAuthed,
+      "invalid_request_error" => PolestarServerErrType::InvalidContent,
+      "not_found_error" => PolestarServerErrType::NotFound,
+      "rate_limit_error" => PolestarServerErrType::OverQuota,
+      "overloaded_error" => PolestarServerErrType::NetWork,
+      "api_error" => PolestarServerErrType::ServerError,
+      _ => PolestarServerErrType::from_status(status),
+    };
+    PolestarServerError {
+      kind,
+      message: self.message,
+      retry_after: None,
+    }
+  }
+}
+
+/// The Anthropic content block of the part, the image of the data url is sent
+/// as the base64 source. Anthropic reject the empty text block.
+fn content_block(part: ContentPart) -> Option<JsonValue> {
+  match part {
+    ContentPart::Text { text } if text.is_empty() => None,
+    ContentPart::Text { text } => Some(json!({ "type": "text", "text": text })),
+    ContentPart::ImageUrl { image_url } => {
+      let source = match image_url
+        .url
+        .strip_prefix("data:")
+        .and_then(|url| url.split_once(";base64,"))
+      {
+        Some((media_type, data)) => {
+          json!({ "type": "base64", "media_type": media_type, "data": data })
+        }
+        None => json!({ "type": "url", "url": image_url.url }),
+      };
+      Some(json!({ "type": "image", "source": source }))
+    }
+  }
+}
+
+/// The Anthropic Messages API.
+#[derive(Debug)]
+pub struct AnthropicProvider;
+
+impl ChatProvider for AnthropicProvider {
+  fn request_body(&self, bot: &Bot, channel: &Channel, content: &str) -> String {
+    let messages = request_messages(bot, channel, content)
+      .into_iter()
+      .filter_map(|msg| {
+        let role = match msg.role? {
+          Role::User => "user",
+          Role::Assistant => "assistant",
+          // Anthropic only accept the system prompt as a top level param.
+          Role::System | Role::Function | Role::Tool => return None,
+        };
+        let content = match msg.content? {
+          MessageContent::Text(text) if text.is_empty() => return None,
+          MessageContent::Text(text) => json!(text),
+          MessageContent::Parts(parts) => parts
+            .into_iter()
+            .filter_map(content_block)
+            .collect::<JsonValue>(),
+        };
+        Some(json!({ "role": role, "content": content }))
+      })
+      .collect::<Vec<_>>();
+
+    let mut params = request_params(bot, channel);
+    // OpenAI params Anthropic don't support.
+    for key in [
+      "n",
+      "presence_penalty",
+      "frequency_penalty",
+      "logit_bias",
+      "response_format",
+      "seed",
+      "user",
+    ] {
+      params.remove(key);
+    }
+    if let Some(stop) = params.remove("stop") {
+      let stop = match stop {
+        JsonValue::String(s) => json!([s]),
+        stop => stop,
+      };
+      params.insert("stop_sequences".to_owned(), stop);
+    }
+    params
+      .entry("max_tokens")
+      .or_insert_with(|| json!(DEFAULT_MAX_TOKENS));
+    let system = request_system_messages(bot, channel)
+      .into_iter()
+      .filter_map(|msg| msg.content.map(MessageContent::into_text))
+      .collect::<Vec<_>>();
+    if !system.is_empty() {
+      params.insert("system".to_owned(), json!(system.join("\n\n")));
+    }
+    params.insert("messages".to_owned(), json!(messages));
+    params.insert("stream".to_owned(), json!(true));
+
+    serde_json::to_string(&params).unwrap_or_default()
+  }
+
+  fn decode(&self, event: &Event) -> Result<ChatDelta, PolestarError> {
+    let data = serde_json::from_str::<JsonValue>(&event.data)?;
+    let kind = data["type"].as_str().unwrap_or(event.event.as_str());
+    match kind {
+      "content_block_delta" => Ok(
+        data["delta"]["text"]
+          .as_str()
+          .map_or(ChatDelta::Ignore, |text| {
+            ChatDelta::Content(text.to_owned())
+          }),
+      ),
+      // the prompt tokens are reported at the start, the completion tokens at
+      // the end.
+      "message_start" => Ok(anthropic_usage(&data["message"]["usage"])),
+      "message_delta" => Ok(anthropic_usage(&data["usage"])),
+      "message_stop" => Ok(ChatDelta::Done),
+      "error" => {
+        let AnthropicErrorResp { error } = serde_json::from_value(data)?;
+        Err(PolestarError::PolestarServerError(
+          error.into_server_error(StatusCode::INTERNAL_SERVER_ERROR),
+        ))
+      }
+      _ => Ok(ChatDelta::Ignore),
+    }
+  }
+
+  fn map_error(&self, status: StatusCode, body: &str) -> PolestarServerError {
+    match serde_json::from_str::<AnthropicErrorResp>(body) {
+      Ok(AnthropicErrorResp { error }) => error.into_server_error(status),
+      Err(_) => status_error(status, Some(body.to_owned())),
+    }
+  }
+}
+
+fn anthropic_usage(usage: &JsonValue) -> ChatDelta {
+  if usage.is_null() {
+    return ChatDelta::Ignore;
+  }
+  let tokens = |key: &str| usage[key].as_u64().unwrap_or_default() as u32;
+  ChatDelta::Usage(TokenUsage {
+    prompt_tokens: tokens("input_tokens"),
+    completion_tokens: tokens("output_tokens"),
+  })
+}
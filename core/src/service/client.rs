@@ -0,0 +1,135 @@
+use std::{
+  ops::Deref,
+  sync::RwLock,
+  time::{Duration, Instant},
+};
+
+use futures_util::{Stream, StreamExt};
+use once_cell::sync::Lazy;
+use reqwest::{Client, NoProxy, Proxy, RequestBuilder, Response};
+
+use crate::{
+  error::{PolestarError, PolestarResult, PolestarServerErrType, PolestarServerError},
+  NetworkCfg, TimeoutCfg,
+};
+
+const TEST_CONNECTION_URL: &str = "https://api.ribir.org";
+
+static HTTP_CLIENT: Lazy<RwLock<HttpClient>> =
+  Lazy::new(|| RwLock::new(build_http_client(&NetworkCfg::default()).unwrap()));
+
+/// The client shared by all the requests, its clones share the same
+/// connection pool.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+  client: Client,
+  timeout: TimeoutCfg,
+}
+
+impl Deref for HttpClient {
+  type Target = Client;
+
+  fn deref(&self) -> &Self::Target { &self.client }
+}
+
+impl HttpClient {
+  /// Send the request, fail with `TimedOut` if the connection can't be built
+  /// or the response doesn't arrive in time.
+  pub async fn send(&self, req: RequestBuilder) -> PolestarResult<Response> {
+    let first_byte = Duration::from_secs(self.timeout.first_byte_secs);
+    match tokio::time::timeout(first_byte, req.send()).await {
+      Ok(Ok(resp)) => Ok(resp),
+      Ok(Err(err)) if err.is_timeout() => Err(timed_out(err.to_string())),
+      Ok(Err(err)) => Err(err.into()),
+      Err(_) => Err(timed_out(format!(
+        "no response in {} seconds",
+        self.timeout.first_byte_secs
+      ))),
+    }
+  }
+
+  /// The body stream of the response, fail with `TimedOut` if no data is
+  /// received in the idle timeout.
+  ///
+  /// The body is read by a task of the tokio runtime, so the stream can be
+  /// polled by any executor.
+  pub fn bytes_stream(
+    &self,
+    resp: Response,
+  ) -> impl Stream<Item = PolestarResult<impl AsRef<[u8]>>> + Send {
+    let idle_secs = self.timeout.idle_secs;
+    let (tx, rx) = tokio::sync::mpsc::channel(16);
+    tokio::spawn(async move {
+      let mut body = resp.bytes_stream();
+      loop {
+        let chunk = tokio::select! {
+          _ = tx.closed() => break,
+          chunk = tokio::time::timeout(Duration::from_secs(idle_secs), body.next()) => chunk,
+        };
+        let chunk = match chunk {
+          Ok(Some(chunk)) => chunk.map_err(PolestarError::from),
+          Ok(None) => break,
+          Err(_) => Err(timed_out(format!(
+            "no data received in {} seconds",
+            idle_secs
+          ))),
+        };
+        let is_err = chunk.is_err();
+        if tx.send(chunk).await.is_err() || is_err {
+          break;
+        }
+      }
+    });
+    futures_util::stream::unfold(rx, |mut rx| async move {
+      rx.recv().await.map(|chunk| (chunk, rx))
+    })
+    .fuse()
+  }
+}
+
+fn timed_out(message: String) -> PolestarError {
+  PolestarError::PolestarServerError(PolestarServerError {
+    kind: PolestarServerErrType::TimedOut,
+    message,
+    retry_after: None,
+  })
+}
+
+pub fn http_client() -> HttpClient { HTTP_CLIENT.read().unwrap().clone() }
+
+/// Build a client goes through the proxy of `cfg`.
+pub fn build_http_client(cfg: &NetworkCfg) -> PolestarResult<HttpClient> {
+  let mut builder = Client::builder()
+    .connect_timeout(Duration::from_secs(cfg.timeout.connect_secs))
+    .pool_idle_timeout(Duration::from_secs(cfg.timeout.pool_idle_secs));
+  if let Some(proxy) = cfg
+    .proxy
+    .as_deref()
+    .map(str::trim)
+    .filter(|proxy| !proxy.is_empty())
+  {
+    let no_proxy = NoProxy::from_string(&cfg.no_proxy.join(","));
+    builder = builder.proxy(Proxy::all(proxy)?.no_proxy(no_proxy));
+  }
+  Ok(HttpClient {
+    client: builder.build()?,
+    timeout: cfg.timeout.clone(),
+  })
+}
+
+/// Replace the shared client with the one built by `cfg`, the old one is kept
+/// if `cfg` is invalid.
+pub fn set_network_cfg(cfg: &NetworkCfg) -> PolestarResult<()> {
+  let client = build_http_client(cfg)?;
+  *HTTP_CLIENT.write().unwrap() = client;
+  Ok(())
+}
+
+/// Check whether the Polestar server can be reached with `cfg`, return the
+/// time it takes.
+pub async fn test_connection(cfg: NetworkCfg) -> PolestarResult<Duration> {
+  let client = build_http_client(&cfg)?;
+  let start = Instant::now();
+  client.send(client.head(TEST_CONNECTION_URL)).await?;
+  Ok(start.elapsed())
+}
@@ -0,0 +1,165 @@
+use serde_json::json;
+
+use crate::{
+  error::PolestarResult,
+  model::{Bot, Channel, Msg, MsgCont, MsgStatus, Rating},
+};
+
+use super::{
+  open_ai::{ChatCompletionResponseStreamMessage, Role},
+  req::{regenerate_context, request_messages, request_system_messages},
+};
+
+/// The datasets of the rated answers, both are JSON Lines in OpenAI's
+/// fine-tuning formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatasetFormat {
+  /// The up-rated answers with the context they are asked in, for supervised
+  /// fine-tuning.
+  FineTune,
+  /// The up-rated answers are preferred to the other versions of the same
+  /// answer, for preference fine-tuning.
+  Preference,
+}
+
+/// Export the rated answers of the channels. Every answer goes with the
+/// messages sent to its bot: the system prompt, the summary and the context
+/// before its question, built as the request to answer it again. The
+/// answers of the bots not in `bots` are skipped.
+pub fn export_dataset(
+  channels: &[&Channel],
+  bots: &[Bot],
+  format: DatasetFormat,
+) -> PolestarResult<String> {
+  let mut lines = vec![];
+  for channel in channels {
+    for msg in channel.msgs() {
+      let Some(msg_bot) = msg.role().bot() else {
+        continue;
+      };
+      let preferred = rated_texts(msg, |rating| rating == Some(Rating::Up));
+      if preferred.is_empty() {
+        continue;
+      }
+      let Some((context, content)) = regenerate_context(channel, msg.id(), None) else {
+        continue;
+      };
+
+      for (cont, text) in preferred {
+        let bot_id = cont.bot().unwrap_or(msg_bot);
+        let Some(bot) = bots.iter().find(|bot| bot.id() == bot_id) else {
+          log::warn!("dataset skip the answer of unknown bot {}", bot_id);
+          continue;
+        };
+        let mut messages = request_system_messages(bot, &context);
+        messages.extend(request_messages(bot, &context, &content));
+
+        match format {
+          DatasetFormat::FineTune => {
+            messages.push(assistant_message(text));
+            lines.push(serde_json::to_string(&json!({ "messages": messages }))?);
+          }
+          DatasetFormat::Preference => {
+            for (_, rejected) in rated_texts(msg, |rating| rating != Some(Rating::Up)) {
+              let line = json!({
+                "input": { "messages": messages },
+                "preferred_output": [assistant_message(text)],
+                "non_preferred_output": [assistant_message(rejected)],
+              });
+              lines.push(serde_json::to_string(&line)?);
+            }
+          }
+        }
+      }
+    }
+  }
+
+  Ok(lines.into_iter().map(|line| line + "\n").collect())
+}
+
+/// The finished text contents of the message whose rating matches.
+fn rated_texts(msg: &Msg, matched: impl Fn(Option<Rating>) -> bool) -> Vec<(&MsgCont, &str)> {
+  msg
+    .cont_list()
+    .iter()
+    .filter(|cont| *cont.status() == MsgStatus::Fulfilled)
+    .filter(|cont| matched(cont.rating().map(|rating| rating.rating)))
+    .filter_map(|cont| Some((cont, cont.text().filter(|text| !text.is_empty())?)))
+    .collect()
+}
+
+fn assistant_message(text: &str) -> ChatCompletionResponseStreamMessage {
+  ChatCompletionResponseStreamMessage {
+    content: Some(text.to_owned().into()),
+    role: Some(Role::Assistant),
+    ..<_>::default()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::Value as JsonValue;
+
+  use super::*;
+  use crate::{
+    model::{ChannelCfg, MsgMeta, MsgRating, MsgRole},
+    service::tests::mock::test_bot,
+  };
+
+  fn bot() -> Bot { test_bot(json!({ "prompt": "You are a bot." })) }
+
+  #[test]
+  fn export_rated_answers() {
+    let mut channel = Channel::new(
+      uuid::Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      ChannelCfg::default(),
+      None,
+      None,
+    );
+    channel.add_msg(Msg::new_user_text("hi", MsgMeta::default()));
+    let question = Msg::new_user_text("why?", MsgMeta::default());
+    let answer = Msg::new(
+      MsgRole::Bot("bot".to_owned()),
+      vec![MsgCont::new_text("no idea"), MsgCont::new_text("because")],
+      MsgMeta::reply(*question.id()),
+      None,
+    );
+    let answer_id = *answer.id();
+    channel.add_msg(question);
+    channel.add_msg(answer);
+    channel.add_msg(Msg::new_user_text("later", MsgMeta::default()));
+
+    let bots = [bot()];
+    let export = |channel: &Channel, format| export_dataset(&[channel], &bots, format).unwrap();
+    assert_eq!(export(&channel, DatasetFormat::FineTune), "");
+
+    channel.rate(&answer_id, 1, Some(MsgRating::new(Rating::Up, None)));
+    let dataset = export(&channel, DatasetFormat::FineTune);
+    let lines = dataset.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+    let line: JsonValue = serde_json::from_str(lines[0]).unwrap();
+    let contents = line["messages"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|m| (m["role"].as_str().unwrap(), m["content"].as_str().unwrap()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      contents,
+      [
+        ("system", "You are a bot."),
+        ("user", "hi"),
+        ("user", "why?"),
+        ("assistant", "because")
+      ]
+    );
+
+    let dataset = export(&channel, DatasetFormat::Preference);
+    let line: JsonValue = serde_json::from_str(dataset.trim()).unwrap();
+    assert_eq!(line["input"]["messages"].as_array().unwrap().len(), 3);
+    assert_eq!(line["preferred_output"][0]["content"], "because");
+    assert_eq!(line["non_preferred_output"][0]["content"], "no idea");
+  }
+}
@@ -0,0 +1,583 @@
+use std::{collections::HashMap, fmt::Write as _, path::Path};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::{
+    Attachment, Bot, Channel, ChannelCfg, ChannelId, ChannelSummary, Image, Msg, MsgId, MsgRating,
+    MsgRole, MIME,
+  },
+};
+
+/// The version of the JSON export schema, bump it when the schema changes.
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+  Markdown,
+  Json,
+  Html,
+}
+
+impl ExportFormat {
+  /// Guess the format by the extension of the file to export to.
+  pub fn from_path(path: &Path) -> Option<Self> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+      "md" | "markdown" => Some(Self::Markdown),
+      "json" => Some(Self::Json),
+      "html" | "htm" => Some(Self::Html),
+      _ => None,
+    }
+  }
+
+  pub fn ext(&self) -> &'static str {
+    match self {
+      Self::Markdown => "md",
+      Self::Json => "json",
+      Self::Html => "html",
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+  pub format: ExportFormat,
+  // export the alternate contents of the messages, not only the current one.
+  pub alternates: bool,
+  // embed the images as data urls, otherwise they are written to `assets_dir`
+  // next to the exported file. The JSON export always embeds them.
+  pub embed_images: bool,
+  pub assets_dir: String,
+}
+
+impl ExportOptions {
+  pub fn new(format: ExportFormat) -> Self {
+    Self {
+      format,
+      alternates: false,
+      embed_images: false,
+      assets_dir: "attachments".to_owned(),
+    }
+  }
+}
+
+/// The exported document and the files it links to.
+#[derive(Debug)]
+pub struct Export {
+  pub content: String,
+  pub files: Vec<ExportFile>,
+}
+
+#[derive(Debug)]
+pub struct ExportFile {
+  // relative to the directory of the exported document.
+  pub path: String,
+  pub data: Vec<u8>,
+}
+
+impl Export {
+  /// Write the document to `path`, and the files it links to next to it.
+  pub fn write(&self, path: &Path) -> PolestarResult<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for file in &self.files {
+      let file_path = dir.join(&file.path);
+      if let Some(parent) = file_path.parent() {
+        std::fs::create_dir_all(parent)?;
+      }
+      std::fs::write(file_path, &file.data)?;
+    }
+    std::fs::write(path, &self.content)?;
+    Ok(())
+  }
+}
+
+/// The lossless JSON export schema, it can be imported back.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportDoc {
+  pub version: u32,
+  pub channels: Vec<ChannelExport>,
+  #[serde(default)]
+  pub attachments: Vec<AttachmentExport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChannelExport {
+  pub id: ChannelId,
+  pub name: String,
+  pub desc: Option<String>,
+  pub cfg: ChannelCfg,
+  #[serde(default)]
+  pub summary: Option<ChannelSummary>,
+  pub msgs: Vec<Msg>,
+  #[serde(default)]
+  pub ratings: Vec<RatingExport>,
+  #[serde(default)]
+  pub arena_picks: Vec<ArenaPickExport>,
+}
+
+/// The answer picked as the best one of the question.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArenaPickExport {
+  pub question_id: MsgId,
+  pub winner_id: MsgId,
+}
+
+/// The rating of the content `cont_idx` of the message, the ratings are not
+/// kept in the messages.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RatingExport {
+  pub msg_id: MsgId,
+  pub cont_idx: usize,
+  pub rating: MsgRating,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AttachmentExport {
+  pub name: Uuid,
+  pub mime: MIME,
+  // base64 encoded.
+  pub data: String,
+}
+
+impl AttachmentExport {
+  pub fn decode(&self) -> PolestarResult<Vec<u8>> {
+    STANDARD
+      .decode(&self.data)
+      .map_err(|err| PolestarError::Import(format!("invalid attachment {}: {}", self.name, err)))
+  }
+}
+
+/// The names of the attachments the messages of the channels refer to.
+pub fn attachment_names<'a>(channels: impl IntoIterator<Item = &'a Channel>) -> Vec<Uuid> {
+  let mut names = vec![];
+  for channel in channels {
+    for msg in channel.msgs() {
+      for cont in msg.cont_list() {
+        let attached = cont.images().iter();
+        for name in cont
+          .image()
+          .into_iter()
+          .chain(attached)
+          .filter_map(Image::file)
+        {
+          if !names.contains(name) {
+            names.push(*name);
+          }
+        }
+      }
+    }
+  }
+  names
+}
+
+pub fn export_channels(
+  channels: &[&Channel],
+  bots: &[Bot],
+  attachments: &HashMap<Uuid, Attachment>,
+  opts: &ExportOptions,
+) -> PolestarResult<Export> {
+  match opts.format {
+    ExportFormat::Json => export_json(channels, attachments),
+    ExportFormat::Markdown => Ok(export_markdown(channels, bots, attachments, opts)),
+    ExportFormat::Html => Ok(export_html(channels, bots, attachments, opts)),
+  }
+}
+
+fn export_json(
+  channels: &[&Channel],
+  attachments: &HashMap<Uuid, Attachment>,
+) -> PolestarResult<Export> {
+  let doc = ExportDoc {
+    version: EXPORT_VERSION,
+    channels: channels
+      .iter()
+      .map(|channel| ChannelExport {
+        id: *channel.id(),
+        name: channel.name().to_owned(),
+        desc: channel.desc().map(String::from),
+        cfg: channel.cfg().clone(),
+        summary: channel.summary().cloned(),
+        msgs: channel.msgs().clone(),
+        ratings: channel
+          .msgs()
+          .iter()
+          .flat_map(|msg| {
+            msg
+              .cont_list()
+              .iter()
+              .enumerate()
+              .filter_map(|(idx, cont)| {
+                Some(RatingExport {
+                  msg_id: *msg.id(),
+                  cont_idx: idx,
+                  rating: cont.rating()?.clone(),
+                })
+              })
+          })
+          .collect(),
+        arena_picks: channel
+          .arena_picks()
+          .iter()
+          .map(|(question_id, winner_id)| ArenaPickExport {
+            question_id: *question_id,
+            winner_id: *winner_id,
+          })
+          .collect(),
+      })
+      .collect(),
+    attachments: attachment_names(channels.iter().copied())
+      .iter()
+      .filter_map(|name| attachments.get(name))
+      .map(|attachment| AttachmentExport {
+        name: *attachment.name(),
+        mime: attachment.mime().clone(),
+        data: STANDARD.encode(attachment.data()),
+      })
+      .collect(),
+  };
+  let content = serde_json::to_string_pretty(&doc)?;
+  Ok(Export { content, files: vec![] })
+}
+
+fn export_markdown(
+  channels: &[&Channel],
+  bots: &[Bot],
+  attachments: &HashMap<Uuid, Attachment>,
+  opts: &ExportOptions,
+) -> Export {
+  let mut images = ImageLinks::new(attachments, opts);
+  let mut md = String::new();
+  for (i, channel) in channels.iter().enumerate() {
+    if i > 0 {
+      md.push_str("\n---\n\n");
+    }
+    let _ = writeln!(md, "# {}\n", channel.name());
+    if let Some(desc) = channel.desc() {
+      let _ = writeln!(md, "{}\n", desc);
+    }
+    for msg in channel.branch_msgs() {
+      let _ = writeln!(
+        md,
+        "### {} · {}\n",
+        sender_name(msg, bots),
+        msg.create_at().format("%Y-%m-%d %H:%M")
+      );
+      if let Some(quote) = quote_text(channel, msg) {
+        for line in quote.lines() {
+          let _ = writeln!(md, "> {}", line);
+        }
+        md.push('\n');
+      }
+
+      let count = msg.cont_list().len();
+      for (idx, cont) in msg.cont_list().iter().enumerate() {
+        let is_cur = idx == msg.cur_idx();
+        if !is_cur && !opts.alternates {
+          continue;
+        }
+        if opts.alternates && count > 1 {
+          let mark = if is_cur { ", current" } else { "" };
+          let _ = writeln!(md, "*Alternate {}/{}{}*\n", idx + 1, count, mark);
+        }
+        if let Some(text) = cont.text() {
+          let _ = writeln!(md, "{}\n", text);
+          for src in cont.images().iter().filter_map(|img| images.src(img)) {
+            let _ = writeln!(md, "![image]({})\n", src);
+          }
+        } else if let Some(src) = cont.image().and_then(|img| images.src(img)) {
+          let _ = writeln!(md, "![image]({})\n", src);
+        }
+      }
+    }
+  }
+
+  Export { content: md, files: images.files }
+}
+
+fn export_html(
+  channels: &[&Channel],
+  bots: &[Bot],
+  attachments: &HashMap<Uuid, Attachment>,
+  opts: &ExportOptions,
+) -> Export {
+  let mut images = ImageLinks::new(attachments, opts);
+  let title = match channels {
+    [channel] => channel.name(),
+    _ => "Polestar Channels",
+  };
+  let mut html = String::new();
+  let _ = write!(
+    html,
+    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+    escape_html(title),
+    HTML_STYLE
+  );
+  for channel in channels {
+    let _ = writeln!(html, "<section>\n<h1>{}</h1>", escape_html(channel.name()));
+    if let Some(desc) = channel.desc() {
+      let _ = writeln!(html, "<p class=\"desc\">{}</p>", escape_html(desc));
+    }
+    for msg in channel.branch_msgs() {
+      let class = match msg.role() {
+        MsgRole::User => "user",
+        MsgRole::Bot(_) => "bot",
+        MsgRole::System(_) => "system",
+        MsgRole::Tool(_) => "tool",
+      };
+      let _ = writeln!(
+        html,
+        "<article class=\"msg {}\">\n<header><strong>{}</strong> <time>{}</time></header>",
+        class,
+        escape_html(&sender_name(msg, bots)),
+        msg.create_at().format("%Y-%m-%d %H:%M")
+      );
+      if let Some(quote) = quote_text(channel, msg) {
+        let _ = writeln!(html, "<blockquote>{}</blockquote>", escape_html(&quote));
+      }
+
+      let count = msg.cont_list().len();
+      for (idx, cont) in msg.cont_list().iter().enumerate() {
+        let is_cur = idx == msg.cur_idx();
+        if !is_cur && !opts.alternates {
+          continue;
+        }
+        let body = if let Some(text) = cont.text() {
+          let mut body = format!("<div class=\"text\">{}</div>", escape_html(text));
+          for src in cont.images().iter().filter_map(|img| images.src(img)) {
+            let _ = write!(body, "<img src=\"{}\">", escape_html(&src));
+          }
+          body
+        } else if let Some(src) = cont.image().and_then(|img| images.src(img)) {
+          format!("<img src=\"{}\">", escape_html(&src))
+        } else {
+          continue;
+        };
+        if is_cur {
+          let _ = writeln!(html, "{}", body);
+        } else {
+          let _ = writeln!(
+            html,
+            "<details><summary>Alternate {}/{}</summary>{}</details>",
+            idx + 1,
+            count,
+            body
+          );
+        }
+      }
+      html.push_str("</article>\n");
+    }
+    html.push_str("</section>\n");
+  }
+  html.push_str("</body>\n</html>\n");
+
+  Export { content: html, files: images.files }
+}
+
+const HTML_STYLE: &str = "body{font-family:sans-serif;max-width:800px;margin:0 \
+                          auto;padding:20px;color:#333}.msg{margin:12px \
+                          0;padding:10px;border-radius:6px;background:#f7f7f5}.msg.user{\
+                          background:#e8f0fe}.msg.system{background:#fff8e1}.msg.tool{\
+                          background:#f1f8e9}header{font-size:\
+                          13px;color:#666;margin-bottom:6px}.text{white-space:pre-wrap}\
+                          blockquote{margin:0 0 6px;padding-left:8px;border-left:3px solid \
+                          #ccc;color:#666;white-space:pre-wrap}img{max-width:100%}";
+
+/// Resolve the `src` of the images, embedded as data urls or written to the
+/// assets directory.
+struct ImageLinks<'a> {
+  attachments: &'a HashMap<Uuid, Attachment>,
+  opts: &'a ExportOptions,
+  files: Vec<ExportFile>,
+}
+
+impl<'a> ImageLinks<'a> {
+  fn new(attachments: &'a HashMap<Uuid, Attachment>, opts: &'a ExportOptions) -> Self {
+    Self { attachments, opts, files: vec![] }
+  }
+
+  fn src(&mut self, img: &Image) -> Option<String> {
+    if let Some(url) = img.url() {
+      return Some(url.to_owned());
+    }
+    let attachment = self.attachments.get(img.file()?)?;
+    let (mime, ext) = (attachment.mime().media_type(), attachment.mime().ext());
+    if self.opts.embed_images {
+      Some(format!(
+        "data:{};base64,{}",
+        mime,
+        STANDARD.encode(attachment.data())
+      ))
+    } else {
+      let path = format!("{}/{}.{}", self.opts.assets_dir, attachment.name(), ext);
+      if self.files.iter().all(|file| file.path != path) {
+        self.files.push(ExportFile {
+          path: path.clone(),
+          data: attachment.data().to_vec(),
+        });
+      }
+      Some(path)
+    }
+  }
+}
+
+/// The name of the sender, the bot answered the current content for a bot's
+/// message.
+fn sender_name(msg: &Msg, bots: &[Bot]) -> String {
+  match msg.role() {
+    MsgRole::User => "User".to_owned(),
+    MsgRole::Bot(bot_id) => {
+      let bot_id = msg.cur_bot().unwrap_or(bot_id);
+      bots
+        .iter()
+        .find(|bot| bot.id() == bot_id)
+        .map_or_else(|| bot_id.clone(), |bot| bot.name().to_owned())
+    }
+    MsgRole::System(_) => "System".to_owned(),
+    MsgRole::Tool(call) => format!("Tool {}({})", call.name, call.arguments),
+  }
+}
+
+/// The text of the message the `msg` quotes, cut to a short line.
+fn quote_text(channel: &Channel, msg: &Msg) -> Option<String> {
+  const QUOTE_CHARS: usize = 120;
+
+  let quote = channel.msg(msg.meta().quote_id()?)?;
+  let text = quote.cur_cont_ref().text()?;
+  let mut short = text.chars().take(QUOTE_CHARS).collect::<String>();
+  if text.chars().nth(QUOTE_CHARS).is_some() {
+    short.push('…');
+  }
+  Some(short)
+}
+
+fn escape_html(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      '\'' => escaped.push_str("&#39;"),
+      c => escaped.push(c),
+    }
+  }
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::{MsgCont, MsgMeta, Rating};
+
+  fn test_channel(img_name: Uuid) -> Channel {
+    let mut channel = Channel::new(
+      Uuid::new_v4(),
+      "Export".to_owned(),
+      Some("<desc>".to_owned()),
+      ChannelCfg::default(),
+      None,
+      None,
+    );
+    let question = Msg::new_user_text("Tell me a <joke>", MsgMeta::default());
+    let question_id = *question.id();
+    channel.add_msg(question);
+    let mut answer = Msg::new(
+      MsgRole::Bot("unknown_bot".to_owned()),
+      vec![
+        MsgCont::new_text("first answer"),
+        MsgCont::new_text("second answer"),
+      ],
+      MsgMeta::quote(question_id),
+      None,
+    );
+    answer.switch_cont(1);
+    channel.add_msg(answer);
+    let img: Image = serde_json::from_value(serde_json::json!({
+      "width": 1,
+      "height": 1,
+      "path": { "file": img_name },
+    }))
+    .unwrap();
+    channel.add_msg(Msg::new_img(MsgRole::User, img, MsgMeta::default()));
+    channel
+  }
+
+  #[test]
+  fn export_markdown_alternates() {
+    let attachment = Attachment::new(MIME::ImagePng, vec![1, 2, 3]);
+    let name = *attachment.name();
+    let channel = test_channel(name);
+    let attachments = HashMap::from([(name, attachment)]);
+
+    let opts = ExportOptions::new(ExportFormat::Markdown);
+    let export = export_channels(&[&channel], &[], &attachments, &opts).unwrap();
+    assert!(export.content.starts_with("# Export\n"));
+    assert!(export.content.contains("### unknown_bot · "));
+    assert!(export.content.contains("> Tell me a <joke>\n"));
+    assert!(export.content.contains("second answer"));
+    assert!(!export.content.contains("first answer"));
+    assert!(export
+      .content
+      .contains(&format!("![image](attachments/{}.png)", name)));
+    assert_eq!(export.files.len(), 1);
+    assert_eq!(export.files[0].data, vec![1, 2, 3]);
+
+    let opts = ExportOptions {
+      alternates: true,
+      embed_images: true,
+      ..ExportOptions::new(ExportFormat::Markdown)
+    };
+    let export = export_channels(&[&channel], &[], &attachments, &opts).unwrap();
+    assert!(export.content.contains("*Alternate 1/2*\n\nfirst answer"));
+    assert!(export
+      .content
+      .contains("*Alternate 2/2, current*\n\nsecond answer"));
+    assert!(export
+      .content
+      .contains("![image](data:image/png;base64,AQID)"));
+    assert!(export.files.is_empty());
+  }
+
+  #[test]
+  fn export_html_escaped() {
+    let channel = test_channel(Uuid::new_v4());
+    let opts = ExportOptions::new(ExportFormat::Html);
+    let export = export_channels(&[&channel], &[], &HashMap::new(), &opts).unwrap();
+    assert!(export.content.contains("Tell me a &lt;joke&gt;"));
+    assert!(export
+      .content
+      .contains("<p class=\"desc\">&lt;desc&gt;</p>"));
+    assert!(!export.content.contains("<img"));
+  }
+
+  #[test]
+  fn export_json_lossless() {
+    let attachment = Attachment::new(MIME::ImagePng, vec![1, 2, 3]);
+    let name = *attachment.name();
+    let mut channel = test_channel(name);
+    let answer_id = *channel.msgs()[1].id();
+    let rating = MsgRating::new(Rating::Up, Some("funny".to_owned()));
+    channel.rate(&answer_id, 1, Some(rating.clone()));
+    let attachments = HashMap::from([(name, attachment)]);
+
+    let opts = ExportOptions::new(ExportFormat::Json);
+    let export = export_channels(&[&channel], &[], &attachments, &opts).unwrap();
+    let doc: ExportDoc = serde_json::from_str(&export.content).unwrap();
+    assert_eq!(doc.version, EXPORT_VERSION);
+    let ratings = &doc.channels[0].ratings;
+    assert_eq!(ratings.len(), 1);
+    assert_eq!((ratings[0].msg_id, ratings[0].cont_idx), (answer_id, 1));
+    assert_eq!(ratings[0].rating, rating);
+    assert_eq!(doc.channels[0].id, *channel.id());
+    assert_eq!(doc.channels[0].msgs.len(), 3);
+    assert_eq!(doc.channels[0].msgs[1].cur_idx(), 1);
+    assert_eq!(doc.channels[0].msgs[1].cont_list().len(), 2);
+    assert_eq!(doc.attachments[0].name, name);
+    assert_eq!(doc.attachments[0].decode().unwrap(), vec![1, 2, 3]);
+  }
+}
@@ -0,0 +1,115 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::{Attachment, Bot, Channel, Image, MIME},
+};
+
+use super::{
+  client::http_client,
+  req::{request_params, TextStreamReq},
+};
+
+/// The model used when neither the bot nor the channel specify one.
+const DEFAULT_IMAGE_MODEL: &str = "dall-e-3";
+
+/// The image drawn by the bot, its data is kept by the attachment.
+#[derive(Debug, Clone)]
+pub struct GeneratedImage {
+  pub image: Image,
+  pub attachment: Attachment,
+}
+
+#[derive(Deserialize)]
+struct ImagesResp {
+  data: Vec<ImageData>,
+}
+
+// the image is given by the base64 data or the url to download.
+#[derive(Deserialize)]
+struct ImageData {
+  b64_json: Option<String>,
+  url: Option<String>,
+}
+
+/// Build the body of an OpenAI `images/generations` request, `content` is the
+/// prompt. The `size`, `quality`, `n` and other params of the bot and the
+/// channel are sent as they are.
+pub fn image_request_content(bot: &Bot, channel: &Channel, content: &str) -> String {
+  let mut params = request_params(bot, channel);
+  params
+    .entry("model")
+    .or_insert_with(|| json!(DEFAULT_IMAGE_MODEL));
+  params.insert("prompt".to_owned(), json!(content));
+  serde_json::to_string(&params).unwrap_or_default()
+}
+
+/// Draw the images by the request `body`, the images given by urls are
+/// downloaded. `on_retry` is called with the number of the next attempt.
+pub async fn generate_images(
+  req: TextStreamReq,
+  body: String,
+  on_retry: impl FnMut(u32),
+) -> PolestarResult<Vec<GeneratedImage>> {
+  // the size asked for, if the size can't be read from the image.
+  let size = serde_json::from_str::<serde_json::Value>(&body)
+    .ok()
+    .and_then(|body| body["size"].as_str().and_then(parse_size));
+  let resp = req.request_json(body, on_retry).await?;
+  let resp = serde_json::from_value::<ImagesResp>(resp)?;
+
+  let mut images = vec![];
+  for item in resp.data {
+    let data = match (item.b64_json, item.url) {
+      (Some(b64), _) => STANDARD
+        .decode(b64.trim())
+        .map_err(|e| PolestarError::Image(format!("invalid base64 data: {}", e)))?,
+      (None, Some(url)) => download(&url).await?,
+      (None, None) => continue,
+    };
+    let mime = MIME::from_image_data(&data)
+      .ok_or_else(|| PolestarError::Image("unsupported image format".to_owned()))?;
+    let (width, height) = image_size(&mime, &data).or(size).unwrap_or_default();
+    let attachment = Attachment::new(mime, data);
+    let image = Image::new_file(*attachment.name(), width, height);
+    images.push(GeneratedImage { image, attachment });
+  }
+  if images.is_empty() {
+    return Err(PolestarError::Image("no image in the response".to_owned()));
+  }
+  Ok(images)
+}
+
+async fn download(url: &str) -> PolestarResult<Vec<u8>> {
+  let client = http_client();
+  let resp = client.send(client.get(url)).await?;
+  let status = resp.status();
+  if !status.is_success() {
+    return Err(PolestarError::Image(format!(
+      "download {} failed: {}",
+      url, status
+    )));
+  }
+  Ok(resp.bytes().await?.to_vec())
+}
+
+/// The width and height of the `1024x1024` like size.
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+  let (width, height) = size.split_once('x')?;
+  Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Read the width and height from the header of the PNG image.
+pub(crate) fn image_size(mime: &MIME, data: &[u8]) -> Option<(u32, u32)> {
+  match mime {
+    // the IHDR chunk follows the signature.
+    MIME::ImagePng if data.len() >= 24 && &data[12..16] == b"IHDR" => {
+      let width = u32::from_be_bytes(data[16..20].try_into().ok()?);
+      let height = u32::from_be_bytes(data[20..24].try_into().ok()?);
+      Some((width, height))
+    }
+    _ => None,
+  }
+}
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::{
+    Attachment, BotId, Channel, ChannelCfg, ChannelSummary, Msg, MsgCont, MsgId, MsgMeta, MsgRole,
+  },
+};
+
+use super::export::{ExportDoc, EXPORT_VERSION};
+
+/// The channels and the attachments parsed from an import file, they are not
+/// added yet.
+#[derive(Debug)]
+pub struct Import {
+  pub channels: Vec<Channel>,
+  pub attachments: Vec<Attachment>,
+}
+
+/// Parse Polestar's JSON export or ChatGPT's `conversations.json`. The
+/// answers of ChatGPT are given to `bot_id`.
+///
+/// The channels and messages get new ids, so a file can be imported again.
+pub fn parse_import(content: &str, bot_id: &BotId) -> PolestarResult<Import> {
+  let value: serde_json::Value = serde_json::from_str(content)?;
+  if value.is_array() {
+    let conversations: Vec<GptConversation> = serde_json::from_value(value)?;
+    let channels = conversations
+      .iter()
+      .map(|conversation| gpt_channel(conversation, bot_id))
+      .collect();
+    Ok(Import { channels, attachments: vec![] })
+  } else {
+    let doc: ExportDoc = serde_json::from_value(value)?;
+    polestar_import(doc)
+  }
+}
+
+fn polestar_import(doc: ExportDoc) -> PolestarResult<Import> {
+  if doc.version > EXPORT_VERSION {
+    return Err(PolestarError::Import(format!(
+      "unsupported export version {}",
+      doc.version
+    )));
+  }
+
+  let mut channels = vec![];
+  for export in doc.channels {
+    let ids: HashMap<MsgId, MsgId> = export
+      .msgs
+      .iter()
+      .map(|msg| (*msg.id(), Uuid::new_v4()))
+      .collect();
+
+    let mut channel = Channel::new(
+      Uuid::new_v4(),
+      export.name,
+      export.desc,
+      export.cfg,
+      None,
+      None,
+    );
+    for msg in export.msgs {
+      channel.add_msg(msg.copy_with_ids(&ids));
+    }
+    channel.load_ratings(
+      export
+        .ratings
+        .into_iter()
+        .filter_map(|rating| Some((*ids.get(&rating.msg_id)?, rating.cont_idx, rating.rating)))
+        .collect(),
+    );
+    channel.load_arena_picks(
+      export
+        .arena_picks
+        .iter()
+        .filter_map(|pick| Some((*ids.get(&pick.question_id)?, *ids.get(&pick.winner_id)?)))
+        .collect(),
+    );
+    let summary = export.summary.and_then(|summary| {
+      let until_msg_id = ids.get(summary.until_msg_id())?;
+      Some(ChannelSummary::new(
+        summary.content().to_owned(),
+        *until_msg_id,
+      ))
+    });
+    channel.load_summary(summary);
+    channels.push(channel);
+  }
+
+  let attachments = doc
+    .attachments
+    .iter()
+    .map(|attachment| {
+      let data = attachment.decode()?;
+      Ok(Attachment::with_name(
+        attachment.name,
+        attachment.mime.clone(),
+        data,
+      ))
+    })
+    .collect::<PolestarResult<Vec<_>>>()?;
+
+  Ok(Import { channels, attachments })
+}
+
+#[derive(Debug, Deserialize)]
+struct GptConversation {
+  #[serde(default)]
+  title: Option<String>,
+  #[serde(default)]
+  mapping: HashMap<String, GptNode>,
+  #[serde(default)]
+  current_node: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GptNode {
+  #[serde(default)]
+  message: Option<GptMessage>,
+  #[serde(default)]
+  parent: Option<String>,
+  #[serde(default)]
+  children: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GptMessage {
+  author: GptAuthor,
+  #[serde(default)]
+  create_time: Option<f64>,
+  content: GptContent,
+}
+
+#[derive(Debug, Deserialize)]
+struct GptAuthor {
+  role: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GptContent {
+  // the text parts, the others like images are skipped.
+  #[serde(default)]
+  parts: Vec<serde_json::Value>,
+  // the code content has text instead of parts.
+  #[serde(default)]
+  text: Option<String>,
+}
+
+impl GptMessage {
+  // the tool messages are skipped.
+  fn role(&self, bot_id: &BotId) -> Option<MsgRole> {
+    match self.author.role.as_str() {
+      "user" => Some(MsgRole::User),
+      "assistant" => Some(MsgRole::Bot(bot_id.clone())),
+      "system" => Some(MsgRole::System(0)),
+      _ => None,
+    }
+  }
+
+  fn text(&self) -> Option<String> {
+    let text = self
+      .content
+      .parts
+      .iter()
+      .filter_map(|part| part.as_str())
+      .collect::<Vec<_>>()
+      .join("\n");
+    let text = if text.is_empty() {
+      self.content.text.clone()?
+    } else {
+      text
+    };
+    (!text.trim().is_empty()).then_some(text)
+  }
+
+  fn created_at(&self) -> Option<i64> { self.create_time.map(|t| (t * 1000.) as i64) }
+}
+
+/// The conversation shown in ChatGPT becomes the messages of the channel, the
+/// other branches of every message become its alternate contents.
+fn gpt_channel(conversation: &GptConversation, bot_id: &BotId) -> Channel {
+  let name = conversation
+    .title
+    .clone()
+    .filter(|title| !title.trim().is_empty())
+    .unwrap_or_else(|| "Untitled".to_owned());
+  let mut channel = Channel::new(
+    Uuid::new_v4(),
+    name,
+    None,
+    ChannelCfg::default(),
+    None,
+    None,
+  );
+
+  let mapping = &conversation.mapping;
+  let mut last_user_msg = None;
+  for node_id in gpt_thread(conversation) {
+    let Some(node) = mapping.get(node_id) else {
+      continue;
+    };
+    let Some(role) = node
+      .message
+      .as_ref()
+      .filter(|msg| msg.text().is_some())
+      .and_then(|msg| msg.role(bot_id))
+    else {
+      continue;
+    };
+
+    let branches = match node.parent.as_ref().and_then(|id| mapping.get(id)) {
+      Some(parent) => parent.children.iter().map(String::as_str).collect(),
+      None => vec![node_id],
+    };
+    let mut cont_list = vec![];
+    let mut cur_idx = 0;
+    let mut created_at = None;
+    for id in branches {
+      let Some(msg) = mapping.get(id).and_then(|node| node.message.as_ref()) else {
+        continue;
+      };
+      let Some(text) = msg
+        .text()
+        .filter(|_| msg.role(bot_id).as_ref() == Some(&role))
+      else {
+        continue;
+      };
+      if id == node_id {
+        cur_idx = cont_list.len();
+        created_at = msg.created_at();
+      }
+      cont_list.push(MsgCont::new_text(&text));
+    }
+
+    let meta = match last_user_msg {
+      Some(user_msg_id) if role.is_bot() => MsgMeta::reply(user_msg_id),
+      _ => MsgMeta::default(),
+    };
+    let mut msg = Msg::new(role, cont_list, meta, created_at);
+    msg.switch_cont(cur_idx);
+    if msg.role().is_user() {
+      last_user_msg = Some(*msg.id());
+    }
+    channel.add_msg(msg);
+  }
+
+  channel
+}
+
+/// The node ids from the root to the current node, or to the last branch if
+/// the current node is unknown. The thread starts after the missing parent in
+/// a partial export.
+fn gpt_thread(conversation: &GptConversation) -> Vec<&str> {
+  let mapping = &conversation.mapping;
+  let leaf = conversation
+    .current_node
+    .as_deref()
+    .filter(|id| mapping.contains_key(*id))
+    .or_else(|| {
+      let (mut id, _) = mapping.iter().find(|(_, node)| node.parent.is_none())?;
+      while let Some(child) = mapping.get(id)?.children.last() {
+        id = child;
+      }
+      Some(id.as_str())
+    });
+
+  let mut thread = vec![];
+  let mut cur = leaf;
+  // the length guards against a broken mapping with a cycle.
+  while let Some(id) = cur.filter(|id| mapping.contains_key(*id) && thread.len() <= mapping.len()) {
+    thread.push(id);
+    cur = mapping.get(id).and_then(|node| node.parent.as_deref());
+  }
+  thread.reverse();
+  thread
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    model::{MsgRating, Rating},
+    service::export::{export_channels, ExportFormat, ExportOptions},
+  };
+
+  const CONVERSATIONS: &str = r#"[{
+    "title": "Rust question",
+    "current_node": "a2",
+    "mapping": {
+      "root": { "message": null, "parent": null, "children": ["sys"] },
+      "sys": {
+        "message": {
+          "author": { "role": "system" },
+          "create_time": null,
+          "content": { "content_type": "text", "parts": [""] }
+        },
+        "parent": "root",
+        "children": ["u1"]
+      },
+      "u1": {
+        "message": {
+          "author": { "role": "user" },
+          "create_time": 1700000000.5,
+          "content": { "content_type": "text", "parts": ["What is a trait?"] }
+        },
+        "parent": "sys",
+        "children": ["a1", "a2"]
+      },
+      "a1": {
+        "message": {
+          "author": { "role": "assistant" },
+          "create_time": 1700000001.0,
+          "content": { "content_type": "text", "parts": ["An interface."] }
+        },
+        "parent": "u1",
+        "children": []
+      },
+      "a2": {
+        "message": {
+          "author": { "role": "assistant" },
+          "create_time": 1700000002.0,
+          "content": { "content_type": "text", "parts": ["A set of methods."] }
+        },
+        "parent": "u1",
+        "children": []
+      }
+    }
+  }]"#;
+
+  #[test]
+  fn import_chat_gpt_branches() {
+    let import = parse_import(CONVERSATIONS, &"gpt".to_owned()).unwrap();
+    assert_eq!(import.channels.len(), 1);
+    let channel = &import.channels[0];
+    assert_eq!(channel.name(), "Rust question");
+
+    // the empty system message is skipped.
+    let msgs = channel.msgs();
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(msgs[0].role(), &MsgRole::User);
+    assert_eq!(msgs[0].create_at().timestamp_millis(), 1700000000500);
+
+    assert_eq!(msgs[1].role(), &MsgRole::Bot("gpt".to_owned()));
+    assert_eq!(msgs[1].meta().source_id(), Some(msgs[0].id()));
+    assert_eq!(msgs[1].cont_list().len(), 2);
+    assert_eq!(msgs[1].cur_idx(), 1);
+    assert_eq!(msgs[1].cur_cont_ref().text(), Some("A set of methods."));
+    assert_eq!(msgs[1].create_at().timestamp(), 1700000002);
+  }
+
+  #[test]
+  fn import_dangling_parent() {
+    // the root and the system message are trimmed from the export.
+    let mut conversations: serde_json::Value = serde_json::from_str(CONVERSATIONS).unwrap();
+    let mapping = conversations[0]["mapping"].as_object_mut().unwrap();
+    mapping.remove("root");
+    mapping.remove("sys");
+    let content = conversations.to_string();
+
+    let import = parse_import(&content, &"gpt".to_owned()).unwrap();
+    let msgs = import.channels[0].msgs();
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(msgs[0].cur_cont_ref().text(), Some("What is a trait?"));
+    assert_eq!(msgs[1].cur_cont_ref().text(), Some("A set of methods."));
+  }
+
+  #[test]
+  fn import_polestar_export() {
+    let mut first = parse_import(CONVERSATIONS, &"gpt".to_owned()).unwrap();
+    let channel = &mut first.channels[0];
+    let answer_id = *channel.msgs()[1].id();
+    let rating = MsgRating::new(Rating::Down, None);
+    channel.rate(&answer_id, 0, Some(rating.clone()));
+    let question_id = *channel.msgs()[0].id();
+    channel.load_arena_picks(vec![(question_id, answer_id)]);
+    let channel = &*channel;
+    let opts = ExportOptions::new(ExportFormat::Json);
+    let export = export_channels(&[channel], &[], &HashMap::new(), &opts).unwrap();
+
+    let import = parse_import(&export.content, &"other".to_owned()).unwrap();
+    let imported = &import.channels[0];
+    assert_ne!(imported.id(), channel.id());
+    assert_eq!(imported.name(), channel.name());
+
+    let msgs = imported.msgs();
+    assert_eq!(msgs.len(), 2);
+    assert_ne!(msgs[0].id(), channel.msgs()[0].id());
+    assert_eq!(msgs[1].meta().source_id(), Some(msgs[0].id()));
+    assert_eq!(msgs[1].role(), &MsgRole::Bot("gpt".to_owned()));
+    assert_eq!(msgs[1].cur_idx(), 1);
+    assert_eq!(msgs[1].create_at(), channel.msgs()[1].create_at());
+    assert_eq!(msgs[1].cont_list()[0].rating(), Some(&rating));
+    assert!(msgs[1].cont_list()[1].rating().is_none());
+    assert_eq!(imported.best_answer(msgs[0].id()), Some(msgs[1].id()));
+  }
+}
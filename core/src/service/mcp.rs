@@ -0,0 +1,442 @@
+use std::{collections::HashMap, process::Stdio, sync::Arc, time::Duration};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value as JsonValue};
+use tokio::{
+  io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+  process::{Child, ChildStdin, ChildStdout, Command},
+  sync::{mpsc, oneshot},
+};
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::{Tool, ToolRegistry},
+  McpServerCfg,
+};
+
+/// The MCP version the client speaks.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// The time to wait for the response of a request, before it fails.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The tool the MCP server provides.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpToolInfo {
+  pub name: String,
+  #[serde(default)]
+  pub description: Option<String>,
+  pub input_schema: JsonValue,
+}
+
+/// The data the MCP server provides, i.e. a file or a database table.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResource {
+  pub uri: String,
+  pub name: String,
+  #[serde(default)]
+  pub description: Option<String>,
+  #[serde(default)]
+  pub mime_type: Option<String>,
+}
+
+/// The prompt template the MCP server provides.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct McpPrompt {
+  pub name: String,
+  #[serde(default)]
+  pub description: Option<String>,
+  #[serde(default)]
+  pub arguments: Vec<McpPromptArg>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct McpPromptArg {
+  pub name: String,
+  #[serde(default)]
+  pub description: Option<String>,
+  #[serde(default)]
+  pub required: bool,
+}
+
+/// The client of the MCP server over its stdio. The server is launched by the
+/// first request, and launched again by the next request after it exits, i.e.
+/// crashes. It's stopped when the client is dropped.
+#[derive(Debug)]
+pub struct McpClient {
+  name: String,
+  tx: mpsc::UnboundedSender<Request>,
+}
+
+struct Request {
+  method: String,
+  params: JsonValue,
+  reply: oneshot::Sender<PolestarResult<JsonValue>>,
+}
+
+impl McpClient {
+  /// The client talks to the server in the background runtime, so it can be
+  /// used in any runtime.
+  pub fn new(cfg: McpServerCfg) -> Self {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let name = cfg.name.clone();
+    crate::db::pool::runtime().spawn(Server::new(cfg).run(rx));
+    Self { name, tx }
+  }
+
+  #[inline]
+  pub fn name(&self) -> &str { &self.name }
+
+  /// Send the JSON-RPC request, return the result of the response.
+  pub async fn request(&self, method: &str, params: JsonValue) -> PolestarResult<JsonValue> {
+    let closed = || PolestarError::Tool(format!("MCP server `{}` is closed", self.name));
+    let (reply, rx) = oneshot::channel();
+    let req = Request {
+      method: method.to_owned(),
+      params,
+      reply,
+    };
+    self.tx.send(req).map_err(|_| closed())?;
+    match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+      Ok(res) => res.map_err(|_| closed())?,
+      Err(_) => Err(PolestarError::Tool(format!(
+        "MCP server `{}` doesn't respond to `{}`",
+        self.name, method
+      ))),
+    }
+  }
+
+  pub async fn list_tools(&self) -> PolestarResult<Vec<McpToolInfo>> {
+    self.list("tools/list", "tools").await
+  }
+
+  pub async fn list_resources(&self) -> PolestarResult<Vec<McpResource>> {
+    self.list("resources/list", "resources").await
+  }
+
+  pub async fn list_prompts(&self) -> PolestarResult<Vec<McpPrompt>> {
+    self.list("prompts/list", "prompts").await
+  }
+
+  /// Call the tool of the server, the text of the result is returned. It fails
+  /// with the text if the server reports the call failed.
+  pub async fn call_tool(&self, name: &str, args: JsonValue) -> PolestarResult<String> {
+    let res = self
+      .request("tools/call", json!({ "name": name, "arguments": args }))
+      .await?;
+    let text = content_text(&res["content"]);
+    if res["isError"].as_bool() == Some(true) {
+      Err(PolestarError::Tool(text))
+    } else {
+      Ok(text)
+    }
+  }
+
+  /// Read the text of the resource.
+  pub async fn read_resource(&self, uri: &str) -> PolestarResult<String> {
+    let res = self
+      .request("resources/read", json!({ "uri": uri }))
+      .await?;
+    let contents = res["contents"].as_array().into_iter().flatten();
+    let texts: Vec<_> = contents
+      .map(|content| match content["text"].as_str() {
+        Some(text) => text.to_owned(),
+        None => format!("[binary {}]", content["uri"].as_str().unwrap_or(uri)),
+      })
+      .collect();
+    Ok(texts.join("\n"))
+  }
+
+  // the list requests are paginated by the cursor.
+  async fn list<T: DeserializeOwned>(&self, method: &str, key: &str) -> PolestarResult<Vec<T>> {
+    let mut items = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+      let params = cursor.map_or_else(|| json!({}), |cursor| json!({ "cursor": cursor }));
+      let mut res = self.request(method, params).await?;
+      items.extend(serde_json::from_value::<Vec<T>>(res[key].take())?);
+      cursor = res["nextCursor"].as_str().map(str::to_owned);
+      if cursor.is_none() {
+        return Ok(items);
+      }
+    }
+  }
+}
+
+/// The tool of the MCP server, named `<server>__<tool>` to tell apart the
+/// tools of the same name of the servers.
+pub struct McpTool {
+  name: String,
+  info: McpToolInfo,
+  client: Arc<McpClient>,
+}
+
+impl McpTool {
+  pub fn new(client: Arc<McpClient>, info: McpToolInfo) -> Self {
+    let name = format!("{}__{}", client.name(), info.name);
+    Self { name, info, client }
+  }
+}
+
+impl Tool for McpTool {
+  fn name(&self) -> &str { &self.name }
+
+  fn desc(&self) -> &str { self.info.description.as_deref().unwrap_or_default() }
+
+  fn schema(&self) -> JsonValue { self.info.input_schema.clone() }
+
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+    self.client.call_tool(&self.info.name, args).boxed()
+  }
+}
+
+/// Launch the MCP servers and register their tools, the server failed to list
+/// its tools is kept without tools. Return the clients of the servers.
+pub async fn load_mcp_tools(
+  servers: &[McpServerCfg],
+  tools: &mut ToolRegistry,
+) -> Vec<Arc<McpClient>> {
+  let mut clients = vec![];
+  for cfg in servers {
+    let client = Arc::new(McpClient::new(cfg.clone()));
+    match client.list_tools().await {
+      Ok(infos) => infos
+        .into_iter()
+        .for_each(|info| tools.register(McpTool::new(client.clone(), info))),
+      Err(e) => log::warn!("list the tools of MCP server {} failed: {}", cfg.name, e),
+    }
+    clients.push(client);
+  }
+  clients
+}
+
+fn content_text(content: &JsonValue) -> String {
+  let items = content.as_array().into_iter().flatten();
+  let texts: Vec<_> = items
+    .map(|item| match item["type"].as_str() {
+      Some("text") => item["text"].as_str().unwrap_or_default().to_owned(),
+      Some("resource") => match item["resource"]["text"].as_str() {
+        Some(text) => text.to_owned(),
+        None => format!(
+          "[resource {}]",
+          item["resource"]["uri"].as_str().unwrap_or_default()
+        ),
+      },
+      Some(kind) => format!("[{}]", kind),
+      None => String::new(),
+    })
+    .collect();
+  texts.join("\n")
+}
+
+/// The running server, it receives the requests of the client and dispatches
+/// the responses of the server.
+struct Server {
+  cfg: McpServerCfg,
+  conn: Option<Conn>,
+  next_id: u64,
+  pending: HashMap<u64, oneshot::Sender<PolestarResult<JsonValue>>>,
+}
+
+struct Conn {
+  // the server is killed when the connection is dropped.
+  _child: Child,
+  stdin: ChildStdin,
+  stdout: Lines<BufReader<ChildStdout>>,
+}
+
+enum Event {
+  Request(Option<Request>),
+  Line(std::io::Result<Option<String>>),
+}
+
+impl Server {
+  fn new(cfg: McpServerCfg) -> Self {
+    Self {
+      cfg,
+      conn: None,
+      next_id: 0,
+      pending: HashMap::new(),
+    }
+  }
+
+  async fn run(mut self, mut rx: mpsc::UnboundedReceiver<Request>) {
+    loop {
+      let event = tokio::select! {
+        req = rx.recv() => Event::Request(req),
+        line = next_line(&mut self.conn) => Event::Line(line),
+      };
+      match event {
+        // the client is dropped.
+        Event::Request(None) => return,
+        Event::Request(Some(req)) => self.send(req).await,
+        Event::Line(Ok(Some(line))) => self.on_line(&line).await,
+        Event::Line(Ok(None)) => self.exited(None),
+        Event::Line(Err(e)) => self.exited(Some(e)),
+      }
+    }
+  }
+
+  async fn send(&mut self, req: Request) {
+    if self.conn.is_none() {
+      match self.launch().await {
+        Ok(conn) => self.conn = Some(conn),
+        Err(e) => {
+          let _ = req.reply.send(Err(e));
+          return;
+        }
+      }
+    }
+    self.next_id += 1;
+    let id = self.next_id;
+    let msg = json!({ "jsonrpc": "2.0", "id": id, "method": req.method, "params": req.params });
+    self.pending.insert(id, req.reply);
+    if let Err(e) = self.write(&msg).await {
+      self.exited(Some(e));
+    }
+  }
+
+  async fn launch(&mut self) -> PolestarResult<Conn> {
+    let mut child = Command::new(&self.cfg.command)
+      .args(&self.cfg.args)
+      .envs(&self.cfg.env)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .kill_on_drop(true)
+      .spawn()?;
+    let (Some(stdin), Some(stdout), Some(stderr)) =
+      (child.stdin.take(), child.stdout.take(), child.stderr.take())
+    else {
+      unreachable!("the stdio of the server is piped");
+    };
+    let name = self.cfg.name.clone();
+    tokio::spawn(async move {
+      let mut lines = BufReader::new(stderr).lines();
+      while let Ok(Some(line)) = lines.next_line().await {
+        log::debug!("MCP server {}: {}", name, line);
+      }
+    });
+    let mut conn = Conn {
+      _child: child,
+      stdin,
+      stdout: BufReader::new(stdout).lines(),
+    };
+
+    self.next_id += 1;
+    let id = self.next_id;
+    let init = json!({
+      "jsonrpc": "2.0",
+      "id": id,
+      "method": "initialize",
+      "params": {
+        "protocolVersion": PROTOCOL_VERSION,
+        "capabilities": {},
+        "clientInfo": { "name": "polestar", "version": env!("CARGO_PKG_VERSION") }
+      }
+    });
+    write_msg(&mut conn.stdin, &init).await?;
+    let exited = || PolestarError::Tool(format!("MCP server `{}` exited", self.cfg.name));
+    let handshake = async {
+      loop {
+        let line = conn.stdout.next_line().await?.ok_or_else(exited)?;
+        match serde_json::from_str::<JsonValue>(&line) {
+          Ok(msg) if msg["id"].as_u64() == Some(id) => return response_result(msg),
+          _ => log::debug!("MCP server {} prints: {}", self.cfg.name, line),
+        }
+      }
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, handshake)
+      .await
+      .map_err(|_| {
+        PolestarError::Tool(format!("MCP server `{}` doesn't start", self.cfg.name))
+      })??;
+    let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+    write_msg(&mut conn.stdin, &initialized).await?;
+    log::info!("MCP server {} launched", self.cfg.name);
+    Ok(conn)
+  }
+
+  async fn on_line(&mut self, line: &str) {
+    let Ok(msg) = serde_json::from_str::<JsonValue>(line) else {
+      // not a message, i.e. the server logs to the stdout.
+      log::debug!("MCP server {} prints: {}", self.cfg.name, line);
+      return;
+    };
+    match (msg["id"].is_null(), msg["method"].as_str()) {
+      // the request of the server, only `ping` is supported.
+      (false, Some(method)) => {
+        let reply = if method == "ping" {
+          json!({ "jsonrpc": "2.0", "id": msg["id"], "result": {} })
+        } else {
+          let error = json!({ "code": -32601, "message": format!("`{}` not supported", method) });
+          json!({ "jsonrpc": "2.0", "id": msg["id"], "error": error })
+        };
+        if let Err(e) = self.write(&reply).await {
+          self.exited(Some(e));
+        }
+      }
+      (false, None) => {
+        let reply = msg["id"].as_u64().and_then(|id| self.pending.remove(&id));
+        if let Some(reply) = reply {
+          let _ = reply.send(response_result(msg));
+        }
+      }
+      // the notifications are ignored.
+      (true, _) => {}
+    }
+  }
+
+  async fn write(&mut self, msg: &JsonValue) -> std::io::Result<()> {
+    match self.conn.as_mut() {
+      Some(conn) => write_msg(&mut conn.stdin, msg).await,
+      None => Err(std::io::ErrorKind::BrokenPipe.into()),
+    }
+  }
+
+  /// The server exited, the waiting requests fail and the next request
+  /// launches it again.
+  fn exited(&mut self, err: Option<std::io::Error>) {
+    self.conn = None;
+    match err {
+      Some(e) => log::warn!("MCP server {} exited: {}", self.cfg.name, e),
+      None => log::warn!("MCP server {} exited", self.cfg.name),
+    }
+    for (_, reply) in self.pending.drain() {
+      let _ = reply.send(Err(PolestarError::Tool(format!(
+        "MCP server `{}` exited",
+        self.cfg.name
+      ))));
+    }
+  }
+}
+
+async fn next_line(conn: &mut Option<Conn>) -> std::io::Result<Option<String>> {
+  match conn {
+    Some(conn) => conn.stdout.next_line().await,
+    None => std::future::pending().await,
+  }
+}
+
+async fn write_msg(stdin: &mut ChildStdin, msg: &JsonValue) -> std::io::Result<()> {
+  let mut line = msg.to_string();
+  line.push('\n');
+  stdin.write_all(line.as_bytes()).await?;
+  stdin.flush().await
+}
+
+fn response_result(mut msg: JsonValue) -> PolestarResult<JsonValue> {
+  match msg["error"].as_object() {
+    Some(error) => Err(PolestarError::Tool(
+      error
+        .get("message")
+        .and_then(JsonValue::as_str)
+        .unwrap_or("unknown error")
+        .to_owned(),
+    )),
+    None => Ok(msg["result"].take()),
+  }
+}
@@ -0,0 +1,119 @@
+use eventsource_stream::Event;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Map as JsonMap, Value as JsonValue};
+
+use crate::{
+  error::{PolestarError, PolestarServerError},
+  model::{Bot, Channel, TokenUsage},
+};
+
+use super::{
+  open_ai::{ChatCompletionResponseStreamMessage, MessageContent},
+  provider::{status_error, ChatDelta, ChatProvider, StreamFraming},
+  req::{request_messages, request_params, request_system_messages},
+};
+
+/// Ollama take the text as the content and the base64 data of the images
+/// apart, the images on the web are not supported.
+fn ollama_message(msg: ChatCompletionResponseStreamMessage) -> JsonValue {
+  let text = msg.text().unwrap_or_default().to_owned();
+  let images = msg
+    .content
+    .iter()
+    .flat_map(MessageContent::image_urls)
+    .filter_map(|url| url.split_once(";base64,"))
+    .map(|(_, data)| data.to_owned())
+    .collect::<Vec<_>>();
+  let mut value = json!(msg);
+  if matches!(msg.content, Some(MessageContent::Parts(_))) {
+    value["content"] = json!(text);
+    value["images"] = json!(images);
+  }
+  value
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaChatResp {
+  message: Option<OllamaMessage>,
+  #[serde(default)]
+  done: bool,
+  error: Option<String>,
+  // the tokens of the prompt and the answer, in the last line.
+  prompt_eval_count: Option<u32>,
+  eval_count: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaMessage {
+  content: String,
+}
+
+/// The Ollama chat API, it streams newline delimited JSON.
+#[derive(Debug)]
+pub struct OllamaProvider;
+
+impl ChatProvider for OllamaProvider {
+  fn request_body(&self, bot: &Bot, channel: &Channel, content: &str) -> String {
+    let messages = request_system_messages(bot, channel)
+      .into_iter()
+      .chain(request_messages(bot, channel, content))
+      .map(ollama_message)
+      .collect::<Vec<_>>();
+
+    // Ollama only accept a few params at top level, the others are model
+    // options.
+    let mut params = JsonMap::new();
+    let mut options = JsonMap::new();
+    for (key, val) in request_params(bot, channel) {
+      match key.as_str() {
+        "model" | "format" | "keep_alive" => {
+          params.insert(key, val);
+        }
+        "max_tokens" => {
+          options.insert("num_predict".to_owned(), val);
+        }
+        _ => {
+          options.insert(key, val);
+        }
+      }
+    }
+    if !options.is_empty() {
+      params.insert("options".to_owned(), options.into());
+    }
+    params.insert("messages".to_owned(), json!(messages));
+    params.insert("stream".to_owned(), json!(true));
+
+    serde_json::to_string(&params).unwrap_or_default()
+  }
+
+  fn framing(&self) -> StreamFraming { StreamFraming::NdJson }
+
+  fn decode(&self, event: &Event) -> Result<ChatDelta, PolestarError> {
+    let resp = serde_json::from_str::<OllamaChatResp>(&event.data)?;
+    if let Some(error) = resp.error {
+      return Err(PolestarError::PolestarServerError(status_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(error),
+      )));
+    }
+    match resp.message {
+      Some(OllamaMessage { content }) if !content.is_empty() => Ok(ChatDelta::Content(content)),
+      // the last line carries the usage, the stream ends after it.
+      _ if resp.done && resp.eval_count.is_some() => Ok(ChatDelta::Usage(TokenUsage {
+        prompt_tokens: resp.prompt_eval_count.unwrap_or_default(),
+        completion_tokens: resp.eval_count.unwrap_or_default(),
+      })),
+      _ if resp.done => Ok(ChatDelta::Done),
+      _ => Ok(ChatDelta::Ignore),
+    }
+  }
+
+  fn map_error(&self, status: StatusCode, body: &str) -> PolestarServerError {
+    let message = serde_json::from_str::<OllamaChatResp>(body)
+      .ok()
+      .and_then(|resp| resp.error)
+      .unwrap_or_else(|| body.to_owned());
+    status_error(status, Some(message))
+  }
+}
@@ -1,14 +1,28 @@
 use eventsource_stream::Event;
-use futures_util::{Stream, StreamExt};
+use futures_util::Stream;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Map as JsonMap, Value as JsonValue};
 
-use crate::{error::PolestarError, model::MsgRole};
+use crate::{
+  error::{PolestarError, PolestarServerError},
+  model::{Bot, Channel, MsgRole, TokenUsage, Tool, ToolCall},
+};
 
+use super::{
+  provider::{deal_chat_stream, status_error, ChatAnswer, ChatDelta, ChatProvider, ToolCallDelta},
+  req::open_ai_request_content,
+};
+
+// Compatible servers like llama.cpp may omit some of the fields.
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub struct CreateChatCompletionStreamResponse {
   pub id: Option<String>,
+  #[serde(default)]
   pub object: String,
+  #[serde(default)]
   pub created: u32,
+  #[serde(default)]
   pub model: String,
   pub choices: Vec<ChatChoiceDelta>,
   pub usage: Option<Usage>,
@@ -28,10 +42,93 @@ pub struct ChatChoiceDelta {
   pub finish_reason: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct ChatCompletionResponseStreamMessage {
-  pub content: Option<String>,
+  pub content: Option<MessageContent>,
   pub role: Option<Role>,
+  // the pieces of the tool calls the answer streams.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tool_calls: Option<Vec<ToolCallChunk>>,
+}
+
+impl ChatCompletionResponseStreamMessage {
+  /// The text of the content, without the images.
+  pub fn text(&self) -> Option<&str> { self.content.as_ref().map(MessageContent::text) }
+}
+
+/// The content of the message, it's the parts if the message carries images.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MessageContent {
+  Text(String),
+  Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+  /// The text, the first text part of the parts.
+  pub fn text(&self) -> &str {
+    match self {
+      MessageContent::Text(text) => text,
+      MessageContent::Parts(parts) => parts
+        .iter()
+        .find_map(|part| match part {
+          ContentPart::Text { text } => Some(text.as_str()),
+          ContentPart::ImageUrl { .. } => None,
+        })
+        .unwrap_or_default(),
+    }
+  }
+
+  pub fn into_text(self) -> String {
+    match self {
+      MessageContent::Text(text) => text,
+      parts => parts.text().to_owned(),
+    }
+  }
+
+  /// The urls of the images, the local images are the base64 data urls.
+  pub fn image_urls(&self) -> impl Iterator<Item = &str> {
+    let parts = match self {
+      MessageContent::Text(_) => &[][..],
+      MessageContent::Parts(parts) => parts,
+    };
+    parts.iter().filter_map(|part| match part {
+      ContentPart::ImageUrl { image_url } => Some(image_url.url.as_str()),
+      ContentPart::Text { .. } => None,
+    })
+  }
+}
+
+impl From<String> for MessageContent {
+  fn from(text: String) -> Self { MessageContent::Text(text) }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+  Text { text: String },
+  ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ImageUrl {
+  pub url: String,
+}
+
+/// A piece of the tool call, the first piece of a call has its id and name,
+/// the arguments come in pieces.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ToolCallChunk {
+  #[serde(default)]
+  pub index: usize,
+  pub id: Option<String>,
+  pub function: Option<FunctionChunk>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FunctionChunk {
+  pub name: Option<String>,
+  pub arguments: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -42,6 +139,7 @@ pub enum Role {
   User,
   Assistant,
   Function,
+  Tool,
 }
 
 impl From<MsgRole> for Role {
@@ -50,25 +148,138 @@ impl From<MsgRole> for Role {
       MsgRole::System(_) => Role::System,
       MsgRole::User => Role::User,
       MsgRole::Bot(_) => Role::Assistant,
+      MsgRole::Tool(_) => Role::Tool,
     }
   }
 }
 
-pub async fn deal_open_ai_stream(
-  stream: &mut (impl Stream<Item = Result<Event, PolestarError>> + Unpin),
-  mut delta_op: impl FnMut(String),
-) -> Result<String, PolestarError> {
-  let mut answer = String::default();
-  loop {
-    let delta = stream_event_source_handler(stream).await?;
-    if let Some(delta) = delta {
-      answer.push_str(delta.as_ref());
-      delta_op(delta);
-    } else {
-      break;
+#[derive(Debug, Deserialize)]
+struct OpenAIErrorResp {
+  error: OpenAIError,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIError {
+  message: String,
+}
+
+/// The OpenAI chat completions API, also used by the compatible servers.
+#[derive(Debug)]
+pub struct OpenAIProvider;
+
+impl ChatProvider for OpenAIProvider {
+  fn request_body(&self, bot: &Bot, channel: &Channel, content: &str) -> String {
+    open_ai_request_content(bot, channel, content)
+  }
+
+  fn decode(&self, event: &Event) -> Result<ChatDelta, PolestarError> {
+    let terminated = "[DONE]";
+    if event.data == terminated {
+      return Ok(ChatDelta::Done);
+    }
+    if let Ok(OpenAIErrorResp { error }) = serde_json::from_str::<OpenAIErrorResp>(&event.data) {
+      return Err(PolestarError::PolestarServerError(status_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Some(error.message),
+      )));
     }
+    let obj = serde_json::from_str::<CreateChatCompletionStreamResponse>(&event.data)?;
+    let delta = obj.choices.into_iter().next().map(|choice| choice.delta);
+    let (content, tool_calls) = delta.map_or((None, None), |delta| {
+      (
+        delta.content.map(MessageContent::into_text),
+        delta.tool_calls,
+      )
+    });
+    if let Some(tool_calls) = tool_calls.filter(|calls| !calls.is_empty()) {
+      let deltas = tool_calls
+        .into_iter()
+        .map(|chunk| ToolCallDelta {
+          index: chunk.index,
+          id: chunk.id,
+          name: chunk.function.as_ref().and_then(|f| f.name.clone()),
+          arguments: chunk.function.and_then(|f| f.arguments).unwrap_or_default(),
+        })
+        .collect();
+      return Ok(ChatDelta::ToolCalls(deltas));
+    }
+    // the usage comes in the last chunk, which has no choices.
+    Ok(match (content, obj.usage) {
+      (Some(content), _) => ChatDelta::Content(content),
+      (None, Some(usage)) => ChatDelta::Usage(TokenUsage {
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+      }),
+      (None, None) => ChatDelta::Ignore,
+    })
+  }
+
+  fn with_tools(&self, body: String, tools: &[&dyn Tool]) -> String {
+    let Ok(mut params) = serde_json::from_str::<JsonMap<String, JsonValue>>(&body) else {
+      return body;
+    };
+    let specs = tools
+      .iter()
+      .map(|tool| {
+        json!({
+          "type": "function",
+          "function": {
+            "name": tool.name(),
+            "description": tool.desc(),
+            "parameters": tool.schema(),
+          }
+        })
+      })
+      .collect::<Vec<_>>();
+    params.insert("tools".to_owned(), json!(specs));
+    serde_json::to_string(&params).unwrap_or(body)
+  }
+
+  fn with_tool_results(&self, body: String, text: &str, results: &[(ToolCall, String)]) -> String {
+    let Ok(mut params) = serde_json::from_str::<JsonMap<String, JsonValue>>(&body) else {
+      return body;
+    };
+    let Some(messages) = params.get_mut("messages").and_then(JsonValue::as_array_mut) else {
+      return body;
+    };
+    let calls = results
+      .iter()
+      .map(|(call, _)| {
+        json!({
+          "id": call.id,
+          "type": "function",
+          "function": { "name": call.name, "arguments": call.arguments },
+        })
+      })
+      .collect::<Vec<_>>();
+    messages.push(json!({
+      "role": "assistant",
+      "content": (!text.is_empty()).then_some(text),
+      "tool_calls": calls,
+    }));
+    for (call, result) in results {
+      messages.push(json!({ "role": "tool", "tool_call_id": call.id, "content": result }));
+    }
+    serde_json::to_string(&params).unwrap_or(body)
   }
-  Ok(answer)
+
+  fn map_error(&self, status: StatusCode, body: &str) -> PolestarServerError {
+    // Polestar server return its own error.
+    if let Ok(err) = serde_json::from_str::<PolestarServerError>(body) {
+      return err;
+    }
+    let message = serde_json::from_str::<OpenAIErrorResp>(body)
+      .map(|resp| resp.error.message)
+      .unwrap_or_else(|_| body.to_owned());
+    status_error(status, Some(message))
+  }
+}
+
+pub async fn deal_open_ai_stream(
+  stream: &mut (impl Stream<Item = Result<Event, PolestarError>> + Unpin),
+  delta_op: impl FnMut(String),
+) -> Result<ChatAnswer, PolestarError> {
+  deal_chat_stream(&OpenAIProvider, stream, delta_op).await
 }
 
 pub fn mock_stream_string(_content: &str, mut delta_op: impl FnMut(String)) {
@@ -89,30 +300,3 @@ pub fn mock_stream_string(_content: &str, mut delta_op: impl FnMut(String)) {
     delta_op(s);
   }
 }
-
-async fn stream_event_source_handler(
-  stream: &mut (impl Stream<Item = Result<Event, PolestarError>> + Unpin),
-) -> Result<Option<String>, PolestarError> {
-  let terminated = "[DONE]";
-  let chunk_size = 256;
-  let items = stream.ready_chunks(chunk_size).next().await;
-
-  let Some(items) = items else { return Ok(None) };
-
-  let mut delta = String::default();
-  for item in items {
-    let data = item?.data;
-    if data == terminated {
-      break;
-    }
-    let obj = serde_json::from_str::<CreateChatCompletionStreamResponse>(&data).unwrap();
-    let choices = obj.choices;
-    assert!(choices.len() == 1);
-
-    if let Some(content) = &choices[0].delta.content {
-      delta.push_str(content);
-    }
-  }
-
-  Ok(Some(delta))
-}
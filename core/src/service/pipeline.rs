@@ -0,0 +1,125 @@
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::{
+    AppInfo, Bot, BotId, Channel, ChannelCfg, Msg, MsgId, MsgMeta, MsgStatus, PipelineLink,
+    PipelineStep,
+  },
+};
+
+use super::req::chat_request_content;
+
+/// A run of the pipeline bot to answer a question. The steps' bots answer in
+/// order, every step's message replies to the previous one and its input is
+/// built from the previous output.
+pub struct PipelineRun {
+  bot_id: BotId,
+  steps: Vec<PipelineStep>,
+  question: String,
+  // the message the next step replies to, the question before the first step.
+  input_id: MsgId,
+  // the input of the current step.
+  input: String,
+  // the index of the next step.
+  next: usize,
+}
+
+impl PipelineRun {
+  /// Start to run the pipeline bot on the question, `None` if the bot is not a
+  /// pipeline.
+  pub fn new(bot: &Bot, question_id: MsgId, question: String) -> Option<Self> {
+    Some(Self {
+      bot_id: bot.id().clone(),
+      steps: bot.pipeline()?,
+      question,
+      input_id: question_id,
+      input: String::new(),
+      next: 0,
+    })
+  }
+
+  /// Check every step's bot exists and is not a pipeline itself.
+  pub fn check(&self, info: &AppInfo) -> PolestarResult<()> {
+    for step in &self.steps {
+      match info.bot(&step.bot) {
+        None => {
+          return Err(PolestarError::Pipeline(format!(
+            "bot `{}` of the pipeline `{}` not found",
+            step.bot, self.bot_id
+          )))
+        }
+        Some(bot) if bot.pipeline().is_some() => {
+          return Err(PolestarError::Pipeline(format!(
+            "pipeline `{}` can't run the pipeline `{}` as a step",
+            self.bot_id, step.bot
+          )))
+        }
+        Some(_) => {}
+      }
+    }
+    Ok(())
+  }
+
+  #[inline]
+  pub fn bot_id(&self) -> &BotId { &self.bot_id }
+
+  #[inline]
+  pub fn steps(&self) -> &[PipelineStep] { &self.steps }
+
+  /// The message for the next step to stream its output into, it replies to
+  /// the previous step's message. `None` if all the steps are done, or the
+  /// previous step is not fulfilled, which stops the pipeline.
+  pub fn next_msg(&mut self, channel: &Channel) -> Option<Msg> {
+    let step = self.steps.get(self.next)?;
+    let output = if self.next == 0 {
+      self.question.clone()
+    } else {
+      let prev = channel.msg(&self.input_id)?.cur_cont_ref();
+      if *prev.status() != MsgStatus::Fulfilled {
+        return None;
+      }
+      prev.text()?.to_owned()
+    };
+    self.input = step.input(&self.question, &output);
+
+    let link = PipelineLink {
+      bot_id: self.bot_id.clone(),
+      step: self.next,
+    };
+    let msg = Msg::new_bot_text(
+      step.bot.clone(),
+      MsgMeta::pipeline_step(self.input_id, link),
+    );
+    self.input_id = *msg.id();
+    self.next += 1;
+    Some(msg)
+  }
+
+  /// The bot of the current step, whose message is the last one returned by
+  /// `next_msg`.
+  pub fn cur_bot(&self) -> Option<&BotId> {
+    self
+      .next
+      .checked_sub(1)
+      .and_then(|idx| self.steps.get(idx))
+      .map(|step| &step.bot)
+  }
+
+  /// The request body of the current step, after its message is added to the
+  /// channel. The first step has the channel's messages as the context, the
+  /// others only the input.
+  pub fn request_content(&self, info: &AppInfo, channel: &Channel) -> Option<String> {
+    let bot = info.bot(self.cur_bot()?)?;
+    if self.next == 1 {
+      return Some(chat_request_content(info, bot, channel, &self.input));
+    }
+    let context = Channel::new(
+      *channel.id(),
+      channel.name().to_owned(),
+      None,
+      ChannelCfg::default(),
+      None,
+      None,
+    );
+    Some(chat_request_content(info, bot, &context, &self.input))
+  }
+}
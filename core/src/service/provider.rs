@@ -0,0 +1,218 @@
+use std::time::Instant;
+
+use eventsource_stream::Event;
+use futures_util::{Stream, StreamExt};
+use reqwest::StatusCode;
+use serde_json::Value as JsonValue;
+
+use crate::{
+  error::{PolestarError, PolestarServerErrType, PolestarServerError},
+  model::{Bot, Channel, MsgUsage, ProviderKind, TokenUsage, Tool, ToolCall},
+};
+
+use super::{
+  anthropic::AnthropicProvider,
+  ollama::OllamaProvider,
+  open_ai::OpenAIProvider,
+  token::{estimate_body_tokens, estimate_tokens},
+};
+
+/// `ChatProvider` speaks the wire format of a chat backend, it builds the
+/// request body, decodes the streamed response and maps the errors.
+pub trait ChatProvider: Sync {
+  /// Build the request body of the `content` asked in the `channel`.
+  fn request_body(&self, bot: &Bot, channel: &Channel, content: &str) -> String;
+
+  /// How the response stream is framed.
+  fn framing(&self) -> StreamFraming { StreamFraming::EventSource }
+
+  /// Advertise the tools the bot can call in the request `body`, the
+  /// providers don't support tools keep the body as it is.
+  fn with_tools(&self, body: String, _tools: &[&dyn Tool]) -> String { body }
+
+  /// The request `body` followed by the answer `text` which calls the tools
+  /// and the results of the calls, to send the results back.
+  fn with_tool_results(
+    &self,
+    body: String,
+    _text: &str,
+    _results: &[(ToolCall, String)],
+  ) -> String {
+    body
+  }
+
+  /// Decode an event of the response stream.
+  fn decode(&self, event: &Event) -> Result<ChatDelta, PolestarError>;
+
+  /// Map a failed response to the server error.
+  fn map_error(&self, status: StatusCode, body: &str) -> PolestarServerError;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFraming {
+  /// Server-sent events, `data` of every event is a payload.
+  EventSource,
+  /// Newline delimited JSON, every line is a payload.
+  NdJson,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatDelta {
+  /// A piece of the answer.
+  Content(String),
+  /// The tokens the request cost, some providers report it in pieces.
+  Usage(TokenUsage),
+  /// The pieces of the tool calls.
+  ToolCalls(Vec<ToolCallDelta>),
+  /// The event don't carry any content, i.e. keep alive or metadata.
+  Ignore,
+  /// The answer is finished.
+  Done,
+}
+
+/// A piece of the `index`th tool call of the answer, its arguments are
+/// appended to the ones received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolCallDelta {
+  pub index: usize,
+  pub id: Option<String>,
+  pub name: Option<String>,
+  pub arguments: String,
+}
+
+pub fn chat_provider(kind: ProviderKind) -> &'static dyn ChatProvider {
+  match kind {
+    ProviderKind::OpenAI | ProviderKind::LlamaCpp => &OpenAIProvider,
+    ProviderKind::Anthropic => &AnthropicProvider,
+    ProviderKind::Ollama => &OllamaProvider,
+  }
+}
+
+/// The server error from the HTTP status, use the body as message if it has.
+pub(crate) fn status_error(status: StatusCode, message: Option<String>) -> PolestarServerError {
+  PolestarServerError {
+    kind: PolestarServerErrType::from_status(status),
+    message: message
+      .filter(|msg| !msg.is_empty())
+      .unwrap_or_else(|| status.to_string()),
+    retry_after: None,
+  }
+}
+
+/// The answer streamed by the provider.
+#[derive(Debug, Clone, Default)]
+pub struct ChatAnswer {
+  pub text: String,
+  /// The tokens the provider reported, if it did.
+  pub usage: Option<TokenUsage>,
+  /// When the first piece of the answer arrived.
+  pub first_token_at: Option<Instant>,
+  /// The tools the answer calls, the answer goes on after their results are
+  /// sent back.
+  pub tool_calls: Vec<ToolCall>,
+}
+
+impl ChatAnswer {
+  /// The usage of the answer to the request `body` sent at `started`, the
+  /// tokens are estimated locally if the provider didn't report them. The
+  /// cost is left to `AppData::record_usage`.
+  pub fn msg_usage(&self, bot: &Bot, body: &str, started: Instant) -> MsgUsage {
+    let (prompt_tokens, completion_tokens, estimated) = match self.usage {
+      Some(usage) => (usage.prompt_tokens, usage.completion_tokens, false),
+      None => (
+        estimate_body_tokens(body) as u32,
+        estimate_tokens(&self.text) as u32,
+        true,
+      ),
+    };
+    let model = serde_json::from_str::<JsonValue>(body)
+      .ok()
+      .and_then(|body| body["model"].as_str().map(str::to_owned))
+      .or_else(|| bot.params()["model"].as_str().map(str::to_owned))
+      .unwrap_or_default();
+    MsgUsage {
+      prompt_tokens,
+      completion_tokens,
+      estimated,
+      model,
+      provider: bot.sp().to_owned(),
+      latency_ms: started.elapsed().as_millis() as u64,
+      first_token_ms: self
+        .first_token_at
+        .map(|at| at.saturating_duration_since(started).as_millis() as u64),
+      cost: None,
+    }
+  }
+}
+
+pub async fn deal_chat_stream(
+  provider: &dyn ChatProvider,
+  stream: &mut (impl Stream<Item = Result<Event, PolestarError>> + Unpin),
+  mut delta_op: impl FnMut(String),
+) -> Result<ChatAnswer, PolestarError> {
+  let mut answer = ChatAnswer::default();
+  loop {
+    let delta = stream_event_source_handler(provider, stream, &mut answer).await?;
+    if let Some(delta) = delta {
+      if !delta.is_empty() && answer.first_token_at.is_none() {
+        answer.first_token_at = Some(Instant::now());
+      }
+      answer.text.push_str(delta.as_ref());
+      delta_op(delta);
+    } else {
+      break;
+    }
+  }
+  Ok(answer)
+}
+
+async fn stream_event_source_handler(
+  provider: &dyn ChatProvider,
+  stream: &mut (impl Stream<Item = Result<Event, PolestarError>> + Unpin),
+  answer: &mut ChatAnswer,
+) -> Result<Option<String>, PolestarError> {
+  let chunk_size = 256;
+  let items = stream.ready_chunks(chunk_size).next().await;
+
+  let Some(items) = items else { return Ok(None) };
+
+  let mut delta = String::default();
+  for item in items {
+    match provider.decode(&item?)? {
+      ChatDelta::Content(content) => delta.push_str(&content),
+      ChatDelta::Usage(reported) => answer
+        .usage
+        .get_or_insert_with(TokenUsage::default)
+        .merge(reported),
+      ChatDelta::ToolCalls(deltas) => {
+        for delta in deltas {
+          let calls = &mut answer.tool_calls;
+          // a new call comes right after the known ones, don't trust the
+          // server to allocate the calls for it.
+          if delta.index > calls.len() {
+            return Err(PolestarError::Tool(format!(
+              "tool call {} skips ahead of {} calls",
+              delta.index,
+              calls.len()
+            )));
+          }
+          if delta.index == calls.len() {
+            calls.push(ToolCall::default());
+          }
+          let call = &mut calls[delta.index];
+          if let Some(id) = delta.id {
+            call.id = id;
+          }
+          if let Some(name) = delta.name {
+            call.name = name;
+          }
+          call.arguments.push_str(&delta.arguments);
+        }
+      }
+      ChatDelta::Ignore => {}
+      ChatDelta::Done => break,
+    }
+  }
+
+  Ok(Some(delta))
+}
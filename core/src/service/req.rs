@@ -1,24 +1,42 @@
-use eventsource_stream::{Event, Eventsource};
-use futures_util::{Stream, TryStreamExt};
+use std::{collections::HashMap, pin::Pin};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use eventsource_stream::{Event, EventStreamError, Eventsource};
+use futures_util::{
+  future::{AbortHandle, Abortable},
+  Future, Stream, TryStreamExt,
+};
 use log::warn;
 use regex::Regex;
 use reqwest::{
-  header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
-  Method, RequestBuilder, StatusCode,
+  header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER, USER_AGENT,
+  },
+  Method, RequestBuilder, Response, StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use serde_json::{json, value::Value as JsonValue};
+use serde_json::{json, value::Value as JsonValue, Map as JsonMap};
 use serde_json_path::JsonPath;
 
 use crate::{
   error::{PolestarError, PolestarResult, PolestarServerError},
   model::{
-    AppInfo, Bot, BotId, Channel, FeedbackMessageListForServer, FeedbackTimestamp, GlbVar, Quota,
+    AppInfo, Bot, BotId, Budget, Channel, ChannelSummary, FeedbackMessageListForServer,
+    FeedbackTimestamp, GlbVar, Image, MsgId, MsgRole, ProviderKind, Quota, RetryPolicy,
     ServerProvider, UserFeedbackMessageForServer, GLOBAL_VARS,
   },
 };
 
-use super::open_ai::{ChatCompletionResponseStreamMessage, Role};
+use super::{
+  client::http_client,
+  image,
+  open_ai::{ChatCompletionResponseStreamMessage, ContentPart, ImageUrl, MessageContent, Role},
+  provider::{chat_provider, status_error, ChatProvider, StreamFraming},
+  token,
+};
+
+/// The events stream of a chat response.
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<Event, PolestarError>> + Send>>;
 
 const POLESTAR_STREAM_URL: &str = "https://api.ribir.org/stream/open_ai";
 
@@ -28,8 +46,7 @@ pub fn req_builder(
   headers: HeaderMap,
   body: Option<String>,
 ) -> RequestBuilder {
-  let client = reqwest::Client::new();
-  let mut req_builder = client.request(method, url);
+  let mut req_builder = http_client().request(method, url);
   for (key, value) in headers.iter() {
     req_builder = req_builder.header(key, value);
   }
@@ -44,49 +61,319 @@ async fn req_stream(
   method: Method,
   headers: HeaderMap,
   body: Option<String>,
-) -> Result<impl Stream<Item = Result<Event, PolestarError>>, PolestarError> {
-  let req_builder = req_builder(&url, method, headers, body);
-  let resp = req_builder.send().await?;
-  let content = resp.headers().get("content-type");
-  let stream_content = "text/event-stream";
-  let content_type = content.and_then(|t| t.to_str().ok());
-  if resp.status() == StatusCode::OK && content_type == Some(stream_content) {
-    let eventsource = resp.bytes_stream().eventsource();
-    Ok(eventsource.map_err(|e| e.into()))
+  provider: &'static dyn ChatProvider,
+) -> Result<EventStream, PolestarError> {
+  let client = http_client();
+  let resp = client
+    .send(req_builder(&url, method, headers, body))
+    .await?;
+  let status = resp.status();
+  let content_type = resp
+    .headers()
+    .get(CONTENT_TYPE)
+    .and_then(|t| t.to_str().ok())
+    .unwrap_or_default();
+  let is_stream = match provider.framing() {
+    StreamFraming::EventSource => content_type.starts_with("text/event-stream"),
+    StreamFraming::NdJson => true,
+  };
+  if status == StatusCode::OK && is_stream {
+    let body = client.bytes_stream(resp);
+    let stream: EventStream = match provider.framing() {
+      StreamFraming::EventSource => Box::pin(body.eventsource().map_err(|e| match e {
+        EventStreamError::Transport(e) => e,
+        EventStreamError::Utf8(e) => EventStreamError::Utf8(e).into(),
+        EventStreamError::Parser(e) => EventStreamError::Parser(e).into(),
+      })),
+      StreamFraming::NdJson => Box::pin(ndjson_events(body)),
+    };
+    Ok(stream)
   } else {
-    Err(PolestarError::PolestarServerError(
-      resp.json::<PolestarServerError>().await?,
-    ))
+    let retry_after = retry_after(status, resp.headers());
+    let body = resp.text().await?;
+    let mut err = provider.map_error(status, &body);
+    err.retry_after = retry_after;
+    Err(PolestarError::PolestarServerError(err))
   }
 }
 
+/// The seconds of the `Retry-After` header, only 429 and 503 responses are
+/// respected.
+fn retry_after(status: StatusCode, headers: &HeaderMap) -> Option<u64> {
+  if !matches!(
+    status,
+    StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+  ) {
+    return None;
+  }
+  headers
+    .get(RETRY_AFTER)
+    .and_then(|val| val.to_str().ok())
+    .and_then(|val| val.trim().parse::<u64>().ok())
+}
+
+/// Call `req` until it succeeds or `policy` stops retrying the error,
+/// `on_retry` is called with the number of the next attempt before waiting.
+pub async fn retry_request<T, Fut>(
+  policy: &RetryPolicy,
+  mut req: impl FnMut() -> Fut,
+  mut on_retry: impl FnMut(u32),
+) -> PolestarResult<T>
+where
+  Fut: Future<Output = PolestarResult<T>>,
+{
+  let mut attempt = 1;
+  loop {
+    match req().await {
+      Err(err) => match policy.retry_delay(attempt, &err) {
+        Some(delay) => {
+          attempt += 1;
+          on_retry(attempt);
+          tokio::time::sleep(delay).await;
+        }
+        None => return Err(err),
+      },
+      rst => return rst,
+    }
+  }
+}
+
+/// Send the request to the Polestar server by the retry `policy`, a failed
+/// response is converted to an error.
+async fn send_with_retry(req: RequestBuilder, policy: &RetryPolicy) -> PolestarResult<Response> {
+  retry_request(
+    policy,
+    || {
+      let req = req.try_clone().expect("request body must be cloneable");
+      async move {
+        let resp = http_client().send(req).await?;
+        let status = resp.status();
+        if status.is_success() {
+          return Ok(resp);
+        }
+        let retry_after = retry_after(status, resp.headers());
+        let body = resp.text().await?;
+        let mut err = serde_json::from_str::<PolestarServerError>(&body)
+          .unwrap_or_else(|_| status_error(status, Some(body)));
+        err.retry_after = retry_after;
+        Err(PolestarError::PolestarServerError(err))
+      }
+    },
+    |_| {},
+  )
+  .await
+}
+
 pub fn create_text_request(info: &AppInfo, bot_id: BotId) -> TextStreamReq {
   let bot = info.bot(&bot_id).unwrap();
   let sp_name = bot.sp();
   let sp = info.providers().get(sp_name);
-  if let Some(sp) = sp {
+  let req = if let Some(sp) = sp {
     create_req_from_bot(bot, Some(sp))
   } else {
     create_req_from_bot(bot, default_polestar_provider(sp_name, info).as_ref())
-  }
+  };
+  let retry = sp
+    .and_then(|sp| sp.retry.clone())
+    .unwrap_or_else(|| info.cfg().retry().clone());
+  req.set_retry(retry).set_budget(info.budget(&bot_id))
+}
+
+/// Split a newline delimited JSON body into events, every line is an event's
+/// data.
+fn ndjson_events<B: AsRef<[u8]>, E: Into<PolestarError>>(
+  stream: impl Stream<Item = Result<B, E>>,
+) -> impl Stream<Item = Result<Event, PolestarError>> {
+  let mut buf = Vec::new();
+  stream
+    .map_err(Into::into)
+    .map_ok(move |bytes| {
+      buf.extend_from_slice(bytes.as_ref());
+      let mut events = vec![];
+      while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+        let line = buf.drain(..=pos).collect::<Vec<_>>();
+        let data = String::from_utf8_lossy(&line).trim().to_owned();
+        if !data.is_empty() {
+          events.push(Ok(Event { data, ..<_>::default() }));
+        }
+      }
+      futures_util::stream::iter(events)
+    })
+    .try_flatten()
 }
 
 pub fn open_ai_request_content<'a>(bot: &'a Bot, channel: &'a Channel, content: &'a str) -> String {
-  let mut messages = vec![];
-  if let Some(prompt) = bot.params().get("prompt").and_then(|v| v.as_str()) {
-    messages.push(ChatCompletionResponseStreamMessage {
-      content: Some(prompt.to_string()),
-      role: Some(super::open_ai::Role::System),
-    });
-  }
-  let context_numbers = channel.cfg().mode().context_number();
-  let content_with_context = channel
-    .msgs()
+  let mut messages = request_system_messages(bot, channel);
+  messages.extend(request_messages(bot, channel, content));
+
+  let mut params = request_params(bot, channel);
+  params
+    .entry("model")
+    .or_insert_with(|| json!(DEFAULT_MODEL));
+  params.insert("messages".to_owned(), json!(messages));
+  params.insert("stream".to_owned(), json!(true));
+  // ask for the usage chunk at the end of the stream only if the server
+  // supports it, the strict servers reject the unknown option. The usage is
+  // estimated if it's not reported.
+  if bot.stream_usage() {
+    params
+      .entry("stream_options")
+      .or_insert_with(|| json!({ "include_usage": true }));
+  }
+
+  serde_json::to_string(&params).unwrap_or_default()
+}
+
+/// Build the request body in the wire format of the bot's provider, with the
+/// tools the bot can call. The body of an image bot is the drawing request.
+pub fn chat_request_content(info: &AppInfo, bot: &Bot, channel: &Channel, content: &str) -> String {
+  if bot.is_image_gen() {
+    return image::image_request_content(bot, channel, content);
+  }
+  let provider = bot_provider(info, bot);
+  let body = provider.request_body(bot, channel, content);
+  let tools = info.tools().bot_tools(bot);
+  if tools.is_empty() {
+    body
+  } else {
+    provider.with_tools(body, &tools)
+  }
+}
+
+/// The chat provider which the bot's service provider speaks.
+pub fn bot_provider(info: &AppInfo, bot: &Bot) -> &'static dyn ChatProvider {
+  let kind = info
+    .providers()
+    .get(bot.sp())
+    .map_or(ProviderKind::default(), |sp| sp.kind);
+  chat_provider(kind)
+}
+
+/// The system prompt of the request, the channel's `prompt` param take
+/// precedence over the bot's.
+pub fn request_prompt<'a>(bot: &'a Bot, channel: &'a Channel) -> Option<&'a str> {
+  channel
+    .cfg()
+    .params()
+    .and_then(|params| params.get("prompt"))
+    .or_else(|| bot.params().get("prompt"))
+    .and_then(|v| v.as_str())
+    .filter(|prompt| !prompt.is_empty())
+}
+
+/// The summary of the channel's earlier messages, if the channel's memory is
+/// on and the summary is of the active branch.
+pub fn request_summary(channel: &Channel) -> Option<&ChannelSummary> {
+  channel
+    .cfg()
+    .memory()
+    .then(|| channel.summary())
+    .flatten()
+    // the summary of another branch is not used.
+    .filter(|summary| {
+      channel
+        .branch_msgs()
+        .iter()
+        .any(|msg| msg.id() == summary.until_msg_id())
+    })
+}
+
+/// The system messages of the request, the system prompt followed by the
+/// summary of the channel's earlier messages.
+pub fn request_system_messages(
+  bot: &Bot,
+  channel: &Channel,
+) -> Vec<ChatCompletionResponseStreamMessage> {
+  request_prompt(bot, channel)
+    .map(str::to_owned)
+    .into_iter()
+    .chain(request_summary(channel).map(|s| format!("{}{}", SUMMARY_PREFIX, s.content())))
+    .map(|content| ChatCompletionResponseStreamMessage {
+      content: Some(content.into()),
+      role: Some(Role::System),
+      ..<_>::default()
+    })
+    .collect()
+}
+
+/// The context messages of the channel followed by the user's `content`.
+///
+/// The context is filled from the newest message until the token budget of
+/// the bot is reached, the system messages and the `content` (which carry the
+/// quoted message) are always kept and counted first. The messages covered by
+/// the channel's summary are not sent again.
+///
+/// The images of the user messages are sent with their text only if the bot
+/// accepts images.
+pub fn request_messages(
+  bot: &Bot,
+  channel: &Channel,
+  content: &str,
+) -> Vec<ChatCompletionResponseStreamMessage> {
+  // the last two messages are the sending one and the bot's pending reply.
+  fill_messages(bot, channel, content, 2)
+    .into_iter()
+    .map(|(mut msg, images)| {
+      if !images.is_empty() && bot.accepts_images() {
+        let text = msg
+          .content
+          .map(MessageContent::into_text)
+          .unwrap_or_default();
+        let mut parts = vec![ContentPart::Text { text }];
+        parts.extend(images.iter().filter_map(|img| {
+          let url = image_url(channel, img)?;
+          Some(ContentPart::ImageUrl { image_url: ImageUrl { url } })
+        }));
+        msg.content = Some(MessageContent::Parts(parts));
+      }
+      msg
+    })
+    .collect()
+}
+
+/// The url sent to the bot for the image, the image stored in the database is
+/// sent as the base64 data url.
+fn image_url(channel: &Channel, image: &Image) -> Option<String> {
+  if let Some(url) = image.url() {
+    return Some(url.to_owned());
+  }
+  let attachment = channel.attachment(image.file()?)?;
+  Some(format!(
+    "data:{};base64,{}",
+    attachment.mime().media_type(),
+    STANDARD.encode(attachment.data())
+  ))
+}
+
+/// The text messages of the request with the images of each message, the
+/// images are not counted to the tokens.
+fn fill_messages<'a>(
+  bot: &Bot,
+  channel: &'a Channel,
+  content: &str,
+  pending: usize,
+) -> Vec<(ChatCompletionResponseStreamMessage, &'a [Image])> {
+  let budget = token::context_budget(bot, channel);
+  let system = request_system_messages(bot, channel);
+  let mut used = token::estimate_request_tokens(system.iter().filter_map(|m| m.text()))
+    + token::estimate_msg_tokens(content);
+  let context = channel.context_msgs();
+  // the images of the sending message, which is one of the pending messages.
+  let images = context
     .iter()
     .rev()
-    .skip(2)
-    .take(context_numbers)
+    .take(pending)
+    .copied()
+    .find(|m| m.role().is_user())
+    .map_or(&[][..], |m| m.cur_cont_ref().images());
+  let summarized = request_summary(channel).map(ChannelSummary::until_msg_id);
+  let mut messages = context
+    .into_iter()
+    // the tool calls are done, their results are already in the answers.
+    .filter(|m| !m.role().is_tool())
     .rev()
+    .skip(pending)
+    .take_while(|m| Some(m.id()) != summarized)
     .map(|m| {
       let quote_text = m.meta().quote_id().and_then(|id| {
         channel
@@ -94,26 +381,283 @@ pub fn open_ai_request_content<'a>(bot: &'a Bot, channel: &'a Channel, content:
           .and_then(|m| m.cur_cont_ref().text().map(|s| s.to_owned()))
       });
       let cont_text = m.cur_cont_ref().text().map(|s| s.to_owned());
-      ChatCompletionResponseStreamMessage {
-        content: Some(quote_text.unwrap_or_default() + &cont_text.unwrap_or_default()),
+      let msg = ChatCompletionResponseStreamMessage {
+        content: Some((quote_text.unwrap_or_default() + &cont_text.unwrap_or_default()).into()),
         role: Some(Role::from(m.role().clone())),
-      }
+        ..<_>::default()
+      };
+      (msg, m.cur_cont_ref().images())
+    })
+    .take_while(|(m, _)| {
+      used += token::estimate_msg_tokens(m.text().unwrap_or_default());
+      used <= budget
     })
     .collect::<Vec<_>>();
-  messages.extend(content_with_context);
-  messages.push(ChatCompletionResponseStreamMessage {
-    content: Some(content.to_owned()),
+  messages.reverse();
+  let msg = ChatCompletionResponseStreamMessage {
+    content: Some(content.to_owned().into()),
     role: Some(Role::User),
-  });
+    ..<_>::default()
+  };
+  messages.push((msg, images));
+  messages
+}
+
+/// The request to answer the question of the bot's message `msg_id` again.
+/// It has the same context as the original request: the messages before the
+/// question in the active branch, and the question with its quoted message.
+/// `params` override the channel's params for this request only.
+pub fn regenerate_request_content(
+  info: &AppInfo,
+  bot: &Bot,
+  channel: &Channel,
+  msg_id: &MsgId,
+  params: Option<&JsonMap<String, JsonValue>>,
+) -> Option<String> {
+  let (context, content) = regenerate_context(channel, msg_id, params)?;
+  Some(chat_request_content(info, bot, &context, &content))
+}
 
-  // TODO:
-  let params = json!({
-    "model": "gpt-3.5-turbo",
-    "messages": messages,
-    "stream": true,
-  });
+/// The channel of the original request of the bot's message, and the content
+/// sent to it.
+pub(crate) fn regenerate_context(
+  channel: &Channel,
+  msg_id: &MsgId,
+  params: Option<&JsonMap<String, JsonValue>>,
+) -> Option<(Channel, String)> {
+  let answer = channel.msg(msg_id)?;
+  let question = channel.msg(answer.meta().source_id()?)?;
+  let content = question.cur_cont_ref().text()?;
+  let quote_text = question
+    .meta()
+    .quote_id()
+    .and_then(|id| channel.msg(id))
+    .and_then(|msg| msg.cur_cont_ref().text());
+  let content = quote_text.map_or_else(
+    || content.to_owned(),
+    |quote_text| format!("{} {}", quote_text, content),
+  );
 
-  serde_json::to_string(&params).unwrap_or_default()
+  let mut msgs = channel
+    .context_msgs()
+    .into_iter()
+    .take_while(|msg| msg.id() != question.id())
+    .cloned()
+    .collect::<Vec<_>>();
+  // the pending question and answer are not sent as the context.
+  msgs.extend([question.clone(), answer.clone()]);
+
+  let mut cfg = channel.cfg().clone();
+  if let Some(params) = params {
+    let mut merged = cfg
+      .params()
+      .and_then(|p| p.as_object())
+      .cloned()
+      .unwrap_or_default();
+    merged.extend(params.clone());
+    cfg.set_params(Some(JsonValue::Object(merged)));
+  }
+  // the database to load the images of the messages.
+  let mut context = Channel::new(
+    *channel.id(),
+    channel.name().to_owned(),
+    None,
+    cfg,
+    None,
+    channel.db(),
+  );
+  let summary = channel
+    .summary()
+    .filter(|summary| msgs.iter().any(|msg| msg.id() == summary.until_msg_id()))
+    .cloned();
+  context.load_msgs(msgs);
+  context.load_summary(summary);
+  Some((context, content))
+}
+
+/// The estimated tokens of the request that send `content` to the bot in the
+/// channel.
+pub fn request_tokens(bot: &Bot, channel: &Channel, content: &str) -> usize {
+  messages_tokens(bot, channel, &fill_messages(bot, channel, content, 2))
+}
+
+/// The estimated tokens of the request if the draft `content` is sent to the
+/// bot, before its messages are added to the channel.
+pub fn draft_tokens(bot: &Bot, channel: &Channel, content: &str) -> usize {
+  messages_tokens(bot, channel, &fill_messages(bot, channel, content, 0))
+}
+
+fn messages_tokens(
+  bot: &Bot,
+  channel: &Channel,
+  messages: &[(ChatCompletionResponseStreamMessage, &[Image])],
+) -> usize {
+  let system = request_system_messages(bot, channel);
+  let contents = system
+    .iter()
+    .chain(messages.iter().map(|(m, _)| m))
+    .map(|m| m.text().unwrap_or_default());
+  token::estimate_request_tokens(contents)
+}
+
+/// The system message of the summary start with it.
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+/// The system prompt asking the bot to summarise the conversation.
+const SUMMARY_PROMPT: &str = "Summarise the conversation below concisely. Keep the facts, \
+                              decisions and open questions, so that the conversation can go \
+                              on without the original messages.";
+/// Summarise only when at least this many messages are out of the context, so
+/// it's not requested on every message.
+const SUMMARY_MIN_MSGS: usize = 4;
+
+/// The request asking the bot to summarise the channel's messages which are
+/// out of the context budget, together with the previous summary. Return the
+/// request body and the id of the newest message it covers, `None` if the
+/// channel's memory is off or there are not enough messages to summarise.
+pub fn summary_request_content(
+  info: &AppInfo,
+  bot: &Bot,
+  channel: &Channel,
+) -> Option<(String, MsgId)> {
+  let (summarizing, transcript, until) = summary_request(bot, channel)?;
+  // the summary request don't call the tools.
+  Some((
+    bot_provider(info, bot).request_body(bot, &summarizing, &transcript),
+    until,
+  ))
+}
+
+/// The channel to send the summary request in, the transcript to summarise and
+/// the id of the newest message in it.
+fn summary_request(bot: &Bot, channel: &Channel) -> Option<(Channel, String, MsgId)> {
+  if !channel.cfg().memory() {
+    return None;
+  }
+  let msgs = channel.context_msgs();
+  // the context of the next message, without the user content.
+  let kept = fill_messages(bot, channel, "", 0).len() - 1;
+  let start = request_summary(channel)
+    .and_then(|s| msgs.iter().position(|m| m.id() == s.until_msg_id()))
+    .map_or(0, |idx| idx + 1);
+  let end = msgs.len().saturating_sub(kept);
+  if end < start + SUMMARY_MIN_MSGS {
+    return None;
+  }
+  let until = *msgs[end - 1].id();
+
+  // the transcript is limited by the budget too, the oldest lines are dropped.
+  let budget = token::context_budget(bot, channel);
+  let mut used = token::estimate_request_tokens([SUMMARY_PROMPT]);
+  let mut lines = msgs[start..end]
+    .iter()
+    .rev()
+    .filter_map(|m| {
+      let text = m.cur_cont_ref().text()?;
+      let role = match m.role() {
+        MsgRole::User => "User",
+        MsgRole::Bot(_) => "Assistant",
+        MsgRole::System(_) => "System",
+        MsgRole::Tool(_) => "Tool",
+      };
+      Some(format!("{}: {}", role, text))
+    })
+    .take_while(|line| {
+      used += token::estimate_tokens(line);
+      used <= budget
+    })
+    .collect::<Vec<_>>();
+  if let Some(summary) = request_summary(channel) {
+    lines.push(format!("{}{}", SUMMARY_PREFIX, summary.content()));
+  }
+  lines.reverse();
+
+  let mut cfg = channel.cfg().clone();
+  let mut params = cfg
+    .params()
+    .and_then(|p| p.as_object())
+    .cloned()
+    .unwrap_or_default();
+  params.insert("prompt".to_owned(), json!(SUMMARY_PROMPT));
+  cfg.set_params(Some(JsonValue::Object(params)));
+  cfg.set_memory(false);
+  let summarizing = Channel::new(
+    *channel.id(),
+    channel.name().to_owned(),
+    None,
+    cfg,
+    None,
+    None,
+  );
+  Some((summarizing, lines.join("\n\n"), until))
+}
+
+/// The model used when neither the bot nor the channel specify one.
+pub(crate) const DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+
+/// The params used by Polestar itself, never sent to the service.
+const LOCAL_PARAMS: [&str; 5] = [
+  "prompt",
+  "context_tokens",
+  "use_tools",
+  "vision",
+  "stream_usage",
+];
+
+/// Merge the bot's `params` with the channel's override into the request
+/// params. `prompt` is excluded because it's sent as the system message,
+/// `context_tokens` because it only limit the context locally, `use_tools`
+/// because the tools are advertised by the request itself, `vision` and
+/// `stream_usage` because they only decide what is sent, and known OpenAI
+/// params with an
+/// invalid value type are dropped. A `null` in the channel's override removes
+/// the bot's param.
+pub fn request_params(bot: &Bot, channel: &Channel) -> JsonMap<String, JsonValue> {
+  let mut params = JsonMap::new();
+  let sources = [
+    (format!("bot {}", bot.id()), Some(bot.params())),
+    (
+      format!("the override of channel {}", channel.id()),
+      channel.cfg().params(),
+    ),
+  ];
+  for (source, obj) in sources {
+    let Some(obj) = obj.and_then(|v| v.as_object()) else {
+      continue;
+    };
+    for (key, val) in obj {
+      if LOCAL_PARAMS.contains(&key.as_str()) {
+        continue;
+      }
+      if val.is_null() {
+        params.remove(key);
+      } else if is_valid_param(key, val) {
+        params.insert(key.clone(), val.clone());
+      } else {
+        warn!("{} has an invalid request param `{}`: {}", source, key, val);
+      }
+    }
+  }
+  params
+}
+
+fn is_valid_param(key: &str, val: &JsonValue) -> bool {
+  match key {
+    "model" | "user" => val.is_string(),
+    "temperature" | "top_p" | "presence_penalty" | "frequency_penalty" => val.is_number(),
+    "n" | "max_tokens" => val.is_u64(),
+    "seed" => val.is_i64() || val.is_u64(),
+    "stop" => {
+      val.is_string()
+        || val
+          .as_array()
+          .is_some_and(|arr| arr.iter().all(JsonValue::is_string))
+    }
+    "logit_bias" | "response_format" | "stream_options" => val.is_object(),
+    // these are built by the request itself and can't be overridden.
+    "messages" | "stream" => false,
+    // unknown params pass through for the compatible providers.
+    _ => true,
+  }
 }
 
 fn default_polestar_provider(model: &str, info: &AppInfo) -> Option<ServerProvider> {
@@ -124,6 +668,10 @@ fn default_polestar_provider(model: &str, info: &AppInfo) -> Option<ServerProvid
         base_url: POLESTAR_STREAM_URL.to_string(),
         token: polestar_token.to_owned(),
         extend: None,
+        kind: ProviderKind::OpenAI,
+        retry: None,
+        prices: HashMap::new(),
+        budget: None,
       });
     }
   }
@@ -142,6 +690,7 @@ fn req_context(sp: Option<&ServerProvider>) -> JsonValue {
 
 pub async fn fetch_feedback(
   utc_time: Option<i64>,
+  retry: RetryPolicy,
 ) -> Result<FeedbackMessageListForServer, PolestarError> {
   let query = if let Some(time_stamp) = utc_time {
     format!(
@@ -152,10 +701,9 @@ pub async fn fetch_feedback(
     "https://api.ribir.org/feedback/messages/?limit=100".to_string()
   };
 
-  let client = reqwest::Client::new();
   let req = {
     let glb = GLOBAL_VARS.lock().unwrap();
-    client
+    http_client()
       .get(&query)
       .header(AUTHORIZATION, glb.get(&GlbVar::PolestarKey).unwrap())
       .header(CONTENT_TYPE, "application/json")
@@ -163,26 +711,20 @@ pub async fn fetch_feedback(
       .header(USER_AGENT, glb.get(&GlbVar::UserAgent).unwrap())
   };
 
-  let res = req.send().await;
-  match res {
-    Ok(res) => {
-      let rst = res.json::<FeedbackMessageListForServer>().await;
-      match rst {
-        Ok(data) => Ok(data),
-        Err(err) => Err(PolestarError::Reqwest(err)),
-      }
-    }
+  let res = send_with_retry(req, &retry).await?;
+  let rst = res.json::<FeedbackMessageListForServer>().await;
+  match rst {
+    Ok(data) => Ok(data),
     Err(err) => Err(PolestarError::Reqwest(err)),
   }
 }
 
-pub async fn req_feedback(content: String) -> Result<(), PolestarError> {
-  let client = reqwest::Client::new();
+pub async fn req_feedback(content: String, retry: RetryPolicy) -> Result<(), PolestarError> {
   let data = UserFeedbackMessageForServer { message: content };
   let params = serde_json::to_string(&data).unwrap();
   let req = {
     let glb = GLOBAL_VARS.lock().unwrap();
-    client
+    http_client()
       .post("https://api.ribir.org/feedback/ask")
       .header(AUTHORIZATION, glb.get(&GlbVar::PolestarKey).unwrap())
       .header(CONTENT_TYPE, "application/json")
@@ -190,41 +732,142 @@ pub async fn req_feedback(content: String) -> Result<(), PolestarError> {
       .header(USER_AGENT, glb.get(&GlbVar::UserAgent).unwrap())
       .body(params)
   };
-  let res = req.send().await;
-
-  match res {
-    Ok(res) => {
-      let rst = res.json::<FeedbackTimestamp>().await;
-      match rst {
-        Ok(_) => Ok(()),
-        Err(err) => Err(PolestarError::Reqwest(err)),
-      }
-    }
+  let res = send_with_retry(req, &retry).await?;
+
+  let rst = res.json::<FeedbackTimestamp>().await;
+  match rst {
+    Ok(_) => Ok(()),
     Err(err) => Err(PolestarError::Reqwest(err)),
   }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TextStreamReq {
   url: String,
   headers: HeaderMap,
+  kind: ProviderKind,
+  retry: RetryPolicy,
+  budget: Option<Budget>,
 }
 
 impl TextStreamReq {
-  pub async fn request(
-    self,
-    body: String,
-  ) -> Result<impl Stream<Item = Result<Event, PolestarError>>, PolestarError> {
+  pub fn new(url: String, headers: HeaderMap, kind: ProviderKind) -> Self {
+    Self {
+      url,
+      headers,
+      kind,
+      retry: RetryPolicy::default(),
+      budget: None,
+    }
+  }
+
+  pub fn set_retry(mut self, retry: RetryPolicy) -> Self {
+    self.retry = retry;
+    self
+  }
+
+  /// The request is refused if the provider is over the hard cap of the
+  /// `budget`.
+  pub fn set_budget(mut self, budget: Option<Budget>) -> Self {
+    self.budget = budget;
+    self
+  }
+
+  pub fn retry(&self) -> &RetryPolicy { &self.retry }
+
+  /// The chat provider to build the body and decode the response stream.
+  pub fn provider(&self) -> &'static dyn ChatProvider { chat_provider(self.kind) }
+
+  pub async fn request(self, body: String) -> Result<EventStream, PolestarError> {
+    if let Some(budget) = self.budget.as_ref().filter(|budget| budget.is_over()) {
+      return Err(PolestarError::OverBudget(
+        budget.message().unwrap_or_default(),
+      ));
+    }
     req_stream(
       self.url.clone(),
       Method::POST,
       self.headers.clone(),
       Some(body),
+      self.provider(),
+    )
+    .await
+  }
+
+  /// Send the request by its retry policy, `on_retry` is called with the
+  /// number of the next attempt.
+  pub async fn request_with_retry(
+    self,
+    body: String,
+    on_retry: impl FnMut(u32),
+  ) -> Result<EventStream, PolestarError> {
+    let retry = self.retry.clone();
+    retry_request(&retry, || self.clone().request(body.clone()), on_retry).await
+  }
+
+  /// Send the request by its retry policy and read the whole JSON response,
+  /// for the services don't stream, i.e. the image generations.
+  pub async fn request_json(
+    self,
+    body: String,
+    on_retry: impl FnMut(u32),
+  ) -> PolestarResult<JsonValue> {
+    if let Some(budget) = self.budget.as_ref().filter(|budget| budget.is_over()) {
+      return Err(PolestarError::OverBudget(
+        budget.message().unwrap_or_default(),
+      ));
+    }
+    let provider = self.provider();
+    retry_request(
+      &self.retry,
+      || {
+        let req = req_builder(
+          &self.url,
+          Method::POST,
+          self.headers.clone(),
+          Some(body.clone()),
+        );
+        async move {
+          let resp = http_client().send(req).await?;
+          let status = resp.status();
+          let retry_after = retry_after(status, resp.headers());
+          let text = resp.text().await?;
+          if status.is_success() {
+            return Ok(serde_json::from_str(&text)?);
+          }
+          let mut err = provider.map_error(status, &text);
+          err.retry_after = retry_after;
+          Err(PolestarError::PolestarServerError(err))
+        }
+      },
+      on_retry,
     )
     .await
   }
 }
 
+/// Handle to stop a running request created by [`cancelable`].
+#[derive(Debug, Clone)]
+pub struct CancelHandle(AbortHandle);
+
+impl CancelHandle {
+  pub fn cancel(&self) { self.0.abort() }
+
+  pub fn is_canceled(&self) -> bool { self.0.is_aborted() }
+}
+
+/// Wrap the request future to make it can be stopped by the returned handle,
+/// the future resolves `PolestarError::Canceled` once canceled, and the data
+/// already delivered by the future is kept by the caller.
+pub fn cancelable<T>(
+  fut: impl Future<Output = PolestarResult<T>>,
+) -> (impl Future<Output = PolestarResult<T>>, CancelHandle) {
+  let (handle, registration) = AbortHandle::new_pair();
+  let fut = Abortable::new(fut, registration);
+  let fut = async move { fut.await.unwrap_or(Err(PolestarError::Canceled)) };
+  (fut, CancelHandle(handle))
+}
+
 fn create_req_from_bot(bot: &Bot, sp: Option<&ServerProvider>) -> TextStreamReq {
   let env = req_context(sp);
   let mut headers: HeaderMap = HeaderMap::default();
@@ -245,7 +888,8 @@ fn create_req_from_bot(bot: &Bot, sp: Option<&ServerProvider>) -> TextStreamReq
   {
     url = format!("{}{}", base_url, url);
   }
-  TextStreamReq { url, headers }
+  let kind = sp.map_or(ProviderKind::default(), |sp| sp.kind);
+  TextStreamReq::new(url, headers, kind)
 }
 
 fn replace_val(src: &str, path_rex: &Regex, env: &JsonValue) -> String {
@@ -291,14 +935,12 @@ pub struct UserQuota {
   pub statistics: serde_json::Value,
 }
 
-pub async fn request_quota(token: Option<String>) -> PolestarResult<Quota> {
+pub async fn request_quota(token: Option<String>, retry: RetryPolicy) -> PolestarResult<Quota> {
   if let Some(token) = token {
-    let client = reqwest::Client::new();
-    let res = client
+    let req = http_client()
       .get("https://api.ribir.org/user_quota")
-      .header(AUTHORIZATION, token)
-      .send()
-      .await?;
+      .header(AUTHORIZATION, token);
+    let res = send_with_retry(req, &retry).await?;
     let user_quota = res.json::<UserQuota>().await?;
     let quota = serde_json::from_value::<Quota>(user_quota.statistics)?;
     Ok(quota)
@@ -309,14 +951,23 @@ pub async fn request_quota(token: Option<String>) -> PolestarResult<Quota> {
 
 #[cfg(test)]
 mod tests {
+  use super::*;
+  use crate::{
+    model::{ChannelCfg, ChannelKind, ChannelMode, Image, Msg, MsgCont, MsgMeta, ProviderKind},
+    service::tests::mock::test_bot,
+  };
+
   #[test]
   fn req_replace_val() {
-    use super::*;
     let env = req_context(Some(&ServerProvider {
       name: "Polestar".to_string(),
       base_url: "https://api.ribir.org/stream/open_ai".to_string(),
       token: "abc".to_string(),
       extend: Some(json!({ "data": "hello"})),
+      kind: ProviderKind::OpenAI,
+      retry: None,
+      prices: HashMap::new(),
+      budget: None,
     }));
     let regex = Regex::new(r"\$\{\s*([^}]*)\s*\}").unwrap();
     let src = r#"${$.sp.name} request to ${$.sp.base_url}/test ${$.sp.extend.data}"#.to_string();
@@ -326,4 +977,290 @@ mod tests {
       "Polestar request to https://api.ribir.org/stream/open_ai/test hello"
     );
   }
+
+  #[test]
+  fn request_body_params() {
+    let bot = test_bot(json!({
+      "model": "gpt-4",
+      "prompt": "You are a bot.",
+      "temperature": 0.2,
+      "max_tokens": "many",
+      "stop": ["\n"]
+    }));
+    let mut cfg = ChannelCfg::new(ChannelMode::Balanced, ChannelKind::Chat, None);
+    cfg.set_params(Some(json!({ "temperature": 1.0, "stream": false })));
+    let mut channel = Channel::new(
+      uuid::Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      cfg,
+      None,
+      None,
+    );
+
+    let body = open_ai_request_content(&bot, &channel, "hello");
+    let body = serde_json::from_str::<JsonValue>(&body).unwrap();
+    assert_eq!(body["model"], "gpt-4");
+    assert_eq!(body["temperature"], 1.0);
+    assert_eq!(body["stop"], json!(["\n"]));
+    assert_eq!(body["stream"], true);
+    assert!(body.get("stream_options").is_none());
+    assert!(body.get("max_tokens").is_none());
+    assert!(body.get("prompt").is_none());
+    assert_eq!(body["messages"][0]["role"], "system");
+    assert_eq!(body["messages"][0]["content"], "You are a bot.");
+    assert_eq!(body["messages"][1]["content"], "hello");
+
+    // the channel removes the bot's param by `null`.
+    let mut cfg = channel.cfg().clone();
+    cfg.set_params(Some(json!({ "stop": null, "max_tokens": 8 })));
+    channel.set_cfg(cfg);
+    let params = request_params(&bot, &channel);
+    assert!(params.get("stop").is_none());
+    assert_eq!(params["max_tokens"], 8);
+    assert_eq!(params["temperature"], 0.2);
+
+    // the usage is asked only from the servers supporting it.
+    let bot = test_bot(json!({ "stream_usage": true }));
+    let body = open_ai_request_content(&bot, &channel, "hello");
+    let body = serde_json::from_str::<JsonValue>(&body).unwrap();
+    assert_eq!(body["stream_options"], json!({ "include_usage": true }));
+    assert!(body.get("stream_usage").is_none());
+  }
+
+  #[test]
+  fn context_fill_token_budget() {
+    let bot = test_bot(json!({ "prompt": "You are a bot.", "context_tokens": 40 }));
+    let cfg = ChannelCfg::new(ChannelMode::Balanced, ChannelKind::Chat, None);
+    let mut channel = Channel::new(
+      uuid::Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      cfg,
+      None,
+      None,
+    );
+    for text in ["one two three four", "five", "six", "seven"] {
+      channel.add_msg(Msg::new_user_text(text, MsgMeta::default()));
+    }
+    // the sending message and the bot's pending reply.
+    channel.add_msg(Msg::new_user_text("hello", MsgMeta::default()));
+    channel.add_msg(Msg::new_user_text("", MsgMeta::default()));
+
+    let messages = request_messages(&bot, &channel, "hello");
+    let contents = messages
+      .iter()
+      .map(|m| m.text().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(contents, ["five", "six", "seven", "hello"]);
+    assert!(request_tokens(&bot, &channel, "hello") <= 40);
+    // a draft also counts the newest messages, they are context for it.
+    let draft = draft_tokens(&bot, &channel, "hi");
+    assert!(draft > request_tokens(&bot, &channel, "hi") && draft <= 40);
+
+    let body = open_ai_request_content(&bot, &channel, "hello");
+    let body = serde_json::from_str::<JsonValue>(&body).unwrap();
+    assert!(body.get("context_tokens").is_none());
+    assert_eq!(body["messages"][0]["content"], "You are a bot.");
+  }
+
+  #[test]
+  fn summarise_out_of_budget_msgs() {
+    let bot = test_bot(json!({ "context_tokens": 70 }));
+    let mut cfg = ChannelCfg::new(ChannelMode::Balanced, ChannelKind::Chat, None);
+    cfg.set_memory(true);
+    let mut channel = Channel::new(
+      uuid::Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      cfg,
+      None,
+      None,
+    );
+    for i in 0..20 {
+      channel.add_msg(Msg::new_user_text(&format!("m{}", i), MsgMeta::default()));
+    }
+
+    // "m8" to "m19" fit the budget, the others need a summary, but the oldest
+    // are out of the budget of the summary request.
+    let (summarizing, transcript, until) = summary_request(&bot, &channel).unwrap();
+    let lines = (2..8).map(|i| format!("User: m{}", i)).collect::<Vec<_>>();
+    assert_eq!(transcript, lines.join("\n\n"));
+    assert_eq!(&until, channel.msgs()[7].id());
+    assert_eq!(request_prompt(&bot, &summarizing), Some(SUMMARY_PROMPT));
+
+    channel.set_summary(Some(ChannelSummary::new("counting".to_owned(), until)));
+    assert!(summary_request(&bot, &channel).is_none());
+
+    let body = open_ai_request_content(&bot, &channel, "eight");
+    let body = serde_json::from_str::<JsonValue>(&body).unwrap();
+    assert_eq!(body["messages"][0]["role"], "system");
+    assert_eq!(
+      body["messages"][0]["content"],
+      format!("{}counting", SUMMARY_PREFIX)
+    );
+
+    // the summarised messages are not sent even there's room for them.
+    let mut cfg = channel.cfg().clone();
+    cfg.set_params(Some(json!({ "context_tokens": 1000 })));
+    channel.set_cfg(cfg);
+    let messages = request_messages(&bot, &channel, "eight");
+    assert_eq!(messages[0].text(), Some("m8"));
+  }
+
+  #[test]
+  fn context_follow_active_branch() {
+    let bot = test_bot(json!({}));
+    let mut channel = Channel::new(
+      uuid::Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      ChannelCfg::default(),
+      None,
+      None,
+    );
+    let bot_msg = |text: &str, source: &Msg| {
+      Msg::new(
+        MsgRole::Bot("bot".to_owned()),
+        vec![MsgCont::new_text(text)],
+        MsgMeta::reply(*source.id()),
+        None,
+      )
+    };
+    let texts = |channel: &Channel| {
+      channel
+        .branch_msgs()
+        .iter()
+        .filter_map(|m| m.cur_cont_ref().text().map(str::to_owned))
+        .collect::<Vec<_>>()
+    };
+
+    let question = Msg::new_user_text("q1", MsgMeta::default());
+    let question_id = *question.id();
+    let answer = bot_msg("a1", &question);
+    channel.add_msg(question);
+    channel.add_msg(answer);
+    let follow_up = Msg::new_user_text("q2", MsgMeta::default());
+    let answer = bot_msg("a2", &follow_up);
+    channel.add_msg(follow_up);
+    channel.add_msg(answer);
+    assert_eq!(texts(&channel), ["q1", "a1", "q2", "a2"]);
+
+    // edit and resend the first question.
+    let idx = channel.edit_msg(&question_id, "q1 edited").unwrap();
+    assert_eq!(idx, 1);
+    assert_eq!(texts(&channel), ["q1 edited"]);
+    let edited = channel.msg(&question_id).unwrap().clone();
+    channel.add_msg(bot_msg("a1 edited", &edited));
+    channel.add_msg(Msg::new_user_text("next", MsgMeta::default()));
+    channel.add_msg(Msg::new_user_text("", MsgMeta::default()));
+
+    let messages = request_messages(&bot, &channel, "next");
+    let contents = messages
+      .iter()
+      .map(|m| m.text().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(contents, ["q1 edited", "a1 edited", "next"]);
+
+    // switch back to the first version switches the whole branch.
+    channel.switch_cont(&question_id, 0);
+    assert_eq!(texts(&channel), ["q1", "a1", "q2", "a2"]);
+    channel.switch_cont(&question_id, 1);
+    assert_eq!(texts(&channel), ["q1 edited", "a1 edited", "next", ""]);
+  }
+
+  #[test]
+  fn regenerate_with_original_context() {
+    let mut channel = Channel::new(
+      uuid::Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      ChannelCfg::default(),
+      None,
+      None,
+    );
+    let quoted = Msg::new_user_text("earlier", MsgMeta::default());
+    let quoted_id = *quoted.id();
+    channel.add_msg(quoted);
+    let question = Msg::new_user_text("why?", MsgMeta::quote(quoted_id));
+    let question_id = *question.id();
+    channel.add_msg(question);
+    let answer = Msg::new(
+      MsgRole::Bot("bot".to_owned()),
+      vec![MsgCont::new_text("because")],
+      MsgMeta::reply(question_id),
+      None,
+    );
+    let answer_id = *answer.id();
+    channel.add_msg(answer);
+    channel.add_msg(Msg::new_user_text("later", MsgMeta::default()));
+
+    assert_eq!(channel.regenerate(&question_id, None), None);
+    let idx = channel
+      .regenerate(&answer_id, Some("other".to_owned()))
+      .unwrap();
+    let answer = channel.msg(&answer_id).unwrap();
+    assert_eq!((idx, answer.cur_idx(), answer.cont_count()), (1, 1, 2));
+    assert_eq!(answer.cur_bot(), Some(&"other".to_owned()));
+    assert_eq!(answer.cont_list()[0].bot(), None);
+
+    let params = json!({ "temperature": 0.1 });
+    let (context, content) = regenerate_context(&channel, &answer_id, params.as_object()).unwrap();
+    assert_eq!(content, "earlier why?");
+    // the messages after the answer are not in the context.
+    let texts = context
+      .msgs()
+      .iter()
+      .map(|m| m.cur_cont_ref().text().unwrap_or_default())
+      .collect::<Vec<_>>();
+    assert_eq!(texts, ["earlier", "why?", ""]);
+    assert_eq!(context.cfg().params(), Some(&params));
+  }
+
+  #[test]
+  fn send_images_to_vision_bot() {
+    let cfg = ChannelCfg::new(ChannelMode::Balanced, ChannelKind::Chat, None);
+    let mut channel = Channel::new(
+      uuid::Uuid::new_v4(),
+      "test".to_owned(),
+      None,
+      cfg,
+      None,
+      None,
+    );
+    let image = Image::new_url("https://example.com/cat.png".to_owned(), 0, 0);
+    channel.add_msg(Msg::new_user_images(
+      "what is it?",
+      vec![image],
+      MsgMeta::default(),
+    ));
+    channel.add_msg(Msg::new_bot_text("bot".to_owned(), MsgMeta::default()));
+
+    let vision = test_bot(json!({ "vision": true }));
+    let body = open_ai_request_content(&vision, &channel, "what is it?");
+    let body = serde_json::from_str::<JsonValue>(&body).unwrap();
+    assert!(body.get("vision").is_none());
+    assert_eq!(
+      body["messages"][0]["content"],
+      json!([
+        { "type": "text", "text": "what is it?" },
+        { "type": "image_url", "image_url": { "url": "https://example.com/cat.png" } }
+      ])
+    );
+    let messages = request_messages(&vision, &channel, "what is it?");
+    assert_eq!(messages[0].text(), Some("what is it?"));
+
+    let body =
+      chat_provider(ProviderKind::Anthropic).request_body(&vision, &channel, "what is it?");
+    let body = serde_json::from_str::<JsonValue>(&body).unwrap();
+    assert_eq!(
+      body["messages"][0]["content"][1],
+      json!({ "type": "image", "source": { "type": "url", "url": "https://example.com/cat.png" } })
+    );
+
+    // the images are not sent to the bot that doesn't accept them.
+    let body = open_ai_request_content(&test_bot(json!({})), &channel, "what is it?");
+    let body = serde_json::from_str::<JsonValue>(&body).unwrap();
+    assert_eq!(body["messages"][0]["content"], "what is it?");
+  }
 }
@@ -0,0 +1,16 @@
+#[cfg(test)]
+mod client;
+#[cfg(test)]
+mod image;
+#[cfg(test)]
+mod mcp;
+#[cfg(test)]
+pub(crate) mod mock;
+#[cfg(test)]
+mod pipeline;
+#[cfg(test)]
+mod provider;
+#[cfg(test)]
+mod retry;
+#[cfg(test)]
+mod tool;
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+
+use crate::{
+  error::{PolestarError, PolestarServerErrType},
+  service::client::build_http_client,
+  NetworkCfg, TimeoutCfg,
+};
+
+use super::mock::{mock_server, MockResp};
+
+#[tokio::test]
+async fn request_through_proxy() {
+  let (addr, bodies) = mock_server(vec![MockResp::new(200, "text/plain", "proxy")]).await;
+  let cfg = NetworkCfg {
+    proxy: Some(addr.clone()),
+    no_proxy: vec![],
+    ..<_>::default()
+  };
+  let client = build_http_client(&cfg).unwrap();
+  let resp = client
+    .post("http://polestar.invalid/chat")
+    .body("hi")
+    .send()
+    .await
+    .unwrap();
+  assert_eq!(resp.text().await.unwrap(), "proxy");
+  assert_eq!(bodies.lock().unwrap().as_slice(), ["hi"]);
+
+  // the host in no proxy list connect directly.
+  let cfg = NetworkCfg {
+    proxy: Some(addr),
+    no_proxy: vec!["polestar.invalid".to_owned()],
+    ..<_>::default()
+  };
+  let client = build_http_client(&cfg).unwrap();
+  assert!(client
+    .get("http://polestar.invalid/chat")
+    .send()
+    .await
+    .is_err());
+  assert_eq!(bodies.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn invalid_proxy() {
+  let cfg = NetworkCfg {
+    proxy: Some("http://[bad".to_owned()),
+    no_proxy: vec![],
+    ..<_>::default()
+  };
+  assert!(build_http_client(&cfg).is_err());
+
+  let cfg = NetworkCfg {
+    proxy: Some("  ".to_owned()),
+    no_proxy: vec![],
+    ..<_>::default()
+  };
+  assert!(build_http_client(&cfg).is_ok());
+}
+
+fn is_timed_out<T>(rst: Result<T, PolestarError>) -> bool {
+  matches!(
+    rst,
+    Err(PolestarError::PolestarServerError(err)) if err.kind == PolestarServerErrType::TimedOut
+  )
+}
+
+fn timeout_cfg() -> NetworkCfg {
+  NetworkCfg {
+    timeout: TimeoutCfg {
+      first_byte_secs: 1,
+      idle_secs: 1,
+      ..<_>::default()
+    },
+    ..<_>::default()
+  }
+}
+
+#[tokio::test]
+async fn first_byte_timeout() {
+  let (addr, _) = mock_server(vec![
+    MockResp::new(200, "text/plain", "late").delay(Duration::from_secs(3))
+  ])
+  .await;
+  let client = build_http_client(&timeout_cfg()).unwrap();
+  assert!(is_timed_out(client.send(client.get(addr)).await));
+}
+
+#[tokio::test]
+async fn idle_timeout() {
+  let (addr, _) = mock_server(vec![MockResp::new(200, "text/plain", "first").hang()]).await;
+  let client = build_http_client(&timeout_cfg()).unwrap();
+  let resp = client.send(client.get(addr)).await.unwrap();
+  let mut body = Box::pin(client.bytes_stream(resp));
+  let first = body.next().await.unwrap().unwrap();
+  assert_eq!(first.as_ref(), b"first");
+  assert!(is_timed_out(body.next().await.unwrap()));
+  assert!(body.next().await.is_none());
+}
@@ -0,0 +1,94 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value as JsonValue};
+use uuid::Uuid;
+
+use crate::{
+  model::{Bot, Channel, ChannelCfg, ProviderKind, MIME},
+  service::{
+    image::{generate_images, image_request_content},
+    req::TextStreamReq,
+  },
+};
+
+use super::mock::{mock_server, test_image_bot, MockResp};
+
+fn image_bot() -> Bot {
+  test_image_bot(json!({ "model": "test-image", "size": "512x256", "quality": "hd", "n": 2 }))
+}
+
+// the signature and the IHDR chunk of a 3x2 PNG image.
+fn png_header() -> Vec<u8> {
+  let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+  data.extend_from_slice(&3_u32.to_be_bytes());
+  data.extend_from_slice(&2_u32.to_be_bytes());
+  data
+}
+
+#[tokio::test]
+async fn generate_base64_images() {
+  let jpeg = [0xFF, 0xD8, 0xFF, 0xE0];
+  let resp = json!({
+    "created": 1700000000,
+    "data": [{ "b64_json": STANDARD.encode(png_header()) }, { "b64_json": STANDARD.encode(jpeg) }]
+  });
+  let (addr, bodies) = mock_server(vec![MockResp::new(
+    200,
+    "application/json",
+    &resp.to_string(),
+  )])
+  .await;
+
+  let bot = image_bot();
+  assert!(bot.is_image_gen());
+  let channel = Channel::new(
+    Uuid::new_v4(),
+    "test".to_owned(),
+    None,
+    ChannelCfg::default(),
+    None,
+    None,
+  );
+  let body = image_request_content(&bot, &channel, "a cat");
+  let req = TextStreamReq::new(
+    format!("{}/v1/images/generations", addr),
+    HeaderMap::new(),
+    ProviderKind::OpenAI,
+  );
+  let images = generate_images(req, body, |_| {}).await.unwrap();
+
+  let sent: JsonValue = serde_json::from_str(&bodies.lock().unwrap()[0]).unwrap();
+  assert_eq!(
+    sent,
+    json!({ "model": "test-image", "prompt": "a cat", "size": "512x256", "quality": "hd", "n": 2 })
+  );
+  assert_eq!(images.len(), 2);
+  let png = &images[0];
+  assert_eq!(png.attachment.mime(), &MIME::ImagePng);
+  assert_eq!(png.image.file(), Some(png.attachment.name()));
+  assert_eq!((png.image.width(), png.image.height()), (3, 2));
+  // the size of the request if it can't be read from the image.
+  let jpeg = &images[1];
+  assert_eq!(jpeg.attachment.mime(), &MIME::ImageJpeg);
+  assert_eq!((jpeg.image.width(), jpeg.image.height()), (512, 256));
+}
+
+#[tokio::test]
+async fn download_url_image() {
+  // it's not an image.
+  let (image_addr, downloads) = mock_server(vec![MockResp::new(200, "text/plain", "hello")]).await;
+  let resp = json!({ "data": [{ "url": format!("{}/image.png", image_addr) }] });
+  let (addr, _) = mock_server(vec![MockResp::new(
+    200,
+    "application/json",
+    &resp.to_string(),
+  )])
+  .await;
+
+  let req = TextStreamReq::new(addr, HeaderMap::new(), ProviderKind::OpenAI);
+  let err = generate_images(req, json!({ "prompt": "a cat" }).to_string(), |_| {})
+    .await
+    .unwrap_err();
+  assert_eq!(downloads.lock().unwrap().len(), 1);
+  assert_eq!(err.to_string(), "image error: unsupported image format");
+}
@@ -0,0 +1,144 @@
+use std::io::{BufRead, Write};
+
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+  model::{ToolCall, ToolRegistry},
+  service::mcp::{load_mcp_tools, McpClient},
+  McpServerCfg,
+};
+
+/// The env var to run the test binary as the echo MCP server.
+const ECHO_SERVER_ENV: &str = "POLESTAR_MCP_ECHO_SERVER";
+
+/// Not a test but the echo MCP server for the tests, they launch the test
+/// binary to run only it.
+#[test]
+fn mcp_echo_server() {
+  if std::env::var(ECHO_SERVER_ENV).is_err() {
+    return;
+  }
+  let mut stdout = std::io::stdout();
+  for line in std::io::stdin().lock().lines() {
+    let msg: JsonValue = serde_json::from_str(&line.unwrap()).unwrap();
+    let params = &msg["params"];
+    let result = match msg["method"].as_str().unwrap_or_default() {
+      "initialize" => json!({
+        "protocolVersion": "2024-11-05",
+        "capabilities": { "tools": {}, "resources": {}, "prompts": {} },
+        "serverInfo": { "name": "echo", "version": "0.1.0" }
+      }),
+      // two pages of the tools.
+      "tools/list" if params["cursor"].is_null() => json!({
+        "tools": [{
+          "name": "echo",
+          "description": "Echo the text.",
+          "inputSchema": { "type": "object", "properties": { "text": { "type": "string" } } }
+        }],
+        "nextCursor": "2"
+      }),
+      "tools/list" => json!({
+        "tools": [{ "name": "crash", "inputSchema": { "type": "object" } }]
+      }),
+      "tools/call" => match params["name"].as_str() {
+        Some("echo") => {
+          json!({ "content": [{ "type": "text", "text": params["arguments"]["text"] }] })
+        }
+        Some("crash") => std::process::exit(1),
+        _ => json!({ "content": [{ "type": "text", "text": "unknown tool" }], "isError": true }),
+      },
+      "resources/list" => json!({
+        "resources": [{ "uri": "echo://readme", "name": "readme", "mimeType": "text/plain" }]
+      }),
+      "resources/read" => json!({
+        "contents": [{ "uri": params["uri"], "text": "Echo everything." }]
+      }),
+      "prompts/list" => json!({
+        "prompts": [{ "name": "greet", "arguments": [{ "name": "who", "required": true }] }]
+      }),
+      // the notifications.
+      _ => continue,
+    };
+    let resp = json!({ "jsonrpc": "2.0", "id": msg["id"], "result": result });
+    writeln!(stdout, "{}", resp).unwrap();
+    stdout.flush().unwrap();
+  }
+}
+
+fn echo_server() -> McpServerCfg {
+  McpServerCfg {
+    name: "echo".to_owned(),
+    command: std::env::current_exe()
+      .unwrap()
+      .to_string_lossy()
+      .into_owned(),
+    // quiet for the test harness prints its lines apart from the messages.
+    args: vec![
+      "service::tests::mcp::mcp_echo_server".to_owned(),
+      "--exact".to_owned(),
+      "--quiet".to_owned(),
+      "--test-threads=1".to_owned(),
+    ],
+    env: [(ECHO_SERVER_ENV.to_owned(), "1".to_owned())].into(),
+  }
+}
+
+#[tokio::test]
+async fn list_and_call() {
+  let client = McpClient::new(echo_server());
+  let tools = client.list_tools().await.unwrap();
+  let names: Vec<_> = tools.iter().map(|tool| tool.name.as_str()).collect();
+  assert_eq!(names, ["echo", "crash"]);
+  assert_eq!(tools[0].description.as_deref(), Some("Echo the text."));
+
+  let resources = client.list_resources().await.unwrap();
+  assert_eq!(resources[0].uri, "echo://readme");
+  assert_eq!(resources[0].mime_type.as_deref(), Some("text/plain"));
+  assert_eq!(
+    client.read_resource("echo://readme").await.unwrap(),
+    "Echo everything."
+  );
+  let prompts = client.list_prompts().await.unwrap();
+  assert_eq!(prompts[0].name, "greet");
+  assert!(prompts[0].arguments[0].required);
+
+  assert_eq!(
+    client
+      .call_tool("echo", json!({ "text": "hi" }))
+      .await
+      .unwrap(),
+    "hi"
+  );
+  let err = client.call_tool("search", json!({})).await.unwrap_err();
+  assert_eq!(err.to_string(), "tool error: unknown tool");
+}
+
+#[tokio::test]
+async fn restart_after_crash() {
+  let client = McpClient::new(echo_server());
+  let err = client.call_tool("crash", json!({})).await.unwrap_err();
+  assert_eq!(err.to_string(), "tool error: MCP server `echo` exited");
+  assert_eq!(
+    client
+      .call_tool("echo", json!({ "text": "again" }))
+      .await
+      .unwrap(),
+    "again"
+  );
+}
+
+#[tokio::test]
+async fn registered_tools() {
+  let mut tools = ToolRegistry::default();
+  let clients = load_mcp_tools(&[echo_server()], &mut tools).await;
+  assert_eq!(clients.len(), 1);
+  let names: Vec<_> = tools.tools().map(|tool| tool.name()).collect();
+  assert_eq!(names, ["echo__crash", "echo__echo"]);
+
+  let call = ToolCall {
+    id: "call_1".to_owned(),
+    name: "echo__echo".to_owned(),
+    arguments: r#"{"text":"hello"}"#.to_owned(),
+  };
+  assert_eq!(tools.invoke(&call).await, "hello");
+}
@@ -0,0 +1,137 @@
+use std::{
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use serde_json::{json, Value as JsonValue};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::TcpListener,
+};
+
+use crate::model::Bot;
+
+pub struct MockResp {
+  pub status: u16,
+  pub headers: Vec<(&'static str, String)>,
+  pub body: String,
+  // wait before responding.
+  pub delay: Option<Duration>,
+  // keep the connection open after the body without finishing it.
+  pub hang: bool,
+}
+
+impl MockResp {
+  pub fn new(status: u16, content_type: &str, body: &str) -> Self {
+    Self {
+      status,
+      headers: vec![("Content-Type", content_type.to_owned())],
+      body: body.to_owned(),
+      delay: None,
+      hang: false,
+    }
+  }
+
+  pub fn delay(mut self, delay: Duration) -> Self {
+    self.delay = Some(delay);
+    self
+  }
+
+  pub fn hang(mut self) -> Self {
+    self.hang = true;
+    self
+  }
+
+  pub fn header(mut self, key: &'static str, val: &str) -> Self {
+    self.headers.push((key, val.to_owned()));
+    self
+  }
+}
+
+/// The chat bot of the tests, only its `params` differ.
+pub fn test_bot(params: JsonValue) -> Bot { bot("/v1/chat/completions", params) }
+
+/// The image generation bot of the tests, which is told by its url.
+pub fn test_image_bot(params: JsonValue) -> Bot { bot("/v1/images/generations", params) }
+
+fn bot(url: &str, params: JsonValue) -> Bot {
+  serde_json::from_value(json!({
+    "id": "bot",
+    "name": "bot",
+    "lang": ["en"],
+    "avatar": { "name": "🤖", "color": "#EDF7FBFF" },
+    "tags": [],
+    "sp": "OpenAI",
+    "url": url,
+    "headers": {},
+    "params": params
+  }))
+  .unwrap()
+}
+
+/// Launch a local HTTP server answers the requests by `resps` in order, the
+/// last one is repeated. Return the server address and the received request
+/// bodies.
+pub async fn mock_server(resps: Vec<MockResp>) -> (String, Arc<Mutex<Vec<String>>>) {
+  let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = format!("http://{}", listener.local_addr().unwrap());
+  let bodies = Arc::new(Mutex::new(vec![]));
+  let received = bodies.clone();
+  tokio::spawn(async move {
+    let mut idx = 0;
+    while let Ok((mut socket, _)) = listener.accept().await {
+      let body = read_request(&mut socket).await;
+      received.lock().unwrap().push(body);
+      let resp = &resps[idx.min(resps.len() - 1)];
+      idx += 1;
+      if let Some(delay) = resp.delay {
+        tokio::time::sleep(delay).await;
+      }
+      let mut head = format!("HTTP/1.1 {} Mock\r\n", resp.status);
+      for (key, val) in resp.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", key, val));
+      }
+      // declare one more byte than sent, so the hanging body never finishes.
+      let len = if resp.hang {
+        resp.body.len() + 1
+      } else {
+        resp.body.len()
+      };
+      head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        len
+      ));
+      let _ = socket.write_all(head.as_bytes()).await;
+      let _ = socket.write_all(resp.body.as_bytes()).await;
+      if resp.hang {
+        tokio::time::sleep(Duration::from_secs(30)).await;
+      }
+      let _ = socket.shutdown().await;
+    }
+  });
+  (addr, bodies)
+}
+
+async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+  let mut buf = vec![];
+  let mut chunk = [0_u8; 1024];
+  while let Ok(n) = socket.read(&mut chunk).await {
+    if n == 0 {
+      break;
+    }
+    buf.extend_from_slice(&chunk[..n]);
+    let Some(head_end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+      continue;
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_lowercase();
+    let len = head
+      .lines()
+      .find_map(|line| line.strip_prefix("content-length:"))
+      .and_then(|len| len.trim().parse::<usize>().ok())
+      .unwrap_or_default();
+    if buf.len() >= head_end + 4 + len {
+      return String::from_utf8_lossy(&buf[head_end + 4..head_end + 4 + len]).to_string();
+    }
+  }
+  String::default()
+}
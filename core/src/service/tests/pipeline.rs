@@ -0,0 +1,72 @@
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+  model::{Bot, Channel, ChannelCfg, Msg, MsgAction, MsgBody, MsgMeta},
+  service::pipeline::PipelineRun,
+};
+
+use super::mock::test_bot;
+
+fn pipeline_bot() -> Bot {
+  test_bot(json!({
+    "pipeline": [
+      { "bot": "draft" },
+      { "bot": "polish", "input": "Polish: ${output}" },
+      { "bot": "translate" }
+    ]
+  }))
+}
+
+fn answer(channel: &mut Channel, msg: Msg, act: MsgAction) {
+  let msg_id = *msg.id();
+  channel.add_msg(msg);
+  if let MsgAction::Fulfilled = act {
+    channel.update_msg(
+      &msg_id,
+      0,
+      MsgAction::Receiving(MsgBody::Text(Some(format!("out of {}", msg_id)))),
+    );
+  }
+  channel.update_msg(&msg_id, 0, act);
+}
+
+#[test]
+fn run_steps_in_order() {
+  let mut channel = Channel::new(
+    Uuid::new_v4(),
+    "test".to_owned(),
+    None,
+    ChannelCfg::default(),
+    None,
+    None,
+  );
+  let question = Msg::new_user_text("q", MsgMeta::default());
+  let question_id = *question.id();
+  channel.add_msg(question);
+  let mut run = PipelineRun::new(&pipeline_bot(), question_id, "q".to_owned()).unwrap();
+
+  let first = run.next_msg(&channel).unwrap();
+  assert_eq!(run.cur_bot().map(String::as_str), Some("draft"));
+  assert_eq!(first.meta().source_id(), Some(&question_id));
+  assert_eq!(first.meta().pipeline().map(|link| link.step), Some(0));
+  let first_id = *first.id();
+  answer(&mut channel, first, MsgAction::Fulfilled);
+
+  let second = run.next_msg(&channel).unwrap();
+  assert_eq!(run.cur_bot().map(String::as_str), Some("polish"));
+  assert_eq!(second.meta().source_id(), Some(&first_id));
+  // the rejected step stops the pipeline.
+  answer(&mut channel, second, MsgAction::Rejected);
+  assert!(run.next_msg(&channel).is_none());
+}
+
+#[test]
+fn not_a_pipeline() {
+  let plain = test_bot(json!({ "model": "test-model" }));
+  assert!(PipelineRun::new(&plain, Uuid::new_v4(), "q".to_owned()).is_none());
+
+  // the pipeline without steps is not a pipeline either.
+  let empty = test_bot(json!({ "model": "test-model", "pipeline": [] }));
+  assert!(PipelineRun::new(&empty, Uuid::new_v4(), "q".to_owned()).is_none());
+}
@@ -0,0 +1,225 @@
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value as JsonValue};
+use uuid::Uuid;
+
+use crate::{
+  error::{PolestarError, PolestarServerErrType},
+  model::{Bot, Budget, BudgetCap, Channel, ChannelCfg, ProviderKind, TokenUsage},
+  service::{
+    provider::{deal_chat_stream, ChatAnswer},
+    req::{cancelable, TextStreamReq},
+  },
+};
+
+use super::mock::{mock_server, test_bot, MockResp};
+
+fn bot() -> Bot {
+  test_bot(json!({
+    "model": "test-model",
+    "prompt": "You are a bot.",
+    "max_tokens": 16,
+    "stop": "\n"
+  }))
+}
+
+fn channel() -> Channel {
+  Channel::new(
+    Uuid::new_v4(),
+    "test".to_owned(),
+    None,
+    ChannelCfg::default(),
+    None,
+    None,
+  )
+}
+
+async fn ask(kind: ProviderKind, resp: MockResp) -> (Result<ChatAnswer, PolestarError>, JsonValue) {
+  let (addr, bodies) = mock_server(vec![resp]).await;
+  let req = TextStreamReq::new(format!("{}/chat", addr), HeaderMap::new(), kind);
+  let provider = req.provider();
+  let body = provider.request_body(&bot(), &channel(), "hello");
+  let rst = match req.request(body).await {
+    Ok(mut stream) => deal_chat_stream(provider, &mut stream, |_| {}).await,
+    Err(err) => Err(err),
+  };
+  let body = serde_json::from_str(&bodies.lock().unwrap()[0]).unwrap();
+  (rst, body)
+}
+
+#[tokio::test]
+async fn open_ai_stream() {
+  let sse = "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"}}]}\n\n\
+             data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"}}]}\n\n\
+             data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\" world\"}}]}\n\n\
+             data: {\"choices\":[],\"usage\":{\"prompt_tokens\":9,\"completion_tokens\":2,\"total_tokens\":11}}\n\n\
+             data: [DONE]\n\n";
+  let (rst, body) = ask(
+    ProviderKind::LlamaCpp,
+    MockResp::new(200, "text/event-stream; charset=utf-8", sse),
+  )
+  .await;
+  let answer = rst.unwrap();
+  assert_eq!(answer.text, "Hello world");
+  assert_eq!(
+    answer.usage,
+    Some(TokenUsage {
+      prompt_tokens: 9,
+      completion_tokens: 2
+    })
+  );
+  assert!(answer.first_token_at.is_some());
+  assert_eq!(body["model"], "test-model");
+  assert_eq!(body["messages"][0]["role"], "system");
+  assert!(body.get("stream_options").is_none());
+}
+
+#[tokio::test]
+async fn anthropic_stream() {
+  let sse = "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":12,\"output_tokens\":1}}}\n\n\
+             event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n\
+             event: ping\ndata: {\"type\":\"ping\"}\n\n\
+             event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" there\"}}\n\n\
+             event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":3}}\n\n\
+             event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n";
+  let (rst, body) = ask(
+    ProviderKind::Anthropic,
+    MockResp::new(200, "text/event-stream", sse),
+  )
+  .await;
+  let answer = rst.unwrap();
+  assert_eq!(answer.text, "Hi there");
+  assert_eq!(
+    answer.usage,
+    Some(TokenUsage {
+      prompt_tokens: 12,
+      completion_tokens: 3
+    })
+  );
+  assert_eq!(body["system"], "You are a bot.");
+  assert_eq!(body["stop_sequences"], json!(["\n"]));
+  assert_eq!(body["max_tokens"], 16);
+  assert_eq!(
+    body["messages"],
+    json!([{ "role": "user", "content": "hello" }])
+  );
+}
+
+#[tokio::test]
+async fn ollama_stream() {
+  let ndjson = "{\"message\":{\"role\":\"assistant\",\"content\":\"Good\"},\"done\":false}\n\
+                {\"message\":{\"role\":\"assistant\",\"content\":\" day\"},\"done\":false}\n\
+                {\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"prompt_eval_count\":7,\"eval_count\":2}\n";
+  let (rst, body) = ask(
+    ProviderKind::Ollama,
+    MockResp::new(200, "application/x-ndjson", ndjson),
+  )
+  .await;
+  let answer = rst.unwrap();
+  assert_eq!(answer.text, "Good day");
+  assert_eq!(
+    answer.usage,
+    Some(TokenUsage {
+      prompt_tokens: 7,
+      completion_tokens: 2
+    })
+  );
+  assert_eq!(body["model"], "test-model");
+  assert_eq!(body["options"]["num_predict"], 16);
+}
+
+#[test]
+fn estimate_usage_without_report() {
+  let body = r#"{"model":"test-model","messages":[{"role":"user","content":"hello world"}]}"#;
+  let answer = ChatAnswer {
+    text: "hi there".to_owned(),
+    ..Default::default()
+  };
+  let usage = answer.msg_usage(&bot(), body, std::time::Instant::now());
+  assert!(usage.estimated);
+  assert!(usage.prompt_tokens > 0);
+  assert_eq!(usage.completion_tokens, 3);
+  assert_eq!(usage.model, "test-model");
+  assert_eq!(usage.provider, "OpenAI");
+  assert_eq!(usage.first_token_ms, None);
+}
+
+#[tokio::test]
+async fn map_provider_errors() {
+  let (rst, _) = ask(
+    ProviderKind::OpenAI,
+    MockResp::new(
+      401,
+      "application/json",
+      r#"{"error":{"message":"Incorrect API key","type":"invalid_request_error"}}"#,
+    ),
+  )
+  .await;
+  let Err(PolestarError::PolestarServerError(err)) = rst else {
+    panic!("expect server error")
+  };
+  assert_eq!(err.kind, PolestarServerErrType::UnAuthed);
+  assert_eq!(err.message, "Incorrect API key");
+
+  let (rst, _) = ask(
+    ProviderKind::Anthropic,
+    MockResp::new(
+      529,
+      "application/json",
+      r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+    ),
+  )
+  .await;
+  let Err(PolestarError::PolestarServerError(err)) = rst else {
+    panic!("expect server error")
+  };
+  assert_eq!(err.kind, PolestarServerErrType::NetWork);
+
+  let (rst, _) = ask(
+    ProviderKind::Ollama,
+    MockResp::new(404, "application/json", r#"{"error":"model not found"}"#),
+  )
+  .await;
+  let Err(PolestarError::PolestarServerError(err)) = rst else {
+    panic!("expect server error")
+  };
+  assert_eq!(err.kind, PolestarServerErrType::NotFound);
+  assert_eq!(err.message, "model not found");
+}
+
+#[tokio::test]
+async fn reject_skipping_tool_call() {
+  let sse = "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":4294967295,\"id\":\"call_1\",\"function\":{\"name\":\"weather\",\"arguments\":\"\"}}]}}]}\n\n\
+             data: [DONE]\n\n";
+  let (rst, _) = ask(
+    ProviderKind::OpenAI,
+    MockResp::new(200, "text/event-stream", sse),
+  )
+  .await;
+  assert!(matches!(rst, Err(PolestarError::Tool(_))));
+}
+
+#[tokio::test]
+async fn refuse_over_budget() {
+  let budget = Budget {
+    provider: "test".to_owned(),
+    spent: 12.,
+    cap: BudgetCap { soft: None, hard: Some(10.) },
+  };
+  let req = TextStreamReq::new(
+    "http://127.0.0.1:1/chat".to_owned(),
+    HeaderMap::new(),
+    ProviderKind::OpenAI,
+  )
+  .set_budget(Some(budget));
+  let rst = req.request("{}".to_owned()).await;
+  assert!(matches!(rst, Err(PolestarError::OverBudget(_))));
+}
+
+#[tokio::test]
+async fn cancel_request() {
+  let (fut, handle) = cancelable(futures_util::future::pending::<Result<String, PolestarError>>());
+  assert!(!handle.is_canceled());
+  handle.cancel();
+  assert!(handle.is_canceled());
+  assert!(matches!(fut.await, Err(PolestarError::Canceled)));
+}
@@ -0,0 +1,113 @@
+use reqwest::header::HeaderMap;
+
+use crate::{
+  error::{PolestarError, PolestarServerErrType},
+  model::{ProviderKind, RetryPolicy},
+  service::req::TextStreamReq,
+};
+
+use super::mock::{mock_server, MockResp};
+
+const SSE: &str = "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"ok\"}}]}\n\n\
+                   data: [DONE]\n\n";
+
+fn policy() -> RetryPolicy {
+  RetryPolicy {
+    max_attempts: 3,
+    base_delay_ms: 1,
+    max_delay_ms: 1_000,
+    jitter: 0.,
+  }
+}
+
+async fn request(resps: Vec<MockResp>) -> (Result<(), PolestarError>, Vec<u32>, usize) {
+  let (addr, bodies) = mock_server(resps).await;
+  let req = TextStreamReq::new(addr, HeaderMap::new(), ProviderKind::OpenAI).set_retry(policy());
+  let mut attempts = vec![];
+  let rst = req
+    .request_with_retry("{}".to_owned(), |attempt| attempts.push(attempt))
+    .await
+    .map(|_| ());
+  let cnt = bodies.lock().unwrap().len();
+  (rst, attempts, cnt)
+}
+
+fn err_kind(rst: Result<(), PolestarError>) -> PolestarServerErrType {
+  match rst {
+    Err(PolestarError::PolestarServerError(err)) => err.kind,
+    _ => panic!("expect a server error"),
+  }
+}
+
+#[tokio::test]
+async fn retry_until_success() {
+  let (rst, attempts, cnt) = request(vec![
+    MockResp::new(503, "text/plain", "busy"),
+    MockResp::new(504, "text/plain", "timeout"),
+    MockResp::new(200, "text/event-stream", SSE),
+  ])
+  .await;
+  assert!(rst.is_ok());
+  assert_eq!(attempts, vec![2, 3]);
+  assert_eq!(cnt, 3);
+}
+
+#[tokio::test]
+async fn retry_stop_at_max_attempts() {
+  let (rst, attempts, cnt) = request(vec![MockResp::new(502, "text/plain", "bad")]).await;
+  assert_eq!(err_kind(rst), PolestarServerErrType::NetWork);
+  assert_eq!(attempts, vec![2, 3]);
+  assert_eq!(cnt, 3);
+}
+
+#[tokio::test]
+async fn no_retry_for_client_error() {
+  let (rst, attempts, cnt) = request(vec![MockResp::new(401, "text/plain", "who")]).await;
+  assert_eq!(err_kind(rst), PolestarServerErrType::UnAuthed);
+  assert!(attempts.is_empty());
+  assert_eq!(cnt, 1);
+}
+
+#[tokio::test]
+async fn honour_retry_after() {
+  let (rst, attempts, cnt) = request(vec![
+    MockResp::new(429, "text/plain", "slow down").header("Retry-After", "0"),
+    MockResp::new(200, "text/event-stream", SSE),
+  ])
+  .await;
+  assert!(rst.is_ok());
+  assert_eq!(attempts, vec![2]);
+  assert_eq!(cnt, 2);
+
+  // a rate limit without `Retry-After` is over quota.
+  let (rst, attempts, _) = request(vec![MockResp::new(429, "text/plain", "quota")]).await;
+  assert_eq!(err_kind(rst), PolestarServerErrType::OverQuota);
+  assert!(attempts.is_empty());
+
+  // wait longer than the max delay, give up.
+  let (rst, attempts, cnt) = request(vec![
+    MockResp::new(503, "text/plain", "busy").header("Retry-After", "60")
+  ])
+  .await;
+  assert_eq!(err_kind(rst), PolestarServerErrType::NetWork);
+  assert!(attempts.is_empty());
+  assert_eq!(cnt, 1);
+}
+
+#[test]
+fn backoff_grows_to_max() {
+  let policy = RetryPolicy {
+    max_attempts: 10,
+    base_delay_ms: 100,
+    max_delay_ms: 1_000,
+    jitter: 0.,
+  };
+  let delays = (1..6)
+    .map(|attempt| policy.backoff(attempt).as_millis())
+    .collect::<Vec<_>>();
+  assert_eq!(delays, vec![100, 200, 400, 800, 1_000]);
+
+  let policy = RetryPolicy { jitter: 0.5, ..policy };
+  let delay = policy.backoff(1).as_millis();
+  assert!((50..=150).contains(&delay));
+}
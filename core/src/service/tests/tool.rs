@@ -0,0 +1,175 @@
+use futures_util::{future::BoxFuture, FutureExt};
+use reqwest::header::HeaderMap;
+use serde_json::{json, Value as JsonValue};
+
+use uuid::Uuid;
+
+use crate::{
+  error::PolestarResult,
+  model::{ProviderKind, TokenUsage, Tool, ToolCall, ToolRegistry},
+  service::{
+    req::TextStreamReq,
+    tool::{builtin_tools, request_with_tools},
+  },
+  ToolsCfg,
+};
+
+use super::mock::{mock_server, test_bot, MockResp};
+
+struct Weather;
+
+impl Tool for Weather {
+  fn name(&self) -> &str { "weather" }
+
+  fn desc(&self) -> &str { "The weather of the city." }
+
+  fn schema(&self) -> JsonValue {
+    json!({ "type": "object", "properties": { "city": { "type": "string" } } })
+  }
+
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+    async move {
+      Ok(format!(
+        "{} is sunny",
+        args["city"].as_str().unwrap_or_default()
+      ))
+    }
+    .boxed()
+  }
+}
+
+#[tokio::test]
+async fn call_tools_until_answered() {
+  let calling = "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"weather\",\"arguments\":\"\"}}]}}]}\n\n\
+                 data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"city\\\":\"}}]}}]}\n\n\
+                 data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"Paris\\\"}\"}}]}}]}\n\n\
+                 data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n\
+                 data: {\"choices\":[],\"usage\":{\"prompt_tokens\":10,\"completion_tokens\":5,\"total_tokens\":15}}\n\n\
+                 data: [DONE]\n\n";
+  let answering = "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Sunny.\"}}]}\n\n\
+                   data: {\"choices\":[],\"usage\":{\"prompt_tokens\":20,\"completion_tokens\":2,\"total_tokens\":22}}\n\n\
+                   data: [DONE]\n\n";
+  let (addr, bodies) = mock_server(vec![
+    MockResp::new(200, "text/event-stream", calling),
+    MockResp::new(200, "text/event-stream", answering),
+  ])
+  .await;
+
+  let mut tools = ToolRegistry::default();
+  tools.register(Weather);
+  let req = TextStreamReq::new(
+    format!("{}/chat", addr),
+    HeaderMap::new(),
+    ProviderKind::OpenAI,
+  );
+  let body =
+    json!({ "model": "test-model", "messages": [{ "role": "user", "content": "weather?" }] });
+  let body = req
+    .provider()
+    .with_tools(body.to_string(), &tools.tools().collect::<Vec<_>>());
+  let mut calls = vec![];
+  let answer = request_with_tools(
+    req,
+    body,
+    &tools,
+    |_| {},
+    |_| {},
+    |_, _| std::future::ready(true),
+    |call, result| calls.push((call, result)),
+  )
+  .await
+  .unwrap();
+
+  assert_eq!(answer.text, "Sunny.");
+  assert_eq!(
+    answer.usage,
+    Some(TokenUsage {
+      prompt_tokens: 30,
+      completion_tokens: 7
+    })
+  );
+  let call = ToolCall {
+    id: "call_1".to_owned(),
+    name: "weather".to_owned(),
+    arguments: "{\"city\":\"Paris\"}".to_owned(),
+  };
+  assert_eq!(calls, vec![(call, "Paris is sunny".to_owned())]);
+
+  let bodies = bodies.lock().unwrap();
+  let first: JsonValue = serde_json::from_str(&bodies[0]).unwrap();
+  assert_eq!(first["tools"][0]["function"]["name"], "weather");
+  let second: JsonValue = serde_json::from_str(&bodies[1]).unwrap();
+  let messages = second["messages"].as_array().unwrap();
+  assert_eq!(messages.len(), 3);
+  assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+  assert_eq!(messages[2]["role"], "tool");
+  assert_eq!(messages[2]["content"], "Paris is sunny");
+}
+
+#[tokio::test]
+async fn refuse_builtin_tools_not_enabled() {
+  let root = std::env::temp_dir().join(format!("polestar-tool-{}", Uuid::new_v4()));
+  std::fs::create_dir_all(&root).unwrap();
+  std::fs::write(root.join("todo.txt"), "buy milk").unwrap();
+  let cfg = ToolsCfg {
+    roots: vec![root.clone()],
+    commands: vec!["echo".to_owned()],
+    ..<_>::default()
+  };
+
+  let calling = "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"function\":{\"name\":\"read_file\",\"arguments\":\"{\\\"path\\\":\\\"todo.txt\\\"}\"}},{\"index\":1,\"id\":\"call_2\",\"function\":{\"name\":\"shell\",\"arguments\":\"{\\\"command\\\":\\\"echo hi\\\"}\"}}]}}]}\n\n\
+                 data: [DONE]\n\n";
+  let answering = "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"No.\"}}]}\n\n\
+                   data: [DONE]\n\n";
+  let (addr, bodies) = mock_server(vec![
+    MockResp::new(200, "text/event-stream", calling),
+    MockResp::new(200, "text/event-stream", answering),
+  ])
+  .await;
+
+  // the bot only enables the clock.
+  let bot = test_bot(json!({ "use_tools": ["clock"] }));
+  let tools = builtin_tools(&cfg).for_bot(&bot);
+  let req = TextStreamReq::new(
+    format!("{}/chat", addr),
+    HeaderMap::new(),
+    ProviderKind::OpenAI,
+  );
+  let body = json!({ "model": "test-model", "messages": [{ "role": "user", "content": "hi" }] });
+  let mut calls = vec![];
+  let mut asked = 0;
+  let answer = request_with_tools(
+    req,
+    body.to_string(),
+    &tools,
+    |_| {},
+    |_| {},
+    |_, _| {
+      asked += 1;
+      std::future::ready(true)
+    },
+    |call, result| calls.push((call.name, result)),
+  )
+  .await
+  .unwrap();
+  // the refused calls are not asked to the user.
+  assert_eq!(asked, 0);
+
+  assert_eq!(answer.text, "No.");
+  assert_eq!(
+    calls,
+    vec![
+      (
+        "read_file".to_owned(),
+        "Error: tool `read_file` not found".to_owned()
+      ),
+      (
+        "shell".to_owned(),
+        "Error: tool `shell` not found".to_owned()
+      ),
+    ]
+  );
+  assert!(!bodies.lock().unwrap()[1].contains("buy milk"));
+
+  std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,130 @@
+use serde_json::Value as JsonValue;
+
+use crate::model::{Bot, Channel};
+
+/// The tokens a chat message costs for its role and separators.
+const MSG_OVERHEAD_TOKENS: usize = 4;
+/// The tokens every reply is primed with.
+const REPLY_PRIMING_TOKENS: usize = 3;
+/// The tokens reserved for the reply when the bot not specify `max_tokens`.
+const DEFAULT_REPLY_TOKENS: usize = 1024;
+/// The context window of the models we don't know.
+const DEFAULT_CONTEXT_WINDOW: usize = 4096;
+
+/// Estimate how many tokens the `text` is, without a real tokenizer.
+///
+/// A run of ASCII letters and digits counts one token per four chars, every
+/// ASCII punctuation and every non-ASCII char (e.g. CJK) counts one token,
+/// whitespace is free.
+pub fn estimate_tokens(text: &str) -> usize {
+  let mut tokens = 0;
+  let mut word_len = 0_usize;
+  for c in text.chars() {
+    if c.is_ascii_alphanumeric() {
+      word_len += 1;
+      continue;
+    }
+    tokens += word_len.div_ceil(4);
+    word_len = 0;
+    if !c.is_whitespace() {
+      tokens += 1;
+    }
+  }
+  tokens + word_len.div_ceil(4)
+}
+
+/// Estimate the tokens of a chat message with `content`.
+pub fn estimate_msg_tokens(content: &str) -> usize {
+  estimate_tokens(content) + MSG_OVERHEAD_TOKENS
+}
+
+/// Estimate the tokens of a whole request that send the messages with
+/// `contents`.
+pub fn estimate_request_tokens<'a>(contents: impl IntoIterator<Item = &'a str>) -> usize {
+  contents.into_iter().map(estimate_msg_tokens).sum::<usize>() + REPLY_PRIMING_TOKENS
+}
+
+/// Estimate the prompt tokens of a request `body` in any provider's format,
+/// by the contents of its messages and its top level system prompt.
+pub fn estimate_body_tokens(body: &str) -> usize {
+  let Ok(body) = serde_json::from_str::<JsonValue>(body) else {
+    return 0;
+  };
+  let messages = body["messages"]
+    .as_array()
+    .into_iter()
+    .flatten()
+    .filter_map(|msg| msg["content"].as_str());
+  let system = body["system"].as_str();
+  estimate_request_tokens(messages.chain(system))
+}
+
+/// The context window of the `model`, by its name prefix.
+pub fn model_context_window(model: &str) -> usize {
+  const WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("claude", 200_000),
+    ("llama3", 8_192),
+    ("mistral", 32_768),
+  ];
+  WINDOWS
+    .iter()
+    .find(|(prefix, _)| model.starts_with(prefix))
+    .map_or(DEFAULT_CONTEXT_WINDOW, |(_, window)| *window)
+}
+
+/// How many tokens the prompt, context and user content of a request can use.
+///
+/// The `context_tokens` param of the channel or bot take precedence,
+/// otherwise it's the part of the model's context window left by the reply,
+/// scaled by the channel mode.
+pub fn context_budget(bot: &Bot, channel: &Channel) -> usize {
+  if let Some(budget) = param(bot, channel, "context_tokens").and_then(JsonValue::as_u64) {
+    return budget as usize;
+  }
+  let model = param(bot, channel, "model")
+    .and_then(JsonValue::as_str)
+    .unwrap_or(super::req::DEFAULT_MODEL);
+  let reply = param(bot, channel, "max_tokens")
+    .and_then(JsonValue::as_u64)
+    .map_or(DEFAULT_REPLY_TOKENS, |n| n as usize);
+  let window = model_context_window(model).saturating_sub(reply);
+  channel.cfg().mode().context_budget(window)
+}
+
+fn param<'a>(bot: &'a Bot, channel: &'a Channel, key: &str) -> Option<&'a JsonValue> {
+  channel
+    .cfg()
+    .params()
+    .and_then(|params| params.get(key))
+    .or_else(|| bot.params().get(key))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn estimate_text_tokens() {
+    assert_eq!(estimate_tokens(""), 0);
+    assert_eq!(estimate_tokens("hello world"), 4);
+    assert_eq!(estimate_tokens("hi, bot!"), 4);
+    assert_eq!(estimate_tokens("你好"), 2);
+    assert_eq!(
+      estimate_request_tokens(["hi"]),
+      1 + MSG_OVERHEAD_TOKENS + REPLY_PRIMING_TOKENS
+    );
+  }
+
+  #[test]
+  fn context_window_by_model() {
+    assert_eq!(model_context_window("gpt-4-0613"), 8_192);
+    assert_eq!(model_context_window("gpt-4o-mini"), 128_000);
+    assert_eq!(model_context_window("claude-3-haiku"), 200_000);
+    assert_eq!(model_context_window("unknown"), DEFAULT_CONTEXT_WINDOW);
+  }
+}
@@ -0,0 +1,113 @@
+use std::{future::Future, time::Duration};
+
+use serde_json::Value as JsonValue;
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::{TokenUsage, ToolCall, ToolRegistry},
+  ToolsCfg,
+};
+
+use super::{
+  provider::{deal_chat_stream, ChatAnswer},
+  req::TextStreamReq,
+};
+
+mod calc;
+pub use calc::*;
+
+mod clock;
+pub use clock::*;
+
+mod fs;
+pub use fs::*;
+
+mod shell;
+pub use shell::*;
+
+/// The result of the call the user refused to run.
+pub const DECLINED_RESULT: &str = "Error: the user declined to run the tool";
+
+/// The tools built in the app, limited by the config. The bot can call them
+/// only if they're enabled by its `use_tools` param.
+pub fn builtin_tools(cfg: &ToolsCfg) -> ToolRegistry {
+  let mut tools = ToolRegistry::default();
+  tools.register(ReadFile::new(cfg.roots.clone()));
+  tools.register(ListDir::new(cfg.roots.clone()));
+  tools.register(Calculator);
+  tools.register(UnitConverter);
+  tools.register(Clock);
+  tools.register(Shell::new(
+    cfg.commands.clone(),
+    cfg.roots.first().cloned(),
+    Duration::from_secs(cfg.shell_timeout_secs),
+  ));
+  tools
+}
+
+fn str_arg<'a>(args: &'a JsonValue, name: &str) -> PolestarResult<&'a str> {
+  args[name]
+    .as_str()
+    .ok_or_else(|| PolestarError::Tool(format!("the argument `{}` is required", name)))
+}
+
+/// The most rounds of the tool calls in an answer, to stop the bot calling
+/// the tools endlessly.
+const MAX_TOOL_ROUNDS: usize = 8;
+
+/// Send the request and stream the answer. The tools the answer calls are run
+/// by `tools`, which should be the bot's ones (see `ToolRegistry::for_bot`),
+/// and their results are sent back, until the bot answers without
+/// calling tools. `confirm` asks the user with the question of the call that
+/// needs it, the call isn't run if it resolves false. `on_tool` is called with
+/// every call and its result, to keep them as the tool messages.
+pub async fn request_with_tools<F: Future<Output = bool>>(
+  req: TextStreamReq,
+  mut body: String,
+  tools: &ToolRegistry,
+  mut on_retry: impl FnMut(u32),
+  mut delta_op: impl FnMut(String),
+  mut confirm: impl FnMut(&ToolCall, &str) -> F,
+  mut on_tool: impl FnMut(ToolCall, String),
+) -> PolestarResult<ChatAnswer> {
+  let provider = req.provider();
+  let mut answer = ChatAnswer::default();
+  for _ in 0..MAX_TOOL_ROUNDS {
+    let mut stream = req
+      .clone()
+      .request_with_retry(body.clone(), &mut on_retry)
+      .await?;
+    let round = deal_chat_stream(provider, &mut stream, &mut delta_op).await?;
+    answer.text.push_str(&round.text);
+    answer.first_token_at = answer.first_token_at.or(round.first_token_at);
+    // every round is a request, their tokens add up.
+    if let Some(usage) = round.usage {
+      let total = answer.usage.get_or_insert_with(TokenUsage::default);
+      total.prompt_tokens += usage.prompt_tokens;
+      total.completion_tokens += usage.completion_tokens;
+    }
+    if round.tool_calls.is_empty() {
+      return Ok(answer);
+    }
+
+    let mut results = vec![];
+    for call in round.tool_calls {
+      let allowed = match tools.confirm(&call) {
+        Some(question) => confirm(&call, &question).await,
+        None => true,
+      };
+      let result = if allowed {
+        tools.invoke(&call).await
+      } else {
+        DECLINED_RESULT.to_owned()
+      };
+      on_tool(call.clone(), result.clone());
+      results.push((call, result));
+    }
+    body = provider.with_tool_results(body, &round.text, &results);
+  }
+  Err(PolestarError::Tool(format!(
+    "the bot still calls the tools after {} rounds",
+    MAX_TOOL_ROUNDS
+  )))
+}
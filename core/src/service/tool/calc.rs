@@ -0,0 +1,422 @@
+use futures_util::{future::BoxFuture, FutureExt};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::Tool,
+};
+
+use super::str_arg;
+
+/// Evaluate the arithmetic expression, the bots are bad at calculating.
+pub struct Calculator;
+
+impl Tool for Calculator {
+  fn name(&self) -> &str { "calculator" }
+
+  fn desc(&self) -> &str {
+    "Evaluate an arithmetic expression with `+ - * / % ^`, parentheses, the constants `pi` and \
+     `e`, and the functions `sqrt abs ln log exp sin cos tan asin acos atan round floor ceil`."
+  }
+
+  fn schema(&self) -> JsonValue {
+    json!({
+      "type": "object",
+      "properties": {
+        "expression": { "type": "string", "description": "i.e. `(1 + 2) * sqrt(16)`." }
+      },
+      "required": ["expression"]
+    })
+  }
+
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+    async move { eval(str_arg(&args, "expression")?).map(fmt_num) }.boxed()
+  }
+}
+
+/// Convert the value between the units of length, mass, time, data size and
+/// temperature.
+pub struct UnitConverter;
+
+impl Tool for UnitConverter {
+  fn name(&self) -> &str { "convert_unit" }
+
+  fn desc(&self) -> &str {
+    "Convert a value between units of length (mm cm m km in ft yd mi), mass (mg g kg t oz lb), \
+     time (ms s min h day week), data size (B KB MB GB TB) or temperature (C F K)."
+  }
+
+  fn schema(&self) -> JsonValue {
+    json!({
+      "type": "object",
+      "properties": {
+        "value": { "type": "number" },
+        "from": { "type": "string", "description": "The unit of the value." },
+        "to": { "type": "string", "description": "The unit to convert to." }
+      },
+      "required": ["value", "from", "to"]
+    })
+  }
+
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+    async move {
+      let value = args["value"]
+        .as_f64()
+        .ok_or_else(|| PolestarError::Tool("the argument `value` is required".to_owned()))?;
+      let (from, to) = (str_arg(&args, "from")?, str_arg(&args, "to")?);
+      let res = convert(value, from, to)?;
+      Ok(format!(
+        "{} {} = {} {}",
+        fmt_num(value),
+        from,
+        fmt_num(res),
+        to
+      ))
+    }
+    .boxed()
+  }
+}
+
+fn fmt_num(v: f64) -> String {
+  if v.fract() == 0. && v.abs() < 1e15 {
+    format!("{}", v as i64)
+  } else {
+    // cut off the float noise, i.e. `0.30000000000000004`.
+    let v: f64 = format!("{:.12}", v).parse().unwrap_or(v);
+    v.to_string()
+  }
+}
+
+fn eval(expr: &str) -> PolestarResult<f64> {
+  let mut parser = Parser {
+    chars: expr.chars().collect(),
+    pos: 0,
+    depth: 0,
+  };
+  let v = parser.expr()?;
+  parser.skip_space();
+  if parser.pos < parser.chars.len() {
+    return Err(parser.error());
+  }
+  if !v.is_finite() {
+    return Err(PolestarError::Tool(
+      "the result is not a finite number".to_owned(),
+    ));
+  }
+  Ok(v)
+}
+
+/// The most nested signs and parentheses, the deeper expression overflows the
+/// stack of the recursive parser.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+  chars: Vec<char>,
+  pos: usize,
+  // the nesting of `unary`, every recursion of the parser goes through it.
+  depth: usize,
+}
+
+impl Parser {
+  fn skip_space(&mut self) {
+    while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+      self.pos += 1;
+    }
+  }
+
+  fn peek(&mut self) -> Option<char> {
+    self.skip_space();
+    self.chars.get(self.pos).copied()
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    let eaten = self.peek() == Some(c);
+    if eaten {
+      self.pos += 1;
+    }
+    eaten
+  }
+
+  fn error(&self) -> PolestarError {
+    let rest: String = self.chars[self.pos.min(self.chars.len())..]
+      .iter()
+      .collect();
+    if rest.is_empty() {
+      PolestarError::Tool("the expression ends unexpectedly".to_owned())
+    } else {
+      PolestarError::Tool(format!("unexpected `{}` in the expression", rest))
+    }
+  }
+
+  // expr := term (('+' | '-') term)*
+  fn expr(&mut self) -> PolestarResult<f64> {
+    let mut v = self.term()?;
+    loop {
+      if self.eat('+') {
+        v += self.term()?;
+      } else if self.eat('-') {
+        v -= self.term()?;
+      } else {
+        return Ok(v);
+      }
+    }
+  }
+
+  // term := unary (('*' | '/' | '%') unary)*
+  fn term(&mut self) -> PolestarResult<f64> {
+    let mut v = self.unary()?;
+    loop {
+      if self.peek() == Some('*') && self.chars.get(self.pos + 1) != Some(&'*') {
+        self.pos += 1;
+        v *= self.unary()?;
+      } else if self.eat('/') {
+        v /= self.unary()?;
+      } else if self.eat('%') {
+        v %= self.unary()?;
+      } else {
+        return Ok(v);
+      }
+    }
+  }
+
+  // unary := ('-' | '+') unary | power
+  fn unary(&mut self) -> PolestarResult<f64> {
+    if self.depth >= MAX_DEPTH {
+      return Err(PolestarError::Tool(format!(
+        "the expression is nested more than {} levels",
+        MAX_DEPTH
+      )));
+    }
+    self.depth += 1;
+    let v = self.signed();
+    self.depth -= 1;
+    v
+  }
+
+  fn signed(&mut self) -> PolestarResult<f64> {
+    if self.eat('-') {
+      Ok(-self.unary()?)
+    } else if self.eat('+') {
+      self.unary()
+    } else {
+      self.power()
+    }
+  }
+
+  // power := atom (('^' | '**') unary)?
+  fn power(&mut self) -> PolestarResult<f64> {
+    let base = self.atom()?;
+    if self.eat('^') {
+      Ok(base.powf(self.unary()?))
+    } else if self.peek() == Some('*') && self.chars.get(self.pos + 1) == Some(&'*') {
+      self.pos += 2;
+      Ok(base.powf(self.unary()?))
+    } else {
+      Ok(base)
+    }
+  }
+
+  // atom := number | '(' expr ')' | name | name '(' expr ')'
+  fn atom(&mut self) -> PolestarResult<f64> {
+    match self.peek() {
+      Some('(') => {
+        self.pos += 1;
+        let v = self.expr()?;
+        if self.eat(')') {
+          Ok(v)
+        } else {
+          Err(self.error())
+        }
+      }
+      Some(c) if c.is_ascii_digit() || c == '.' => {
+        let start = self.pos;
+        while self
+          .chars
+          .get(self.pos)
+          .is_some_and(|c| c.is_ascii_digit() || *c == '.' || *c == '_')
+        {
+          self.pos += 1;
+        }
+        let num: String = self.chars[start..self.pos]
+          .iter()
+          .filter(|c| **c != '_')
+          .collect();
+        num
+          .parse()
+          .map_err(|_| PolestarError::Tool(format!("invalid number `{}`", num)))
+      }
+      Some(c) if c.is_ascii_alphabetic() => {
+        let start = self.pos;
+        while self
+          .chars
+          .get(self.pos)
+          .is_some_and(|c| c.is_ascii_alphanumeric())
+        {
+          self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        match name.as_str() {
+          "pi" => return Ok(std::f64::consts::PI),
+          "e" => return Ok(std::f64::consts::E),
+          _ => {}
+        }
+        if !self.eat('(') {
+          return Err(PolestarError::Tool(format!("unknown name `{}`", name)));
+        }
+        let arg = self.expr()?;
+        if !self.eat(')') {
+          return Err(self.error());
+        }
+        let f: fn(f64) -> f64 = match name.as_str() {
+          "sqrt" => f64::sqrt,
+          "abs" => f64::abs,
+          "ln" => f64::ln,
+          "log" => f64::log10,
+          "exp" => f64::exp,
+          "sin" => f64::sin,
+          "cos" => f64::cos,
+          "tan" => f64::tan,
+          "asin" => f64::asin,
+          "acos" => f64::acos,
+          "atan" => f64::atan,
+          "round" => f64::round,
+          "floor" => f64::floor,
+          "ceil" => f64::ceil,
+          _ => return Err(PolestarError::Tool(format!("unknown function `{}`", name))),
+        };
+        Ok(f(arg))
+      }
+      _ => Err(self.error()),
+    }
+  }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Dimension {
+  Length,
+  Mass,
+  Time,
+  Data,
+}
+
+/// The units with their dimension and how many base units, meter, gram,
+/// second and byte, they are.
+const UNITS: &[(&[&str], Dimension, f64)] = &[
+  (&["mm", "millimeter"], Dimension::Length, 0.001),
+  (&["cm", "centimeter"], Dimension::Length, 0.01),
+  (&["m", "meter"], Dimension::Length, 1.),
+  (&["km", "kilometer"], Dimension::Length, 1000.),
+  (&["in", "inch"], Dimension::Length, 0.0254),
+  (&["ft", "foot", "feet"], Dimension::Length, 0.3048),
+  (&["yd", "yard"], Dimension::Length, 0.9144),
+  (&["mi", "mile"], Dimension::Length, 1609.344),
+  (&["mg", "milligram"], Dimension::Mass, 0.001),
+  (&["g", "gram"], Dimension::Mass, 1.),
+  (&["kg", "kilogram"], Dimension::Mass, 1000.),
+  (&["t", "ton", "tonne"], Dimension::Mass, 1_000_000.),
+  (&["oz", "ounce"], Dimension::Mass, 28.349523125),
+  (&["lb", "pound"], Dimension::Mass, 453.59237),
+  (&["ms", "millisecond"], Dimension::Time, 0.001),
+  (&["s", "sec", "second"], Dimension::Time, 1.),
+  (&["min", "minute"], Dimension::Time, 60.),
+  (&["h", "hour"], Dimension::Time, 3600.),
+  (&["day"], Dimension::Time, 86400.),
+  (&["week"], Dimension::Time, 604800.),
+  (&["b", "byte"], Dimension::Data, 1.),
+  (&["kb", "kilobyte"], Dimension::Data, 1024.),
+  (&["mb", "megabyte"], Dimension::Data, 1024. * 1024.),
+  (&["gb", "gigabyte"], Dimension::Data, 1024. * 1024. * 1024.),
+  (
+    &["tb", "terabyte"],
+    Dimension::Data,
+    1024. * 1024. * 1024. * 1024.,
+  ),
+];
+
+fn unit(name: &str) -> Option<(Dimension, f64)> {
+  let name = name.trim().to_lowercase();
+  let find = |name: &str| UNITS.iter().find(|(names, ..)| names.contains(&name));
+  // the plural names, i.e. `miles`, but `ms` is not `m`.
+  let unit = find(&name).or_else(|| find(name.strip_suffix('s')?))?;
+  Some((unit.1, unit.2))
+}
+
+/// The temperature in kelvin of the `value` in the `unit`.
+fn to_kelvin(value: f64, unit: &str) -> Option<f64> {
+  match unit.trim().trim_start_matches('°').to_lowercase().as_str() {
+    "c" | "celsius" => Some(value + 273.15),
+    "f" | "fahrenheit" => Some((value - 32.) * 5. / 9. + 273.15),
+    "k" | "kelvin" => Some(value),
+    _ => None,
+  }
+}
+
+fn from_kelvin(kelvin: f64, unit: &str) -> Option<f64> {
+  match unit.trim().trim_start_matches('°').to_lowercase().as_str() {
+    "c" | "celsius" => Some(kelvin - 273.15),
+    "f" | "fahrenheit" => Some((kelvin - 273.15) * 9. / 5. + 32.),
+    "k" | "kelvin" => Some(kelvin),
+    _ => None,
+  }
+}
+
+fn convert(value: f64, from: &str, to: &str) -> PolestarResult<f64> {
+  if let Some(kelvin) = to_kelvin(value, from) {
+    return from_kelvin(kelvin, to)
+      .ok_or_else(|| PolestarError::Tool(format!("can't convert temperature to `{}`", to)));
+  }
+  let unknown = |unit: &str| PolestarError::Tool(format!("unknown unit `{}`", unit));
+  let (from_dim, from_factor) = unit(from).ok_or_else(|| unknown(from))?;
+  let (to_dim, to_factor) = unit(to).ok_or_else(|| unknown(to))?;
+  if from_dim != to_dim {
+    return Err(PolestarError::Tool(format!(
+      "can't convert `{}` to `{}`",
+      from, to
+    )));
+  }
+  Ok(value * from_factor / to_factor)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn calculate() {
+    let calc = |expr| eval(expr).map(fmt_num).unwrap();
+    assert_eq!(calc("1 + 2 * 3"), "7");
+    assert_eq!(calc("(1 + 2) * 3"), "9");
+    assert_eq!(calc("-2 ^ 2"), "-4");
+    assert_eq!(calc("2 ** 3 ** 2"), "512");
+    assert_eq!(calc("10 % 4 / 4"), "0.5");
+    assert_eq!(calc("0.1 + 0.2"), "0.3");
+    assert_eq!(calc("sqrt(16) + abs(-1_000)"), "1004");
+    assert_eq!(calc("round(pi * 100)"), "314");
+
+    assert!(eval("1 +").is_err());
+    assert!(eval("2 * (3").is_err());
+    assert!(eval("1 / 0").is_err());
+    assert!(eval("foo(1)").is_err());
+    assert!(eval("1 2").is_err());
+
+    assert_eq!(calc(&format!("{}1{}", "(".repeat(30), ")".repeat(30))), "1");
+    let err = eval(&"-".repeat(100_000)).unwrap_err();
+    assert!(err.to_string().contains("nested more than"));
+    assert!(eval(&"(".repeat(100_000)).is_err());
+  }
+
+  #[test]
+  fn convert_units() {
+    assert_eq!(fmt_num(convert(1., "mile", "km").unwrap()), "1.609344");
+    assert_eq!(fmt_num(convert(2., "lbs", "oz").unwrap()), "32");
+    assert_eq!(fmt_num(convert(1.5, "h", "min").unwrap()), "90");
+    assert_eq!(fmt_num(convert(1500., "ms", "s").unwrap()), "1.5");
+    assert_eq!(fmt_num(convert(1., "GB", "MB").unwrap()), "1024");
+    assert_eq!(fmt_num(convert(100., "°C", "F").unwrap()), "212");
+    assert_eq!(fmt_num(convert(0., "K", "celsius").unwrap()), "-273.15");
+
+    assert!(convert(1., "kg", "m").is_err());
+    assert!(convert(1., "C", "kg").is_err());
+    assert!(convert(1., "parsec", "m").is_err());
+  }
+}
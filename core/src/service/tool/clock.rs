@@ -0,0 +1,70 @@
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use futures_util::{future::BoxFuture, FutureExt};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::Tool,
+};
+
+/// The current date and time, the bot doesn't know it by itself.
+pub struct Clock;
+
+impl Tool for Clock {
+  fn name(&self) -> &str { "clock" }
+
+  fn desc(&self) -> &str {
+    "Get the current date, time and weekday, in the local time zone of the user or the given UTC \
+     offset."
+  }
+
+  fn schema(&self) -> JsonValue {
+    json!({
+      "type": "object",
+      "properties": {
+        "utc_offset": {
+          "type": "string",
+          "description": "The UTC offset of the time zone, i.e. `+08:00` or `-05:00`."
+        }
+      }
+    })
+  }
+
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+    async move { now_at(Utc::now(), args["utc_offset"].as_str()) }.boxed()
+  }
+}
+
+fn now_at(now: DateTime<Utc>, utc_offset: Option<&str>) -> PolestarResult<String> {
+  let now = match utc_offset {
+    Some(offset) => {
+      let offset = offset
+        .parse::<FixedOffset>()
+        .map_err(|_| PolestarError::Tool(format!("invalid UTC offset `{}`", offset)))?;
+      now.with_timezone(&offset)
+    }
+    None => now.with_timezone(&Local).fixed_offset(),
+  };
+  Ok(now.format("%Y-%m-%d %H:%M:%S %:z, %A").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use chrono::TimeZone;
+
+  use super::*;
+
+  #[test]
+  fn time_at_offset() {
+    let now = Utc.with_ymd_and_hms(2024, 5, 1, 20, 30, 0).unwrap();
+    assert_eq!(
+      now_at(now, Some("+08:00")).unwrap(),
+      "2024-05-02 04:30:00 +08:00, Thursday"
+    );
+    assert_eq!(
+      now_at(now, Some("-05:00")).unwrap(),
+      "2024-05-01 15:30:00 -05:00, Wednesday"
+    );
+    assert!(now_at(now, Some("Mars")).is_err());
+  }
+}
@@ -0,0 +1,180 @@
+use std::{
+  fs,
+  io::Read,
+  path::{Path, PathBuf},
+};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::Tool,
+};
+
+use super::str_arg;
+
+/// The most bytes of a file sent to the bot, the rest is cut off.
+const MAX_READ_BYTES: usize = 64 * 1024;
+
+/// Read the text file in the allowed folders.
+pub struct ReadFile {
+  roots: Vec<PathBuf>,
+}
+
+impl ReadFile {
+  pub fn new(roots: Vec<PathBuf>) -> Self { Self { roots } }
+}
+
+impl Tool for ReadFile {
+  fn name(&self) -> &str { "read_file" }
+
+  fn desc(&self) -> &str {
+    "Read the content of a local text file. A relative path is relative to the first allowed \
+     folder."
+  }
+
+  fn schema(&self) -> JsonValue {
+    json!({
+      "type": "object",
+      "properties": { "path": { "type": "string", "description": "The path of the file." } },
+      "required": ["path"]
+    })
+  }
+
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+    async move {
+      let path = allowed_path(&self.roots, str_arg(&args, "path")?)?;
+      let file = fs::File::open(path)?;
+      let len = file.metadata()?.len();
+      // only the part sent is read, the file may be huge.
+      let mut bytes = vec![];
+      file.take(MAX_READ_BYTES as u64).read_to_end(&mut bytes)?;
+      let mut text = String::from_utf8_lossy(&bytes).into_owned();
+      if len > MAX_READ_BYTES as u64 {
+        text.push_str(&format!("\n[cut off, the file has {} bytes]", len));
+      }
+      Ok(text)
+    }
+    .boxed()
+  }
+}
+
+/// List the entries of the folder in the allowed folders.
+pub struct ListDir {
+  roots: Vec<PathBuf>,
+}
+
+impl ListDir {
+  pub fn new(roots: Vec<PathBuf>) -> Self { Self { roots } }
+}
+
+impl Tool for ListDir {
+  fn name(&self) -> &str { "list_dir" }
+
+  fn desc(&self) -> &str {
+    "List the files and folders in a local folder, the folders end with `/`. A relative path is \
+     relative to the first allowed folder."
+  }
+
+  fn schema(&self) -> JsonValue {
+    json!({
+      "type": "object",
+      "properties": { "path": { "type": "string", "description": "The path of the folder." } },
+      "required": ["path"]
+    })
+  }
+
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+    async move {
+      let path = allowed_path(&self.roots, str_arg(&args, "path")?)?;
+      let mut entries = vec![];
+      for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() {
+          entries.push(format!("{}/", name));
+        } else {
+          entries.push(format!("{} ({} bytes)", name, entry.metadata()?.len()));
+        }
+      }
+      entries.sort();
+      Ok(if entries.is_empty() {
+        "[empty folder]".to_owned()
+      } else {
+        entries.join("\n")
+      })
+    }
+    .boxed()
+  }
+}
+
+/// The real path of `path` if it's in one of the `roots`, the links and `..`
+/// are resolved before the check.
+pub(crate) fn allowed_path(roots: &[PathBuf], path: &str) -> PolestarResult<PathBuf> {
+  let Some(first) = roots.first() else {
+    return Err(PolestarError::Tool(
+      "no folder is allowed, add it to the `tools.roots` of the config".to_owned(),
+    ));
+  };
+  let path = Path::new(path);
+  let path = if path.is_relative() {
+    first.join(path)
+  } else {
+    path.to_owned()
+  };
+  let path = path.canonicalize()?;
+  roots
+    .iter()
+    .filter_map(|root| root.canonicalize().ok())
+    .any(|root| path.starts_with(root))
+    .then_some(path)
+    .ok_or_else(|| PolestarError::Tool("the path is out of the allowed folders".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+  use uuid::Uuid;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn only_allowed_folders() {
+    let tmp = std::env::temp_dir().join(format!("polestar-tool-{}", Uuid::new_v4()));
+    let root = tmp.join("root");
+    fs::create_dir_all(root.join("notes")).unwrap();
+    fs::write(root.join("todo.txt"), "buy milk").unwrap();
+    fs::write(root.join("big.txt"), "a".repeat(MAX_READ_BYTES + 10)).unwrap();
+    fs::write(tmp.join("secret.txt"), "password").unwrap();
+
+    let read = ReadFile::new(vec![root.clone()]);
+    let text = read.invoke(json!({ "path": "todo.txt" })).await.unwrap();
+    assert_eq!(text, "buy milk");
+    let text = read.invoke(json!({ "path": "big.txt" })).await.unwrap();
+    let note = format!("\n[cut off, the file has {} bytes]", MAX_READ_BYTES + 10);
+    assert_eq!(text.len(), MAX_READ_BYTES + note.len());
+    assert!(text.ends_with(&note));
+    assert!(read
+      .invoke(json!({ "path": "../secret.txt" }))
+      .await
+      .is_err());
+    let secret = tmp.join("secret.txt").to_string_lossy().into_owned();
+    assert!(read.invoke(json!({ "path": secret })).await.is_err());
+
+    let list = ListDir::new(vec![root.clone()]);
+    let entries = list.invoke(json!({ "path": "." })).await.unwrap();
+    assert_eq!(
+      entries,
+      format!(
+        "big.txt ({} bytes)\nnotes/\ntodo.txt (8 bytes)",
+        MAX_READ_BYTES + 10
+      )
+    );
+    assert!(list.invoke(json!({ "path": ".." })).await.is_err());
+
+    let none = ListDir::new(vec![]);
+    assert!(none.invoke(json!({ "path": "." })).await.is_err());
+
+    fs::remove_dir_all(tmp).unwrap();
+  }
+}
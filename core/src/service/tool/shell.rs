@@ -0,0 +1,142 @@
+use std::{path::PathBuf, process::Stdio, time::Duration};
+
+use futures_util::{future::BoxFuture, FutureExt};
+use serde_json::{json, Value as JsonValue};
+use tokio::process::Command;
+
+use crate::{
+  error::{PolestarError, PolestarResult},
+  model::Tool,
+};
+
+use super::str_arg;
+
+/// The most bytes of the output sent to the bot, the rest is cut off.
+const MAX_OUTPUT_BYTES: usize = 16 * 1024;
+
+/// Run the allowed program, the command is split like a shell does but isn't
+/// run by a shell, so the pipes and the redirections don't work. It's asked
+/// to the user before running.
+pub struct Shell {
+  commands: Vec<String>,
+  // the working folder of the commands.
+  cwd: Option<PathBuf>,
+  timeout: Duration,
+}
+
+impl Shell {
+  pub fn new(commands: Vec<String>, cwd: Option<PathBuf>, timeout: Duration) -> Self {
+    Self { commands, cwd, timeout }
+  }
+}
+
+impl Tool for Shell {
+  fn name(&self) -> &str { "shell" }
+
+  fn desc(&self) -> &str {
+    "Run a local command without pipes or redirections, only the allowed programs can run and the \
+     user confirms it first."
+  }
+
+  fn schema(&self) -> JsonValue {
+    json!({
+      "type": "object",
+      "properties": {
+        "command": { "type": "string", "description": "The command line, i.e. `git status`." }
+      },
+      "required": ["command"]
+    })
+  }
+
+  fn confirm(&self, args: &JsonValue) -> Option<String> {
+    let command = args["command"].as_str()?;
+    Some(format!("Run the command `{}`?", command))
+  }
+
+  fn invoke(&self, args: JsonValue) -> BoxFuture<'_, PolestarResult<String>> {
+    async move {
+      let command = str_arg(&args, "command")?;
+      let words = shlex::split(command)
+        .ok_or_else(|| PolestarError::Tool(format!("invalid command `{}`", command)))?;
+      let Some((program, args)) = words.split_first() else {
+        return Err(PolestarError::Tool("the command is empty".to_owned()));
+      };
+      if !self.commands.contains(program) {
+        return Err(PolestarError::Tool(format!(
+          "`{}` is not allowed, add it to the `tools.commands` of the config",
+          program
+        )));
+      }
+
+      let mut cmd = Command::new(program);
+      cmd.args(args).stdin(Stdio::null()).kill_on_drop(true);
+      if let Some(cwd) = &self.cwd {
+        cmd.current_dir(cwd);
+      }
+      let output = tokio::time::timeout(self.timeout, cmd.output())
+        .await
+        .map_err(|_| {
+          PolestarError::Tool(format!(
+            "the command is killed after {} seconds",
+            self.timeout.as_secs()
+          ))
+        })??;
+
+      let mut res = match output.status.code() {
+        Some(code) => format!("exit code: {}\n", code),
+        None => "killed by a signal\n".to_owned(),
+      };
+      res.push_str(&String::from_utf8_lossy(&output.stdout));
+      if !output.stderr.is_empty() {
+        res.push_str("\nstderr:\n");
+        res.push_str(&String::from_utf8_lossy(&output.stderr));
+      }
+      if res.len() > MAX_OUTPUT_BYTES {
+        let mut end = MAX_OUTPUT_BYTES;
+        while !res.is_char_boundary(end) {
+          end -= 1;
+        }
+        res.truncate(end);
+        res.push_str("\n[cut off]");
+      }
+      Ok(res)
+    }
+    .boxed()
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn only_allowed_commands() {
+    let shell = Shell::new(
+      vec!["echo".to_owned(), "sleep".to_owned()],
+      None,
+      Duration::from_millis(200),
+    );
+    let args = json!({ "command": "echo 'hello world'" });
+    assert_eq!(
+      shell.confirm(&args).as_deref(),
+      Some("Run the command `echo 'hello world'`?")
+    );
+    assert_eq!(
+      shell.invoke(args).await.unwrap(),
+      "exit code: 0\nhello world\n"
+    );
+
+    let err = shell
+      .invoke(json!({ "command": "rm -rf /tmp/x" }))
+      .await
+      .unwrap_err();
+    assert!(err.to_string().contains("`rm` is not allowed"));
+    // no shell to run the second command.
+    let res = shell
+      .invoke(json!({ "command": "echo hi; rm -rf /tmp/x" }))
+      .await
+      .unwrap();
+    assert_eq!(res, "exit code: 0\nhi; rm -rf /tmp/x\n");
+    assert!(shell.invoke(json!({ "command": "sleep 5" })).await.is_err());
+  }
+}
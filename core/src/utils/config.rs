@@ -2,12 +2,12 @@ use std::{collections::HashMap, fs, io::Read, path::PathBuf};
 
 use log::warn;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
   error::PolestarResult,
   launch::write_default_bot_config,
-  model::{Bot, BotId, PartialBot, ServerProvider},
+  model::{Bot, BotId, ModelPrice, PartialBot, RetryPolicy, ServerProvider},
   project_config_path, user_cfg_path, user_data_path,
 };
 
@@ -15,18 +15,25 @@ use crate::{
 struct BotFileCfg {
   bots: Option<Vec<Bot>>,
   providers: Option<Vec<ServerProvider>>,
+  prices: Option<HashMap<String, ModelPrice>>,
 }
 
 #[derive(Deserialize, Debug)]
 struct PartialBotFileCfg {
   bots: Option<Vec<PartialBot>>,
   providers: Option<Vec<ServerProvider>>,
+  prices: Option<HashMap<String, ModelPrice>>,
 }
 
 #[derive(Deserialize, Debug)]
 struct UserFileCfg {
   base: Option<UserFileBase>,
   files: Option<Vec<String>>,
+  retry: Option<RetryPolicy>,
+  trash_days: Option<u32>,
+  prices: Option<HashMap<String, ModelPrice>>,
+  tools: Option<ToolsCfg>,
+  mcp_servers: Option<Vec<McpServerCfg>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -53,7 +60,7 @@ pub fn load_bot_cfg(uid: &str) -> PolestarResult<BotCfg> {
         "/..",
         "/config/bot.json"
       ));
-      let BotFileCfg { bots, providers } = parse_bot_config(content)?;
+      let BotFileCfg { bots, providers, prices } = parse_bot_config(content)?;
       Ok(BotCfg {
         bots: bots.unwrap_or_default(),
         providers: providers
@@ -61,6 +68,11 @@ pub fn load_bot_cfg(uid: &str) -> PolestarResult<BotCfg> {
           .into_iter()
           .map(|sp| (sp.name.clone(), sp))
           .collect(),
+        retry: None,
+        trash_days: None,
+        prices: prices.unwrap_or_default(),
+        tools: ToolsCfg::default(),
+        mcp_servers: vec![],
       })
     })
 }
@@ -79,10 +91,12 @@ fn parse_user_bot_cfgs(user_data_path: PathBuf, file: &str) -> PolestarResult<Bo
   let user_file_cfg = serde_json::from_str::<UserFileCfg>(file)?;
   let mut user_partial_bots = vec![];
   let mut user_sp = HashMap::new();
+  let mut user_prices = HashMap::new();
   if let Some(files) = user_file_cfg.files {
     for file in files {
-      let PartialBotFileCfg { bots, providers } =
+      let PartialBotFileCfg { bots, providers, prices } =
         parse_partial_bot_config_file(&user_data_path.join(file))?;
+      user_prices.extend(prices.unwrap_or_default());
       if let Some(bots) = bots {
         user_partial_bots.extend(bots.into_iter());
       }
@@ -100,8 +114,15 @@ fn parse_user_bot_cfgs(user_data_path: PathBuf, file: &str) -> PolestarResult<Bo
   }
 
   let mut official_bots = vec![];
+  // the prices of the user's files and config override the official ones.
+  let mut prices = HashMap::new();
   if let Some(base) = user_file_cfg.base {
-    let BotFileCfg { bots, providers } = parse_bot_config_file(&user_data_path.join(base.extends))?;
+    let BotFileCfg {
+      bots,
+      providers,
+      prices: official_prices,
+    } = parse_bot_config_file(&user_data_path.join(base.extends))?;
+    prices.extend(official_prices.unwrap_or_default());
 
     if let Some(bots) = bots {
       official_bots.extend(
@@ -140,10 +161,17 @@ fn parse_user_bot_cfgs(user_data_path: PathBuf, file: &str) -> PolestarResult<Bo
   }
 
   user_bots.extend(user_partial_bots.into_iter().filter_map(|bot| bot.to_bot()));
+  prices.extend(user_prices);
+  prices.extend(user_file_cfg.prices.unwrap_or_default());
 
   Ok(BotCfg {
     bots: official_bots.into_iter().chain(user_bots).collect(),
     providers: user_sp,
+    retry: user_file_cfg.retry,
+    trash_days: user_file_cfg.trash_days,
+    prices,
+    tools: user_file_cfg.tools.unwrap_or_default(),
+    mcp_servers: user_file_cfg.mcp_servers.unwrap_or_default(),
   })
 }
 
@@ -156,6 +184,90 @@ struct CfgVars {
 pub struct BotCfg {
   pub bots: Vec<Bot>,
   pub providers: HashMap<String, ServerProvider>,
+  pub retry: Option<RetryPolicy>,
+  // how many days the deleted channels and messages are kept in the trash.
+  pub trash_days: Option<u32>,
+  // the price of the models per 1K tokens, the providers can override it.
+  pub prices: HashMap<String, ModelPrice>,
+  // what the built-in local tools are allowed to touch.
+  pub tools: ToolsCfg,
+  // the MCP servers whose tools the bots can call.
+  pub mcp_servers: Vec<McpServerCfg>,
+}
+
+/// The limits of the built-in local tools, nothing is allowed by default.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ToolsCfg {
+  /// the folders the file tools can read and list, with their sub folders.
+  pub roots: Vec<PathBuf>,
+  /// the programs the shell tool can run, i.e. `ls` or `git`.
+  pub commands: Vec<String>,
+  /// the time a shell command can run before it's killed.
+  pub shell_timeout_secs: u64,
+}
+
+impl Default for ToolsCfg {
+  fn default() -> Self {
+    Self {
+      roots: vec![],
+      commands: vec![],
+      shell_timeout_secs: 30,
+    }
+  }
+}
+
+/// The MCP server the app launches and talks to over its stdio.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct McpServerCfg {
+  /// the unique name of the server, the prefix of its tool names.
+  pub name: String,
+  /// the program to launch the server.
+  pub command: String,
+  #[serde(default)]
+  pub args: Vec<String>,
+  /// the environment variables of the server, besides the app's ones.
+  #[serde(default)]
+  pub env: HashMap<String, String>,
+}
+
+/// The network settings of the user, saved in its own file beside the user's
+/// config file.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct NetworkCfg {
+  /// the proxy of all requests, support `http`, `https` and `socks5` scheme.
+  pub proxy: Option<String>,
+  /// the hosts not go through the proxy.
+  #[serde(default)]
+  pub no_proxy: Vec<String>,
+  #[serde(default)]
+  pub timeout: TimeoutCfg,
+}
+
+/// The timeouts of the http requests, expiring any of them fails the request
+/// with `TimedOut`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TimeoutCfg {
+  /// the time to build the connection.
+  pub connect_secs: u64,
+  /// the time from sending the request to receiving the response head.
+  pub first_byte_secs: u64,
+  /// the time between two chunks of the response body.
+  pub idle_secs: u64,
+  /// the time to keep an idle connection in the pool.
+  pub pool_idle_secs: u64,
+}
+
+impl Default for TimeoutCfg {
+  fn default() -> Self {
+    Self {
+      connect_secs: 10,
+      first_byte_secs: 60,
+      idle_secs: 60,
+      pool_idle_secs: 90,
+    }
+  }
 }
 
 pub fn open_user_config_folder() {
@@ -318,6 +430,35 @@ mod test {
     );
   }
 
+  #[test]
+  fn prices_and_budget_parser() {
+    let file = r#"{
+      "prices": {
+        "gpt-4o": { "input": 0.005, "output": 0.015 }
+      },
+      "providers": [
+        {
+          "name": "OpenAI",
+          "base_url": "https://api.openai.com",
+          "token": "abc",
+          "prices": {
+            "gpt-4o": { "input": 0.0025, "output": 0.01 }
+          },
+          "budget": { "soft": 8.0, "hard": 10.0 }
+        }
+      ]
+    }"#;
+
+    let cfg = parse_bot_config(file).expect("can't parse bots config");
+    let prices = cfg.prices.unwrap();
+    assert_eq!(prices["gpt-4o"].output, 0.015);
+    let providers = cfg.providers.unwrap();
+    assert_eq!(providers[0].prices["gpt-4o"].input, 0.0025);
+    let budget = providers[0].budget.unwrap();
+    assert_eq!(budget.soft, Some(8.));
+    assert_eq!(budget.hard, Some(10.));
+  }
+
   #[test]
   fn test() {
     let reg = Regex::new(r"\{\s*([^}]*)\s*\}").unwrap();
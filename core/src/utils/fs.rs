@@ -4,7 +4,7 @@ use home::home_dir;
 
 use crate::error::PolestarResult;
 
-use super::LocalState;
+use super::{LocalState, NetworkCfg};
 
 static POLESTAR_FOLDER: &str = ".polestar_v1";
 static USERS_FOLDER: &str = "users";
@@ -15,7 +15,9 @@ static CURRENT_USER: &str = "current_user";
 static NONCE_FILE: &str = "nonce";
 static TOKEN_FILE: &str = "token";
 static LOCAL_STATE: &str = "local_state";
+static NETWORK_CONFIG_FILE: &str = "network.json";
 static POLESTAR_STATIC: &str = "static";
+static EXPORT_FOLDER: &str = "exports";
 
 pub fn project_home_path() -> PathBuf {
   home_dir()
@@ -50,6 +52,13 @@ pub fn project_bot_config_path() -> PathBuf {
   path
 }
 
+/// The default directory to export the channels to.
+pub fn project_export_path() -> PathBuf {
+  let mut path = project_home_path();
+  path.push(EXPORT_FOLDER);
+  path
+}
+
 fn project_user_path() -> PathBuf {
   let mut path = project_home_path();
   path.push(USERS_FOLDER);
@@ -93,6 +102,35 @@ pub fn write_local_state(uid: &str, local_state: &LocalState) -> PolestarResult<
   Ok(())
 }
 
+/// The network config shared by all users before every user has their own,
+/// only read for the users who haven't saved theirs yet.
+pub fn read_network_cfg() -> PolestarResult<NetworkCfg> {
+  let mut path = project_config_path();
+  path.push(NETWORK_CONFIG_FILE);
+  let content = std::fs::read_to_string(&path)?;
+  let network_cfg = serde_json::from_str::<NetworkCfg>(&content)?;
+  Ok(network_cfg)
+}
+
+pub fn read_user_network_cfg(uid: &str) -> PolestarResult<NetworkCfg> {
+  let mut path = user_data_path(uid);
+  path.push(NETWORK_CONFIG_FILE);
+  let content = std::fs::read_to_string(&path)?;
+  let network_cfg = serde_json::from_str::<NetworkCfg>(&content)?;
+  Ok(network_cfg)
+}
+
+/// Save the network config beside the user's config file but not in it, so
+/// the file the user writes is never rewritten by the app.
+pub fn write_user_network_cfg(uid: &str, network_cfg: &NetworkCfg) -> PolestarResult<()> {
+  let mut path = user_data_path(uid);
+  std::fs::create_dir_all(&path)?;
+  path.push(NETWORK_CONFIG_FILE);
+  let content = serde_json::to_string_pretty(network_cfg)?;
+  std::fs::write(&path, content)?;
+  Ok(())
+}
+
 pub fn create_if_not_exist_dir(path: PathBuf) {
   if let Err(err) = std::fs::metadata(&path) {
     if err.kind() == std::io::ErrorKind::NotFound {
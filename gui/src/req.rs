@@ -1,39 +1,144 @@
 use polestar_core::{
+  db::pool::runtime,
   error::{PolestarError, PolestarResult},
-  model::{AppInfo, BotId, FeedbackMessageListForServer, Quota},
+  model::{AppInfo, BotId, FeedbackMessageListForServer, Quota, RetryPolicy, ToolCall},
   service::{
-    open_ai::deal_open_ai_stream,
+    client::test_connection,
+    image::{generate_images, GeneratedImage},
+    provider::ChatAnswer,
     req::{create_text_request, fetch_feedback, req_feedback, request_quota},
+    tool::request_with_tools,
   },
+  NetworkCfg,
+};
+use std::{future::Future, time::Duration};
+use tokio::{
+  sync::{mpsc, oneshot},
+  task::JoinHandle,
 };
 
 use ribir::prelude::*;
 
+/// What the request running in the tokio runtime sends back to update the UI.
+enum Notify {
+  Retry(u32),
+  Delta(String),
+  Confirm(String, oneshot::Sender<bool>),
+  Tool(ToolCall, String),
+}
+
 pub async fn query_open_ai(
   info: impl StateReader<Value = AppInfo>,
   bot_id: BotId,
   content: String,
-  delta_op: impl FnMut(String),
-) -> Result<String, PolestarError> {
+  mut retry_op: impl FnMut(u32),
+  mut delta_op: impl FnMut(String),
+  mut confirm_op: impl FnMut(String) -> oneshot::Receiver<bool>,
+  mut tool_op: impl FnMut(ToolCall, String),
+) -> Result<ChatAnswer, PolestarError> {
+  // the bot calls only the tools it enables.
+  let tools = {
+    let info = info.read();
+    info
+      .bot(&bot_id)
+      .map(|bot| info.tools().for_bot(bot))
+      .unwrap_or_default()
+  };
   let req = create_text_request(&info.read(), bot_id);
 
   println!("request content: {}", content);
 
-  let mut stream = req.request(content).to_ribir_future().await?;
+  let (tx, mut rx) = mpsc::unbounded_channel();
+  let query = RequestTask::spawn(async move {
+    request_with_tools(
+      req,
+      content,
+      &tools,
+      |attempt| {
+        let _ = tx.send(Notify::Retry(attempt));
+      },
+      |delta| {
+        let _ = tx.send(Notify::Delta(delta));
+      },
+      |_, question| {
+        let (answer, answered) = oneshot::channel();
+        let _ = tx.send(Notify::Confirm(question.to_owned(), answer));
+        // a dropped confirmation is a refusal.
+        async move { answered.await.unwrap_or(false) }
+      },
+      |call, result| {
+        let _ = tx.send(Notify::Tool(call, result));
+      },
+    )
+    .await
+  });
+  // the channel is closed when the request is done.
+  while let Some(notify) = rx.recv().await {
+    match notify {
+      Notify::Retry(attempt) => retry_op(attempt),
+      Notify::Delta(delta) => delta_op(delta),
+      Notify::Confirm(question, answer) => {
+        let allowed = confirm_op(question).await.unwrap_or(false);
+        let _ = answer.send(allowed);
+      }
+      Notify::Tool(call, result) => tool_op(call, result),
+    }
+  }
+  query.join().await
+}
+
+/// Draw the images by the image bot, the request `content` is retried like
+/// `query_open_ai`.
+pub async fn query_images(
+  info: impl StateReader<Value = AppInfo>,
+  bot_id: BotId,
+  content: String,
+  mut retry_op: impl FnMut(u32),
+) -> Result<Vec<GeneratedImage>, PolestarError> {
+  let req = create_text_request(&info.read(), bot_id);
+  let (tx, mut rx) = mpsc::unbounded_channel();
+  let query = RequestTask::spawn(async move {
+    generate_images(req, content, |attempt| {
+      let _ = tx.send(attempt);
+    })
+    .await
+  });
+  while let Some(attempt) = rx.recv().await {
+    retry_op(attempt);
+  }
+  query.join().await
+}
+
+/// The request running in the tokio runtime, its notifies are sent back to
+/// update the UI in the ribir runtime. The request is stopped when the task
+/// is dropped, so canceling the query cancels it too.
+struct RequestTask<T>(JoinHandle<T>);
+
+impl<T: Send + 'static> RequestTask<T> {
+  fn spawn(fut: impl Future<Output = T> + Send + 'static) -> Self { Self(runtime().spawn(fut)) }
 
-  deal_open_ai_stream(&mut stream, delta_op).await
+  async fn join(mut self) -> T { (&mut self.0).await.expect("the request task panicked") }
 }
 
-pub async fn query_feedback(content: String) {
-  let _ = req_feedback(content).to_ribir_future().await;
+impl<T> Drop for RequestTask<T> {
+  fn drop(&mut self) { self.0.abort() }
+}
+
+pub async fn query_feedback(content: String, retry: RetryPolicy) {
+  let _ = req_feedback(content, retry).to_ribir_future().await;
 }
 
 pub async fn query_fetch_feedback(
   utc_time: Option<i64>,
+  retry: RetryPolicy,
 ) -> Result<FeedbackMessageListForServer, PolestarError> {
-  fetch_feedback(utc_time).to_ribir_future().await
+  fetch_feedback(utc_time, retry).to_ribir_future().await
+}
+
+pub async fn query_test_connection(network: NetworkCfg) -> PolestarResult<Duration> {
+  test_connection(network).to_ribir_future().await
 }
 
-pub async fn query_quota(token: Option<String>) -> PolestarResult<Quota> {
-  request_quota(token).to_ribir_future().await
+pub async fn query_quota(token: Option<String>, retry: RetryPolicy) -> PolestarResult<Quota> {
+  request_quota(token, retry).to_ribir_future().await
 }
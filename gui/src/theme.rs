@@ -43,6 +43,12 @@ pub mod polestar_svg {
     EDIT,
     SEND,
     TRASH,
+    STOP,
+    FORK,
+    THUMB_UP,
+    THUMB_UP_FILLED,
+    THUMB_DOWN,
+    THUMB_DOWN_FILLED,
     MATERIAL_THEME_END
   }
 }
@@ -114,7 +120,13 @@ pub fn polestar_theme() -> InheritTheme {
     polestar_svg::KEYBOARD_RETURN: "./theme/icons/keyboard_return_FILL0_wght400_GRAD0_opsz48.svg",
     polestar_svg::EDIT: "./theme/icons/pencil.svg",
     polestar_svg::SEND: "./theme/icons/send.svg",
-    polestar_svg::TRASH: "./theme/icons/trash.svg"
+    polestar_svg::TRASH: "./theme/icons/trash.svg",
+    polestar_svg::STOP: "./theme/icons/stop_circle_FILL0_wght400_GRAD0_opsz48.svg",
+    polestar_svg::FORK: "./theme/icons/fork.svg",
+    polestar_svg::THUMB_UP: "./theme/icons/thumb_up.svg",
+    polestar_svg::THUMB_UP_FILLED: "./theme/icons/thumb_up_filled.svg",
+    polestar_svg::THUMB_DOWN: "./theme/icons/thumb_down.svg",
+    polestar_svg::THUMB_DOWN_FILLED: "./theme/icons/thumb_down_filled.svg"
   }
 
   inherit_theme.icons = Some(icons);
@@ -1,15 +1,31 @@
-use polestar_core::model::{
-  init_app_data, AppData, AppInfo, Bot, BotId, Channel, ChannelCfg, ChannelId, Msg, MsgAction,
-  MsgCont, MsgId, User,
+use polestar_core::{
+  error::PolestarResult,
+  model::{
+    init_app_data, AppData, AppInfo, Attachment, Bot, BotId, Channel, ChannelCfg, ChannelId,
+    ChannelSummary, Image, Msg, MsgAction, MsgId, MsgRating, MsgSearchFilter, MsgSearchHit,
+    MsgUsage, RetryPolicy, UsageGroup, UsageStat, User,
+  },
+  project_export_path,
+  service::{
+    export::{ExportFormat, ExportOptions},
+    image::GeneratedImage,
+    req::CancelHandle,
+  },
+  NetworkCfg,
 };
 use ribir::prelude::*;
 use ribir_algo::Sc;
-use std::{collections::HashMap, rc::Rc};
+use std::{
+  collections::{HashMap, VecDeque},
+  path::PathBuf,
+  rc::Rc,
+};
+use tokio::sync::oneshot;
 use url::Url;
 use uuid::Uuid;
 
 use super::{
-  common::{PartialPath, Route, Router, Tooltip},
+  common::{IconButton, Modal, PartialPath, Route, Router, Tooltip},
   home::w_home,
   login::w_login,
   permission::w_permission,
@@ -18,22 +34,100 @@ use crate::{theme::polestar_theme, widgets::modify_channel::w_modify_channel_mod
 
 pub trait Chat: 'static {
   fn add_msg(&mut self, channel_id: &ChannelId, msg: Msg);
-  fn add_msg_cont(
+  fn switch_cont(&mut self, channel_id: &ChannelId, msg_id: &MsgId, idx: usize);
+
+  /// Add a fresh content to the bot's message to answer it again, return
+  /// its index.
+  fn regenerate(
     &mut self,
     channel_id: &ChannelId,
     msg_id: &MsgId,
-    cont: MsgCont,
+    bot_id: Option<BotId>,
   ) -> Option<usize>;
 
-  fn switch_cont(&mut self, channel_id: &ChannelId, msg_id: &MsgId, idx: usize);
+  /// Add the edited text as a new content of the user message, the replies
+  /// after it are kept in the branch of its previous content.
+  fn edit_msg(&mut self, channel_id: &ChannelId, msg_id: &MsgId, text: &str) -> Option<usize>;
 
   fn update_msg_cont(&mut self, channel_id: &ChannelId, msg_id: &MsgId, idx: usize, act: MsgAction);
 
+  /// Record the tokens and the time the answer in the content `idx` cost,
+  /// call it before the answer is finished to save them together.
+  fn set_msg_usage(&mut self, channel_id: &ChannelId, msg_id: &MsgId, idx: usize, usage: MsgUsage);
+
+  /// Rate the content `idx` of the bot's message, `None` to clear it.
+  fn rate_msg(
+    &mut self,
+    channel_id: &ChannelId,
+    msg_id: &MsgId,
+    idx: usize,
+    rating: Option<MsgRating>,
+  );
+
+  /// Pick the answer as the best one of the bots answered the question, only
+  /// it is carried into the context. `None` to clear the pick.
+  fn pick_best(&mut self, channel_id: &ChannelId, question_id: &MsgId, winner_id: Option<MsgId>);
+
+  /// Keep the tool call of the answer and its result, before the answer.
+  fn add_tool_msg(&mut self, channel_id: &ChannelId, answer_id: &MsgId, msg: Msg);
+
+  /// Put the drawn images into the content `idx` of the message and finish
+  /// it, the extra images are added as the alternatives.
+  fn add_images(
+    &mut self,
+    channel_id: &ChannelId,
+    msg_id: &MsgId,
+    idx: usize,
+    images: Vec<GeneratedImage>,
+  );
+
+  fn attachment(&self, name: &Uuid) -> Option<Attachment>;
+
+  /// Attach the image file or url to the user message.
+  fn attach_image(&mut self, src: &str) -> PolestarResult<Image>;
+
+  /// Attach the image data to the user message, i.e. the pasted image.
+  fn attach_image_data(&mut self, data: Vec<u8>) -> PolestarResult<Image>;
+
+  /// Save the image to the export folder, return the path of the file.
+  fn save_image(&self, img: &Image) -> PolestarResult<PathBuf>;
+
+  /// Move the message to the trash, with the question and all its answers if
+  /// `group`. It can be undone by `ChannelMgr::undo_remove`.
+  fn remove_msg(&mut self, channel_id: &ChannelId, msg_id: &MsgId, group: bool);
+
+  /// Fork the channel from the message to a new channel and switch to it.
+  fn fork_channel(&mut self, channel_id: &ChannelId, msg_id: &MsgId) -> Option<ChannelId>;
+
   fn channel(&self, channel_id: &ChannelId) -> Option<&Channel>;
 
   fn msg(&self, channel_id: &ChannelId, msg_id: &MsgId) -> Option<&Msg>;
 
+  /// Replace the summary of the channel's earlier messages, `None` to reset
+  /// it.
+  fn update_summary(&mut self, channel_id: &ChannelId, summary: Option<ChannelSummary>);
+
   fn info(&self) -> &AppInfo;
+
+  /// Keep the handle of the response stream of the message, so it can be
+  /// stopped by `stop_stream`.
+  fn add_stream(&mut self, msg_id: MsgId, handle: CancelHandle);
+
+  fn remove_stream(&mut self, msg_id: &MsgId);
+
+  fn stop_stream(&mut self, msg_id: &MsgId);
+
+  fn is_streaming(&self, msg_id: &MsgId) -> bool;
+
+  /// Ask the user the question before running the tool call, the receiver
+  /// gets whether it's allowed.
+  fn confirm_tool(&mut self, question: String) -> oneshot::Receiver<bool>;
+
+  /// The question of the tool call waiting for the user's answer.
+  fn tool_question(&self) -> Option<&str>;
+
+  /// Answer the tool call the `tool_question` asks for.
+  fn answer_tool(&mut self, allowed: bool);
 }
 
 pub trait ChannelMgr: 'static {
@@ -45,7 +139,23 @@ pub trait ChannelMgr: 'static {
   fn update_channel_desc(&mut self, channel_id: &ChannelId, desc: Option<String>);
   fn update_channel_name(&mut self, channel_id: &ChannelId, name: String);
   fn update_channel_cfg(&mut self, channel_id: &ChannelId, cfg: ChannelCfg);
+  fn reset_channel_summary(&mut self, channel_id: &ChannelId);
+  /// Search the messages of all channels, the best matched first.
+  fn search_msgs(&self, query: &str, filter: &MsgSearchFilter) -> Vec<MsgSearchHit>;
+  /// The tokens and the time the answers cost, grouped by `group`.
+  fn usage_stats(&self, group: UsageGroup) -> Vec<UsageStat>;
+  /// Export the channel to the export folder, return the exported file path.
+  fn export_channel(&self, channel_id: &ChannelId, format: ExportFormat)
+    -> PolestarResult<PathBuf>;
+  /// Import the channels of a JSON export, return the new channel ids.
+  fn import_channels(&mut self, content: &str) -> PolestarResult<Vec<ChannelId>>;
+  /// Move the channel to the trash, it can be undone by `undo_remove`.
   fn remove_channel(&mut self, channel_id: &ChannelId);
+  /// What the last deletion moved to the trash, `None` if nothing to undo.
+  fn removed_hint(&self) -> Option<String>;
+  /// Take the channel or the messages of the last deletion out of the trash.
+  fn undo_remove(&mut self);
+  fn dismiss_undo(&mut self);
 }
 
 pub trait UIState: 'static {
@@ -64,6 +174,9 @@ pub trait UserConfig: 'static {
   fn need_login(&self) -> bool;
   fn bots(&self) -> Rc<Vec<Bot>>;
   fn default_bot_id(&self) -> BotId;
+  fn retry(&self) -> &RetryPolicy;
+  fn network(&self) -> &NetworkCfg;
+  fn set_network(&mut self, network: NetworkCfg) -> PolestarResult<()>;
 }
 
 pub struct AppGUI {
@@ -71,6 +184,19 @@ pub struct AppGUI {
   cur_router_path: String,
   modify_channel_id: Option<Uuid>,
   tooltip: Option<String>,
+  streams: HashMap<MsgId, CancelHandle>,
+  removed: Option<Removed>,
+  // the tool calls waiting for the user's confirmation, the first is asked.
+  tool_confirms: VecDeque<(String, oneshot::Sender<bool>)>,
+}
+
+/// The last deletion, kept to undo it until the next deletion.
+enum Removed {
+  Channel(Channel),
+  Msgs {
+    channel_id: ChannelId,
+    msgs: Vec<Msg>,
+  },
 }
 
 impl AppGUI {
@@ -87,6 +213,9 @@ impl AppGUI {
       cur_router_path,
       modify_channel_id: None,
       tooltip: None,
+      streams: HashMap::new(),
+      removed: None,
+      tool_confirms: VecDeque::new(),
     }
   }
 
@@ -124,7 +253,37 @@ impl ChannelMgr for AppGUI {
     self.data.new_channel(name, desc, cfg)
   }
 
-  fn remove_channel(&mut self, channel_id: &ChannelId) { self.data.remove_channel(channel_id); }
+  fn remove_channel(&mut self, channel_id: &ChannelId) {
+    if let Some(channel) = self.data.remove_channel(channel_id) {
+      self.removed = Some(Removed::Channel(channel));
+    }
+  }
+
+  fn removed_hint(&self) -> Option<String> {
+    match self.removed.as_ref()? {
+      Removed::Channel(channel) => Some(format!("Channel \"{}\" deleted", channel.name())),
+      Removed::Msgs { msgs, .. } if msgs.len() == 1 => Some("Message deleted".to_owned()),
+      Removed::Msgs { msgs, .. } => Some(format!("{} messages deleted", msgs.len())),
+    }
+  }
+
+  fn undo_remove(&mut self) {
+    match self.removed.take() {
+      Some(Removed::Channel(channel)) => {
+        let channel_id = *channel.id();
+        self.data.restore_channel(channel);
+        self.data.switch_channel(&channel_id);
+      }
+      Some(Removed::Msgs { channel_id, msgs }) => {
+        if let Some(ch) = self.data.get_channel_mut(&channel_id) {
+          ch.restore_msgs(msgs);
+        }
+      }
+      None => {}
+    }
+  }
+
+  fn dismiss_undo(&mut self) { self.removed = None; }
 
   fn switch_channel(&mut self, channel_id: &ChannelId) { self.data.switch_channel(channel_id); }
 
@@ -145,6 +304,61 @@ impl ChannelMgr for AppGUI {
       channel.set_cfg(cfg);
     }
   }
+
+  fn reset_channel_summary(&mut self, channel_id: &ChannelId) {
+    if let Some(channel) = self.data.get_channel_mut(channel_id) {
+      channel.set_summary(None);
+    }
+  }
+
+  fn search_msgs(&self, query: &str, filter: &MsgSearchFilter) -> Vec<MsgSearchHit> {
+    self.data.search_msgs(query, filter).unwrap_or_else(|e| {
+      log::warn!("search msgs failed: {}", e);
+      vec![]
+    })
+  }
+
+  fn usage_stats(&self, group: UsageGroup) -> Vec<UsageStat> {
+    self.data.usage_stats(group, None).unwrap_or_else(|e| {
+      log::warn!("query usage stats failed: {}", e);
+      vec![]
+    })
+  }
+
+  fn export_channel(
+    &self,
+    channel_id: &ChannelId,
+    format: ExportFormat,
+  ) -> PolestarResult<PathBuf> {
+    let name = self
+      .data
+      .get_channel(channel_id)
+      .map_or("channel", |channel| channel.name())
+      .chars()
+      .map(|c| {
+        if c.is_alphanumeric() || c == '-' {
+          c
+        } else {
+          '_'
+        }
+      })
+      .collect::<String>();
+    let dir = project_export_path();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.{}", name, format.ext()));
+
+    let opts = ExportOptions {
+      alternates: true,
+      ..ExportOptions::new(format)
+    };
+    let export = self.data.export_channels(&[*channel_id], &opts)?;
+    export.write(&path)?;
+    Ok(path)
+  }
+
+  fn import_channels(&mut self, content: &str) -> PolestarResult<Vec<ChannelId>> {
+    self.data.import_channels(content)
+  }
 }
 
 impl Chat for AppGUI {
@@ -154,27 +368,29 @@ impl Chat for AppGUI {
     }
   }
 
-  fn add_msg_cont(
+  fn switch_cont(&mut self, channel_id: &ChannelId, msg_id: &MsgId, idx: usize) {
+    if let Some(ch) = self.data.get_channel_mut(channel_id) {
+      ch.switch_cont(msg_id, idx);
+    }
+  }
+
+  fn regenerate(
     &mut self,
     channel_id: &ChannelId,
     msg_id: &MsgId,
-    cont: MsgCont,
+    bot_id: Option<BotId>,
   ) -> Option<usize> {
     self
       .data
       .get_channel_mut(channel_id)
-      .and_then(|ch| ch.msg_mut(msg_id))
-      .map(|msg| msg.add_cont(cont))
+      .and_then(|ch| ch.regenerate(msg_id, bot_id))
   }
 
-  fn switch_cont(&mut self, channel_id: &ChannelId, msg_id: &MsgId, idx: usize) {
-    if let Some(msg) = self
+  fn edit_msg(&mut self, channel_id: &ChannelId, msg_id: &MsgId, text: &str) -> Option<usize> {
+    self
       .data
       .get_channel_mut(channel_id)
-      .and_then(|ch| ch.msg_mut(msg_id))
-    {
-      msg.switch_cont(idx);
-    }
+      .and_then(|ch| ch.edit_msg(msg_id, text))
   }
 
   fn update_msg_cont(
@@ -189,6 +405,82 @@ impl Chat for AppGUI {
     }
   }
 
+  fn set_msg_usage(&mut self, channel_id: &ChannelId, msg_id: &MsgId, idx: usize, usage: MsgUsage) {
+    self.data.record_usage(channel_id, msg_id, idx, usage);
+  }
+
+  fn rate_msg(
+    &mut self,
+    channel_id: &ChannelId,
+    msg_id: &MsgId,
+    idx: usize,
+    rating: Option<MsgRating>,
+  ) {
+    if let Some(ch) = self.data.get_channel_mut(channel_id) {
+      ch.rate(msg_id, idx, rating);
+    }
+  }
+
+  fn pick_best(&mut self, channel_id: &ChannelId, question_id: &MsgId, winner_id: Option<MsgId>) {
+    if let Some(ch) = self.data.get_channel_mut(channel_id) {
+      ch.pick_best(question_id, winner_id);
+    }
+  }
+
+  fn add_tool_msg(&mut self, channel_id: &ChannelId, answer_id: &MsgId, msg: Msg) {
+    if let Some(ch) = self.data.get_channel_mut(channel_id) {
+      ch.add_tool_msg(answer_id, msg);
+    }
+  }
+
+  fn add_images(
+    &mut self,
+    channel_id: &ChannelId,
+    msg_id: &MsgId,
+    idx: usize,
+    images: Vec<GeneratedImage>,
+  ) {
+    self.data.add_images(channel_id, msg_id, idx, images);
+  }
+
+  fn attachment(&self, name: &Uuid) -> Option<Attachment> {
+    self
+      .data
+      .attachment(name)
+      .map_err(|e| log::warn!("load attachment {} failed: {}", name, e))
+      .ok()
+  }
+
+  fn attach_image(&mut self, src: &str) -> PolestarResult<Image> { self.data.attach_image(src) }
+
+  fn attach_image_data(&mut self, data: Vec<u8>) -> PolestarResult<Image> {
+    self.data.attach_image_data(data)
+  }
+
+  fn save_image(&self, img: &Image) -> PolestarResult<PathBuf> {
+    let dir = project_export_path();
+    std::fs::create_dir_all(&dir)?;
+    self.data.save_image(img, &dir)
+  }
+
+  fn remove_msg(&mut self, channel_id: &ChannelId, msg_id: &MsgId, group: bool) {
+    let Some(ch) = self.data.get_channel_mut(channel_id) else {
+      return;
+    };
+    let msgs = if group {
+      ch.remove_msg_group(msg_id)
+    } else {
+      ch.remove_msg(msg_id)
+    };
+    if !msgs.is_empty() {
+      self.removed = Some(Removed::Msgs { channel_id: *channel_id, msgs });
+    }
+  }
+
+  fn fork_channel(&mut self, channel_id: &ChannelId, msg_id: &MsgId) -> Option<ChannelId> {
+    self.data.fork_channel(channel_id, msg_id)
+  }
+
   fn channel(&self, channel_id: &ChannelId) -> Option<&Channel> {
     self.data.get_channel(channel_id)
   }
@@ -201,6 +493,47 @@ impl Chat for AppGUI {
       .get_channel(channel_id)
       .and_then(|ch| ch.msg(msg_id))
   }
+
+  fn update_summary(&mut self, channel_id: &ChannelId, summary: Option<ChannelSummary>) {
+    if let Some(ch) = self.data.get_channel_mut(channel_id) {
+      ch.set_summary(summary);
+    }
+  }
+
+  fn add_stream(&mut self, msg_id: MsgId, handle: CancelHandle) {
+    if let Some(old) = self.streams.insert(msg_id, handle) {
+      old.cancel();
+    }
+  }
+
+  fn remove_stream(&mut self, msg_id: &MsgId) { self.streams.remove(msg_id); }
+
+  fn stop_stream(&mut self, msg_id: &MsgId) {
+    if let Some(handle) = self.streams.remove(msg_id) {
+      handle.cancel();
+    }
+  }
+
+  fn is_streaming(&self, msg_id: &MsgId) -> bool { self.streams.contains_key(msg_id) }
+
+  fn confirm_tool(&mut self, question: String) -> oneshot::Receiver<bool> {
+    let (tx, rx) = oneshot::channel();
+    self.tool_confirms.push_back((question, tx));
+    rx
+  }
+
+  fn tool_question(&self) -> Option<&str> {
+    self
+      .tool_confirms
+      .front()
+      .map(|(question, _)| question.as_str())
+  }
+
+  fn answer_tool(&mut self, allowed: bool) {
+    if let Some((_, tx)) = self.tool_confirms.pop_front() {
+      let _ = tx.send(allowed);
+    }
+  }
 }
 
 impl UIState for AppGUI {
@@ -234,6 +567,14 @@ impl UserConfig for AppGUI {
   fn bots(&self) -> Rc<Vec<Bot>> { self.data.info().bots_rc() }
 
   fn default_bot_id(&self) -> BotId { self.data.info().cfg().def_bot_id().clone() }
+
+  fn retry(&self) -> &RetryPolicy { self.data.info().cfg().retry() }
+
+  fn network(&self) -> &NetworkCfg { self.data.info().cfg().network() }
+
+  fn set_network(&mut self, network: NetworkCfg) -> PolestarResult<()> {
+    self.data.info_mut().set_network_cfg(network)
+  }
 }
 
 impl Compose for AppGUI {
@@ -281,6 +622,20 @@ impl Compose for AppGUI {
                   w_tooltip($this.tooltip())
                 }
               }
+              @ {
+                pipe!($chat.tool_question().map(str::to_owned))
+                  .map(move |question| {
+                    let _ = || $chat.write();
+                    question.map(|question| w_tool_confirm(chat.clone_writer(), question))
+                  })
+              }
+              @ {
+                pipe!($channel_mgr.removed_hint())
+                  .map(move |hint| {
+                    let _ = || $channel_mgr.write();
+                    hint.map(|hint| w_undo_bar(channel_mgr.clone_writer(), hint))
+                  })
+              }
               @ {
                 pipe!($ui_state;)
                   .map(move |_| {
@@ -393,6 +748,55 @@ fn w_tooltip(content: Option<String>) -> Option<impl WidgetBuilder> {
   })
 }
 
+/// The hint of the last deletion with the button to undo it.
+fn w_undo_bar(
+  channel_mgr: impl StateWriter<Value = dyn ChannelMgr>,
+  hint: String,
+) -> impl WidgetBuilder {
+  fn_widget! {
+    @Row {
+      h_align: HAlign::Center,
+      v_align: VAlign::Bottom,
+      margin: EdgeInsets::only_bottom(24.),
+      padding: EdgeInsets::new(4., 8., 4., 16.),
+      background: Palette::of(ctx!()).surface_variant(),
+      border_radius: Radius::all(8.),
+      align_items: Align::Center,
+      item_gap: 8.,
+      @Text { text: hint }
+      @Button {
+        cursor: CursorIcon::Pointer,
+        on_tap: move |_| $channel_mgr.write().undo_remove(),
+        @ { Label::new("Undo") }
+      }
+      @IconButton {
+        size: IconSize::of(ctx!()).small,
+        on_tap: move |_| $channel_mgr.write().dismiss_undo(),
+        @ { svgs::CLOSE }
+      }
+    }
+  }
+}
+
+fn w_tool_confirm(
+  chat: impl StateWriter<Value = dyn Chat>,
+  question: String,
+) -> impl WidgetBuilder {
+  fn_widget! {
+    @Modal {
+      title: "Run the tool?",
+      size: Size::new(480., 240.),
+      confirm_cb: Box::new(move || $chat.write().answer_tool(true)) as Box<dyn Fn()>,
+      cancel_cb: Box::new(move || $chat.write().answer_tool(false)) as Box<dyn Fn()>,
+      @Text {
+        margin: EdgeInsets::vertical(20.),
+        text: question,
+        overflow: Overflow::AutoWrap,
+      }
+    }
+  }
+}
+
 // launch App need to do some init work.
 // 1. [x] load bot config file.
 // 2. [ ] if has user module, need check user login status.
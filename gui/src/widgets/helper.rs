@@ -1,11 +1,16 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, rc::Rc, time::Instant};
 
-use crate::req::query_open_ai;
+use crate::req::{query_images, query_open_ai};
 use polestar_core::{
-  model::{BotId, ChannelId, MsgAction, MsgBody},
-  service::req::open_ai_request_content,
+  error::PolestarError,
+  model::{BotId, ChannelId, ChannelSummary, Msg, MsgAction, MsgBody, MsgId, MsgMeta},
+  service::{
+    pipeline::PipelineRun,
+    req::{cancelable, chat_request_content, regenerate_request_content, summary_request_content},
+  },
 };
 use ribir::prelude::*;
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
 use super::app::Chat;
@@ -20,11 +25,6 @@ pub fn send_msg(
   quote_id: Option<Uuid>,
 ) {
   let _ = AppCtx::spawn_local(async move {
-    let update_msg = |act| {
-      let mut chat = chat.write();
-      chat.update_msg_cont(&channel_id, &msg_id, idx, act);
-    };
-
     let quote_text = {
       quote_id.and_then(|quote_id| {
         chat.read().channel(&channel_id).and_then(|channel| {
@@ -39,31 +39,265 @@ pub fn send_msg(
       .map(|quote_text| format!("{} {}", quote_text, content))
       .unwrap_or(content);
 
-    let text = chat
-      .read()
-      .channel(&channel_id)
-      .map(|channel| {
-        let bot = channel
-          .bots()
-          .and_then(|bots| bots.iter().find(|bot| bot.id() == &bot_id))
-          .unwrap();
-        open_ai_request_content(bot, channel, &content)
+    let text = {
+      let chat = chat.read();
+      chat
+        .channel(&channel_id)
+        .map(|channel| {
+          let bot = channel
+            .bots()
+            .and_then(|bots| bots.iter().find(|bot| bot.id() == &bot_id))
+            .unwrap();
+          chat_request_content(chat.info(), bot, channel, &content)
+        })
+        .unwrap_or(content)
+    };
+
+    stream_answer(chat, channel_id, msg_id, idx, bot_id, text).await;
+  });
+}
+
+/// Send the question to the pipeline bot, its steps answer one by one in the
+/// channel until one of them is not answered.
+pub fn run_pipeline(
+  chat: impl StateWriter<Value = dyn Chat>,
+  channel_id: ChannelId,
+  bot_id: BotId,
+  question_id: MsgId,
+  content: String,
+) {
+  let _ = AppCtx::spawn_local(async move {
+    let run = {
+      let chat = chat.read();
+      let run = chat
+        .info()
+        .bot(&bot_id)
+        .and_then(|bot| PipelineRun::new(bot, question_id, content));
+      run.map(|run| run.check(chat.info()).map(|_| run))
+    };
+    let mut run = match run {
+      Some(Ok(run)) => run,
+      Some(Err(e)) => {
+        let msg = Msg::new_bot_text(bot_id, MsgMeta::reply(question_id));
+        let msg_id = *msg.id();
+        let mut chat = chat.write();
+        chat.add_msg(&channel_id, msg);
+        let text = MsgBody::Text(Some(format!("Error: {}", e)));
+        chat.update_msg_cont(&channel_id, &msg_id, 0, MsgAction::Receiving(text));
+        chat.update_msg_cont(&channel_id, &msg_id, 0, MsgAction::Fulfilled);
+        return;
+      }
+      None => return,
+    };
+
+    loop {
+      let msg = {
+        let chat = chat.read();
+        chat
+          .channel(&channel_id)
+          .and_then(|channel| run.next_msg(channel))
+      };
+      let Some(msg) = msg else { break };
+      let (msg_id, idx) = (*msg.id(), msg.cur_idx());
+      let step_bot = run.cur_bot().cloned().unwrap();
+      chat.write().add_msg(&channel_id, msg);
+      let text = {
+        let chat = chat.read();
+        chat
+          .channel(&channel_id)
+          .and_then(|channel| run.request_content(chat.info(), channel))
+      };
+      let Some(text) = text else { break };
+      let answered =
+        stream_answer(chat.clone_writer(), channel_id, msg_id, idx, step_bot, text).await;
+      if !answered {
+        break;
+      }
+    }
+  });
+}
+
+/// Answer the question of the bot's message again as a new alternative, with
+/// the context of the original request. `bot_id` is the bot to answer it, the
+/// message's bot if `None`.
+pub fn regenerate_msg(
+  chat: impl StateWriter<Value = dyn Chat>,
+  channel_id: ChannelId,
+  msg_id: Uuid,
+  bot_id: Option<BotId>,
+) {
+  let _ = AppCtx::spawn_local(async move {
+    let req = {
+      let chat = chat.read();
+      chat.channel(&channel_id).and_then(|channel| {
+        let bot_id = bot_id.or_else(|| channel.msg(&msg_id)?.role().bot().cloned())?;
+        let bot = chat.info().bot(&bot_id)?;
+        let text = regenerate_request_content(chat.info(), bot, channel, &msg_id, None)?;
+        Some((bot_id, text))
       })
-      .unwrap_or(content);
+    };
+    let Some((bot_id, text)) = req else { return };
+    let idx = chat
+      .write()
+      .regenerate(&channel_id, &msg_id, Some(bot_id.clone()));
+    if let Some(idx) = idx {
+      stream_answer(chat, channel_id, msg_id, idx, bot_id, text).await;
+    }
+  });
+}
+
+/// Stream the answer of the request `text` into the content `idx` of the
+/// message. Return whether the answer is received without error.
+async fn stream_answer(
+  chat: impl StateWriter<Value = dyn Chat>,
+  channel_id: ChannelId,
+  msg_id: Uuid,
+  idx: usize,
+  bot_id: BotId,
+  text: String,
+) -> bool {
+  let is_image_gen = chat
+    .read()
+    .info()
+    .bot(&bot_id)
+    .is_some_and(|bot| bot.is_image_gen());
+  if is_image_gen {
+    return generate_image(chat, channel_id, msg_id, idx, bot_id, text).await;
+  }
+  let update_msg = |act| {
+    let mut chat = chat.write();
+    chat.update_msg_cont(&channel_id, &msg_id, idx, act);
+  };
 
-    let res = query_open_ai(chat.map_reader(|chat| chat.info()), bot_id, text, |delta| {
+  let started = Instant::now();
+  let (query, handle) = cancelable(query_open_ai(
+    chat.map_reader(|chat| chat.info()),
+    bot_id.clone(),
+    text.clone(),
+    |attempt| update_msg(MsgAction::Retrying(attempt)),
+    |delta| {
       update_msg(MsgAction::Receiving(MsgBody::Text(Some(delta))));
-    })
-    .await;
+    },
+    |question| chat.write().confirm_tool(question),
+    |call, result| {
+      let question_id = chat
+        .read()
+        .msg(&channel_id, &msg_id)
+        .and_then(|msg| msg.meta().source_id().copied());
+      let meta = question_id.map_or_else(MsgMeta::default, MsgMeta::reply);
+      let msg = Msg::new_tool(call, &result, meta);
+      chat.write().add_tool_msg(&channel_id, &msg_id, msg);
+    },
+  ));
+  chat.write().add_stream(msg_id, handle);
+  let res = query.await;
+  chat.write().remove_stream(&msg_id);
 
-    if let Err(e) = res {
+  match res {
+    Err(PolestarError::Canceled) => {
+      update_msg(MsgAction::Interrupted);
+      false
+    }
+    Err(e) => {
       update_msg(MsgAction::Receiving(MsgBody::Text(Some(format!(
         "Error: {}",
         e
       )))));
+      update_msg(MsgAction::Fulfilled);
+      false
     }
-    update_msg(MsgAction::Fulfilled);
-  });
+    Ok(answer) => {
+      let usage = {
+        let chat = chat.read();
+        let bot = chat.info().bot(&bot_id);
+        bot.map(|bot| answer.msg_usage(bot, &text, started))
+      };
+      if let Some(usage) = usage {
+        chat.write().set_msg_usage(&channel_id, &msg_id, idx, usage);
+      }
+      update_msg(MsgAction::Fulfilled);
+      update_summary(chat, channel_id, bot_id).await;
+      true
+    }
+  }
+}
+
+/// Draw the images of the image bot into the content `idx` of the message.
+/// Return whether the images are received without error.
+async fn generate_image(
+  chat: impl StateWriter<Value = dyn Chat>,
+  channel_id: ChannelId,
+  msg_id: Uuid,
+  idx: usize,
+  bot_id: BotId,
+  text: String,
+) -> bool {
+  let update_msg = |act| {
+    let mut chat = chat.write();
+    chat.update_msg_cont(&channel_id, &msg_id, idx, act);
+  };
+
+  let (query, handle) = cancelable(query_images(
+    chat.map_reader(|chat| chat.info()),
+    bot_id,
+    text,
+    |attempt| update_msg(MsgAction::Retrying(attempt)),
+  ));
+  chat.write().add_stream(msg_id, handle);
+  let res = query.await;
+  chat.write().remove_stream(&msg_id);
+
+  match res {
+    Ok(images) => {
+      chat.write().add_images(&channel_id, &msg_id, idx, images);
+      true
+    }
+    Err(PolestarError::Canceled) => {
+      update_msg(MsgAction::Interrupted);
+      false
+    }
+    Err(e) => {
+      update_msg(MsgAction::Receiving(MsgBody::Text(Some(format!(
+        "Error: {}",
+        e
+      )))));
+      update_msg(MsgAction::Fulfilled);
+      false
+    }
+  }
+}
+
+/// Summarise the channel's messages out of the context, if its memory is on.
+async fn update_summary(
+  chat: impl StateWriter<Value = dyn Chat>,
+  channel_id: ChannelId,
+  bot_id: BotId,
+) {
+  let req = {
+    let chat = chat.read();
+    chat.channel(&channel_id).and_then(|channel| {
+      let bot = chat.info().bot(&bot_id)?;
+      summary_request_content(chat.info(), bot, channel)
+    })
+  };
+  let Some((body, until)) = req else { return };
+  let info = chat.map_reader(|chat| chat.info());
+  match query_open_ai(
+    info,
+    bot_id,
+    body,
+    |_| {},
+    |_| {},
+    |_| oneshot::channel().1,
+    |_, _| {},
+  )
+  .await
+  {
+    Ok(summary) => chat
+      .write()
+      .update_summary(&channel_id, Some(ChannelSummary::new(summary.text, until))),
+    Err(e) => log::warn!("summarise channel {} failed: {}", channel_id, e),
+  }
 }
 
 struct LocalChannel<T>(Rc<RefCell<Vec<T>>>);
@@ -63,9 +63,14 @@ pub fn w_home(
                     pipe! {
                       let _ = || {
                         $config.write();
+                        $channel_mgr.write();
                         $ui_state.write();
                       };
-                      w_settings(config.clone_writer(), ui_state.clone_writer())
+                      w_settings(
+                        config.clone_writer(),
+                        channel_mgr.clone_writer(),
+                        ui_state.clone_writer()
+                      )
                     }
                   }
                 }
@@ -1,7 +1,7 @@
 use std::rc::Rc;
 
 use polestar_core::model::{
-  Bot, BotId, ChannelId, FeedbackUserIdForServer, Msg, MsgCont, MsgMeta, MsgRole,
+  Bot, BotId, ChannelId, FeedbackUserIdForServer, Msg, MsgCont, MsgMeta, MsgRole, RetryPolicy,
 };
 use ribir::prelude::*;
 
@@ -41,7 +41,7 @@ pub fn w_chat(
           .msgs()
           .last()
           .map(|msg| msg.create_at().timestamp_millis());
-      fetch_feedbacks(sx, time_stamp);
+      fetch_feedbacks(sx, time_stamp, $chat.info().cfg().retry().clone());
       guard = Some(watch!($rx.take_all()).subscribe(move |feedbacks|{
         feedbacks.into_iter().for_each(|msg| {
           let t = msg.create_at().timestamp_millis();
@@ -68,9 +68,10 @@ pub fn w_chat(
 fn fetch_feedbacks(
   sender: impl StateWriter<Value = impl StateSink<Item = Msg>>,
   utc_time: Option<i64>,
+  retry: RetryPolicy,
 ) {
   let _ = AppCtx::spawn_local(async move {
-    let data = query_fetch_feedback(utc_time).await;
+    let data = query_fetch_feedback(utc_time, retry).await;
 
     if let Ok(data) = data {
       data.data.iter().rev().for_each(|m| {
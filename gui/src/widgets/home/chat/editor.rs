@@ -1,9 +1,16 @@
 use crate::{
   req::query_feedback,
-  style::WHITE,
-  widgets::{app::Chat, common::BotList, helper::send_msg},
+  style::{SPANISH_GRAY, WHITE},
+  widgets::{
+    app::Chat,
+    common::BotList,
+    helper::{run_pipeline, send_msg},
+  },
+};
+use polestar_core::{
+  model::{Bot, BotId, ChannelId, Msg, MsgMeta, RetryPolicy},
+  service::req::draft_tokens,
 };
-use polestar_core::model::{Bot, BotId, ChannelId, Msg, MsgMeta};
 use ribir::{core::ticker::FrameMsg, prelude::*};
 use std::ops::Range;
 use std::rc::Rc;
@@ -26,6 +33,7 @@ pub fn w_editor(
     let send_msg_by_icon_quote_id = quote_id.clone_writer();
     let is_feedback = $chat.channel(&channel_id).unwrap().is_feedback();
     let def_bot_id_2 = def_bot_id.clone();
+    let def_bot_id_3 = def_bot_id.clone();
 
     let send_icon = @IconButton {
       on_tap: move |_| {
@@ -46,6 +54,22 @@ pub fn w_editor(
       @ { polestar_svg::SEND }
     };
 
+    let pick_icon = @IconButton {
+      visible: !is_feedback,
+      on_tap: move |_| {
+        let files = rfd::FileDialog::new()
+          .add_filter("Image", &["png", "jpg", "jpeg", "webp"])
+          .pick_files()
+          .unwrap_or_default();
+        $text_area.write().images.extend(
+          files
+            .into_iter()
+            .map(|path| EditorImage::Src(path.to_string_lossy().into_owned())),
+        );
+      },
+      @ { svgs::ADD }
+    };
+
     if !is_feedback {
       watch!($text_area.bot_hint())
         .distinct_until_changed()
@@ -143,6 +167,24 @@ pub fn w_editor(
                 })
               }
             }
+            @ {
+              pipe! {
+                $text_area.images()
+                  .iter()
+                  .enumerate()
+                  .map(|(idx, img)| {
+                    @Row {
+                      background: Color::from_u32(WHITE),
+                      @Icon {
+                        on_tap: move |_| $text_area.write().remove_image(idx),
+                        @ { svgs::CLOSE }
+                      }
+                      @Text { text: format!("Image: {}", img.label()) }
+                    }
+                  })
+                  .collect::<Vec<_>>()
+              }
+            }
             @Row {
               padding: EdgeInsets::all(10.),
               background: Color::from_u32(CULTURED_F4F4F4_FF),
@@ -153,8 +195,46 @@ pub fn w_editor(
                   @ { Placeholder::new("Type a message") }
                 }
               }
+              @ { pick_icon }
               @ { send_icon }
             }
+            @ {
+              pipe! {
+                let _ = || $quote_id.write();
+                let content = $text_area.edit_message.message_content();
+                let bot_id = $text_area.edit_message.related_bot().into_iter().next();
+                (!is_feedback && !content.is_empty())
+                  .then(|| {
+                    let chat = $chat;
+                    let bot_id = bot_id.as_ref().or_else(|| {
+                      chat.channel(&channel_id).and_then(|c| c.cfg().def_bot_id())
+                    });
+                    let bot_id = bot_id.unwrap_or(&def_bot_id_3);
+                    let tokens =
+                      draft_tokens_hint(&*chat, &channel_id, bot_id, *$quote_id, &content)?;
+                    // warn the provider's spending is near or over its budget.
+                    let budget = chat.info().budget(bot_id).and_then(|budget| budget.message());
+                    let no_vision = !$text_area.images().is_empty()
+                      && !chat.info().bot(bot_id).is_some_and(|bot| bot.accepts_images());
+                    let budget = match (budget, no_vision) {
+                      (budget, false) => budget,
+                      (Some(budget), true) => Some(format!("{}, the images are not sent", budget)),
+                      (None, true) => {
+                        Some("the bot doesn't accept images, they are not sent".to_owned())
+                      }
+                    };
+                    Some((tokens, budget))
+                  })
+                  .flatten()
+                  .map(|(tokens, budget)| @Text {
+                    text: match budget {
+                      Some(budget) => format!("~{} tokens, {}", tokens, budget),
+                      None => format!("~{} tokens", tokens),
+                    },
+                    foreground: Color::from_u32(SPANISH_GRAY),
+                  })
+              }
+            }
           }
         }
       }
@@ -176,12 +256,13 @@ fn send_feedback(
   let user_msg = Msg::new_user_text(&text, MsgMeta::default());
   chat.write().add_msg(&channel_id, user_msg);
 
-  submit_feedback(text);
+  let retry = chat.read().info().cfg().retry().clone();
+  submit_feedback(text, retry);
 }
 
-fn submit_feedback(content: String) {
+fn submit_feedback(content: String, retry: RetryPolicy) {
   let _ = AppCtx::spawn_local(async move {
-    query_feedback(content).await;
+    query_feedback(content, retry).await;
   });
 }
 
@@ -200,7 +281,18 @@ fn send_question(
 
   let msg_quote_id = *quote_id.read();
   *quote_id.write() = None;
-  let user_msg = Msg::new_user_text(&text, MsgMeta::new(msg_quote_id, None));
+  let mut images = vec![];
+  for img in text_area.images() {
+    let attached = match img {
+      EditorImage::Src(src) => chat.write().attach_image(src),
+      EditorImage::Data(data) => chat.write().attach_image_data(data.clone()),
+    };
+    match attached {
+      Ok(image) => images.push(image),
+      Err(e) => log::warn!("attach image {} failed: {}", img.label(), e),
+    }
+  }
+  let user_msg = Msg::new_user_images(&text, images, MsgMeta::new(msg_quote_id, None));
   let user_msg_id = *user_msg.id();
   chat.write().add_msg(&channel_id, user_msg);
 
@@ -215,6 +307,31 @@ fn send_question(
     bots.push(def_bot);
   }
   for bot_id in bots.into_iter() {
+    let is_pipeline = chat
+      .read()
+      .info()
+      .bot(&bot_id)
+      .is_some_and(|bot| bot.pipeline().is_some());
+    if is_pipeline {
+      let content = text_area.edit_message.message_content();
+      let quote_text = msg_quote_id.and_then(|id| {
+        chat
+          .read()
+          .msg(&channel_id, &id)
+          .and_then(|msg| msg.cur_cont_ref().text().map(str::to_owned))
+      });
+      let content = quote_text
+        .map(|quote_text| format!("{} {}", quote_text, content))
+        .unwrap_or(content);
+      run_pipeline(
+        chat.clone_writer(),
+        channel_id,
+        bot_id,
+        user_msg_id,
+        content,
+      );
+      continue;
+    }
     let bot_msg = Msg::new_bot_text(bot_id.clone(), MsgMeta::new(None, Some(user_msg_id)));
     let id = *bot_msg.id();
     let idx = bot_msg.cur_idx();
@@ -232,6 +349,26 @@ fn send_question(
   }
 }
 
+/// The estimated tokens of the request if the draft `content` is sent to the
+/// bot, with the quoted message prepended as `send_msg` does.
+fn draft_tokens_hint(
+  chat: &dyn Chat,
+  channel_id: &ChannelId,
+  bot_id: &BotId,
+  quote_id: Option<Uuid>,
+  content: &str,
+) -> Option<usize> {
+  let channel = chat.channel(channel_id)?;
+  let bot = chat.info().bot(bot_id)?;
+  let quote_text = quote_id
+    .and_then(|id| chat.msg(channel_id, &id))
+    .and_then(|msg| msg.cur_cont_ref().text().map(str::to_string));
+  let content = quote_text
+    .map(|quote_text| format!("{} {}", quote_text, content))
+    .unwrap_or_else(|| content.to_string());
+  Some(draft_tokens(bot, channel, &content))
+}
+
 fn select_bot(text_area: &mut MessageEditor, bots: &BotList) {
   let hint = text_area.bot_hint();
   if let Some(hint) = hint {
@@ -503,12 +640,41 @@ pub struct MessageEditor {
   edit_message: EditedMessage,
   #[declare(skip)]
   caret: CaretState,
+  // the images pasted or picked, they're attached when the message is sent.
+  #[declare(skip)]
+  images: Vec<EditorImage>,
+}
+
+/// The image waiting in the editor to be attached to the message.
+pub enum EditorImage {
+  // the image file or url.
+  Src(String),
+  // the image data pasted.
+  Data(Vec<u8>),
+}
+
+impl EditorImage {
+  fn label(&self) -> String {
+    match self {
+      EditorImage::Src(src) => src.clone(),
+      EditorImage::Data(data) => format!("pasted, {} KB", data.len().div_ceil(1024)),
+    }
+  }
 }
 
 impl MessageEditor {
   pub fn reset(&mut self) {
     self.edit_message = EditedMessage::default();
     self.caret = CaretState::default();
+    self.images.clear();
+  }
+
+  pub fn images(&self) -> &[EditorImage] { &self.images }
+
+  pub fn remove_image(&mut self, idx: usize) {
+    if idx < self.images.len() {
+      self.images.remove(idx);
+    }
   }
 
   pub fn display_text(&self) -> String { self.edit_message.display_message() }
@@ -691,8 +857,17 @@ fn deal_copy_paste(this: &impl StateWriter<Value = MessageEditor>, e: &KeyboardE
   };
   match e.key_code() {
     PhysicalKey::Code(KeyCode::KeyV) => {
-      if let Ok(txt) = AppCtx::clipboard().borrow_mut().read_text() {
-        this.write().insert_str(&txt);
+      let clipboard = AppCtx::clipboard();
+      let txt = clipboard.borrow_mut().read_text().ok();
+      match (txt.as_deref().and_then(image_src), txt) {
+        (Some(src), _) => this.write().images.push(EditorImage::Src(src)),
+        (None, Some(txt)) if !txt.is_empty() => this.write().insert_str(&txt),
+        // no text, it may be the copied image.
+        _ => {
+          if let Ok(data) = clipboard.borrow_mut().read("image/png") {
+            this.write().images.push(EditorImage::Data(data.to_vec()));
+          }
+        }
       }
       true
     }
@@ -711,6 +886,20 @@ fn deal_copy_paste(this: &impl StateWriter<Value = MessageEditor>, e: &KeyboardE
   }
 }
 
+/// The image file or url if the pasted text is, i.e. the file copied in the
+/// file manager, it's attached to the message instead of inserted.
+fn image_src(txt: &str) -> Option<String> {
+  let src = txt.trim();
+  let src = src.strip_prefix("file://").unwrap_or(src);
+  let lower = src.to_ascii_lowercase();
+  let is_image = [".png", ".jpg", ".jpeg", ".webp"]
+    .iter()
+    .any(|ext| lower.ends_with(ext));
+  let is_url = lower.starts_with("http://") || lower.starts_with("https://");
+  (is_image && !src.contains('\n') && (is_url || std::path::Path::new(src).is_file()))
+    .then(|| src.to_owned())
+}
+
 fn deal_delete(this: &impl StateWriter<Value = MessageEditor>, e: &KeyboardEvent) -> bool {
   match e.key() {
     VirtualKey::Named(NamedKey::Backspace) => {
@@ -1,13 +1,16 @@
-use polestar_core::model::{BotAvatar, ChannelId, MsgCont, MsgId, MsgRole};
+use polestar_core::model::{
+  BotAvatar, Channel, ChannelId, Image, Msg, MsgId, MsgMeta, MsgRating, MsgRole, MsgStatus,
+  MsgUsage, Rating, MIME,
+};
 use ribir::prelude::*;
 use uuid::Uuid;
 
 use crate::style::decorator::channel::message_style;
-use crate::style::{GAINSBORO, WHITE};
+use crate::style::{BRIGHT_GRAY_EAE9E9_FF, GAINSBORO, SPANISH_GRAY};
 use crate::theme::polestar_svg;
 use crate::widgets::app::Chat;
 use crate::widgets::common::{w_avatar, IconButton};
-use crate::widgets::helper::send_msg;
+use crate::widgets::helper::{regenerate_msg, send_msg};
 
 use super::onboarding::w_msg_onboarding;
 
@@ -44,7 +47,7 @@ pub fn w_msg_list(
     // TODO: use `guard` to unsubscribe when widget is disposed.
     // Related to Ribir #507, should be auto unsubscribe_when_dropped when unvalid
     let mut guard = Some(watch!((
-      $channel.msgs().len(),
+      $channel.branch_msgs().len(),
       $channel.last_msg().map_or(0, |msg| msg.cont_size())
     ))
       .distinct_until_changed()
@@ -70,9 +73,14 @@ pub fn w_msg_list(
                 let quote_id = quote_id.clone_writer();
                 let channel_id = *$channel.id();
                 let chat = chat.clone_writer();
-                $channel.msgs().iter().map(move |m| {
-                  let id = *m.id();
-                  @ { w_msg(chat.clone_writer(), channel_id, id, quote_id.clone_writer()) }
+                msg_groups(&$channel).into_iter().map(move |ids| {
+                  let chat = chat.clone_writer();
+                  let quote_id = quote_id.clone_writer();
+                  if ids.len() > 1 {
+                    w_arena(chat, channel_id, ids, quote_id).widget_build(ctx!())
+                  } else {
+                    w_msg(chat, channel_id, ids[0], quote_id).widget_build(ctx!())
+                  }
                 }).collect::<Vec<_>>()
               }
             }
@@ -83,6 +91,94 @@ pub fn w_msg_list(
   }
 }
 
+/// The messages of the active branch, the answers of the bots to the same
+/// question are grouped together to compare them side by side.
+fn msg_groups(channel: &Channel) -> Vec<Vec<MsgId>> {
+  let mut groups: Vec<Vec<MsgId>> = vec![];
+  let mut last_source = None;
+  for msg in channel.branch_msgs() {
+    let source = msg.meta().source_id().filter(|_| msg.role().is_bot());
+    match groups.last_mut() {
+      Some(group) if source.is_some() && source == last_source => group.push(*msg.id()),
+      _ => groups.push(vec![*msg.id()]),
+    }
+    last_source = source;
+  }
+  groups
+}
+
+/// The answers of the bots to the same question in parallel columns, with the
+/// time and the tokens every answer cost, to pick the best one.
+fn w_arena(
+  chat: impl StateWriter<Value = dyn Chat>,
+  channel_id: ChannelId,
+  answer_ids: Vec<MsgId>,
+  quote_id: impl StateWriter<Value = Option<Uuid>>,
+) -> impl WidgetBuilder {
+  fn_widget! {
+    let question_id = $chat
+      .msg(&channel_id, &answer_ids[0])
+      .and_then(|msg| msg.meta().source_id().copied())
+      .unwrap();
+    let best = $chat
+      .channel(&channel_id)
+      .and_then(|ch| ch.best_answer(&question_id).copied());
+    @Row {
+      item_gap: 8.,
+      @ {
+        answer_ids.into_iter().map(move |msg_id| {
+          let usage = $chat
+            .msg(&channel_id, &msg_id)
+            .and_then(|msg| msg.cur_cont_ref().usage().map(usage_text));
+          let is_best = best == Some(msg_id);
+          let is_streaming = $chat.is_streaming(&msg_id);
+          let chat = chat.clone_writer();
+          @Expanded {
+            flex: 1.,
+            @Column {
+              item_gap: 4.,
+              @ { w_msg(chat.clone_writer(), channel_id, msg_id, quote_id.clone_writer()) }
+              @ {
+                usage.map(|usage| @Text {
+                  text: usage,
+                  foreground: Color::from_u32(SPANISH_GRAY),
+                })
+              }
+              @ {
+                (!is_streaming).then(move || @Button {
+                  cursor: CursorIcon::Pointer,
+                  color: Color::from_u32(BRIGHT_GRAY_EAE9E9_FF),
+                  on_tap: move |_| {
+                    // pick the best again to clear the pick.
+                    let winner_id = (!is_best).then_some(msg_id);
+                    $chat.write().pick_best(&channel_id, &question_id, winner_id);
+                  },
+                  @ { Label::new(if is_best { "Best ✓" } else { "Pick best" }) }
+                })
+              }
+            }
+          }
+        }).collect::<Vec<_>>()
+      }
+    }
+  }
+}
+
+/// The time and the tokens the answer cost, `~` marks the estimated tokens.
+fn usage_text(usage: &MsgUsage) -> String {
+  let estimated = if usage.estimated { "~" } else { "" };
+  let mut text = format!(
+    "{}ms · {}{} tokens",
+    usage.latency_ms,
+    estimated,
+    usage.total_tokens()
+  );
+  if let Some(first_token_ms) = usage.first_token_ms {
+    text.push_str(&format!(" · first token {}ms", first_token_ms));
+  }
+  text
+}
+
 #[derive(Declare)]
 struct MsgOps;
 
@@ -145,6 +241,7 @@ fn w_msg(
   fn_widget! {
     @ {
         let mut stack = @Stack {};
+        let editing = State::value(false);
         let chat_ref = $chat;
         let msg = chat_ref.msg(&channel_id, &msg_id).unwrap();
         let role = msg.role().clone();
@@ -193,6 +290,34 @@ fn w_msg(
                       }
                     })
                   }
+                  @ {
+                    let editing = editing.clone_writer();
+                    let is_streaming = $chat.is_streaming(&msg_id);
+                    (msg.role().is_user() && !is_streaming).then(move || {
+                      @MsgOp {
+                        cb: Box::new(move || *$editing.write() = true) as Box<dyn Fn()>,
+                        @IconButton {
+                          padding: EdgeInsets::all(4.),
+                          size: IconSize::of(ctx!()).tiny,
+                          @ { polestar_svg::EDIT }
+                        }
+                      }
+                    })
+                  }
+                  @MsgOp {
+                    cb: Box::new(move || {
+                      let _hint = || $chat.write();
+                      let chat = chat.clone_writer();
+                      let _ = AppCtx::spawn_local(async move {
+                        chat.write().fork_channel(&channel_id, &msg_id);
+                      });
+                    }) as Box<dyn Fn()>,
+                    @IconButton {
+                      padding: EdgeInsets::all(4.),
+                      size: IconSize::of(ctx!()).tiny,
+                      @ { polestar_svg::FORK }
+                    }
+                  }
                   @MsgOp {
                     cb: Box::new(move || {
                       let chat = $chat;
@@ -210,39 +335,29 @@ fn w_msg(
                     }
                   }
                   @ {
-                    let source_id = msg.meta().source_id().cloned();
-                    let bot_id = msg.role().bot().cloned();
+                    let chat = chat.clone_writer();
+                    $chat.is_streaming(&msg_id).then(move || {
+                      @MsgOp {
+                        cb: Box::new(move || {
+                          $chat.write().stop_stream(&msg_id);
+                        }) as Box<dyn Fn()>,
+                        @IconButton {
+                          padding: EdgeInsets::all(4.),
+                          size: IconSize::of(ctx!()).tiny,
+                          @ { polestar_svg::STOP }
+                        }
+                      }
+                    })
+                  }
+                  @ {
                     let msg_id = *msg.id();
                     let chat = chat.clone_writer();
                     let role = msg.role().clone();
-                    (role.is_bot()).then(move || {
-                      let _hint = || $chat.write();
+                    let is_streaming = $chat.is_streaming(&msg_id);
+                    (role.is_bot() && !is_streaming).then(move || {
                       @MsgOp {
-                        // TODO: cb code is messy, need to refactor.
                         cb: Box::new(move || {
-                          let _hint = || $chat.write();
-                          let (source_msg, idx) = {
-                            let mut chat = $chat.write();
-                            (
-                              chat
-                                .msg(&channel_id, &source_id.unwrap())
-                                .and_then(
-                                  |msg| msg.cur_cont_ref().text().map(|text| text.to_owned())
-                                )
-                                .unwrap_or_default().clone(),
-                              chat.add_msg_cont(&channel_id, &msg_id, MsgCont::init_text()).unwrap()
-                            )
-                          };
-                          $chat.write().switch_cont(&channel_id, &msg_id, idx);
-                          send_msg(
-                            chat.clone_writer(),
-                            channel_id,
-                            msg_id,
-                            idx,
-                            bot_id.clone().unwrap(),
-                            source_msg,
-                            None,
-                          );
+                          regenerate_msg(chat.clone_writer(), channel_id, msg_id, None);
                         }) as Box<dyn Fn()>,
                         @IconButton {
                           padding: EdgeInsets::all(4.),
@@ -252,6 +367,82 @@ fn w_msg(
                       }
                     })
                   }
+                  @ {
+                    let chat = chat.clone_writer();
+                    let idx = msg.cur_idx();
+                    let rated = msg.cur_cont_ref().rating().map(|rating| rating.rating);
+                    let is_streaming = $chat.is_streaming(&msg_id);
+                    (msg.role().is_bot() && !is_streaming).then(move || {
+                      @MsgOp {
+                        cb: Box::new(move || {
+                          // rate it again to clear the rating.
+                          let rating = (rated != Some(Rating::Up))
+                            .then(|| MsgRating::new(Rating::Up, None));
+                          $chat.write().rate_msg(&channel_id, &msg_id, idx, rating);
+                        }) as Box<dyn Fn()>,
+                        @IconButton {
+                          padding: EdgeInsets::all(4.),
+                          size: IconSize::of(ctx!()).tiny,
+                          @ {
+                            if rated == Some(Rating::Up) {
+                              polestar_svg::THUMB_UP_FILLED
+                            } else {
+                              polestar_svg::THUMB_UP
+                            }
+                          }
+                        }
+                      }
+                    })
+                  }
+                  @ {
+                    let chat = chat.clone_writer();
+                    let idx = msg.cur_idx();
+                    let rated = msg.cur_cont_ref().rating().map(|rating| rating.rating);
+                    let is_streaming = $chat.is_streaming(&msg_id);
+                    (msg.role().is_bot() && !is_streaming).then(move || {
+                      @MsgOp {
+                        cb: Box::new(move || {
+                          // rate it again to clear the rating.
+                          let rating = (rated != Some(Rating::Down))
+                            .then(|| MsgRating::new(Rating::Down, None));
+                          $chat.write().rate_msg(&channel_id, &msg_id, idx, rating);
+                        }) as Box<dyn Fn()>,
+                        @IconButton {
+                          padding: EdgeInsets::all(4.),
+                          size: IconSize::of(ctx!()).tiny,
+                          @ {
+                            if rated == Some(Rating::Down) {
+                              polestar_svg::THUMB_DOWN_FILLED
+                            } else {
+                              polestar_svg::THUMB_DOWN
+                            }
+                          }
+                        }
+                      }
+                    })
+                  }
+                  @ {
+                    let chat = chat.clone_writer();
+                    // delete the question with its answers, or only the answer.
+                    let group = msg.role().is_user();
+                    let is_streaming = $chat.is_streaming(&msg_id);
+                    (!is_streaming).then(move || {
+                      @MsgOp {
+                        cb: Box::new(move || {
+                          let _hint = || $chat.write();
+                          let chat = chat.clone_writer();
+                          let _ = AppCtx::spawn_local(async move {
+                            chat.write().remove_msg(&channel_id, &msg_id, group);
+                          });
+                        }) as Box<dyn Fn()>,
+                        @IconButton {
+                          padding: EdgeInsets::all(4.),
+                          size: IconSize::of(ctx!()).tiny,
+                          @ { polestar_svg::TRASH }
+                        }
+                      }
+                    })
+                  }
                 }.widget_build(ctx!())
               },
               _ => {
@@ -272,7 +463,7 @@ fn w_msg(
               url: "outlined_logo.png".to_owned(),
             })
           }
-          MsgRole::System(_) => {
+          MsgRole::System(_) | MsgRole::Tool(_) => {
             BotAvatar::Image {
               url: "outlined_logo.png".to_owned(),
             }
@@ -290,10 +481,21 @@ fn w_msg(
               }
               @Column {
                 @ {
-                  msg.role().bot().map(move |bot_id| {
+                  msg.cur_bot().map(move |bot_id| {
                     let chat = $chat;
                     let bot = chat.info().get_bot_or_default(Some(bot_id));
-                    @Text { text: bot.name().to_owned() }
+                    // the output of a pipeline step, i.e. `Polish · Writer 2/3`.
+                    let step = msg.meta().pipeline().and_then(|link| {
+                      let pipeline = chat.info().bot(&link.bot_id)?;
+                      let steps = pipeline.pipeline()?.len();
+                      Some(format!(" · {} {}/{}", pipeline.name(), link.step + 1, steps))
+                    });
+                    @Text { text: format!("{}{}", bot.name(), step.unwrap_or_default()) }
+                  })
+                }
+                @ {
+                  msg.role().tool().map(|call| {
+                    @Text { text: format!("Tool {}({})", call.name, call.arguments) }
                   })
                 }
                 @ConstrainedBox {
@@ -303,7 +505,6 @@ fn w_msg(
                   },
                   @ {
                       let default_txt = String::new();
-                      // TODO: support Image Type.
                       let text = msg
                         .cur_cont_ref()
                         .text()
@@ -314,17 +515,57 @@ fn w_msg(
                           @ { w_msg_quote(&*$chat, &channel_id, quote_id) }
                           @ {
                             let chat = chat.clone_writer();
-                            (msg.cont_list().len() > 1).then(move || {
-                              w_msg_multi_rst(chat, channel_id, msg_id)
+                            (msg.cont_count() > 1).then(move || {
+                              w_msg_cont_switcher(chat, channel_id, msg_id)
                             })
                           }
+                          @ {
+                            let is_image_gen = msg.cur_bot().is_some_and(|bot_id| {
+                              $chat.info().bot(bot_id).is_some_and(|bot| bot.is_image_gen())
+                            });
+                            match msg.cur_cont_ref().status() {
+                              MsgStatus::Retrying(attempt) => Some(@Text {
+                                text: format!("Retrying, attempt {}...", attempt),
+                                foreground: Color::from_u32(SPANISH_GRAY),
+                              }),
+                              MsgStatus::Pending if is_image_gen => Some(@Text {
+                                text: "Generating image...",
+                                foreground: Color::from_u32(SPANISH_GRAY),
+                              }),
+                              _ => None,
+                            }
+                          }
+                          @ {
+                            let chat = chat.clone_writer();
+                            msg
+                              .cur_cont_ref()
+                              .image()
+                              .cloned()
+                              .map(move |img| w_msg_image(chat, img))
+                          }
+                          @ {
+                            msg
+                              .cur_cont_ref()
+                              .images()
+                              .iter()
+                              .map(|img| w_msg_image(chat.clone_writer(), img.clone()))
+                              .collect::<Vec<_>>()
+                          }
                           @TextSelectable {
+                            visible: pipe!(!*$editing),
                             @Text {
-                              text,
+                              text: text.clone(),
                               overflow: Overflow::AutoWrap,
                               text_style: TypographyTheme::of(ctx!()).body_large.text.clone()
                             }
                           }
+                          @ {
+                            let chat = chat.clone_writer();
+                            let editing = editing.clone_writer();
+                            msg.role().is_user().then(move || {
+                              w_msg_edit(chat, channel_id, msg_id, text, editing)
+                            })
+                          }
                         }.widget_build(ctx!()),
                         msg.role().clone()
                       )
@@ -339,6 +580,141 @@ fn w_msg(
   }
 }
 
+/// The image of the message with the button to save it, only PNG images are
+/// shown and the image on the web is shown by its url.
+fn w_msg_image(chat: impl StateWriter<Value = dyn Chat>, img: Image) -> impl WidgetBuilder {
+  fn_widget! {
+    let png = img
+      .file()
+      .and_then(|name| $chat.attachment(name))
+      .filter(|attachment| attachment.mime() == &MIME::ImagePng);
+    let url = img.url().map(str::to_owned);
+    let tip: State<Option<String>> = State::value(None);
+    @Column {
+      item_gap: 4.,
+      @ {
+        match png {
+          Some(png) => @ConstrainedBox {
+            clamp: BoxClamp {
+              min: Size::zero(),
+              max: Size::new(512., 512.),
+            },
+            @FittedBox {
+              box_fit: BoxFit::Contain,
+              @ { ShareResource::new(PixelImage::from_png(png.data())) }
+            }
+          }.widget_build(ctx!()),
+          None => @Text {
+            text: url.clone().map_or_else(
+              || "The image can't be shown here, save it to view.".to_owned(),
+              |url| format!("Image: {}", url),
+            ),
+            foreground: Color::from_u32(SPANISH_GRAY),
+          }.widget_build(ctx!()),
+        }
+      }
+      @Row {
+        align_items: Align::Center,
+        item_gap: 8.,
+        @Button {
+          visible: url.is_none(),
+          cursor: CursorIcon::Pointer,
+          on_tap: move |_| {
+            *$tip.write() = Some(match $chat.save_image(&img) {
+              Ok(path) => format!("Saved to {}", path.display()),
+              Err(e) => format!("Save failed: {}", e),
+            });
+          },
+          @ { Label::new("Save image") }
+        }
+        @Text {
+          visible: pipe!($tip.is_some()),
+          text: pipe!($tip.clone().unwrap_or_default()),
+          foreground: Color::from_u32(SPANISH_GRAY),
+        }
+      }
+    }
+  }
+}
+
+fn w_msg_edit(
+  chat: impl StateWriter<Value = dyn Chat>,
+  channel_id: ChannelId,
+  msg_id: MsgId,
+  text: String,
+  editing: impl StateWriter<Value = bool>,
+) -> impl WidgetBuilder {
+  fn_widget! {
+    let input = @Input {};
+    $input.write().set_text(&text);
+    @Column {
+      visible: pipe!(*$editing),
+      item_gap: 8.,
+      @ { input }
+      @Row {
+        h_align: HAlign::Right,
+        @Button {
+          cursor: CursorIcon::Pointer,
+          margin: EdgeInsets::only_right(10.),
+          on_tap: move |_| *$editing.write() = false,
+          @ { Label::new("Cancel") }
+        }
+        @FilledButton {
+          cursor: CursorIcon::Pointer,
+          on_tap: move |_| {
+            let text = $input.text().trim().to_owned();
+            if !text.is_empty() {
+              *$editing.write() = false;
+              resend_edited(chat.clone_writer(), channel_id, msg_id, text);
+            }
+          },
+          @ { Label::new("Send") }
+        }
+      }
+    }
+  }
+}
+
+/// Edit the user message and ask the bot of its previous reply again, the
+/// new reply belongs to the new branch.
+fn resend_edited(
+  chat: impl StateWriter<Value = dyn Chat>,
+  channel_id: ChannelId,
+  msg_id: MsgId,
+  text: String,
+) {
+  if chat.write().edit_msg(&channel_id, &msg_id, &text).is_none() {
+    return;
+  }
+
+  let bot_id = {
+    let chat = chat.read();
+    let channel = chat.channel(&channel_id);
+    let replied_bot = channel.and_then(|ch| {
+      ch.msgs()
+        .iter()
+        .find(|msg| msg.meta().source_id() == Some(&msg_id))
+        .and_then(|msg| msg.role().bot())
+    });
+    let def_bot = channel.and_then(|ch| ch.cfg().def_bot_id());
+    chat
+      .info()
+      .get_bot_or_default(replied_bot.or(def_bot))
+      .id()
+      .clone()
+  };
+  let quote_id = chat
+    .read()
+    .msg(&channel_id, &msg_id)
+    .and_then(|msg| msg.meta().quote_id().cloned());
+  let bot_msg = Msg::new_bot_text(bot_id.clone(), MsgMeta::reply(msg_id));
+  let id = *bot_msg.id();
+  let idx = bot_msg.cur_idx();
+  chat.write().add_msg(&channel_id, bot_msg);
+
+  send_msg(chat, channel_id, id, idx, bot_id, text, quote_id);
+}
+
 fn w_msg_quote(
   chat: &dyn Chat,
   channel_id: &ChannelId,
@@ -359,60 +735,40 @@ fn w_msg_quote(
   })
 }
 
-fn w_msg_multi_rst(
+/// The "2/3" switcher of the message's alternatives.
+fn w_msg_cont_switcher(
   chat: impl StateWriter<Value = dyn Chat>,
   channel_id: ChannelId,
   msg_id: MsgId,
 ) -> impl WidgetBuilder {
   fn_widget! {
-    let scrollable_widget = @ScrollableWidget {
-      scrollable: Scrollable::X,
-    };
+    let (cur_idx, count) = $chat
+      .msg(&channel_id, &msg_id)
+      .map_or((0, 0), |msg| (msg.cur_idx(), msg.cont_count()));
     @Row {
-      @Visibility {
-        @Void {}
-      }
-      @Expanded {
-        flex: 1.,
-        @$scrollable_widget {
-          @Row {
-            item_gap: 8.,
-            @ {
-              $chat
-                .msg(&channel_id, &msg_id)
-                .unwrap()
-                .cont_list()
-                .iter()
-                .enumerate()
-                .map(|(idx, cont)| {
-                  let text = cont.text().map(|s| s.to_owned()).unwrap_or_default();
-                  let w_thumbnail = w_msg_thumbnail(text);
-                  @$w_thumbnail {
-                    on_tap: move |_| {
-                      $chat.write().switch_cont(&channel_id, &msg_id, idx);
-                    }
-                  }
-                }).collect::<Vec<_>>()
-            }
-          }
-        }
-      }
-      @Visibility {
-        @Void {}
+      align_items: Align::Center,
+      item_gap: 4.,
+      @IconButton {
+        padding: EdgeInsets::all(4.),
+        size: IconSize::of(ctx!()).tiny,
+        visible: cur_idx > 0,
+        on_tap: move |_| {
+          $chat.write().switch_cont(&channel_id, &msg_id, cur_idx - 1);
+        },
+        @ { polestar_svg::LEFT_ARROW }
       }
-    }
-  }
-}
-
-fn w_msg_thumbnail(text: String) -> impl WidgetBuilder {
-  fn_widget! {
-    @SizedBox {
-      size: Size::new(150., 60.),
-      background: Color::from_u32(WHITE),
       @Text {
-        text,
-        overflow: Overflow::AutoWrap,
-        text_style: TypographyTheme::of(ctx!()).body_large.text.clone()
+        text: format!("{}/{}", cur_idx + 1, count),
+        foreground: Color::from_u32(SPANISH_GRAY),
+      }
+      @IconButton {
+        padding: EdgeInsets::all(4.),
+        size: IconSize::of(ctx!()).tiny,
+        visible: cur_idx + 1 < count,
+        on_tap: move |_| {
+          $chat.write().switch_cont(&channel_id, &msg_id, cur_idx + 1);
+        },
+        @ { polestar_svg::RIGHT_ARROW }
       }
     }
   }
@@ -3,18 +3,21 @@ use ribir::prelude::*;
 use crate::{
   platform,
   style::{COMMON_RADIUS, WHITE},
-  widgets::app::{UIState, UserConfig},
+  widgets::app::{ChannelMgr, UIState, UserConfig},
 };
 
 mod account;
+mod data;
 mod general;
 mod network;
-use account::{w_email, w_subscription, AccountItem};
+use account::{w_email, w_subscription, w_usage_stats, AccountItem};
+use data::w_data_settings;
 use general::w_general_settings;
 use network::w_network_settings;
 
 pub fn w_settings(
   config: impl StateWriter<Value = dyn UserConfig>,
+  channel_mgr: impl StateWriter<Value = dyn ChannelMgr>,
   ui_state: impl StateWriter<Value = dyn UIState>,
 ) -> impl WidgetBuilder {
   fn_widget! {
@@ -34,7 +37,11 @@ pub fn w_settings(
             }
             @AccountItem {
               name: "Subscription",
-              @ { w_subscription(config) }
+              @ { w_subscription(config.clone_writer()) }
+            }
+            @AccountItem {
+              name: "Usage",
+              @ { w_usage_stats(channel_mgr.clone_writer()) }
             }
           }
           @ {
@@ -47,7 +54,11 @@ pub fn w_settings(
           }
           @SettingItem {
             name: "Network Settings",
-            @ { w_network_settings() }
+            @ { w_network_settings(config) }
+          }
+          @SettingItem {
+            name: "Data",
+            @ { w_data_settings(channel_mgr) }
           }
         }
       }
@@ -1,9 +1,9 @@
-use polestar_core::model::Quota;
+use polestar_core::model::{Quota, UsageGroup, UsageKey};
 use ribir::prelude::*;
 
 use crate::req::query_quota;
-use crate::style::{BLACK, CHINESE_WHITE, COMMON_RADIUS, ISABELLINE, WHITE};
-use crate::widgets::app::{UIState, UserConfig};
+use crate::style::{BLACK, BRIGHT_GRAY_EAE9E9_FF, CHINESE_WHITE, COMMON_RADIUS, ISABELLINE, WHITE};
+use crate::widgets::app::{ChannelMgr, UIState, UserConfig};
 use crate::widgets::common::ProgressBar;
 
 #[derive(Declare)]
@@ -68,8 +68,9 @@ pub(super) fn w_subscription(
     let token = $config.user().and_then(|user| user.token().map(|s| s.to_owned()));
     let quota = State::value(None);
     let quota_writer = quota.clone_writer();
+    let retry = $config.retry().clone();
     let _ = AppCtx::spawn_local(async move {
-      let quota = query_quota(token).await.ok();
+      let quota = query_quota(token, retry).await.ok();
       *quota_writer.write() = quota;
     });
     @ConstrainedBox {
@@ -98,6 +99,83 @@ pub(super) fn w_subscription(
   }
 }
 
+pub(super) fn w_usage_stats(
+  channel_mgr: impl StateWriter<Value = dyn ChannelMgr>,
+) -> impl WidgetBuilder {
+  fn_widget! {
+    let group = State::value(UsageGroup::Day);
+    let groups = [
+      ("By Day", UsageGroup::Day),
+      ("By Bot", UsageGroup::Bot),
+      ("By Provider", UsageGroup::Provider),
+      ("By Channel", UsageGroup::Channel),
+    ];
+    @Column {
+      item_gap: 10.,
+      margin: EdgeInsets::only_top(10.),
+      @Row {
+        item_gap: 10.,
+        @ {
+          groups.into_iter().map(move |(name, value)| {
+            @Button {
+              cursor: CursorIcon::Pointer,
+              color: Color::from_u32(BRIGHT_GRAY_EAE9E9_FF),
+              on_tap: move |_| *$group.write() = value,
+              @ { Label::new(name) }
+            }
+          }).collect::<Vec<_>>()
+        }
+      }
+      @Column {
+        item_gap: 6.,
+        @ {
+          pipe! {
+            // only query again when the group changed, the settings page is
+            // built again every time it's opened.
+            let stats = channel_mgr.read().usage_stats(*$group);
+            let mut rows = stats.into_iter().map(|stat| {
+              let name = match &stat.key {
+                UsageKey::Channel(id) => channel_mgr
+                  .read()
+                  .channel(id)
+                  .map_or_else(|| id.to_string(), |channel| channel.name().to_owned()),
+                key => key.to_string(),
+              };
+              // `~` marks the groups with the tokens estimated locally.
+              let estimated = if stat.estimated > 0 { "~" } else { "" };
+              @Row {
+                justify_content: JustifyContent::SpaceBetween,
+                @Text { text: name }
+                @Text {
+                  text: format!(
+                    "{} answers, {}{} prompt + {}{} completion tokens, {}ms avg, cost {:.4}",
+                    stat.answers,
+                    estimated,
+                    stat.prompt_tokens,
+                    estimated,
+                    stat.completion_tokens,
+                    stat.avg_latency_ms,
+                    stat.cost,
+                  ),
+                  foreground: Palette::of(ctx!()).outline(),
+                }
+              }.widget_build(ctx!())
+            }).collect::<Vec<_>>();
+            if rows.is_empty() {
+              let empty = @Text {
+                text: "No answers with usage yet.",
+                foreground: Palette::of(ctx!()).outline(),
+              };
+              rows.push(empty.widget_build(ctx!()));
+            }
+            rows
+          }
+        }
+      }
+    }
+  }
+}
+
 fn w_free_plan(config: impl StateReader<Value = Option<Quota>>) -> impl WidgetBuilder {
   fn_widget! {
     @Row {
@@ -0,0 +1,61 @@
+use ribir::prelude::*;
+
+use crate::style::{BRIGHT_GRAY_EAE9E9_FF, CHINESE_WHITE, CULTURED_F7F7F5_FF};
+use crate::widgets::app::ChannelMgr;
+
+pub(super) fn w_data_settings(
+  channel_mgr: impl StateWriter<Value = dyn ChannelMgr>,
+) -> impl WidgetBuilder {
+  fn_widget! {
+    let path_input = @Input {
+      cursor: CursorIcon::Text,
+      background: Color::from_u32(CULTURED_F7F7F5_FF),
+      padding: EdgeInsets::new(10., 5., 10., 5.),
+      border: Border::all(BorderSide {
+        width: 1.,
+        color: Color::from_u32(CHINESE_WHITE).into(),
+      }),
+      border_radius: Radius::all(6.),
+      @ { Placeholder::new("Path of Polestar's JSON export or ChatGPT's conversations.json") }
+    };
+    let tip: State<Option<String>> = State::value(None);
+
+    @Column {
+      item_gap: 10.,
+      @Text {
+        text: "Import the channels of a JSON export.",
+        foreground: Palette::of(ctx!()).outline(),
+      }
+      @Row {
+        align_items: Align::Center,
+        @Expanded {
+          flex: 1.,
+          @ { path_input }
+        }
+        @Button {
+          cursor: CursorIcon::Pointer,
+          color: Color::from_u32(BRIGHT_GRAY_EAE9E9_FF),
+          margin: EdgeInsets::only_left(10.),
+          on_tap: move |_| {
+            let path = $path_input.text().trim().to_owned();
+            let rst = std::fs::read_to_string(&path)
+              .map_err(|err| err.to_string())
+              .and_then(|content| {
+                $channel_mgr.write().import_channels(&content).map_err(|err| err.to_string())
+              });
+            *$tip.write() = Some(match rst {
+              Ok(ids) => format!("Imported {} channels.", ids.len()),
+              Err(err) => format!("Import failed: {}", err),
+            });
+          },
+          @ { Label::new("Import") }
+        }
+      }
+      @Text {
+        visible: pipe!($tip.is_some()),
+        text: pipe!($tip.clone().unwrap_or_default()),
+        foreground: Palette::of(ctx!()).outline(),
+      }
+    }
+  }
+}
@@ -1,8 +1,13 @@
+use polestar_core::NetworkCfg;
 use ribir::prelude::*;
 
+use crate::req::query_test_connection;
 use crate::style::{BRIGHT_GRAY_EAE9E9_FF, CHINESE_WHITE, CULTURED_F7F7F5_FF};
+use crate::widgets::app::UserConfig;
 
-pub(super) fn w_network_settings() -> impl WidgetBuilder {
+pub(super) fn w_network_settings(
+  config: impl StateWriter<Value = dyn UserConfig>,
+) -> impl WidgetBuilder {
   fn_widget! {
     @Column {
       @Row {
@@ -26,14 +31,15 @@ pub(super) fn w_network_settings() -> impl WidgetBuilder {
           }
         }
       }
-      @ { w_setting_input() }
+      @ { w_setting_input(config) }
     }
   }
 }
 
-fn w_setting_input() -> impl WidgetBuilder {
+fn w_setting_input(config: impl StateWriter<Value = dyn UserConfig>) -> impl WidgetBuilder {
   fn_widget! {
-    let input = @Input {
+    let network = $config.network().clone();
+    let proxy_input = @Input {
       cursor: CursorIcon::Text,
       background: Color::from_u32(CULTURED_F7F7F5_FF),
       padding: EdgeInsets::new(10., 5., 10., 5.),
@@ -42,25 +48,85 @@ fn w_setting_input() -> impl WidgetBuilder {
         color: Color::from_u32(CHINESE_WHITE).into(),
       }),
       border_radius: Radius::all(6.),
-      @ { Placeholder::new("Input your proxy address here") }
+      @ { Placeholder::new("Input your proxy address here, like socks5://127.0.0.1:1080") }
     };
+    $proxy_input.write().set_text(network.proxy.as_deref().unwrap_or_default());
+    let no_proxy_input = @Input {
+      cursor: CursorIcon::Text,
+      background: Color::from_u32(CULTURED_F7F7F5_FF),
+      padding: EdgeInsets::new(10., 5., 10., 5.),
+      border: Border::all(BorderSide {
+        width: 1.,
+        color: Color::from_u32(CHINESE_WHITE).into(),
+      }),
+      border_radius: Radius::all(6.),
+      @ { Placeholder::new("Hosts that don't use the proxy, separated by comma") }
+    };
+    $no_proxy_input.write().set_text(&network.no_proxy.join(","));
+    let tip: State<Option<String>> = State::value(None);
 
-    @Row {
-      justify_content: JustifyContent::SpaceBetween,
-      align_items: Align::Center,
-      @Expanded {
-        flex: 1.,
-        @ { input }
+    @Column {
+      item_gap: 10.,
+      @Row {
+        justify_content: JustifyContent::SpaceBetween,
+        align_items: Align::Center,
+        @Expanded {
+          flex: 1.,
+          @ { proxy_input }
+        }
+        @Button {
+          cursor: CursorIcon::Pointer,
+          color: Color::from_u32(BRIGHT_GRAY_EAE9E9_FF),
+          margin: EdgeInsets::only_left(10.),
+          on_tap: move |_| {
+            let network = input_network($config.network(), &$proxy_input, &$no_proxy_input);
+            *$tip.write() = Some("Testing...".to_owned());
+            let tip = tip.clone_writer();
+            let _ = AppCtx::spawn_local(async move {
+              let rst = match query_test_connection(network).await {
+                Ok(cost) => format!("Connected in {} ms.", cost.as_millis()),
+                Err(err) => format!("Connect failed: {}", err),
+              };
+              *tip.write() = Some(rst);
+            });
+          },
+          @ { Label::new("Test connection") }
+        }
+        @Button {
+          cursor: CursorIcon::Pointer,
+          color: Color::from_u32(BRIGHT_GRAY_EAE9E9_FF),
+          margin: EdgeInsets::only_left(10.),
+          on_tap: move |_| {
+            let network = input_network($config.network(), &$proxy_input, &$no_proxy_input);
+            let rst = $config.write().set_network(network);
+            *$tip.write() = Some(match rst {
+              Ok(_) => "Saved.".to_owned(),
+              Err(err) => format!("Save failed: {}", err),
+            });
+          },
+          @ { Label::new("Save") }
+        }
       }
-      @Button {
-        cursor: CursorIcon::Pointer,
-        color: Color::from_u32(BRIGHT_GRAY_EAE9E9_FF),
-        margin: EdgeInsets::only_left(10.),
-        on_tap: move |_| {
-
-        },
-        @ { Label::new("Save") }
+      @ { no_proxy_input }
+      @Text {
+        visible: pipe!($tip.is_some()),
+        text: pipe!($tip.clone().unwrap_or_default()),
+        foreground: Palette::of(ctx!()).outline(),
       }
     }
   }
 }
+
+fn input_network(base: &NetworkCfg, proxy: &Input, no_proxy: &Input) -> NetworkCfg {
+  let proxy = proxy.text().trim().to_owned();
+  NetworkCfg {
+    proxy: (!proxy.is_empty()).then_some(proxy),
+    no_proxy: no_proxy
+      .text()
+      .split(',')
+      .map(|host| host.trim().to_owned())
+      .filter(|host| !host.is_empty())
+      .collect(),
+    ..base.clone()
+  }
+}
@@ -2,7 +2,7 @@ use polestar_core::{model::ChannelCfg, open_user_config_folder};
 use ribir::prelude::*;
 
 use crate::{
-  style::APP_SIDEBAR_HEADER_HEIGHT,
+  style::{APP_SIDEBAR_HEADER_HEIGHT, CHINESE_WHITE, CULTURED_F7F7F5_FF},
   widgets::{
     app::{ChannelMgr, UIState},
     common::{IconButton, InteractiveList},
@@ -12,17 +12,49 @@ use crate::{
 
 mod channel_thumbnail_list;
 use channel_thumbnail_list::w_channel_thumbnail_list;
+mod msg_search;
+use msg_search::w_msg_search_result;
 
 pub fn w_sidebar(
   channel_mgr: impl StateWriter<Value = dyn ChannelMgr>,
   ui_state: impl StateWriter<Value = dyn UIState>,
 ) -> impl WidgetBuilder {
   fn_widget! {
+    let search_input = @Input {
+      cursor: CursorIcon::Text,
+      margin: EdgeInsets::new(0., 10., 8., 10.),
+      background: Color::from_u32(CULTURED_F7F7F5_FF),
+      padding: EdgeInsets::new(10., 5., 10., 5.),
+      border: Border::all(BorderSide {
+        width: 1.,
+        color: Color::from_u32(CHINESE_WHITE).into(),
+      }),
+      border_radius: Radius::all(6.),
+    };
+    let query = State::value(String::new());
+    watch!($search_input.text().trim().to_owned())
+      .distinct_until_changed()
+      .subscribe(move |text| *$query.write() = text);
+
+    let channel_list =
+      w_channel_thumbnail_list(channel_mgr.clone_writer(), ui_state.clone_writer());
+    let search_result = w_msg_search_result(
+      channel_mgr.clone_writer(),
+      ui_state.clone_writer(),
+      query.clone_reader(),
+    );
+
     @Column {
       @ { w_sidebar_header(channel_mgr.clone_writer()) }
+      @$search_input {
+        @ { Placeholder::new("Search messages") }
+      }
       @Expanded {
         flex: 1.,
-        @ { w_channel_thumbnail_list(channel_mgr.clone_writer(), ui_state.clone_writer()) }
+        @Stack {
+          @$channel_list { visible: pipe!($query.is_empty()) }
+          @$search_result { visible: pipe!(!$query.is_empty()) }
+        }
       }
       @ { w_sidebar_others(channel_mgr.clone_writer(), ui_state) }
     }
@@ -0,0 +1,49 @@
+use crate::widgets::app::{ChannelMgr, UIState};
+use crate::widgets::common::InteractiveList;
+use polestar_core::model::MsgSearchFilter;
+use ribir::prelude::*;
+
+pub fn w_msg_search_result(
+  channel_mgr: impl StateWriter<Value = dyn ChannelMgr>,
+  ui_state: impl StateWriter<Value = dyn UIState>,
+  query: impl StateReader<Value = String>,
+) -> impl WidgetBuilder {
+  fn_widget! {
+    @InteractiveList {
+      highlight_visible: false,
+      @ {
+        pipe! {
+          // only search again when the query changed, but not every time the
+          // channels changed.
+          let hits = channel_mgr
+            .read()
+            .search_msgs(&$query, &MsgSearchFilter::default());
+          let mut rst = vec![];
+          for hit in hits {
+            let channel_id = hit.channel_id;
+            let channel_name = channel_mgr
+              .read()
+              .channel(&channel_id)
+              .map_or_else(String::new, |channel| channel.name().to_owned());
+            let item = @ListItem {
+              on_tap: move |_| {
+                let _ = || $channel_mgr.write();
+                let _ = || $ui_state.write();
+                let channel_mgr = channel_mgr.clone_writer();
+                let ui_state = ui_state.clone_writer();
+                let _ = AppCtx::spawn_local(async move {
+                  channel_mgr.write().switch_channel(&channel_id);
+                  ui_state.write().navigate_to("/home/chat");
+                });
+              },
+              @HeadlineText(Label::new(channel_name))
+              @SupportingText(Label::new(hit.snippet))
+            };
+            rst.push(item);
+          }
+          rst
+        }
+      }
+    }
+  }
+}
@@ -1,4 +1,7 @@
-use polestar_core::model::{BotId, ChannelMode};
+use polestar_core::{
+  model::{BotId, ChannelMode},
+  service::export::ExportFormat,
+};
 use ribir::prelude::*;
 use uuid::Uuid;
 
@@ -55,6 +58,7 @@ struct ChannelState {
   pub bot_list_top: f32,
   pub channel_mode: ChannelMode,
   pub selected_bot: Option<BotId>,
+  pub memory: bool,
 }
 
 pub fn w_modify_channel_modal(
@@ -81,6 +85,7 @@ pub fn w_modify_channel_modal(
     let channel_state = State::value(ChannelState {
       channel_mode: channel_ref.cfg().mode(),
       selected_bot: channel_ref.cfg().def_bot_id().cloned(),
+      memory: channel_ref.cfg().memory(),
       ..Default::default()
     });
 
@@ -99,7 +104,7 @@ pub fn w_modify_channel_modal(
 
     @Modal {
       title: "Channel Settings",
-      size: Size::new(480., 530.),
+      size: Size::new(480., 780.),
       confirm_cb: Box::new(move || {
         let _ = || $ui_state.write();
         let rename = $channel_rename;
@@ -115,6 +120,7 @@ pub fn w_modify_channel_modal(
           if $channel_state.channel_mode != cfg.mode() {
             cfg.set_mode($channel_state.channel_mode);
           }
+          cfg.set_memory($channel_state.memory);
           mgr.update_channel_cfg(&channel_id, cfg);
         }
         ui_state.write().set_modify_channel_id(None);
@@ -192,6 +198,85 @@ pub fn w_modify_channel_modal(
               }
             }
           }
+          @Text {
+            margin: EdgeInsets::only_top(10.),
+            text: "Memory",
+            text_style: TypographyTheme::of(ctx!()).title_medium.text.clone(),
+          }
+          @Row {
+            align_items: Align::Center,
+            cursor: CursorIcon::Pointer,
+            on_tap: move |_| {
+              let memory = $channel_state.memory;
+              $channel_state.write().memory = !memory;
+            },
+            @Checkbox { checked: pipe!($channel_state.memory) }
+            @Text {
+              text: "Summarise the earlier messages out of the context and send the summary instead.",
+              overflow: Overflow::AutoWrap,
+            }
+          }
+          @Row {
+            align_items: Align::Center,
+            margin: EdgeInsets::only_top(6.),
+            @Expanded {
+              flex: 1.,
+              @ConstrainedBox {
+                clamp: BoxClamp::fixed_height(80.),
+                @VScrollBar {
+                  @Text {
+                    text: pipe! {
+                      $channel_mgr
+                        .channel(&channel_id)
+                        .and_then(|channel| channel.summary())
+                        .map_or("No summary yet.".to_owned(), |s| s.content().to_owned())
+                    },
+                    overflow: Overflow::AutoWrap,
+                  }
+                }
+              }
+            }
+            @Button {
+              cursor: CursorIcon::Pointer,
+              margin: EdgeInsets::only_left(10.),
+              on_tap: move |_| {
+                $channel_mgr.write().reset_channel_summary(&channel_id);
+              },
+              @ { Label::new("Reset") }
+            }
+          }
+          @Text {
+            margin: EdgeInsets::only_top(10.),
+            text: "Export",
+            text_style: TypographyTheme::of(ctx!()).title_medium.text.clone(),
+          }
+          @Row {
+            margin: EdgeInsets::only_top(6.),
+            @ {
+              [
+                ("Markdown", ExportFormat::Markdown),
+                ("JSON", ExportFormat::Json),
+                ("HTML", ExportFormat::Html),
+              ]
+              .into_iter()
+              .map(|(label, format)| {
+                @Button {
+                  cursor: CursorIcon::Pointer,
+                  margin: EdgeInsets::only_right(10.),
+                  on_tap: move |_| {
+                    let rst = $channel_mgr.export_channel(&channel_id, format);
+                    let tip = match rst {
+                      Ok(path) => format!("Exported to {}", path.display()),
+                      Err(e) => format!("Export failed: {}", e),
+                    };
+                    $ui_state.write().set_tooltip(Some(&tip));
+                  },
+                  @ { Label::new(label) }
+                }
+              })
+              .collect::<Vec<_>>()
+            }
+          }
         }
         @ConstrainedBox {
           clamp: BoxClamp::fixed_height(120.),